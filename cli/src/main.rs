@@ -0,0 +1,711 @@
+use anyhow::{anyhow, bail, ensure};
+use ndoors::puzzle::{Answer, Puzzle};
+use ndoors::*;
+use ndoors_client::Client;
+use std::io::{BufRead, Write};
+use std::process::ExitCode;
+use std::time::Duration;
+
+/// 决定退出码的错误，其他错误的退出码是 1
+#[derive(Debug, thiserror::Error)]
+enum CliError {
+    /// 参数无效，退出码 2
+    #[error("{0}")]
+    Usage(String),
+    /// 模拟结果不符合断言，退出码 3
+    #[error("{0}")]
+    Assertion(String),
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("puzzle") => puzzle(&args[1..]),
+        Some("hotseat") => hotseat(&args[1..]),
+        Some("doctor") => doctor(&args[1..]),
+        _ => simulate(&args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{:#}", error);
+            match error.downcast_ref::<CliError>() {
+                Some(CliError::Usage(_)) => ExitCode::from(2),
+                Some(CliError::Assertion(_)) => ExitCode::from(3),
+                None => ExitCode::FAILURE,
+            }
+        }
+    }
+}
+
+const SIMULATE_USAGE: &str = "用法：
+  ndoors [--quiet] [--assert-switch-rate <中奖率> [--tolerance <误差>]]
+  ndoors puzzle ...
+  ndoors hotseat ...
+  ndoors doctor ...";
+
+/// 随机模拟很多轮并输出统计。`--quiet` 不输出统计，`--assert-switch-rate` 检查改变选择的中奖率
+/// 和期望值的差不超过 `--tolerance`（默认 0.01）
+fn simulate(args: &[String]) -> anyhow::Result<()> {
+    let usage = || CliError::Usage(SIMULATE_USAGE.to_string());
+    let mut quiet = false;
+    let mut expected = None;
+    let mut tolerance = 0.01;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || -> std::result::Result<f64, CliError> {
+            args.next()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(usage)
+        };
+        match arg.as_str() {
+            "--quiet" | "-q" => quiet = true,
+            "--assert-switch-rate" => expected = Some(value()?),
+            "--tolerance" => tolerance = value()?,
+            _ => return Err(usage().into()),
+        }
+    }
+
+    let doors = 3;
+    let rounds = 100000;
+
+    // 生成主持人
+    let host = Uuid::new_v4();
+
+    // 游戏设置
+    let settings = Settings::new(doors, rounds);
+
+    // 创建房间
+    let mut room = Room::create(host, settings)?;
+
+    // 生成挑战者
+    let contestant = Uuid::new_v4();
+
+    // 挑战者进房间对设置满意并点击就绪
+    room.accept_contestant(contestant)?;
+    room.contestant_ready(true)?;
+
+    // 预先分配所有轮次的结果空间
+    room.reserve_rounds();
+
+    for _ in 0..room.settings().rounds {
+        // 开始一轮随机游戏
+        room.start_random()?;
+
+        // 挑战者随机选择
+        room.choose_random()?;
+
+        // 主持人随机揭示
+        room.reveal_random()?;
+
+        // 挑战者随机做出抉择
+        room.decide(rand::random())?;
+    }
+
+    // 完成本局游戏并获得每一轮的结果
+    let results = room.complete(false)?;
+
+    // 统计游戏结果
+    let result = GameResult::calculate(room.settings(), results);
+    let switch_rate = result.switch_win() as f64 / result.switch() as f64;
+    if !quiet {
+        report(&result);
+    }
+    if let Some(expected) = expected {
+        if (switch_rate - expected).abs() > tolerance {
+            return Err(CliError::Assertion(format!(
+                "改变选择的中奖率 {:.4} 和期望的 {:.4} 相差超过 {}",
+                switch_rate, expected, tolerance
+            ))
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// 输出模拟的统计
+fn report(result: &GameResult) {
+    let settings = result.settings();
+    println!(
+        "游戏设置: 共 {} 个门，进行了 {} 轮游戏；",
+        settings.doors, settings.rounds
+    );
+    println!(
+        "共中奖 {} 轮，未中奖 {} 轮，中奖率 {:.2}%；",
+        result.win(),
+        settings.rounds - result.win(),
+        result.win() as f64 * 100.0 / settings.rounds as f64
+    );
+    println!(
+        "第一次就选中奖品 {} 轮，第一次未选中奖品 {} 轮，第一次选中奖品概率 {:.2}%；",
+        result.chosen_win(),
+        settings.rounds - result.chosen_win(),
+        result.chosen_win() as f64 * 100.0 / settings.rounds as f64
+    );
+    println!(
+        "坚持选择 {} 轮，坚持后中奖 {} 轮，坚持选择中奖率 {:.2}%；",
+        result.stick(),
+        result.stick_win(),
+        result.stick_win() as f64 * 100.0 / result.stick() as f64
+    );
+    println!(
+        "改变选择 {} 轮，改变后中奖 {} 轮，改变选择中奖率 {:.2}%。",
+        result.switch(),
+        result.switch_win(),
+        result.switch_win() as f64 * 100.0 / result.switch() as f64
+    );
+}
+
+const DOCTOR_USAGE: &str = "用法：
+  ndoors doctor [ws://地址/ws]";
+
+/// 检查随机数均匀性的轮数
+const UNIFORMITY_ROUNDS: u32 = 50_000;
+/// 检查随机数均匀性使用的门数
+const UNIFORMITY_DOORS: u32 = 5;
+/// 卡方检验的显著性水平 0.001 对应的标准正态分位数，正常的随机数生成器每项检查约有千分之一的概率误报
+const Z_999: f64 = 3.09;
+/// 每种设置的冒烟测试使用的种子数
+const SMOKE_SEEDS: u64 = 10;
+/// 冒烟测试每局最多的轮数，无尽模式和加赛在这里停止
+const MAX_SMOKE_ROUNDS: u32 = 200;
+/// 连接服务器的时限
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 快速自检：随机放置奖品、选择和揭示的均匀性，各种设置下状态机的不变量，
+/// 指定了服务器地址时还用 `ndoors-client` 检查能否连接并收到欢迎消息。逐项输出结果，有失败的检查时返回错误
+fn doctor(args: &[String]) -> anyhow::Result<()> {
+    if args.len() > 1 {
+        return Err(CliError::Usage(DOCTOR_USAGE.to_string()).into());
+    }
+    let mut checks = vec![];
+    checks.extend(uniformity());
+    checks.extend(smoke_tests());
+    if let Some(url) = args.first() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let result = runtime.block_on(async {
+            match tokio::time::timeout(CONNECT_TIMEOUT, probe(url)).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow!("timed out after {:?}", CONNECT_TIMEOUT)),
+            }
+        });
+        checks.push((format!("connection to {}", url), result));
+    }
+
+    let mut failed = 0;
+    for (name, result) in &checks {
+        match result {
+            Ok(detail) => println!("PASS {}: {}", name, detail),
+            Err(cause) => {
+                failed += 1;
+                println!("FAIL {}: {}", name, cause);
+            }
+        }
+    }
+    println!("{}/{} checks passed", checks.len() - failed, checks.len());
+
+    if failed > 0 {
+        bail!("{} of {} checks failed.", failed, checks.len());
+    }
+    Ok(())
+}
+
+type Check = (String, anyhow::Result<String>);
+
+/// 用默认的随机数来源玩很多轮，检查奖品位置、挑战者的随机选择和主持人的随机揭示是否均匀，
+/// 以及一直改变选择的中奖率是否接近理论值
+fn uniformity() -> Vec<Check> {
+    let doors = UNIFORMITY_DOORS as usize;
+    let mut prizes = vec![0; doors];
+    let mut chosen = vec![0; doors];
+    // 挑战者选中奖品时主持人留下的门，按除了已选的门之外的序号计数
+    let mut left = vec![0; doors - 1];
+    let mut wins = 0;
+    let played = (|| -> anyhow::Result<()> {
+        let settings = Settings::new(UNIFORMITY_DOORS, UNIFORMITY_ROUNDS);
+        let mut room = Room::create(Uuid::new_v4(), settings)?;
+        room.accept_contestant(Uuid::new_v4())?;
+        room.contestant_ready(true)?;
+        room.reserve_rounds();
+        for _ in 0..UNIFORMITY_ROUNDS {
+            let prize = room.start_random()?;
+            let choice = room.choose_random()?;
+            let kept = room.reveal_random()?;
+            prizes[prize as usize] += 1;
+            chosen[choice as usize] += 1;
+            if choice == prize {
+                left[(kept - u32::from(kept > choice)) as usize] += 1;
+            }
+            if room.decide(Decision::Switch)?.win() {
+                wins += 1;
+            }
+        }
+        room.complete(false)?;
+        Ok(())
+    })();
+    if let Err(cause) = played {
+        return vec![("uniformity".to_string(), Err(cause))];
+    }
+
+    let expected = (UNIFORMITY_DOORS - 1) as f64 / UNIFORMITY_DOORS as f64;
+    let observed = wins as f64 / UNIFORMITY_ROUNDS as f64;
+    let switch = if (observed - expected).abs() < 0.01 {
+        Ok(format!("{:.4} (expected {:.4})", observed, expected))
+    } else {
+        Err(anyhow!("{:.4}, expected {:.4}", observed, expected))
+    };
+    vec![
+        ("prize placement".to_string(), chi_square(&prizes)),
+        ("random choice".to_string(), chi_square(&chosen)),
+        ("random reveal".to_string(), chi_square(&left)),
+        ("switch win rate".to_string(), switch),
+    ]
+}
+
+/// 均匀分布的卡方检验，临界值用 Wilson-Hilferty 近似
+fn chi_square(counts: &[u64]) -> anyhow::Result<String> {
+    let total: u64 = counts.iter().sum();
+    ensure!(total > 0, "no samples");
+    let expected = total as f64 / counts.len() as f64;
+    let statistic: f64 = counts
+        .iter()
+        .map(|count| (*count as f64 - expected).powi(2) / expected)
+        .sum();
+    let df = (counts.len() - 1) as f64;
+    let critical = df * (1.0 - 2.0 / (9.0 * df) + Z_999 * (2.0 / (9.0 * df)).sqrt()).powi(3);
+    if statistic <= critical {
+        Ok(format!(
+            "chi-square {:.2} <= {:.2} over {} samples",
+            statistic, critical, total
+        ))
+    } else {
+        Err(anyhow!(
+            "chi-square {:.2} > {:.2}, counts {:?}",
+            statistic,
+            critical,
+            counts
+        ))
+    }
+}
+
+/// 用固定的种子在各种设置下玩完整局游戏，检查状态机的不变量
+fn smoke_tests() -> Vec<Check> {
+    let variants = [
+        ("classic", Settings::new(3, 20)),
+        ("two doors", Settings::new(2, 20)),
+        (
+            "many doors",
+            Settings::new(10, 20).with_closed(3).with_prizes(2),
+        ),
+        ("schedule", Settings::new(3, 20).with_schedule(&[3, 5, 8])),
+        ("sudden death", Settings::new(3, 2).with_sudden_death(true)),
+        ("endless", Settings::new(3, 1).with_endless(true)),
+        ("forgetful", Settings::new(5, 20).with_forgetful(true)),
+        ("evil", Settings::new(3, 20).with_evil(true)),
+        ("deals", Settings::new(3, 20).with_deals(true)),
+    ];
+    variants
+        .into_iter()
+        .map(|(name, settings)| {
+            let result = (0..SMOKE_SEEDS)
+                .map(|seed| {
+                    smoke(&settings, seed).map_err(|cause| anyhow!("seed {}: {}", seed, cause))
+                })
+                .sum::<anyhow::Result<u32>>()
+                .map(|rounds| format!("{} games, {} rounds", SMOKE_SEEDS, rounds));
+            (format!("state machine ({})", name), result)
+        })
+        .collect()
+}
+
+/// 玩一局游戏，每一步之后检查快照可以恢复，返回进行的轮数
+fn smoke(settings: &Settings, seed: u64) -> anyhow::Result<u32> {
+    let mut room = Room::create_seeded(Uuid::new_v4(), settings.clone(), seed)?;
+    room.accept_contestant(Uuid::new_v4())?;
+    room.contestant_ready(true)?;
+    let mut played = 0;
+    loop {
+        room.start_random()?;
+        let round = room.round_settings();
+        let prizes = match room.state() {
+            RoomState::Started {
+                prize,
+                other_prizes,
+                ..
+            } => {
+                let mut prizes = vec![*prize];
+                prizes.extend(other_prizes);
+                prizes
+            }
+            _ => bail!("round {} did not start", played),
+        };
+        ensure!(
+            prizes.len() as u32 == settings.prizes(round),
+            "round {} placed {} prizes",
+            played,
+            prizes.len()
+        );
+        room.choose_random()?;
+        restorable(&room)?;
+        if let RoomState::Started {
+            stage: Stage::Reveal { .. },
+            ..
+        } = room.state()
+        {
+            // 邪恶的主持人每三轮拒绝一次
+            if settings.evil && played % 3 == 0 {
+                room.decline()?;
+            } else {
+                room.reveal_random()?;
+            }
+            restorable(&room)?;
+        }
+        if settings.deals {
+            room.offer_deal(played)?;
+            restorable(&room)?;
+        }
+        match room.state() {
+            RoomState::Started {
+                stage:
+                    Stage::Decide {
+                        chosen,
+                        left,
+                        others,
+                        ..
+                    }
+                    | Stage::Deal {
+                        chosen,
+                        left,
+                        others,
+                        ..
+                    },
+                ..
+            } => {
+                let chosen = *chosen;
+                let mut kept = others.clone();
+                kept.push(*left);
+                ensure!(
+                    kept.len() as u32 == settings.closed(round) && !kept.contains(&chosen),
+                    "round {} left doors {:?} besides door {}",
+                    played,
+                    kept,
+                    chosen
+                );
+                ensure!(
+                    prizes.iter().all(|p| *p == chosen || kept.contains(p)),
+                    "round {} opened a prize door",
+                    played
+                );
+                // 允许买断时每三轮接受一次出价
+                let decision = if settings.deals && played % 3 == 0 {
+                    Decision::TakeDeal(played)
+                } else if played % 2 == 0 {
+                    Decision::Switch
+                } else {
+                    Decision::Stick
+                };
+                let result = room.decide(decision)?;
+                let door = match decision {
+                    Decision::Stick => chosen,
+                    _ => result.left(),
+                };
+                if let Decision::TakeDeal(offer) = decision {
+                    ensure!(
+                        !result.win() && result.score() == offer,
+                        "round {} took the deal but scored {}",
+                        played,
+                        result.score()
+                    );
+                } else {
+                    ensure!(
+                        result.win() == prizes.contains(&door),
+                        "round {} reported win {} behind door {}",
+                        played,
+                        result.win(),
+                        door
+                    );
+                }
+            }
+            // 只有健忘的主持人会打开有奖的门，本轮直接作废；只有邪恶的主持人会不给改变选择的机会
+            RoomState::Started {
+                stage: Stage::End,
+                results,
+                ..
+            } => {
+                let ended = results.last().is_some_and(|result| {
+                    settings.forgetful && result.void() && !result.win()
+                        || settings.evil
+                            && !result.offered()
+                            && result.win() == prizes.contains(&result.chosen())
+                });
+                ensure!(ended, "round {} ended without a decision", played);
+            }
+            state => bail!("round {} reached {:?}", played, state),
+        }
+        restorable(&room)?;
+        played += 1;
+        if !room.has_next_round() || played >= MAX_SMOKE_ROUNDS {
+            break;
+        }
+    }
+    // 加赛停在上限时可能还没有分出胜负，提前结束
+    let results = match room.complete(false) {
+        Ok(results) => results,
+        Err(_) if room.needs_tiebreak() => room.force_complete()?,
+        Err(cause) => return Err(cause.into()),
+    };
+    ensure!(
+        results.len() as u32 == played,
+        "{} results for {} rounds",
+        results.len(),
+        played
+    );
+    let stats = *GameResult::calculate(settings, &results).stats();
+    ensure!(
+        stats.rounds() + stats.void() == played,
+        "stats count {} rounds and {} void for {} rounds",
+        stats.rounds(),
+        stats.void(),
+        played
+    );
+    Ok(played)
+}
+
+fn restorable(room: &Room) -> anyhow::Result<()> {
+    Room::restore(room.snapshot())
+        .map(|_| ())
+        .map_err(|cause| anyhow!("snapshot does not restore: {}", cause))
+}
+
+/// 用客户端连接服务器，连接时收到的第一条消息应当是 `UserCreated` 或者 `SessionResumed`
+async fn probe(url: &str) -> anyhow::Result<String> {
+    let client = Client::connect(url).await?;
+    let id = client.id();
+    client.close().await?;
+    Ok(format!("connected as user {}", id))
+}
+
+const PUZZLE_USAGE: &str = "用法：
+  ndoors puzzle create <谜题文件> [门数] [轮数] [种子]
+  ndoors puzzle play <谜题文件> <答卷文件>
+  ndoors puzzle verify <谜题文件> <答卷文件>";
+
+/// 离线谜题：生成谜题、在本地玩谜题、验证答卷
+fn puzzle(args: &[String]) -> anyhow::Result<()> {
+    let arg = |i: usize| args.get(i).map(String::as_str);
+    match (arg(0), arg(1), arg(2)) {
+        (Some("create"), Some(path), _) => {
+            let number = |i: usize, default: u32| match arg(i) {
+                Some(n) => n.parse().map_err(anyhow::Error::from),
+                None => Ok(default),
+            };
+            let seed = match arg(4) {
+                Some(seed) => seed.to_string(),
+                None => format!("{:032x}", rand::random::<u128>()),
+            };
+            let settings = Settings::new(number(2, 3)?, number(3, 5)?);
+            let puzzle = Puzzle::create(settings, &seed)?;
+            std::fs::write(path, serde_json::to_string_pretty(&puzzle)?)?;
+            println!("谜题已保存到 {}，承诺 {}", path, puzzle.commitment);
+            Ok(())
+        }
+        (Some("play"), Some(path), Some(answer)) => {
+            let puzzle: Puzzle = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            let results = play(&puzzle)?;
+            let answer_file = Answer {
+                commitment: puzzle.commitment.clone(),
+                results,
+            };
+            std::fs::write(answer, serde_json::to_string_pretty(&answer_file)?)?;
+            println!("答卷已保存到 {}", answer);
+            Ok(())
+        }
+        (Some("verify"), Some(path), Some(answer)) => {
+            let puzzle: Puzzle = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            let answer: Answer = serde_json::from_str(&std::fs::read_to_string(answer)?)?;
+            let result = puzzle.verify(&answer)?;
+            println!(
+                "答卷有效：共 {} 轮，中奖 {} 轮。",
+                result.stats().rounds(),
+                result.win()
+            );
+            Ok(())
+        }
+        _ => Err(CliError::Usage(PUZZLE_USAGE.to_string()).into()),
+    }
+}
+
+/// 在终端玩一局谜题，程序担任主持人，门的编号从 1 开始
+fn play(puzzle: &Puzzle) -> anyhow::Result<Vec<RoundResult>> {
+    let mut room = puzzle.room()?;
+    let mut ask = prompter();
+    loop {
+        puzzle.start(&mut room)?;
+        contestant_round(&mut room, &mut ask)?;
+        if !room.has_next_round() {
+            break;
+        }
+    }
+    Ok(room.complete(false)?)
+}
+
+const HOTSEAT_USAGE: &str = "用法：
+  ndoors hotseat [门数] [轮数]";
+
+/// 两人在同一个终端轮流操作：主持人输入奖品所在的门（不回显），挑战者接着玩这一轮
+fn hotseat(args: &[String]) -> anyhow::Result<()> {
+    let number = |i: usize, default: u32| match args.get(i) {
+        Some(n) => n
+            .parse()
+            .map_err(|_| CliError::Usage(HOTSEAT_USAGE.to_string())),
+        None => Ok(default),
+    };
+    let settings = Settings::new(number(0, 3)?, number(1, 5)?);
+    let mut room = Room::create(Uuid::new_v4(), settings)?;
+    room.accept_contestant(Uuid::new_v4())?;
+    room.contestant_ready(true)?;
+    let mut ask = prompter();
+    loop {
+        let doors = room.next_round_settings().doors;
+        println!("第 {} 轮，请挑战者转过身去。", room.next_round() + 1);
+        loop {
+            let prompt = format!("主持人输入奖品所在的门（1-{}，输入不显示）：", doors);
+            let prize = rpassword::prompt_password(prompt)?;
+            match prize.trim().parse::<u32>() {
+                Ok(prize) if (1..=doors).contains(&prize) => {
+                    room.start(prize - 1)?;
+                    break;
+                }
+                _ => println!("没有这个门。"),
+            }
+        }
+        contestant_round(&mut room, &mut ask)?;
+        if !room.has_next_round() {
+            break;
+        }
+    }
+    let results = room.complete(false)?;
+    let result = GameResult::calculate(room.settings(), results);
+    println!(
+        "游戏结束：共 {} 轮，挑战者中奖 {} 轮。",
+        result.stats().rounds(),
+        result.win()
+    );
+    Ok(())
+}
+
+/// 从标准输入逐行读取回答
+fn prompter() -> impl FnMut(&str) -> anyhow::Result<String> {
+    let mut lines = std::io::stdin().lock().lines();
+    move |prompt: &str| {
+        print!("{}", prompt);
+        std::io::stdout().flush()?;
+        match lines.next() {
+            Some(line) => Ok(line?.trim().to_string()),
+            None => anyhow::bail!("输入已结束"),
+        }
+    }
+}
+
+/// 挑战者在终端玩已经开始的一轮，程序随机揭示，门的编号从 1 开始
+fn contestant_round(
+    room: &mut Room,
+    ask: &mut impl FnMut(&str) -> anyhow::Result<String>,
+) -> anyhow::Result<()> {
+    let doors = room.round_settings().doors;
+    println!("第 {} 轮，共 {} 个门。", room.next_round(), doors);
+    loop {
+        let chosen = ask(&format!("选择一个门（1-{}）：", doors))?;
+        match chosen.parse::<u32>() {
+            Ok(chosen) if (1..=doors).contains(&chosen) => {
+                room.choose(chosen - 1)?;
+                break;
+            }
+            _ => println!("没有这个门。"),
+        }
+    }
+    if let RoomState::Started {
+        stage: Stage::Reveal { .. },
+        ..
+    } = room.state()
+    {
+        room.reveal_random()?;
+    }
+    let (chosen, left) = match room.state() {
+        RoomState::Started {
+            stage:
+                Stage::Decide {
+                    chosen,
+                    left,
+                    others,
+                    ..
+                },
+            ..
+        } => {
+            let mut remaining = others.clone();
+            remaining.push(*left);
+            remaining.sort_unstable();
+            (*chosen, remaining)
+        }
+        _ => anyhow::bail!("房间状态不正确"),
+    };
+    let closed: Vec<String> = left.iter().map(|door| (door + 1).to_string()).collect();
+    println!(
+        "除了你选择的 {} 号门，还关着的是 {} 号门。",
+        chosen + 1,
+        closed.join("、")
+    );
+    if room.settings().quiz {
+        loop {
+            let percent = ask("你估计改变选择的中奖率是百分之多少：")?;
+            match percent.parse().map(|percent| room.estimate(percent)) {
+                Ok(Ok(())) => break,
+                _ => println!("请输入 0 到 100 的整数。"),
+            }
+        }
+    }
+    let result = loop {
+        let answer = ask("坚持选择输入 0，改变选择输入要换到的门：")?;
+        let decision = match answer.parse::<u32>() {
+            Ok(0) => Decision::Stick,
+            Ok(door) if left.contains(&door.wrapping_sub(1)) => Decision::SwitchTo(door - 1),
+            _ => {
+                println!("只能换到还关着的门。");
+                continue;
+            }
+        };
+        break room.decide(decision)?;
+    };
+    println!(
+        "奖品在 {} 号门，你{}了。",
+        result.prize() + 1,
+        if result.win() { "赢" } else { "输" }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chi_square_() {
+        assert!(chi_square(&[1000, 1000, 1000, 1000, 1000]).is_ok());
+        assert!(chi_square(&[1030, 980, 1010, 990, 990]).is_ok());
+        assert!(chi_square(&[1300, 900, 950, 950, 900]).is_err());
+        assert!(chi_square(&[5000, 0]).is_err());
+        assert!(chi_square(&[0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn smoke_() {
+        for (name, result) in smoke_tests() {
+            assert!(result.is_ok(), "{}: {:?}", name, result);
+        }
+        assert_eq!(smoke(&Settings::new(3, 20), 0).unwrap(), 20);
+        assert!(smoke(&Settings::new(3, 1).with_endless(true), 0).unwrap() <= MAX_SMOKE_ROUNDS);
+    }
+}
@@ -0,0 +1,484 @@
+use futures::stream::{self, Stream};
+use futures::{SinkExt, StreamExt};
+use ndoors::protocol::*;
+use ndoors::{ContestantStrategy, Decision, GameResult, RoundResult, Settings, Stage, Team, Uuid};
+use std::collections::VecDeque;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+pub use ndoors::protocol;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+    #[error("Malformed message: {0}")]
+    Json(serde_json::Error),
+    #[error("Connection closed")]
+    Closed,
+    #[error("Not in a room")]
+    NotInRoom,
+    #[error("Unexpected response: {0:?}")]
+    Unexpected(Box<GameResponse>),
+    #[error("Game error: {0}")]
+    Game(ndoors::Error),
+    #[error("Server error: {0}")]
+    Server(ServerError),
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(cause: tungstenite::Error) -> Self {
+        Error::WebSocket(Box::new(cause))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(cause: serde_json::Error) -> Self {
+        Error::Json(cause)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// ndoors 服务器的异步客户端，请求和响应使用和服务器相同的协议类型
+#[derive(Debug)]
+pub struct Client {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    id: Uuid,
+    resume_token: Option<Uuid>,
+    /// 当前所在的房间
+    room: Option<RoomInfo>,
+    /// 等待某个响应时收到的其他消息，之后由 `next` 依次返回
+    pending: VecDeque<ServerMessage>,
+}
+
+impl Client {
+    /// 连接服务器的 `/ws`，例如 `ws://localhost:3000/ws`。
+    /// 令牌、恢复会话等选项和浏览器一样放在查询参数中：`/ws?token=...&resume=...`
+    pub async fn connect(url: &str) -> Result<Self> {
+        let (socket, _) = connect_async(url).await?;
+        let mut client = Self {
+            socket,
+            id: Uuid::nil(),
+            resume_token: None,
+            room: None,
+            pending: VecDeque::new(),
+        };
+        match client.receive().await?.response {
+            GameResponse::UserCreated { id, resume_token } => {
+                client.id = id;
+                client.resume_token = resume_token;
+            }
+            GameResponse::SessionResumed { id, info } => {
+                client.id = id;
+                client.room = info;
+            }
+            response => return Err(Error::Unexpected(Box::new(response))),
+        }
+        Ok(client)
+    }
+
+    /// 服务器分配的用户 ID
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// 断开后恢复会话的令牌，服务器没有开启会话恢复时为空
+    pub fn resume_token(&self) -> Option<Uuid> {
+        self.resume_token
+    }
+
+    /// 当前所在房间进入时的信息
+    pub fn room(&self) -> Option<&RoomInfo> {
+        self.room.as_ref()
+    }
+
+    /// 发送请求，不等待响应
+    pub async fn send(&mut self, request: GameRequest) -> Result<()> {
+        let message = ClientMessage {
+            request,
+            expected_seq: None,
+        };
+        let text = serde_json::to_string(&message)?;
+        self.socket.send(Message::Text(text)).await?;
+        Ok(())
+    }
+
+    /// 下一条服务器消息，连接关闭时返回 [`Error::Closed`]
+    pub async fn next(&mut self) -> Result<ServerMessage> {
+        match self.pending.pop_front() {
+            Some(message) => Ok(message),
+            None => self.receive().await,
+        }
+    }
+
+    /// 把之后的服务器消息转换成流，连接关闭时结束
+    pub fn events(self) -> impl Stream<Item = Result<ServerMessage>> {
+        stream::unfold(self, |mut client| async move {
+            match client.next().await {
+                Err(Error::Closed) => None,
+                result => Some((result, client)),
+            }
+        })
+    }
+
+    /// 关闭连接
+    pub async fn close(mut self) -> Result<()> {
+        self.socket.close(None).await?;
+        Ok(())
+    }
+
+    /// 大厅中的一页房间
+    pub async fn list_rooms(&mut self, page: u32, size: u32) -> Result<RoomPage> {
+        self.send(GameRequest::ListRooms { page, size }).await?;
+        self.expect(|response| match response {
+            GameResponse::RoomList {
+                rooms,
+                page,
+                size,
+                total,
+            } => Some(RoomPage {
+                rooms: rooms.clone(),
+                page: *page,
+                size: *size,
+                total: *total,
+            }),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 创建房间并成为主持人，`settings` 为空时使用服务器的默认设置
+    pub async fn create_room(&mut self, settings: Option<Settings>) -> Result<RoomInfo> {
+        self.send(GameRequest::CreateRoom {
+            settings,
+            pacing: None,
+            class: None,
+            lesson: None,
+            correspondence: None,
+            fair: false,
+            commentary: None,
+            name: None,
+            description: None,
+        })
+        .await?;
+        let info = self
+            .expect(|response| match response {
+                GameResponse::RoomCreated { info } => Some(info.clone()),
+                _ => None,
+            })
+            .await?;
+        self.room = Some(info.clone());
+        Ok(info)
+    }
+
+    /// 作为挑战者进入房间
+    pub async fn join(&mut self, id: Uuid) -> Result<RoomInfo> {
+        self.send(GameRequest::EnterRoom { id }).await?;
+        let info = self
+            .expect(|response| match response {
+                GameResponse::ContestantRoomEntered { info } => Some(info.clone()),
+                _ => None,
+            })
+            .await?;
+        self.room = Some(info.clone());
+        Ok(info)
+    }
+
+    /// 作为观众进入房间
+    pub async fn spectate(&mut self, id: Uuid) -> Result<RoomInfo> {
+        self.send(GameRequest::Spectate { id }).await?;
+        let info = self
+            .expect(|response| match response {
+                GameResponse::Spectating { info } => Some(info.clone()),
+                _ => None,
+            })
+            .await?;
+        self.room = Some(info.clone());
+        Ok(info)
+    }
+
+    /// 离开当前房间，不等待响应
+    pub async fn exit(&mut self) -> Result<()> {
+        let id = self.room.take().ok_or(Error::NotInRoom)?.id;
+        self.send(GameRequest::ExitRoom { id }).await
+    }
+
+    /// 挑战者准备或者取消准备
+    pub async fn ready(&mut self, ready: bool) -> Result<()> {
+        self.send(GameRequest::Ready { ready }).await?;
+        self.expect(|response| match response {
+            GameResponse::Ready { .. } => Some(()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 公平模式下挑战者在开始前提供随机串
+    pub async fn set_nonce(&mut self, nonce: &str) -> Result<()> {
+        self.send(GameRequest::SetNonce {
+            nonce: nonce.to_string(),
+        })
+        .await?;
+        self.expect(|response| match response {
+            GameResponse::NonceSet { .. } => Some(()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 主持人开始一轮游戏，返回奖品所在的门
+    pub async fn start(&mut self, prize: Index) -> Result<u32> {
+        self.send(GameRequest::Start {
+            prize,
+            others: vec![],
+            doors: None,
+        })
+        .await?;
+        self.expect(|response| match response {
+            GameResponse::Started { prize, .. } => Some(*prize),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 挑战者选择门，返回选择的门
+    pub async fn choose(&mut self, chosen: Index) -> Result<u32> {
+        self.send(GameRequest::Choose { chosen }).await?;
+        self.expect(|response| match response {
+            GameResponse::Chosen { chosen, .. } => Some(*chosen),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 主持人揭示，返回留下的门。健忘的主持人打开了有奖的门时本轮作废，返回 [`Error::Unexpected`]
+    pub async fn reveal(&mut self, left: Index) -> Result<u32> {
+        self.send(GameRequest::Reveal {
+            left,
+            others: vec![],
+        })
+        .await?;
+        self.expect(|response| match response {
+            GameResponse::Revealed { left, .. } => Some(Ok(*left)),
+            GameResponse::RoundVoided { .. } => {
+                Some(Err(Error::Unexpected(Box::new(response.clone()))))
+            }
+            _ => None,
+        })
+        .await?
+    }
+
+    /// 邪恶的主持人不给改变选择的机会，返回本轮的结果
+    pub async fn decline(&mut self) -> Result<RoundResult> {
+        self.send(GameRequest::Decline).await?;
+        self.expect(|response| match response {
+            GameResponse::Decided { result, .. } => Some(*result),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 主持人在揭示之后出价买断，挑战者可以用 [`Decision::TakeDeal`] 接受
+    pub async fn offer_deal(&mut self, offer: u32) -> Result<()> {
+        self.send(GameRequest::OfferDeal { offer }).await?;
+        self.expect(|response| match response {
+            GameResponse::DealOffered { .. } => Some(()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 主持人修改房间名称和介绍，为空时清除，返回修改后的名称和介绍
+    pub async fn update_details(
+        &mut self,
+        name: Option<&str>,
+        description: Option<&str>,
+    ) -> Result<(Option<String>, Option<String>)> {
+        self.send(GameRequest::UpdateDetails {
+            name: name.map(str::to_string),
+            description: description.map(str::to_string),
+        })
+        .await?;
+        self.expect(|response| match response {
+            GameResponse::DetailsUpdated { name, description } => {
+                Some((name.clone(), description.clone()))
+            }
+            _ => None,
+        })
+        .await
+    }
+
+    /// 为已完成的一局游戏生成分享卡片，`game` 为空时是最近完成的一局，返回卡片的短代码
+    pub async fn share_game(&mut self, game: Option<u64>) -> Result<String> {
+        self.send(GameRequest::ShareGame { game }).await?;
+        self.expect(|response| match response {
+            GameResponse::GameShared { code, .. } => Some(code.clone()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 主持人在游戏开始前添加团队，返回所有团队
+    pub async fn add_team(&mut self, name: &str, captain: Uuid) -> Result<Vec<Team>> {
+        self.send(GameRequest::AddTeam {
+            name: name.to_string(),
+            captain,
+        })
+        .await?;
+        self.expect_teams().await
+    }
+
+    /// 加入序号为 `team` 的团队，返回所有团队
+    pub async fn join_team(&mut self, team: u32) -> Result<Vec<Team>> {
+        self.send(GameRequest::JoinTeam { team }).await?;
+        self.expect_teams().await
+    }
+
+    async fn expect_teams(&mut self) -> Result<Vec<Team>> {
+        self.expect(|response| match response {
+            GameResponse::TeamsUpdated { teams } => Some(teams.clone()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 主持人撤销本轮最近的一次操作，返回恢复后的阶段
+    pub async fn undo(&mut self) -> Result<Stage> {
+        self.send(GameRequest::Undo).await?;
+        self.expect(|response| match response {
+            GameResponse::Undone { stage } => Some(stage.clone()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 主持人放弃进行中的一轮，返回放弃的轮次
+    pub async fn abort_round(&mut self) -> Result<u32> {
+        self.send(GameRequest::AbortRound).await?;
+        self.expect(|response| match response {
+            GameResponse::RoundAborted { round } => Some(*round),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 测验模式下挑战者估计改变选择的中奖率
+    pub async fn estimate(&mut self, percent: u32) -> Result<()> {
+        self.send(GameRequest::Estimate { percent }).await?;
+        self.expect(|response| match response {
+            GameResponse::Estimated { .. } => Some(()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 挑战者做出抉择，返回本轮的结果
+    pub async fn decide(&mut self, decision: Decision) -> Result<RoundResult> {
+        self.send(GameRequest::Decide { decision }).await?;
+        self.expect(|response| match response {
+            GameResponse::Decided { result, .. } => Some(*result),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 主持人完成本局游戏，返回本局的结果
+    pub async fn complete(&mut self, kick_contestant: bool) -> Result<GameResult> {
+        self.send(GameRequest::Complete { kick_contestant }).await?;
+        self.expect(|response| match response {
+            GameResponse::Completed { result, .. } => Some(*result.clone()),
+            _ => None,
+        })
+        .await
+    }
+
+    /// 挑战者进入房间后用 `strategy` 玩完一局：准备好之后按策略选择、估计和抉择，
+    /// 直到主持人完成本局游戏
+    pub async fn play<S>(&mut self, strategy: &mut S) -> Result<GameResult>
+    where
+        S: ContestantStrategy + ?Sized,
+    {
+        let mut settings = self
+            .room
+            .as_ref()
+            .map(|info| info.settings.clone())
+            .ok_or(Error::NotInRoom)?;
+        self.send(GameRequest::Ready { ready: true }).await?;
+        let mut round = 0;
+        let mut chosen = 0;
+        let mut left = 0;
+        loop {
+            let doors = settings.round(round).doors;
+            match self.next().await?.response {
+                GameResponse::SettingsUpdated {
+                    settings: updated, ..
+                } => settings = updated,
+                // 主持人撤销了选择或者估计时重新操作
+                GameResponse::ContestantStarted { .. }
+                | GameResponse::Undone {
+                    stage: Stage::Choose,
+                } => {
+                    let chosen = Index::Specified(strategy.choose(doors));
+                    self.send(GameRequest::Choose { chosen }).await?;
+                }
+                GameResponse::Chosen { chosen: door, .. } => chosen = door,
+                GameResponse::Revealed { left: door, .. } => {
+                    left = door;
+                    // 测验模式下先等服务器要求估计
+                    if !settings.quiz {
+                        let decision = strategy.decide(doors, chosen, left);
+                        self.send(GameRequest::Decide { decision }).await?;
+                    }
+                }
+                GameResponse::EstimateRequested { .. }
+                | GameResponse::Undone {
+                    stage: Stage::Decide { estimate: None, .. },
+                } if settings.quiz => {
+                    let percent = strategy.estimate(doors);
+                    self.send(GameRequest::Estimate { percent }).await?;
+                }
+                GameResponse::Estimated { .. } => {
+                    let decision = strategy.decide(doors, chosen, left);
+                    self.send(GameRequest::Decide { decision }).await?;
+                }
+                GameResponse::Decided { .. } | GameResponse::RoundVoided { .. } => round += 1,
+                GameResponse::Completed { result, .. } => return Ok(*result),
+                GameResponse::GameError { cause } => return Err(Error::Game(cause)),
+                GameResponse::ServerError { cause } => return Err(Error::Server(cause)),
+                _ => {}
+            }
+        }
+    }
+
+    /// 等待 `f` 接受的响应，期间收到的其他消息留给 `next`。
+    /// 先收到游戏错误或者服务器错误时返回错误
+    async fn expect<T>(&mut self, mut f: impl FnMut(&GameResponse) -> Option<T>) -> Result<T> {
+        loop {
+            let message = self.receive().await?;
+            match &message.response {
+                GameResponse::GameError { cause } => return Err(Error::Game(*cause)),
+                GameResponse::ServerError { cause } => return Err(Error::Server(cause.clone())),
+                response => {
+                    if let Some(value) = f(response) {
+                        return Ok(value);
+                    }
+                }
+            }
+            self.pending.push_back(message);
+        }
+    }
+
+    async fn receive(&mut self) -> Result<ServerMessage> {
+        loop {
+            match self.socket.next().await {
+                Some(Ok(Message::Text(text))) => return Ok(serde_json::from_str(&text)?),
+                Some(Ok(Message::Close(_))) | None => return Err(Error::Closed),
+                // 没有请求压缩，不会收到二进制帧
+                Some(Ok(_)) => {}
+                Some(Err(cause)) => return Err(cause.into()),
+            }
+        }
+    }
+}
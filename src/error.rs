@@ -6,8 +6,32 @@ pub enum Error {
     InvalidOperation,
     #[error("Invalid door index")]
     InvalidDoorIndex,
+    #[error("Invalid settings: at least 2 doors and 1 round")]
+    InvalidSettings,
     #[error("Impossible")]
     Impossible,
+    #[error("Estimate the switch win probability before deciding")]
+    EstimateRequired,
+    #[error("Invalid estimate: a percentage from 0 to 100")]
+    InvalidEstimate,
+    #[error("Door {} was not left closed by the host", .door)]
+    DoorNotLeft { door: u32 },
+    #[error("The host cannot enter their own room as the contestant")]
+    HostAsContestant,
+    #[error("Invalid room snapshot")]
+    InvalidSnapshot,
+    #[error("The game is paused")]
+    Paused,
+    #[error("Invalid fairness key or proof")]
+    InvalidProof,
+    #[error("The contestant has not provided a nonce for the fair room")]
+    NonceRequired,
+    #[error("The host's seed and the contestant's nonce are both required to start the round")]
+    ExchangeIncomplete,
+    #[error("Invalid puzzle, or the answer does not match the puzzle")]
+    InvalidPuzzle,
+    #[error("The room name or description is empty or too long")]
+    InvalidDetails,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+use crate::Uuid;
+#[cfg(feature = "fairness")]
+use crate::{Error, Result};
+
+/// VRF 的签名上下文
+#[cfg(feature = "fairness")]
+const CONTEXT: &[u8] = b"ndoors";
+
+/// 从 VRF 输出生成随机数时的标签
+#[cfg(feature = "fairness")]
+const PLACEMENT: &[u8] = b"prizes";
+
+/// 公平模式下一轮的证明。奖品位置由服务器私钥对（房间 ID、轮次、挑战者提供的随机串）的 VRF 输出决定，
+/// 任何人都可以用服务器公钥和 [`verify`] 独立验证
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct RoundProof {
+    /// 轮次，从 0 开始
+    pub round: u32,
+    /// 本轮门数
+    pub doors: u32,
+    /// 挑战者提供的随机串
+    pub nonce: String,
+    /// 奖品所在的门，从小到大排列
+    pub prizes: Vec<u32>,
+    /// VRF 输出（十六进制）
+    pub output: String,
+    /// VRF 证明（十六进制）
+    pub proof: String,
+}
+
+/// VRF 的输入：房间 ID 的 16 个字节、轮次（4 个字节，大端）和随机串的 UTF-8 字节
+pub fn message(room_id: &Uuid, round: u32, nonce: &str) -> Vec<u8> {
+    let mut message = room_id.as_bytes().to_vec();
+    message.extend_from_slice(&round.to_be_bytes());
+    message.extend_from_slice(nonce.as_bytes());
+    message
+}
+
+/// 由随机字节决定 `count` 个奖品的位置：前 8 个字节（小端）作为 SplitMix64 的初始状态，
+/// 第 i 个奖品从剩下的 `doors - i` 个门中取第 `next() % (doors - i)` 个（部分 Fisher-Yates 洗牌），
+/// 返回从小到大排列的门序号。只保存被交换过的门，内存和 `count` 成正比，与门数无关
+pub fn placement(bytes: &[u8], doors: u32, count: u32) -> Vec<u32> {
+    let mut seed = [0; 8];
+    let len = bytes.len().min(8);
+    seed[..len].copy_from_slice(&bytes[..len]);
+    let mut state = u64::from_le_bytes(seed);
+    let mut next = move || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    // 剩下的门只记录和初始位置不同的项，门数可能来自不可信的证明，不能按门数分配内存
+    let mut moved: HashMap<u32, u32> = HashMap::new();
+    let mut prizes: Vec<u32> = (0..count.min(doors))
+        .map(|i| {
+            let last = doors - i - 1;
+            let picked = (next() % (last as u64 + 1)) as u32;
+            let door = moved.get(&picked).copied().unwrap_or(picked);
+            let tail = moved.remove(&last).unwrap_or(last);
+            if picked != last {
+                moved.insert(picked, tail);
+            }
+            door
+        })
+        .collect();
+    prizes.sort_unstable();
+    prizes
+}
+
+/// 主持人种子的承诺：种子 UTF-8 字节的 SHA-256（十六进制）
+pub fn commitment(seed: &str) -> String {
+    encode(&Sha256::digest(seed.as_bytes()))
+}
+
+/// 双方共同决定奖品位置时使用的随机字节：SHA-256（种子的字节数（4 个字节，大端）、种子、随机串），
+/// 和 [`placement`] 一起决定奖品位置
+pub fn shared_entropy(seed: &str, nonce: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update((seed.len() as u32).to_be_bytes());
+    hasher.update(seed.as_bytes());
+    hasher.update(nonce.as_bytes());
+    hasher.finalize().into()
+}
+
+/// 服务器的 VRF 私钥
+#[cfg(feature = "fairness")]
+pub struct FairnessKey {
+    keypair: schnorrkel::Keypair,
+}
+
+#[cfg(feature = "fairness")]
+impl std::fmt::Debug for FairnessKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FairnessKey")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+#[cfg(feature = "fairness")]
+impl FairnessKey {
+    /// 从 32 个字节的私钥（十六进制）创建
+    pub fn from_hex(secret: &str) -> Result<Self> {
+        let secret = decode(secret).ok_or(Error::InvalidProof)?;
+        let secret =
+            schnorrkel::MiniSecretKey::from_bytes(&secret).map_err(|_| Error::InvalidProof)?;
+        Ok(Self {
+            keypair: secret.expand_to_keypair(schnorrkel::ExpansionMode::Ed25519),
+        })
+    }
+
+    /// 公钥（十六进制），公开给所有人用来验证
+    pub fn public_key(&self) -> String {
+        encode(&self.keypair.public.to_bytes())
+    }
+
+    /// 决定一轮的奖品位置并生成证明
+    pub fn prove(
+        &self,
+        room_id: &Uuid,
+        round: u32,
+        doors: u32,
+        count: u32,
+        nonce: &str,
+    ) -> RoundProof {
+        let context = schnorrkel::signing_context(CONTEXT);
+        let (io, proof, _) = self
+            .keypair
+            .vrf_sign(context.bytes(&message(room_id, round, nonce)));
+        let bytes: [u8; 32] = io.make_bytes(PLACEMENT);
+        RoundProof {
+            round,
+            doors,
+            nonce: nonce.to_string(),
+            prizes: placement(&bytes, doors, count),
+            output: encode(&io.to_preout().to_bytes()),
+            proof: encode(&proof.to_bytes()),
+        }
+    }
+}
+
+/// 用服务器公钥（十六进制）验证一轮的证明，证明无效或者奖品位置和 VRF 输出不符时返回
+/// [`Error::InvalidProof`]
+#[cfg(feature = "fairness")]
+pub fn verify(public_key: &str, room_id: &Uuid, proof: &RoundProof) -> Result<()> {
+    let invalid = |_| Error::InvalidProof;
+    let public_key = decode(public_key).ok_or(Error::InvalidProof)?;
+    let public_key = schnorrkel::PublicKey::from_bytes(&public_key).map_err(invalid)?;
+    let output = decode(&proof.output).ok_or(Error::InvalidProof)?;
+    let output = schnorrkel::vrf::VRFPreOut::from_bytes(&output).map_err(invalid)?;
+    let signature = decode(&proof.proof).ok_or(Error::InvalidProof)?;
+    let signature = schnorrkel::vrf::VRFProof::from_bytes(&signature).map_err(invalid)?;
+    let context = schnorrkel::signing_context(CONTEXT);
+    let (io, _) = public_key
+        .vrf_verify(
+            context.bytes(&message(room_id, proof.round, &proof.nonce)),
+            &output,
+            &signature,
+        )
+        .map_err(invalid)?;
+    let bytes: [u8; 32] = io.make_bytes(PLACEMENT);
+    let count = proof.prizes.len() as u32;
+    if count == 0 || placement(&bytes, proof.doors, count) != proof.prizes {
+        return Err(Error::InvalidProof);
+    }
+    Ok(())
+}
+
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() & 1 == 1 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -1,15 +1,30 @@
 mod error;
+pub mod fairness;
+pub mod protocol;
+pub mod puzzle;
+mod rules;
+mod strategy;
+pub mod typed;
+mod vectors;
 
 pub use error::*;
 use rand::distributions::Standard;
 use rand::prelude::Distribution;
+pub use rules::*;
+pub use strategy::*;
 pub use uuid::Uuid;
+pub use vectors::*;
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
 
 /// 房间状态
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
 pub enum RoomState {
     /// 刚刚创建
     Created,
@@ -31,14 +46,34 @@ pub enum RoomState {
         /// 当前游戏轮数
         current_round: u32,
 
-        /// 当前轮游戏奖品所在门序号
+        /// 当前轮游戏奖品所在门序号，有多个奖品时是序号最小的一个
         prize: u32,
 
+        /// 有多个奖品时其余奖品所在的门序号，从小到大排列
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        other_prizes: Vec<u32>,
+
         /// 当前已经赢的轮数
         results: Vec<RoundResult>,
 
         /// 当前轮状态
         stage: Stage,
+
+        /// 暂停中，不能进行游戏操作
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        paused: bool,
+
+        /// 当前阶段的截止时间（Unix 时间戳，毫秒），设置中这个阶段没有时限或者暂停中时为空
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deadline: Option<u64>,
+
+        /// 主持人只为当前轮指定的门数，为空时使用设置中这一轮的门数
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doors: Option<u32>,
+
+        /// 本局已经赢得的奖品的总价值
+        #[serde(default, skip_serializing_if = "is_zero")]
+        score: u64,
     },
 }
 
@@ -49,7 +84,7 @@ impl Default for RoomState {
 }
 
 /// 一轮游戏的各个阶段
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
 pub enum Stage {
     /// 挑战者选择
     Choose,
@@ -65,12 +100,36 @@ pub enum Stage {
         /// 挑战者已经选择的门序号
         chosen: u32,
 
-        /// 主持人揭示后留给挑战者的门序号
+        /// 主持人揭示后留给挑战者的门序号，留下多个门时是序号最小的一个
+        left: u32,
+
+        /// 留下多个门时其余的门序号，从小到大排列
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        others: Vec<u32>,
+
+        /// 测验模式下挑战者估计的改变选择中奖率（百分比）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        estimate: Option<u32>,
+    },
+
+    /// 主持人出价买断，挑战者可以接受出价（[`Decision::TakeDeal`]），也可以照常抉择。
+    /// 除了 `offer` 之外和 `Decide` 相同
+    Deal {
+        chosen: u32,
         left: u32,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        others: Vec<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        estimate: Option<u32>,
+        /// 主持人的出价
+        offer: u32,
     },
 
-    /// 游戏结束
-    End { result: RoundResult },
+    /// 游戏结束，本轮结果是 `results` 的最后一项
+    End,
+
+    /// 本轮已放弃，没有结果，等待主持人重新开始这一轮
+    Aborted,
 }
 
 impl Default for Stage {
@@ -81,53 +140,785 @@ impl Default for Stage {
 
 impl Stage {
     pub fn is_end(&self) -> bool {
-        matches!(self, Stage::End { .. })
+        matches!(self, Stage::End)
+    }
+
+    /// 抉择阶段除 `left` 之外主持人留下的门
+    pub fn others(&self) -> &[u32] {
+        match self {
+            Stage::Decide { others, .. } | Stage::Deal { others, .. } => others,
+            _ => &[],
+        }
     }
 }
 
 /// 一轮游戏的结果
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct RoundResult {
-    /// 奖品所在门序号
+    /// 本轮门数
+    doors: u32,
+
+    /// 奖品所在门序号，有多个奖品时依次优先挑战者选择的门、改变选择换到的门、序号最小的门
     prize: u32,
 
+    /// 本轮奖品数
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    prizes: u32,
+
     /// 挑战者选择门序号
     chosen: u32,
 
-    /// 主持人揭示后剩下的门序号
+    /// 挑战者改变选择时换到的门序号，坚持选择时为主持人留下的（序号最小的）门
     left: u32,
 
+    /// 主持人揭示后除挑战者选择的门之外保持关闭的门数
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    closed: u32,
+
     /// 挑战者的抉择
     decision: Decision,
 
     /// 是否赢的奖品
     win: bool,
+
+    /// 本轮奖品的价值
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    value: u32,
+
+    /// 是否是平局后的加赛
+    #[serde(default)]
+    sudden_death: bool,
+
+    /// 健忘的主持人打开了有奖的门，这一轮作废：没有抉择（记为 `Stick`），不算输赢。
+    /// `prize` 是被打开的有奖的门，`left` 和 `closed` 是主持人留下的门
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    void: bool,
+
+    /// 邪恶的主持人没有给改变选择的机会，按挑战者的选择直接结束：`left` 等于 `chosen`，
+    /// 抉择记为 `Stick`，`closed` 是挑战者没有选择的门数
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    declined: bool,
+
+    /// 主持人最后一次的买断出价，没有出价时为空。挑战者接受时抉择记为 [`Decision::TakeDeal`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    offer: Option<u32>,
+
+    /// 团队模式下进行本轮的团队的序号
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    team: Option<u32>,
+
+    /// 从看到揭示结果到做出抉择的毫秒数，调用者没有提供时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decision_ms: Option<u64>,
+
+    /// 从本轮开始到做出抉择的毫秒数，调用者没有记录时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    round_ms: Option<u64>,
+
+    /// 测验模式下挑战者抉择前估计的改变选择中奖率（百分比）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimate: Option<u32>,
+
+    /// 本轮开始的时间（Unix 时间戳，毫秒），由房间记录，从快照恢复之前开始的一轮为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    started_at: Option<u64>,
+
+    /// 从本轮开始到挑战者选择的毫秒数，由房间记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    choose_ms: Option<u64>,
+
+    /// 从挑战者选择到主持人揭示的毫秒数，没有揭示阶段时为 0，由房间记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reveal_ms: Option<u64>,
+
+    /// 从主持人揭示到挑战者抉择的毫秒数，由房间记录。`decision_ms` 是调用者测量的同一段时间
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decide_ms: Option<u64>,
 }
 
-/// 游戏设置
+impl RoundResult {
+    /// 本轮门数
+    pub fn doors(&self) -> u32 {
+        self.doors
+    }
+
+    /// 奖品所在门序号
+    pub fn prize(&self) -> u32 {
+        self.prize
+    }
+
+    /// 本轮奖品数
+    pub fn prizes(&self) -> u32 {
+        self.prizes
+    }
+
+    /// 主持人留下的门后的奖品数（主持人不会打开有奖的门）
+    pub fn left_prizes(&self) -> u32 {
+        self.prizes - u32::from(self.chosen == self.prize)
+    }
+
+    /// 挑战者选择门序号
+    pub fn chosen(&self) -> u32 {
+        self.chosen
+    }
+
+    /// 挑战者改变选择时换到的门序号，坚持选择时为主持人留下的（序号最小的）门
+    pub fn left(&self) -> u32 {
+        self.left
+    }
+
+    /// 主持人揭示后除挑战者选择的门之外保持关闭的门数
+    pub fn closed(&self) -> u32 {
+        self.closed
+    }
+
+    /// 挑战者的抉择
+    pub fn decision(&self) -> Decision {
+        self.decision
+    }
+
+    /// 是否赢得奖品
+    pub fn win(&self) -> bool {
+        self.win
+    }
+
+    /// 本轮奖品的价值
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    /// 本轮得分，赢得奖品时为奖品的价值，接受买断时为出价
+    pub fn score(&self) -> u32 {
+        match self.decision {
+            Decision::TakeDeal(value) => value,
+            _ if self.win => self.value,
+            _ => 0,
+        }
+    }
+
+    /// 是否是平局后的加赛
+    pub fn sudden_death(&self) -> bool {
+        self.sudden_death
+    }
+
+    /// 是否因为主持人打开了有奖的门而作废
+    pub fn void(&self) -> bool {
+        self.void
+    }
+
+    /// 主持人是否给了改变选择的机会，只有邪恶的主持人会拒绝
+    pub fn offered(&self) -> bool {
+        !self.declined
+    }
+
+    /// 主持人最后一次的买断出价
+    pub fn offer(&self) -> Option<u32> {
+        self.offer
+    }
+
+    /// 团队模式下进行本轮的团队的序号
+    pub fn team(&self) -> Option<u32> {
+        self.team
+    }
+
+    /// 从看到揭示结果到做出抉择的毫秒数
+    pub fn decision_ms(&self) -> Option<u64> {
+        self.decision_ms
+    }
+
+    /// 从本轮开始到做出抉择的毫秒数
+    pub fn round_ms(&self) -> Option<u64> {
+        self.round_ms
+    }
+
+    /// 挑战者估计的改变选择中奖率（百分比）
+    pub fn estimate(&self) -> Option<u32> {
+        self.estimate
+    }
+
+    /// 本轮开始的时间（Unix 时间戳，毫秒）
+    pub fn started_at(&self) -> Option<u64> {
+        self.started_at
+    }
+
+    /// 挑战者选择用的毫秒数
+    pub fn choose_ms(&self) -> Option<u64> {
+        self.choose_ms
+    }
+
+    /// 主持人揭示用的毫秒数
+    pub fn reveal_ms(&self) -> Option<u64> {
+        self.reveal_ms
+    }
+
+    /// 挑战者抉择用的毫秒数（包括测验模式下的估计）
+    pub fn decide_ms(&self) -> Option<u64> {
+        self.decide_ms
+    }
+}
+
+fn one() -> u32 {
+    1
+}
+
+fn is_one(n: &u32) -> bool {
+    *n == 1
+}
+
+/// 门序号的存储类型
+pub trait DoorIndex: Copy + TryFrom<u32> + Into<u32> {}
+
+impl DoorIndex for u8 {}
+impl DoorIndex for u16 {}
+impl DoorIndex for u32 {}
+
+/// 紧凑的一轮游戏结果，门数和门序号用 `I` 存储，抉择和输赢合并成一个字节，不保留用时、奖品数、保持关闭的门数、奖品价值和团队。
+/// 接受买断时保留金额，没有接受的出价不保留。门数不超过 255 时，`SmallRoundResult<u8>` 只占 9 个字节。
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+pub struct SmallRoundResult<I = u8> {
+    doors: I,
+    prize: I,
+    chosen: I,
+    left: I,
+    flags: u8,
+    /// 接受的买断金额，小端字节序，用字节数组保持一字节对齐
+    deal: [u8; 4],
+}
+
+impl<I> SmallRoundResult<I> {
+    const SWITCH: u8 = 0b001;
+    const WIN: u8 = 0b010;
+    const SUDDEN_DEATH: u8 = 0b100;
+    const VOID: u8 = 0b1000;
+    const DECLINED: u8 = 0b10000;
+    const DEAL: u8 = 0b100000;
+}
+
+impl<I: DoorIndex> TryFrom<RoundResult> for SmallRoundResult<I> {
+    type Error = Error;
+
+    fn try_from(result: RoundResult) -> Result<Self> {
+        let index = |door: u32| I::try_from(door).map_err(|_| Error::InvalidDoorIndex);
+        let mut flags = 0;
+        if let Decision::Switch | Decision::SwitchTo(_) = result.decision {
+            flags |= Self::SWITCH;
+        }
+        if result.win {
+            flags |= Self::WIN;
+        }
+        if result.sudden_death {
+            flags |= Self::SUDDEN_DEATH;
+        }
+        if result.void {
+            flags |= Self::VOID;
+        }
+        if result.declined {
+            flags |= Self::DECLINED;
+        }
+        let mut deal = 0;
+        if let Decision::TakeDeal(offer) = result.decision {
+            flags |= Self::DEAL;
+            deal = offer;
+        }
+        Ok(Self {
+            doors: index(result.doors)?,
+            prize: index(result.prize)?,
+            chosen: index(result.chosen)?,
+            left: index(result.left)?,
+            flags,
+            deal: deal.to_le_bytes(),
+        })
+    }
+}
+
+impl<I: DoorIndex> From<SmallRoundResult<I>> for RoundResult {
+    fn from(result: SmallRoundResult<I>) -> Self {
+        Self {
+            doors: result.doors.into(),
+            prize: result.prize.into(),
+            prizes: 1,
+            chosen: result.chosen.into(),
+            left: result.left.into(),
+            closed: 1,
+            decision: if result.flags & SmallRoundResult::<I>::DEAL != 0 {
+                Decision::TakeDeal(u32::from_le_bytes(result.deal))
+            } else if result.flags & SmallRoundResult::<I>::SWITCH != 0 {
+                Decision::Switch
+            } else {
+                Decision::Stick
+            },
+            win: result.flags & SmallRoundResult::<I>::WIN != 0,
+            value: 1,
+            sudden_death: result.flags & SmallRoundResult::<I>::SUDDEN_DEATH != 0,
+            void: result.flags & SmallRoundResult::<I>::VOID != 0,
+            declined: result.flags & SmallRoundResult::<I>::DECLINED != 0,
+            offer: None,
+            team: None,
+            decision_ms: None,
+            round_ms: None,
+            estimate: None,
+            started_at: None,
+            choose_ms: None,
+            reveal_ms: None,
+            decide_ms: None,
+        }
+    }
+}
+
+/// 一局游戏没有打完所有轮次就结束的原因
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum EarlyEnd {
+    /// 挑战者中途离开
+    ContestantLeft,
+    /// 轮到挑战者操作时超时
+    TimedOut,
+    /// 版主或者主持人强制结束
+    Forced,
+}
+
+impl EarlyEnd {
+    /// 是否是挑战者弃权
+    pub fn is_forfeit(&self) -> bool {
+        matches!(self, EarlyEnd::ContestantLeft | EarlyEnd::TimedOut)
+    }
+}
+
+/// 每个阶段的时限（秒），没有设置的阶段不限时
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct Timeouts {
+    /// 挑战者选择
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub choose_secs: Option<u64>,
+
+    /// 主持人揭示
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reveal_secs: Option<u64>,
+
+    /// 挑战者抉择（包括测验模式下的估计）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decide_secs: Option<u64>,
+}
+
+impl Timeouts {
+    /// 阶段的时限，结束或者放弃的一轮不限时
+    pub fn of(&self, stage: &Stage) -> Option<u64> {
+        match stage {
+            Stage::Choose => self.choose_secs,
+            Stage::Reveal { .. } => self.reveal_secs,
+            Stage::Decide { .. } | Stage::Deal { .. } => self.decide_secs,
+            Stage::End | Stage::Aborted => None,
+        }
+    }
+}
+
+/// 单轮游戏设置
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct RoundSettings {
+    /// 门数
+    pub doors: u32,
+
+    /// 这一轮奖品的价值，为空时使用 [`Settings::prize_value`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<u32>,
+}
+
+impl RoundSettings {
+    pub fn new(doors: u32) -> Self {
+        Self { doors, value: None }
+    }
+
+    /// 这一轮奖品的价值
+    pub fn with_value(mut self, value: u32) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    /// 只有 2 个门时主持人没有可以打开的门，选择之后直接进入抉择
+    pub fn skips_reveal(&self) -> bool {
+        self.doors == 2
+    }
+}
+
+/// 游戏设置
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub struct Settings {
     /// 门数
     pub doors: u32,
 
     /// 轮数
     pub rounds: u32,
+
+    /// 每轮的设置，轮数超过列表长度时从头循环；为空时每轮都使用 `doors`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<RoundSettings>,
+
+    /// 挑战者与庄家打平时自动加赛，直到分出胜负
+    #[serde(default)]
+    pub sudden_death: bool,
+
+    /// 使用的规则名称，由 [`apply_rules`] 设置，见 [`Rules`]。设置了规则时 [`GameResult::score`] 按规则报告得分
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<String>,
+
+    /// 测验模式：挑战者抉择前需要估计改变选择的中奖率
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub quiz: bool,
+
+    /// 主持人揭示后除挑战者选择的门之外保持关闭的门数，默认 1（经典的三门问题），
+    /// 超过门数 - 1 时所有门都保持关闭
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub closed: Option<u32>,
+
+    /// 每轮的奖品数，默认 1，至少留下 1 个没有奖品的门。主持人留下的门数不少于奖品数，
+    /// 这样总能打开没有奖品的门
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prizes: Option<u32>,
+
+    /// 允许主持人自己作为挑战者进入房间，用于练习和演示
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub self_play: bool,
+
+    /// 每个阶段的时限，超时后由 [`Room::check_timeout`] 随机完成这一阶段，默认不限时
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeouts: Option<Timeouts>,
+
+    /// 双方共同决定奖品位置：每轮开始前主持人提交种子（房间只公布承诺），挑战者随后提供随机串，
+    /// 奖品位置由两者的哈希决定，主持人不能指定奖品位置
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub shared_entropy: bool,
+
+    /// 无尽模式：不限轮数，主持人可以一直开始新的一轮，直到明确完成本局。`rounds` 和加赛不再生效
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub endless: bool,
+
+    /// 每轮奖品的价值（分数或者金额），默认 1，`schedule` 中每轮的 `value` 优先。
+    /// 设置了价值时 [`GameResult::score`] 报告赢得的总价值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prize_value: Option<u32>,
+
+    /// 健忘的主持人（Monty Fall）：主持人不记得奖品的位置，随机打开挑战者没有选择的门，
+    /// 打开了有奖的门时这一轮作废，见 [`RoundResult::void`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub forgetful: bool,
+
+    /// 邪恶的主持人（Evil Monty）：挑战者选择之后，主持人可以不揭示、不给改变选择的机会，
+    /// 直接按挑战者的选择结束这一轮，见 [`Room::decline`] 和 [`RoundResult::offered`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub evil: bool,
+
+    /// 允许主持人在揭示之后出价买断，见 [`Room::offer_deal`]。接受出价的轮次得分为出价，
+    /// [`GameResult::score`] 报告得分
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub deals: bool,
 }
 
 impl Settings {
+    /// 从默认设置（3 个门、10 轮）开始构建设置，见 [`SettingsBuilder`]
+    pub fn builder() -> SettingsBuilder {
+        SettingsBuilder::default()
+    }
+
     pub fn new(doors: u32, rounds: u32) -> Self {
-        Self { doors, rounds }
+        Self {
+            doors,
+            rounds,
+            schedule: vec![],
+            sudden_death: false,
+            rules: None,
+            quiz: false,
+            closed: None,
+            prizes: None,
+            self_play: false,
+            timeouts: None,
+            shared_entropy: false,
+            endless: false,
+            prize_value: None,
+            forgetful: false,
+            evil: false,
+            deals: false,
+        }
+    }
+
+    /// 打平时是否加赛
+    pub fn with_sudden_death(mut self, sudden_death: bool) -> Self {
+        self.sudden_death = sudden_death;
+        self
+    }
+
+    /// 是否开启测验模式
+    pub fn with_quiz(mut self, quiz: bool) -> Self {
+        self.quiz = quiz;
+        self
+    }
+
+    /// 每个阶段的时限
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = Some(timeouts);
+        self
+    }
+
+    /// 双方共同决定奖品位置
+    pub fn with_shared_entropy(mut self, shared_entropy: bool) -> Self {
+        self.shared_entropy = shared_entropy;
+        self
+    }
+
+    /// 无尽模式
+    pub fn with_endless(mut self, endless: bool) -> Self {
+        self.endless = endless;
+        self
+    }
+
+    /// 每轮奖品的价值
+    pub fn with_prize_value(mut self, value: u32) -> Self {
+        self.prize_value = Some(value);
+        self
+    }
+
+    /// 健忘的主持人
+    pub fn with_forgetful(mut self, forgetful: bool) -> Self {
+        self.forgetful = forgetful;
+        self
+    }
+
+    /// 邪恶的主持人
+    pub fn with_evil(mut self, evil: bool) -> Self {
+        self.evil = evil;
+        self
+    }
+
+    /// 允许主持人出价买断
+    pub fn with_deals(mut self, deals: bool) -> Self {
+        self.deals = deals;
+        self
+    }
+
+    /// 一轮中奖品的价值
+    pub fn value(&self, round: RoundSettings) -> u32 {
+        round.value.or(self.prize_value).unwrap_or(1)
+    }
+
+    /// 是否设置了奖品的价值
+    pub fn has_values(&self) -> bool {
+        self.prize_value.is_some() || self.schedule.iter().any(|round| round.value.is_some())
+    }
+
+    /// 允许主持人自己作为挑战者
+    pub fn with_self_play(mut self, self_play: bool) -> Self {
+        self.self_play = self_play;
+        self
+    }
+
+    /// 主持人揭示后保持关闭的门数
+    pub fn with_closed(mut self, closed: u32) -> Self {
+        self.closed = Some(closed);
+        self
+    }
+
+    /// 一轮中主持人揭示后除挑战者选择的门之外保持关闭的门数
+    pub fn closed(&self, round: RoundSettings) -> u32 {
+        self.closed
+            .unwrap_or(1)
+            .max(self.prizes(round))
+            .clamp(1, round.doors - 1)
+    }
+
+    /// 每轮的奖品数
+    pub fn with_prizes(mut self, prizes: u32) -> Self {
+        self.prizes = Some(prizes);
+        self
+    }
+
+    /// 一轮中的奖品数
+    pub fn prizes(&self, round: RoundSettings) -> u32 {
+        self.prizes.unwrap_or(1).clamp(1, round.doors - 1)
+    }
+
+    /// 按门数列表设置每轮的门数，例如 `[3, 5, 10, 20]` 逐轮增加难度
+    pub fn with_schedule(mut self, doors: &[u32]) -> Self {
+        self.schedule = doors
+            .iter()
+            .map(|doors| RoundSettings::new(*doors))
+            .collect();
+        self
+    }
+
+    /// 第 `round` 轮（从 0 开始）的设置
+    pub fn round(&self, round: u32) -> RoundSettings {
+        if self.schedule.is_empty() {
+            RoundSettings::new(self.doors)
+        } else {
+            self.schedule[round as usize % self.schedule.len()]
+        }
+    }
+
+    /// 至少需要 2 个门和 1 轮游戏，每轮的设置也至少需要 2 个门，主持人至少留下 1 个门，至少有 1 个奖品，
+    /// 阶段时限至少 1 秒，规则名称有效
+    pub fn validate(&self) -> Result<()> {
+        let timeouts = self.timeouts.unwrap_or_default();
+        if self.doors >= 2
+            && self.rules.iter().all(|name| valid_rules_name(name))
+            && self.rounds >= 1
+            && self.schedule.iter().all(|round| round.doors >= 2)
+            && self.closed != Some(0)
+            && self.prizes != Some(0)
+            && self.prize_value != Some(0)
+            && self.schedule.iter().all(|round| round.value != Some(0))
+            && ![
+                timeouts.choose_secs,
+                timeouts.reveal_secs,
+                timeouts.decide_secs,
+            ]
+            .contains(&Some(0))
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidSettings)
+        }
+    }
+}
+
+/// 设置的构建器，未指定的选项使用默认值，[`SettingsBuilder::build`] 时检查设置。
+/// 新增的选项只会增加方法，不会改变已有的调用
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SettingsBuilder {
+    settings: Settings,
+}
+
+impl Default for SettingsBuilder {
+    /// 经典的三门问题，10 轮
+    fn default() -> Self {
+        Self {
+            settings: Settings::new(3, 10),
+        }
+    }
+}
+
+impl From<Settings> for SettingsBuilder {
+    /// 在已有设置的基础上修改
+    fn from(settings: Settings) -> Self {
+        Self { settings }
+    }
+}
+
+impl SettingsBuilder {
+    /// 门数
+    pub fn doors(mut self, doors: u32) -> Self {
+        self.settings.doors = doors;
+        self
+    }
+
+    /// 轮数
+    pub fn rounds(mut self, rounds: u32) -> Self {
+        self.settings.rounds = rounds;
+        self
+    }
+
+    /// 每轮的门数，见 [`Settings::with_schedule`]
+    pub fn schedule(mut self, doors: &[u32]) -> Self {
+        self.settings = self.settings.with_schedule(doors);
+        self
+    }
+
+    /// 打平时加赛
+    pub fn sudden_death(mut self, sudden_death: bool) -> Self {
+        self.settings.sudden_death = sudden_death;
+        self
+    }
+
+    /// 测验模式
+    pub fn quiz(mut self, quiz: bool) -> Self {
+        self.settings.quiz = quiz;
+        self
+    }
+
+    /// 主持人揭示后保持关闭的门数
+    pub fn closed(mut self, closed: u32) -> Self {
+        self.settings.closed = Some(closed);
+        self
+    }
+
+    /// 每轮的奖品数
+    pub fn prizes(mut self, prizes: u32) -> Self {
+        self.settings.prizes = Some(prizes);
+        self
+    }
+
+    /// 允许主持人自己作为挑战者
+    pub fn self_play(mut self, self_play: bool) -> Self {
+        self.settings.self_play = self_play;
+        self
+    }
+
+    /// 每个阶段的时限
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.settings.timeouts = Some(timeouts);
+        self
+    }
+
+    /// 双方共同决定奖品位置
+    pub fn shared_entropy(mut self, shared_entropy: bool) -> Self {
+        self.settings.shared_entropy = shared_entropy;
+        self
+    }
+
+    /// 无尽模式
+    pub fn endless(mut self, endless: bool) -> Self {
+        self.settings.endless = endless;
+        self
+    }
+
+    /// 每轮奖品的价值
+    pub fn prize_value(mut self, value: u32) -> Self {
+        self.settings.prize_value = Some(value);
+        self
+    }
+
+    /// 健忘的主持人
+    pub fn forgetful(mut self, forgetful: bool) -> Self {
+        self.settings.forgetful = forgetful;
+        self
+    }
+
+    /// 邪恶的主持人
+    pub fn evil(mut self, evil: bool) -> Self {
+        self.settings.evil = evil;
+        self
+    }
+
+    /// 允许主持人出价买断
+    pub fn deals(mut self, deals: bool) -> Self {
+        self.settings.deals = deals;
+        self
+    }
+
+    /// 检查并返回设置，设置无效时返回 [`Error::InvalidSettings`]
+    pub fn build(self) -> Result<Settings> {
+        self.settings.validate()?;
+        Ok(self.settings)
     }
 }
 
 /// 挑战者抉择
-#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
 pub enum Decision {
     /// 改变选择
     Switch,
 
     /// 坚持选择
     Stick,
+
+    /// 改变选择并换到主持人留下的指定的门，结果中记为 `Switch`，换到的门记为 `left`
+    SwitchTo(u32),
+
+    /// 接受主持人的买断出价，金额必须等于当前的出价。不赢得奖品，本轮得分为出价
+    TakeDeal(u32),
 }
 
 impl Default for Decision {
@@ -146,447 +937,5575 @@ impl Distribution<Decision> for Standard {
     }
 }
 
-/// 游戏房间
-#[derive(Debug, Serialize, Deserialize)]
+/// 游戏房间。复制的房间不带观察者，使用种子时随机数生成器从当前位置继续；
+/// 两个房间的快照（[`Room::snapshot`]）相同时相等
+///
+/// 每个状态变化都产生 [`RoomEvent`]，[`Room::apply`] 是取得一个操作所产生事件的唯一接口。
+/// 各个操作方法（例如 [`Room::choose`]、[`Room::decide`]）保持原来的返回值，不返回事件，
+/// 直接调用它们时事件只能通过观察者（[`Room::set_observer`]）或者记录（[`Room::take_events`]）得到
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Room {
     /// 房间 ID
     id: Uuid,
     /// 主持人 ID
     host: Uuid,
+    /// 副主持人 ID
+    #[serde(default)]
+    co_hosts: Vec<Uuid>,
     /// 游戏设置
     settings: Settings,
     /// 房间状态
     state: RoomState,
+    /// 主持人提议、等待挑战者确认的设置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proposed_settings: Option<Settings>,
+    /// 开始游戏时为所有轮次预留结果空间
+    #[serde(skip)]
+    reserve_rounds: bool,
+    /// 房间所有随机操作使用的随机数生成器
+    #[serde(skip)]
+    rng: RoomRng,
+    /// 还没有取出的房间事件，只在开启记录（[`Room::record_events`]）或者执行 [`Room::apply`] 时保存
+    #[serde(skip)]
+    events: Vec<RoomEvent>,
+    /// 是否记录事件供 [`Room::take_events`] 取出
+    #[serde(skip)]
+    recording: bool,
+    /// 事件发生时立即通知的观察者
+    #[serde(skip)]
+    observer: Observer,
+    /// 计算本局得分的规则，没有设置时按 [`RoundResult::score`] 计分
+    #[serde(skip)]
+    rules: RoomRules,
+    /// 本轮之前的各个阶段，用于撤销，新的一轮开始时清空
+    #[serde(skip)]
+    history: Vec<Stage>,
+    /// 本轮各阶段开始的时间，用于记录各阶段的用时
+    #[serde(skip)]
+    clock: StageClock,
+    /// 双方共同决定奖品位置的交换
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exchange: Option<Exchange>,
+    /// 团队模式下的团队，按轮次轮流进行
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    teams: Vec<Team>,
+    /// 观众 ID，按进入的顺序排列
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    spectators: Vec<Uuid>,
+    /// 房间名称，在大厅中代替房间 ID 显示
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// 房间介绍
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
 }
 
-impl Room {
-    /// 创建房间
-    pub fn create(host: Uuid, settings: Settings) -> Self {
-        Self {
-            id: Uuid::new_v4(),
-            host,
-            settings,
+/// 开启 [`Settings::shared_entropy`] 时每轮开始前的交换：主持人提交种子，挑战者随后提供随机串。
+/// 本轮按两者放置奖品后，在本轮结束时公布种子
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Exchange {
+    /// 交换所属的轮次
+    round: u32,
+    /// 主持人的种子，本轮结束前保密，所以不序列化。反序列化得到的交换没有种子，恢复房间时丢弃
+    #[serde(default, skip_serializing)]
+    seed: String,
+    /// 挑战者的随机串
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    /// 本轮的奖品已经按种子和随机串放置
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    placed: bool,
+}
+
+impl Exchange {
+    /// 交换所属的轮次
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    /// 公布给挑战者的种子承诺，见 [`fairness::commitment`]
+    pub fn commitment(&self) -> String {
+        fairness::commitment(&self.seed)
+    }
+
+    /// 挑战者的随机串
+    pub fn nonce(&self) -> Option<&str> {
+        self.nonce.as_deref()
+    }
+
+    /// 本轮的奖品已经按种子和随机串放置
+    pub fn is_placed(&self) -> bool {
+        self.placed
+    }
+
+    /// 按种子和随机串决定的奖品位置，还没有随机串时为空
+    fn prizes(&self, round: RoundSettings, count: u32) -> Option<Vec<u32>> {
+        let entropy = fairness::shared_entropy(&self.seed, self.nonce.as_deref()?);
+        Some(fairness::placement(&entropy, round.doors, count))
+    }
+}
+
+/// 团队模式下的一个团队。团队按轮次轮流进行，第 `i` 轮由第 `i % 团队数` 个团队进行，
+/// 成员一起讨论，由队长提交本轮的选择、估计和抉择
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct Team {
+    /// 团队名称
+    name: String,
+    /// 队长 ID
+    captain: Uuid,
+    /// 除队长之外的成员 ID
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    members: Vec<Uuid>,
+}
+
+impl Team {
+    /// 团队名称
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 队长 ID
+    pub fn captain(&self) -> &Uuid {
+        &self.captain
+    }
+
+    /// 除队长之外的成员 ID
+    pub fn members(&self) -> &[Uuid] {
+        &self.members
+    }
+
+    /// 是否是队长或者成员
+    pub fn contains(&self, id: &Uuid) -> bool {
+        self.captain == *id || self.members.contains(id)
+    }
+}
+
+/// 本轮开始、挑战者选择和主持人揭示的时间（Unix 时间戳，毫秒），暂停的时间也计算在内
+#[derive(Debug, Default, Clone, Copy)]
+struct StageClock {
+    started: Option<u64>,
+    chosen: Option<u64>,
+    revealed: Option<u64>,
+}
+
+impl StageClock {
+    /// 阶段变化时记录时间，撤销或者退回到之前的阶段时清除之后的时间
+    fn record(&mut self, event: &RoomEvent) {
+        let now = protocol::timestamp();
+        match event {
+            RoomEvent::RoundStarted { .. } => {
+                *self = Self {
+                    started: Some(now),
+                    ..Self::default()
+                }
+            }
+            RoomEvent::Chosen { .. } => {
+                self.chosen = Some(now);
+                self.revealed = None;
+            }
+            RoomEvent::Revealed { .. } => self.revealed = Some(now),
+            RoomEvent::Undone {
+                stage: Stage::Choose,
+            }
+            | RoomEvent::RoundRolledBack { .. } => {
+                self.chosen = None;
+                self.revealed = None;
+            }
+            RoomEvent::Undone {
+                stage: Stage::Reveal { .. },
+            } => self.revealed = None,
+            _ => {}
+        }
+    }
+}
+
+/// 两个时间之间的毫秒数，任意一个没有记录时为空
+fn elapsed(from: Option<u64>, to: Option<u64>) -> Option<u64> {
+    Some(to?.saturating_sub(from?))
+}
+
+/// 房间状态变化产生的事件，由 [`Room::apply`] 返回，开启记录时由 [`Room::take_events`] 按发生顺序取出。
+/// 其他操作方法不返回事件
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum RoomEvent {
+    CoHostAdded {
+        id: Uuid,
+    },
+    CoHostRemoved {
+        id: Uuid,
+    },
+    /// 主持人添加了团队，`team` 是团队的序号
+    TeamAdded {
+        team: u32,
+        name: String,
+        captain: Uuid,
+    },
+    TeamJoined {
+        team: u32,
+        member: Uuid,
+    },
+    TeamLeft {
+        team: u32,
+        member: Uuid,
+    },
+    SpectatorJoined {
+        id: Uuid,
+    },
+    SpectatorLeft {
+        id: Uuid,
+    },
+    ContestantJoined {
+        contestant: Uuid,
+    },
+    ContestantKicked,
+    Ready {
+        ready: bool,
+    },
+    /// 设置已经生效
+    SettingsUpdated {
+        settings: Settings,
+    },
+    /// 主持人提议的设置，等待挑战者确认
+    SettingsProposed {
+        settings: Settings,
+    },
+    /// 挑战者拒绝了提议的设置
+    SettingsRejected {
+        settings: Settings,
+    },
+    /// 主持人撤回了提议
+    ProposalWithdrawn,
+    /// 房间名称和介绍已经修改
+    DetailsUpdated {
+        name: Option<String>,
+        description: Option<String>,
+    },
+    /// 新的一轮开始，`prizes` 是所有奖品所在的门，从小到大排列
+    RoundStarted {
+        round: u32,
+        prizes: Vec<u32>,
+    },
+    Chosen {
+        chosen: u32,
+    },
+    /// 主持人揭示，没有可以打开的门时紧跟在 `Chosen` 之后
+    Revealed {
+        left: u32,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        others: Vec<u32>,
+    },
+    Estimated {
+        percent: u32,
+    },
+    /// 主持人出价买断，再次出价时代替之前的出价
+    DealOffered {
+        offer: u32,
+    },
+    Decided {
+        result: RoundResult,
+    },
+    /// 健忘的主持人打开了有奖的门，这一轮作废，代替 `Revealed` 和 `Decided`
+    RoundVoided {
+        result: RoundResult,
+    },
+    /// 撤销了本轮最近的一次操作，`stage` 是恢复后的阶段
+    Undone {
+        stage: Stage,
+    },
+    /// 放弃了进行中的一轮
+    RoundAborted {
+        round: u32,
+    },
+    /// 进行中的一轮回到挑战者选择
+    RoundRolledBack {
+        round: u32,
+    },
+    /// 游戏暂停
+    Paused,
+    /// 游戏继续
+    Resumed,
+    /// 本局结束，`forced` 表示在任意阶段强制结束
+    Completed {
+        rounds: u32,
+        forced: bool,
+    },
+    /// 主持人为 `round` 提交了种子，`commitment` 见 [`fairness::commitment`]
+    SeedCommitted {
+        round: u32,
+        commitment: String,
+    },
+    /// 挑战者为 `round` 提供了随机串
+    NonceSupplied {
+        round: u32,
+        nonce: String,
+    },
+    /// 按种子和随机串放置奖品的一轮结束（抉择、放弃或者本局结束），公布种子供挑战者验证
+    SeedRevealed {
+        round: u32,
+        seed: String,
+        nonce: String,
+    },
+}
+
+/// 房间的一个操作，由 [`Room::apply`] 执行，门序号为空时随机选择
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub enum Command {
+    AddCoHost {
+        id: Uuid,
+    },
+    RemoveCoHost {
+        id: Uuid,
+    },
+    AddTeam {
+        name: String,
+        captain: Uuid,
+    },
+    JoinTeam {
+        team: u32,
+        member: Uuid,
+    },
+    LeaveTeam {
+        member: Uuid,
+    },
+    AddSpectator {
+        id: Uuid,
+    },
+    RemoveSpectator {
+        id: Uuid,
+    },
+    AcceptContestant {
+        contestant: Uuid,
+    },
+    KickContestant,
+    Ready {
+        ready: bool,
+    },
+    UpdateSettings {
+        settings: Settings,
+    },
+    AnswerSettings {
+        accept: bool,
+    },
+    UpdateDetails {
+        name: Option<String>,
+        description: Option<String>,
+    },
+    /// `others` 是其余奖品所在的门，为空时随机放置；`doors` 是只在这一轮使用的门数，
+    /// 不能和 `others` 一起使用
+    Start {
+        prize: Option<u32>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        others: Vec<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doors: Option<u32>,
+    },
+    Choose {
+        chosen: Option<u32>,
+    },
+    /// `others` 是其余留下的门，为空时随机补足
+    Reveal {
+        left: Option<u32>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        others: Vec<u32>,
+    },
+    Estimate {
+        percent: u32,
+    },
+    OfferDeal {
+        offer: u32,
+    },
+    /// `latency_ms` 是从看到揭示结果到做出抉择的毫秒数
+    Decide {
+        decision: Decision,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        latency_ms: Option<u64>,
+    },
+    /// 邪恶的主持人不给改变选择的机会
+    Decline,
+    /// 撤销本轮最近的一次操作
+    Undo,
+    AbortRound,
+    Pause,
+    Resume,
+    Complete {
+        kick_contestant: bool,
+    },
+    ForceComplete,
+    CompleteEarly {
+        reason: EarlyEnd,
+    },
+    CommitSeed {
+        seed: String,
+    },
+    SupplyNonce {
+        nonce: String,
+    },
+}
+
+/// 房间状态变化的观察者，由 [`Room::set_observer`] 设置。
+/// 默认的 [`RoomObserver::on_event`] 按事件调用对应的方法，所有方法默认什么都不做
+pub trait RoomObserver: Send + Sync {
+    fn on_event(&mut self, event: &RoomEvent) {
+        match event {
+            RoomEvent::CoHostAdded { id } => self.on_co_host_added(id),
+            RoomEvent::CoHostRemoved { id } => self.on_co_host_removed(id),
+            RoomEvent::TeamAdded {
+                team,
+                name,
+                captain,
+            } => self.on_team_added(*team, name, captain),
+            RoomEvent::TeamJoined { team, member } => self.on_team_joined(*team, member),
+            RoomEvent::TeamLeft { team, member } => self.on_team_left(*team, member),
+            RoomEvent::SpectatorJoined { id } => self.on_spectator_joined(id),
+            RoomEvent::SpectatorLeft { id } => self.on_spectator_left(id),
+            RoomEvent::ContestantJoined { contestant } => self.on_contestant_joined(contestant),
+            RoomEvent::ContestantKicked => self.on_contestant_kicked(),
+            RoomEvent::Ready { ready } => self.on_ready(*ready),
+            RoomEvent::SettingsUpdated { settings } => self.on_settings_updated(settings),
+            RoomEvent::SettingsProposed { settings } => self.on_settings_proposed(settings),
+            RoomEvent::SettingsRejected { settings } => self.on_settings_rejected(settings),
+            RoomEvent::ProposalWithdrawn => self.on_proposal_withdrawn(),
+            RoomEvent::DetailsUpdated { name, description } => {
+                self.on_details_updated(name.as_deref(), description.as_deref())
+            }
+            RoomEvent::RoundStarted { round, prizes } => self.on_round_started(*round, prizes),
+            RoomEvent::Chosen { chosen } => self.on_chosen(*chosen),
+            RoomEvent::Revealed { left, others } => self.on_revealed(*left, others),
+            RoomEvent::Estimated { percent } => self.on_estimated(*percent),
+            RoomEvent::DealOffered { offer } => self.on_deal_offered(*offer),
+            RoomEvent::Decided { result } => self.on_decided(result),
+            RoomEvent::RoundVoided { result } => self.on_round_voided(result),
+            RoomEvent::Undone { stage } => self.on_undone(stage),
+            RoomEvent::RoundAborted { round } => self.on_round_aborted(*round),
+            RoomEvent::RoundRolledBack { round } => self.on_round_rolled_back(*round),
+            RoomEvent::Paused => self.on_paused(),
+            RoomEvent::Resumed => self.on_resumed(),
+            RoomEvent::Completed { rounds, forced } => self.on_completed(*rounds, *forced),
+            RoomEvent::SeedCommitted { round, commitment } => {
+                self.on_seed_committed(*round, commitment)
+            }
+            RoomEvent::NonceSupplied { round, nonce } => self.on_nonce_supplied(*round, nonce),
+            RoomEvent::SeedRevealed { round, seed, nonce } => {
+                self.on_seed_revealed(*round, seed, nonce)
+            }
+        }
+    }
+
+    fn on_co_host_added(&mut self, _id: &Uuid) {}
+
+    fn on_co_host_removed(&mut self, _id: &Uuid) {}
+
+    fn on_team_added(&mut self, _team: u32, _name: &str, _captain: &Uuid) {}
+
+    fn on_team_joined(&mut self, _team: u32, _member: &Uuid) {}
+
+    fn on_team_left(&mut self, _team: u32, _member: &Uuid) {}
+
+    fn on_spectator_joined(&mut self, _id: &Uuid) {}
+
+    fn on_spectator_left(&mut self, _id: &Uuid) {}
+
+    fn on_contestant_joined(&mut self, _contestant: &Uuid) {}
+
+    fn on_contestant_kicked(&mut self) {}
+
+    fn on_ready(&mut self, _ready: bool) {}
+
+    fn on_settings_updated(&mut self, _settings: &Settings) {}
+
+    fn on_settings_proposed(&mut self, _settings: &Settings) {}
+
+    fn on_settings_rejected(&mut self, _settings: &Settings) {}
+
+    fn on_proposal_withdrawn(&mut self) {}
+
+    fn on_details_updated(&mut self, _name: Option<&str>, _description: Option<&str>) {}
+
+    /// `prizes` 是所有奖品所在的门，从小到大排列
+    fn on_round_started(&mut self, _round: u32, _prizes: &[u32]) {}
+
+    fn on_chosen(&mut self, _chosen: u32) {}
+
+    fn on_revealed(&mut self, _left: u32, _others: &[u32]) {}
+
+    fn on_estimated(&mut self, _percent: u32) {}
+
+    /// 主持人出价买断
+    fn on_deal_offered(&mut self, _offer: u32) {}
+
+    fn on_decided(&mut self, _result: &RoundResult) {}
+
+    /// 健忘的主持人打开了有奖的门，这一轮作废
+    fn on_round_voided(&mut self, _result: &RoundResult) {}
+
+    /// 撤销了本轮最近的一次操作
+    fn on_undone(&mut self, _stage: &Stage) {}
+
+    /// 放弃了进行中的一轮
+    fn on_round_aborted(&mut self, _round: u32) {}
+
+    /// 进行中的一轮回到挑战者选择
+    fn on_round_rolled_back(&mut self, _round: u32) {}
+
+    fn on_paused(&mut self) {}
+
+    fn on_resumed(&mut self) {}
+
+    fn on_completed(&mut self, _rounds: u32, _forced: bool) {}
+
+    fn on_seed_committed(&mut self, _round: u32, _commitment: &str) {}
+
+    fn on_nonce_supplied(&mut self, _round: u32, _nonce: &str) {}
+
+    fn on_seed_revealed(&mut self, _round: u32, _seed: &str, _nonce: &str) {}
+}
+
+/// 房间的观察者，没有设置时为空
+#[derive(Default)]
+struct Observer(Option<Box<dyn RoomObserver>>);
+
+impl Clone for Observer {
+    /// 观察者不能复制，复制的房间没有观察者
+    fn clone(&self) -> Self {
+        Self(None)
+    }
+}
+
+impl std::fmt::Debug for Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(if self.0.is_some() { "Some(..)" } else { "None" })
+    }
+}
+
+/// 房间的规则，复制的房间共享同一个规则
+#[derive(Clone, Default)]
+struct RoomRules(Option<Arc<dyn Rules + Send + Sync>>);
+
+impl RoomRules {
+    /// 一轮的得分，没有设置规则时见 [`RoundResult::score`]
+    fn score(&self, result: &RoundResult) -> u64 {
+        match &self.0 {
+            Some(rules) => rules.score(result) as u64,
+            None => result.score() as u64,
+        }
+    }
+}
+
+impl std::fmt::Debug for RoomRules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(rules) => write!(f, "Some({})", rules.name()),
+            None => f.write_str("None"),
+        }
+    }
+}
+
+/// 房间的随机数生成器，默认使用线程随机数生成器，指定种子时可以重现整局游戏
+#[derive(Debug, Clone, Default)]
+enum RoomRng {
+    #[default]
+    Thread,
+    Seeded(StdRng),
+}
+
+impl RngCore for RoomRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            RoomRng::Thread => rand::thread_rng().next_u32(),
+            RoomRng::Seeded(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            RoomRng::Thread => rand::thread_rng().next_u64(),
+            RoomRng::Seeded(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            RoomRng::Thread => rand::thread_rng().fill_bytes(dest),
+            RoomRng::Seeded(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), rand::Error> {
+        match self {
+            RoomRng::Thread => rand::thread_rng().try_fill_bytes(dest),
+            RoomRng::Seeded(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+impl PartialEq for Room {
+    fn eq(&self, other: &Self) -> bool {
+        self.snapshot() == other.snapshot()
+    }
+}
+
+impl Eq for Room {}
+
+impl Room {
+    /// 创建房间
+    pub fn create(host: Uuid, settings: Settings) -> Result<Self> {
+        Self::create_with_id(Uuid::new_v4(), host, settings)
+    }
+
+    /// 使用指定的 ID 创建房间，例如测试或者持久化时需要已知的房间 ID
+    pub fn create_with_id(id: Uuid, host: Uuid, settings: Settings) -> Result<Self> {
+        settings.validate()?;
+        Ok(Self {
+            id,
+            host,
+            co_hosts: vec![],
+            settings,
             state: RoomState::default(),
+            proposed_settings: None,
+            reserve_rounds: false,
+            rng: RoomRng::default(),
+            events: vec![],
+            recording: false,
+            observer: Observer::default(),
+            rules: RoomRules::default(),
+            history: vec![],
+            clock: StageClock::default(),
+            exchange: None,
+            teams: vec![],
+            spectators: vec![],
+            name: None,
+            description: None,
+        })
+    }
+
+    /// 创建房间，所有随机操作使用以 `seed` 为种子的随机数生成器，相同的种子和操作得到相同的游戏
+    pub fn create_seeded(host: Uuid, settings: Settings, seed: u64) -> Result<Self> {
+        let mut room = Self::create(host, settings)?;
+        room.reseed(Some(seed));
+        Ok(room)
+    }
+
+    /// 更换之后的随机操作使用的随机数生成器：以 `seed` 为种子，为空时使用线程随机数生成器。
+    /// 例如每轮开始前用公开的随机信标设置种子，任何人都可以重现本轮的随机操作
+    pub fn reseed(&mut self, seed: Option<u64>) {
+        self.rng = match seed {
+            Some(seed) => RoomRng::Seeded(StdRng::seed_from_u64(seed)),
+            None => RoomRng::Thread,
+        };
+    }
+
+    /// 同 [`Room::reseed`]，以 32 个字节为种子，种子需要保密时使用
+    pub fn reseed_from_bytes(&mut self, seed: [u8; 32]) {
+        self.rng = RoomRng::Seeded(StdRng::from_seed(seed));
+    }
+
+    /// 房间快照，用于持久化
+    pub fn snapshot(&self) -> RoomSnapshot {
+        RoomSnapshot {
+            id: self.id,
+            host: self.host,
+            co_hosts: self.co_hosts.clone(),
+            settings: self.settings.clone(),
+            state: self.state.clone(),
+            proposed_settings: self.proposed_settings.clone(),
+            exchange: self.exchange.clone(),
+            teams: self.teams.clone(),
+            spectators: self.spectators.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+        }
+    }
+
+    /// 从快照恢复房间，快照不满足房间的不变量时返回 [`Error::InvalidSnapshot`]
+    pub fn restore(mut snapshot: RoomSnapshot) -> Result<Self> {
+        // 序列化的快照不带种子，进行中的一轮照常继续，只是结束时不再公布种子
+        if snapshot
+            .exchange
+            .as_ref()
+            .is_some_and(|exchange| exchange.seed.is_empty())
+        {
+            snapshot.exchange = None;
+        }
+        snapshot.validate()?;
+        Ok(Self {
+            id: snapshot.id,
+            host: snapshot.host,
+            co_hosts: snapshot.co_hosts,
+            settings: snapshot.settings,
+            state: snapshot.state,
+            proposed_settings: snapshot.proposed_settings,
+            reserve_rounds: false,
+            rng: RoomRng::default(),
+            events: vec![],
+            recording: false,
+            observer: Observer::default(),
+            rules: RoomRules::default(),
+            history: vec![],
+            clock: StageClock::default(),
+            exchange: snapshot.exchange,
+            teams: snapshot.teams,
+            spectators: snapshot.spectators,
+            name: snapshot.name,
+            description: snapshot.description,
+        })
+    }
+
+    /// 开启或者关闭事件记录，默认关闭。开启后事件保留到 [`Room::take_events`] 取出为止，
+    /// 调用者需要定期取出；关闭时丢弃还没有取出的事件
+    pub fn record_events(&mut self, recording: bool) {
+        self.recording = recording;
+        if !recording {
+            self.events = vec![];
+        }
+    }
+
+    /// 取出上次调用以来记录的事件，没有开启记录时为空
+    pub fn take_events(&mut self) -> Vec<RoomEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// 设置观察者，之后每个事件发生时立即调用观察者对应的方法，替换之前的观察者。
+    /// 开启记录时事件同样会记录下来，可以用 [`Room::take_events`] 取出
+    pub fn set_observer(&mut self, observer: impl RoomObserver + 'static) {
+        self.observer = Observer(Some(Box::new(observer)));
+    }
+
+    /// 移除并返回观察者
+    pub fn remove_observer(&mut self) -> Option<Box<dyn RoomObserver>> {
+        self.observer.0.take()
+    }
+
+    /// 设置计算进行中的得分（[`Room::score`]）的规则，替换之前的规则。
+    /// 规则不会序列化，调用者需要保证规则与设置中的规则名称一致，并在设置变化或者恢复房间后重新设置
+    pub fn set_rules(&mut self, rules: Arc<dyn Rules + Send + Sync>) {
+        self.rules = RoomRules(Some(rules));
+    }
+
+    /// 移除并返回规则，之后按 [`RoundResult::score`] 计分
+    pub fn remove_rules(&mut self) -> Option<Arc<dyn Rules + Send + Sync>> {
+        self.rules.0.take()
+    }
+
+    /// 记录事件并通知观察者，阶段变化的事件重新计算当前阶段的截止时间
+    fn emit(&mut self, event: RoomEvent) {
+        if matches!(
+            event,
+            RoomEvent::RoundStarted { .. }
+                | RoomEvent::Chosen { .. }
+                | RoomEvent::Revealed { .. }
+                | RoomEvent::DealOffered { .. }
+                | RoomEvent::Decided { .. }
+                | RoomEvent::RoundVoided { .. }
+                | RoomEvent::Undone { .. }
+                | RoomEvent::RoundAborted { .. }
+                | RoomEvent::RoundRolledBack { .. }
+                | RoomEvent::Paused
+                | RoomEvent::Resumed
+        ) {
+            self.restart_deadline();
+            self.clock.record(&event);
+        }
+        let ends_round = matches!(
+            event,
+            RoomEvent::Decided { .. }
+                | RoomEvent::RoundVoided { .. }
+                | RoomEvent::RoundAborted { .. }
+                | RoomEvent::Completed { .. }
+                | RoomEvent::ContestantKicked
+        );
+        if let Some(observer) = &mut self.observer.0 {
+            observer.on_event(&event);
+        }
+        if self.recording {
+            self.events.push(event);
+        }
+        if ends_round {
+            self.end_exchange();
+        }
+    }
+
+    /// 按种子和随机串放置奖品的一轮结束时公布种子，还没有放置奖品的交换直接丢弃
+    fn end_exchange(&mut self) {
+        if let Some(Exchange {
+            round,
+            seed,
+            nonce: Some(nonce),
+            placed: true,
+        }) = self.exchange.take()
+        {
+            self.emit(RoomEvent::SeedRevealed { round, seed, nonce });
+        }
+    }
+
+    /// 执行一个操作，返回操作产生的事件，按发生顺序排列（包括操作引发的后续事件，例如一轮结束后公布种子的
+    /// [`RoomEvent::SeedRevealed`]）。这是按操作取得事件的唯一接口；没有对应 [`Command`] 的操作
+    /// （例如 [`Room::check_timeout`]、[`Room::rollback_round`]）产生的事件通过观察者或者记录取得。
+    /// 开启记录时事件同样会记录下来，可以用 [`Room::take_events`] 取出
+    pub fn apply(&mut self, command: Command) -> Result<Vec<RoomEvent>> {
+        let recording = std::mem::replace(&mut self.recording, true);
+        let pending = self.events.len();
+        let result = self.execute(command);
+        self.recording = recording;
+        let events = if recording {
+            self.events[pending..].to_vec()
+        } else {
+            self.events.split_off(pending)
+        };
+        result.map(|_| events)
+    }
+
+    fn execute(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::AddCoHost { id } => self.add_co_host(id),
+            Command::RemoveCoHost { id } => self.remove_co_host(&id),
+            Command::AddTeam { name, captain } => self.add_team(name, captain).map(|_| ()),
+            Command::JoinTeam { team, member } => self.join_team(team, member),
+            Command::LeaveTeam { member } => self.leave_team(&member),
+            Command::AddSpectator { id } => self.add_spectator(id),
+            Command::RemoveSpectator { id } => self.remove_spectator(&id),
+            Command::AcceptContestant { contestant } => self.accept_contestant(contestant),
+            Command::KickContestant => self.kick_contestant(),
+            Command::Ready { ready } => self.contestant_ready(ready),
+            Command::UpdateSettings { settings } => self.update_settings(settings).map(|_| ()),
+            Command::AnswerSettings { accept } => self.answer_settings(accept).map(|_| ()),
+            Command::UpdateDetails { name, description } => self.update_details(name, description),
+            Command::Start {
+                doors: Some(_),
+                others,
+                ..
+            } if !others.is_empty() => Err(Error::InvalidOperation),
+            Command::Start {
+                prize,
+                doors: Some(doors),
+                ..
+            } => {
+                let prize = match prize {
+                    Some(prize) => prize,
+                    None => self.with_rng(|_, rng| rng.gen_range(0..doors.max(1))),
+                };
+                self.start_with(doors, prize)
+            }
+            Command::Start { prize: None, .. } => self.start_random().map(|_| ()),
+            Command::Start {
+                prize: Some(prize),
+                others,
+                ..
+            } if others.is_empty() => self.start(prize),
+            Command::Start {
+                prize: Some(prize),
+                mut others,
+                ..
+            } => {
+                others.push(prize);
+                self.start_with_prizes(&others)
+            }
+            Command::Choose { chosen: None } => self.choose_random().map(|_| ()),
+            Command::Choose {
+                chosen: Some(chosen),
+            } => self.choose(chosen),
+            Command::Reveal { left: None, .. } => self.reveal_random().map(|_| ()),
+            Command::Reveal {
+                left: Some(left),
+                others,
+            } if others.is_empty() => self.reveal(left),
+            Command::Reveal {
+                left: Some(left),
+                mut others,
+            } => {
+                others.push(left);
+                self.reveal_doors(&others)
+            }
+            Command::Estimate { percent } => self.estimate(percent),
+            Command::Decline => self.decline().map(|_| ()),
+            Command::OfferDeal { offer } => self.offer_deal(offer),
+            Command::Decide {
+                decision,
+                latency_ms: None,
+            } => self.decide(decision).map(|_| ()),
+            Command::Decide {
+                decision,
+                latency_ms: Some(ms),
+            } => self
+                .decide_with_latency(decision, Duration::from_millis(ms))
+                .map(|_| ()),
+            Command::Complete { kick_contestant } => self.complete(kick_contestant).map(|_| ()),
+            Command::Undo => self.undo(),
+            Command::AbortRound => self.abort_round(),
+            Command::Pause => self.pause(),
+            Command::Resume => self.resume(),
+            Command::ForceComplete => self.force_complete().map(|_| ()),
+            Command::CompleteEarly { reason } => self.complete_early(reason).map(|_| ()),
+            Command::CommitSeed { seed } => self.commit_seed(seed),
+            Command::SupplyNonce { nonce } => self.supply_nonce(nonce),
         }
     }
 
-    /// 房间 ID
-    pub fn id(&self) -> &Uuid {
-        &self.id
+    /// 使用房间的随机数生成器执行操作
+    fn with_rng<T>(&mut self, f: impl FnOnce(&mut Self, &mut RoomRng) -> T) -> T {
+        let mut rng = std::mem::take(&mut self.rng);
+        let result = f(self, &mut rng);
+        self.rng = rng;
+        result
+    }
+
+    /// 房间 ID
+    pub fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    /// 房间名称
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// 房间介绍
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// 修改房间名称和介绍，为空时清除。名称最多 [`MAX_ROOM_NAME_LEN`] 个字符，
+    /// 介绍最多 [`MAX_ROOM_DESCRIPTION_LEN`] 个字符，首尾的空白会被去掉，只有空白时返回 [`Error::InvalidDetails`]
+    pub fn update_details(
+        &mut self,
+        name: Option<String>,
+        description: Option<String>,
+    ) -> Result<()> {
+        let name = name
+            .map(|name| details_text(&name, MAX_ROOM_NAME_LEN))
+            .transpose()?;
+        let description = description
+            .map(|description| details_text(&description, MAX_ROOM_DESCRIPTION_LEN))
+            .transpose()?;
+        self.name = name.clone();
+        self.description = description.clone();
+        self.emit(RoomEvent::DetailsUpdated { name, description });
+        Ok(())
+    }
+
+    /// 主持人 ID
+    pub fn host(&self) -> &Uuid {
+        &self.host
+    }
+
+    /// 副主持人 ID
+    pub fn co_hosts(&self) -> &[Uuid] {
+        &self.co_hosts
+    }
+
+    /// 是否是主持人或副主持人
+    pub fn is_host(&self, id: &Uuid) -> bool {
+        self.host == *id || self.is_co_host(id)
+    }
+
+    /// 是否是副主持人
+    pub fn is_co_host(&self, id: &Uuid) -> bool {
+        self.co_hosts.contains(id)
+    }
+
+    /// 添加副主持人，副主持人可以代替主持人开始游戏和揭示
+    pub fn add_co_host(&mut self, id: Uuid) -> Result<()> {
+        let is_contestant = matches!(
+            self.state,
+            RoomState::Joined { contestant, .. } | RoomState::Started { contestant, .. } if contestant == id
+        );
+        if self.is_host(&id)
+            || is_contestant
+            || self.team_of(&id).is_some()
+            || self.is_spectator(&id)
+        {
+            return Err(Error::InvalidOperation);
+        }
+        self.co_hosts.push(id);
+        self.emit(RoomEvent::CoHostAdded { id });
+        Ok(())
+    }
+
+    /// 移除副主持人
+    pub fn remove_co_host(&mut self, id: &Uuid) -> Result<()> {
+        let len = self.co_hosts.len();
+        self.co_hosts.retain(|co_host| co_host != id);
+        if self.co_hosts.len() < len {
+            self.emit(RoomEvent::CoHostRemoved { id: *id });
+            Ok(())
+        } else {
+            Err(Error::InvalidOperation)
+        }
+    }
+
+    /// 观众 ID，按进入的顺序排列
+    pub fn spectators(&self) -> &[Uuid] {
+        &self.spectators
+    }
+
+    /// 是否是观众
+    pub fn is_spectator(&self, id: &Uuid) -> bool {
+        self.spectators.contains(id)
+    }
+
+    /// 观众进入房间，主持人、副主持人和挑战者不能同时是观众
+    pub fn add_spectator(&mut self, id: Uuid) -> Result<()> {
+        let is_contestant = matches!(
+            self.state,
+            RoomState::Joined { contestant, .. } | RoomState::Started { contestant, .. } if contestant == id
+        );
+        if self.is_host(&id) || is_contestant || self.is_spectator(&id) {
+            return Err(Error::InvalidOperation);
+        }
+        self.spectators.push(id);
+        self.emit(RoomEvent::SpectatorJoined { id });
+        Ok(())
+    }
+
+    /// 观众离开房间
+    pub fn remove_spectator(&mut self, id: &Uuid) -> Result<()> {
+        let len = self.spectators.len();
+        self.spectators.retain(|spectator| spectator != id);
+        if self.spectators.len() < len {
+            self.emit(RoomEvent::SpectatorLeft { id: *id });
+            Ok(())
+        } else {
+            Err(Error::InvalidOperation)
+        }
+    }
+
+    /// 团队模式下的团队，没有团队时不是团队模式
+    pub fn teams(&self) -> &[Team] {
+        &self.teams
+    }
+
+    /// 添加团队，返回团队的序号。只能在游戏开始前添加，名称不能为空或者重复，
+    /// 队长不能是主持人、副主持人或者其他团队的成员
+    pub fn add_team(&mut self, name: String, captain: Uuid) -> Result<u32> {
+        if matches!(self.state, RoomState::Started { .. })
+            || name.is_empty()
+            || self.teams.iter().any(|team| team.name == name)
+            || self.is_host(&captain)
+            || self.team_of(&captain).is_some()
+        {
+            return Err(Error::InvalidOperation);
+        }
+        let team = self.teams.len() as u32;
+        self.teams.push(Team {
+            name: name.clone(),
+            captain,
+            members: vec![],
+        });
+        self.emit(RoomEvent::TeamAdded {
+            team,
+            name,
+            captain,
+        });
+        Ok(team)
+    }
+
+    /// 加入团队，每个用户最多属于一个团队，主持人和副主持人不能加入
+    pub fn join_team(&mut self, team: u32, member: Uuid) -> Result<()> {
+        if self.is_host(&member) || self.team_of(&member).is_some() {
+            return Err(Error::InvalidOperation);
+        }
+        self.teams
+            .get_mut(team as usize)
+            .ok_or(Error::InvalidOperation)?
+            .members
+            .push(member);
+        self.emit(RoomEvent::TeamJoined { team, member });
+        Ok(())
+    }
+
+    /// 离开团队，队长不能离开
+    pub fn leave_team(&mut self, member: &Uuid) -> Result<()> {
+        let team = self.team_of(member).ok_or(Error::InvalidOperation)?;
+        let members = &mut self.teams[team as usize].members;
+        let len = members.len();
+        members.retain(|id| id != member);
+        if members.len() == len {
+            return Err(Error::InvalidOperation);
+        }
+        self.emit(RoomEvent::TeamLeft {
+            team,
+            member: *member,
+        });
+        Ok(())
+    }
+
+    /// 用户所在团队的序号
+    pub fn team_of(&self, id: &Uuid) -> Option<u32> {
+        self.teams
+            .iter()
+            .position(|team| team.contains(id))
+            .map(|team| team as u32)
+    }
+
+    /// 进行 `round` 轮的团队的序号，不是团队模式时为空
+    pub fn team_for_round(&self, round: u32) -> Option<u32> {
+        match self.teams.len() as u32 {
+            0 => None,
+            count => Some(round % count),
+        }
+    }
+
+    /// 进行当前轮（还没有开始时为下一轮）的团队的序号，不是团队模式时为空
+    pub fn playing_team(&self) -> Option<u32> {
+        let round = match &self.state {
+            RoomState::Started {
+                current_round,
+                stage,
+                ..
+            } if !stage.is_end() => *current_round,
+            _ => self.next_round(),
+        };
+        self.team_for_round(round)
+    }
+
+    /// 当前由谁提交选择、估计和抉择：团队模式下是进行当前轮的团队的队长，否则是挑战者
+    pub fn decider(&self) -> Option<Uuid> {
+        match self.playing_team() {
+            Some(team) => Some(self.teams[team as usize].captain),
+            None => self.contestant(),
+        }
+    }
+
+    /// 房间中的挑战者，还没有挑战者时为空
+    pub fn contestant(&self) -> Option<Uuid> {
+        match &self.state {
+            RoomState::Joined { contestant, .. } | RoomState::Started { contestant, .. } => {
+                Some(*contestant)
+            }
+            RoomState::Created => None,
+        }
+    }
+
+    /// 当前游戏配置
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// 主持人提议、等待挑战者确认的设置
+    pub fn proposed_settings(&self) -> Option<&Settings> {
+        self.proposed_settings.as_ref()
+    }
+
+    /// 当前轮的设置，游戏开始前为第一轮的设置，主持人为当前轮指定的门数优先
+    pub fn round_settings(&self) -> RoundSettings {
+        match &self.state {
+            RoomState::Started {
+                current_round,
+                doors: Some(doors),
+                ..
+            } => RoundSettings {
+                doors: *doors,
+                ..self.settings.round(*current_round)
+            },
+            RoomState::Started { current_round, .. } => self.settings.round(*current_round),
+            _ => self.settings.round(0),
+        }
+    }
+
+    /// 本局已经赢得的奖品的总价值，按房间的规则（见 [`Room::set_rules`]）计算，游戏开始前为 0
+    pub fn score(&self) -> u64 {
+        match &self.state {
+            RoomState::Started { score, .. } => *score,
+            _ => 0,
+        }
+    }
+
+    /// 游戏是否暂停中
+    pub fn is_paused(&self) -> bool {
+        matches!(self.state, RoomState::Started { paused: true, .. })
+    }
+
+    /// 当前阶段的截止时间（Unix 时间戳，毫秒）
+    pub fn deadline(&self) -> Option<u64> {
+        match &self.state {
+            RoomState::Started { deadline, .. } => *deadline,
+            _ => None,
+        }
+    }
+
+    /// 按设置的时限从现在开始重新计时，暂停中不计时
+    fn restart_deadline(&mut self) {
+        let timeouts = self.settings.timeouts.unwrap_or_default();
+        if let RoomState::Started {
+            stage,
+            paused,
+            deadline,
+            ..
+        } = &mut self.state
+        {
+            *deadline = timeouts
+                .of(stage)
+                .filter(|_| !*paused)
+                .map(|secs| protocol::timestamp() + secs * 1000);
+        }
+    }
+
+    /// 当前阶段在 `now`（Unix 时间戳，毫秒）已经超时时随机完成这一阶段：挑战者随机选择、
+    /// 主持人随机揭示或者挑战者随机抉择，测验模式下超时的抉择不需要估计。
+    /// 返回是否处理了超时，暂停中不会超时
+    pub fn check_timeout(&mut self, now: u64) -> Result<bool> {
+        let stage = match &self.state {
+            RoomState::Started {
+                stage,
+                paused: false,
+                deadline: Some(deadline),
+                ..
+            } if *deadline <= now => stage.clone(),
+            _ => return Ok(false),
+        };
+        match stage {
+            Stage::Choose => self.choose_random().map(|_| ())?,
+            Stage::Reveal { .. } => self.reveal_random().map(|_| ())?,
+            Stage::Decide { .. } | Stage::Deal { .. } => {
+                let decision = self.with_rng(|_, rng| rng.gen());
+                self.make_decision(decision, None, true).map(|_| ())?
+            }
+            Stage::End | Stage::Aborted => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// 规定轮数已经打完且打平，需要加赛才能完成本局
+    pub fn needs_tiebreak(&self) -> bool {
+        match &self.state {
+            RoomState::Started { results, .. } => needs_tiebreak(&self.settings, results),
+            _ => false,
+        }
+    }
+
+    /// 本轮结束后还有下一轮（包括加赛），游戏开始前为 `false`
+    pub fn has_next_round(&self) -> bool {
+        match &self.state {
+            // 放弃的一轮总是要重新开始
+            RoomState::Started {
+                stage: Stage::Aborted,
+                ..
+            } => true,
+            RoomState::Started {
+                current_round,
+                results,
+                ..
+            } => has_next_round(&self.settings, *current_round, results),
+            _ => false,
+        }
+    }
+
+    /// 下一次开始的轮次的设置
+    pub fn next_round_settings(&self) -> RoundSettings {
+        self.settings.round(self.next_round())
+    }
+
+    /// 下一次开始的轮次（从 0 开始），重新开始放弃的一轮时是当前轮次
+    pub fn next_round(&self) -> u32 {
+        match &self.state {
+            RoomState::Started {
+                current_round,
+                stage: Stage::Aborted,
+                ..
+            } => *current_round,
+            RoomState::Started { current_round, .. } => *current_round + 1,
+            _ => 0,
+        }
+    }
+
+    /// 当前房间状态
+    pub fn state(&self) -> &RoomState {
+        &self.state
+    }
+
+    /// 游戏进行中时当前的轮次（从 0 开始），游戏开始前为空
+    pub fn current_round(&self) -> Option<u32> {
+        match &self.state {
+            RoomState::Started { current_round, .. } => Some(*current_round),
+            _ => None,
+        }
+    }
+
+    /// 游戏进行中时当前轮的阶段，游戏开始前为空
+    pub fn stage(&self) -> Option<&Stage> {
+        match &self.state {
+            RoomState::Started { stage, .. } => Some(stage),
+            _ => None,
+        }
+    }
+
+    /// 本局已经结束的轮数和总轮数，用于显示进度。加赛和无尽模式下总轮数随进行中的轮次增加，
+    /// 游戏开始前已经结束的轮数为 0
+    pub fn progress(&self) -> (u32, u32) {
+        match &self.state {
+            RoomState::Started {
+                current_round,
+                results,
+                ..
+            } => {
+                let done = results.len() as u32;
+                (done, self.settings.rounds.max(*current_round + 1))
+            }
+            _ => (0, self.settings.rounds),
+        }
+    }
+
+    /// 为整局游戏的所有轮次预留结果空间，避免多轮模拟时反复扩容。
+    /// 游戏开始前调用时，之后每局游戏开始时都会预留。
+    pub fn reserve_rounds(&mut self) {
+        let rounds = self.settings.rounds as usize;
+        match &mut self.state {
+            RoomState::Started { results, .. } => {
+                results.reserve_exact(rounds.saturating_sub(results.len()))
+            }
+            _ => self.reserve_rounds = true,
+        }
+    }
+
+    /// 接收挑战者，没有开启 [`Settings::self_play`] 时主持人不能作为挑战者
+    pub fn accept_contestant(&mut self, contestant: Uuid) -> Result<()> {
+        if contestant == self.host && !self.settings.self_play {
+            return Err(Error::HostAsContestant);
+        }
+        if let (RoomState::Created, false) = (&self.state, self.is_spectator(&contestant)) {
+            self.proposed_settings = None;
+            self.state = RoomState::Joined {
+                contestant,
+                ready: false,
+            };
+            self.emit(RoomEvent::ContestantJoined { contestant });
+            Ok(())
+        } else {
+            Err(Error::InvalidOperation)
+        }
+    }
+
+    /// 踢出挑战者
+    pub fn kick_contestant(&mut self) -> Result<()> {
+        if matches!(
+            self.state,
+            RoomState::Joined { .. } | RoomState::Started { .. }
+        ) {
+            self.state = RoomState::Created;
+            self.proposed_settings = None;
+            self.emit(RoomEvent::ContestantKicked);
+            Ok(())
+        } else {
+            Err(Error::InvalidOperation)
+        }
+    }
+
+    /// 挑战者就绪
+    pub fn contestant_ready(&mut self, ready: bool) -> Result<()> {
+        match &mut self.state {
+            RoomState::Joined { ready: r, .. } => {
+                *r = ready;
+                self.emit(RoomEvent::Ready { ready });
+                Ok(())
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 更新设置。挑战者已经进入房间时设置成为提议，由挑战者确认后才生效，
+    /// 返回 `true` 表示需要通知挑战者确认
+    pub fn update_settings(&mut self, settings: Settings) -> Result<bool> {
+        settings.validate()?;
+        match &mut self.state {
+            RoomState::Created => {
+                self.settings = settings.clone();
+                self.emit(RoomEvent::SettingsUpdated { settings });
+                Ok(false)
+            }
+            RoomState::Joined { .. } => {
+                // 提议与当前配置相同时撤回之前的提议
+                if self.settings == settings {
+                    if self.proposed_settings.take().is_some() {
+                        self.emit(RoomEvent::ProposalWithdrawn);
+                    }
+                    Ok(false)
+                } else {
+                    self.proposed_settings = Some(settings.clone());
+                    self.emit(RoomEvent::SettingsProposed { settings });
+                    Ok(true)
+                }
+            }
+            RoomState::Started { .. } => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 挑战者确认或者拒绝主持人提议的设置，返回提议的设置
+    pub fn answer_settings(&mut self, accept: bool) -> Result<Settings> {
+        if !matches!(self.state, RoomState::Joined { .. }) {
+            return Err(Error::InvalidOperation);
+        }
+        let settings = self
+            .proposed_settings
+            .take()
+            .ok_or(Error::InvalidOperation)?;
+        if accept {
+            self.settings = settings.clone();
+            self.emit(RoomEvent::SettingsUpdated {
+                settings: settings.clone(),
+            });
+        } else {
+            self.emit(RoomEvent::SettingsRejected {
+                settings: settings.clone(),
+            });
+        }
+        Ok(settings)
+    }
+
+    /// 当前交换，没有开启 [`Settings::shared_entropy`] 或者主持人还没有提交种子时为空
+    pub fn exchange(&self) -> Option<&Exchange> {
+        self.exchange.as_ref()
+    }
+
+    /// 等待开始下一轮：挑战者已经进入还没有开始，或者上一轮已经结束或者放弃且还有下一轮
+    fn awaits_round(&self) -> bool {
+        match &self.state {
+            RoomState::Joined { .. } => true,
+            RoomState::Started {
+                stage: Stage::End | Stage::Aborted,
+                ..
+            } => self.has_next_round(),
+            _ => false,
+        }
+    }
+
+    /// 主持人为下一轮提交种子，房间只公布种子的承诺，种子不能为空。挑战者提供随机串之前可以重新提交
+    pub fn commit_seed(&mut self, seed: String) -> Result<()> {
+        self.check_running()?;
+        if !self.settings.shared_entropy
+            || seed.is_empty()
+            || !self.awaits_round()
+            || self
+                .exchange
+                .as_ref()
+                .is_some_and(|exchange| exchange.nonce.is_some())
+        {
+            return Err(Error::InvalidOperation);
+        }
+        let exchange = Exchange {
+            round: self.next_round(),
+            seed,
+            nonce: None,
+            placed: false,
+        };
+        let event = RoomEvent::SeedCommitted {
+            round: exchange.round,
+            commitment: exchange.commitment(),
+        };
+        self.exchange = Some(exchange);
+        self.emit(event);
+        Ok(())
+    }
+
+    /// 挑战者在主持人提交种子之后为下一轮提供随机串，每轮只能提供一次
+    pub fn supply_nonce(&mut self, nonce: String) -> Result<()> {
+        self.check_running()?;
+        if !self.awaits_round() {
+            return Err(Error::InvalidOperation);
+        }
+        match &mut self.exchange {
+            Some(exchange) if exchange.nonce.is_none() => {
+                exchange.nonce = Some(nonce.clone());
+                let round = exchange.round;
+                self.emit(RoomEvent::NonceSupplied { round, nonce });
+                Ok(())
+            }
+            Some(_) => Err(Error::InvalidOperation),
+            None => Err(Error::ExchangeIncomplete),
+        }
+    }
+
+    /// 按交换的种子和随机串放置奖品，返回序号最小的奖品所在门序号
+    fn start_shared(&mut self) -> Result<u32> {
+        let next = self.next_round();
+        let round = self.settings.round(next);
+        let prizes = self
+            .exchange
+            .as_ref()
+            .filter(|exchange| exchange.round == next && !exchange.placed)
+            .and_then(|exchange| exchange.prizes(round, self.settings.prizes(round)))
+            .ok_or(Error::ExchangeIncomplete)?;
+        let prize = prizes[0];
+        self.place(prizes, round)?;
+        if let Some(exchange) = &mut self.exchange {
+            exchange.placed = true;
+        }
+        Ok(prize)
+    }
+
+    /// 开始游戏并将奖品随机放到门内，返回（序号最小的）奖品所在门序号。
+    /// 开启 [`Settings::shared_entropy`] 时按交换的种子和随机串放置，交换还没有完成时返回
+    /// [`Error::ExchangeIncomplete`]
+    pub fn start_random(&mut self) -> Result<u32> {
+        self.with_rng(|room, rng| room.start_random_with_rng(rng))
+    }
+
+    /// 同 [`Room::start_random`]，使用指定的随机数生成器，便于重现模拟和测试
+    pub fn start_random_with_rng<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<u32> {
+        if self.settings.shared_entropy {
+            return self.start_shared();
+        }
+        let round = self.settings.round(self.next_round());
+        let prizes = random_doors(rng, round.doors, self.settings.prizes(round), &[]);
+        let prize = prizes.iter().copied().min().unwrap_or(0);
+        self.place(prizes, round)?;
+        Ok(prize)
+    }
+
+    /// 开始游戏并将奖品放到序号指定的门内，有多个奖品时其余奖品随机放置。
+    /// 开启 [`Settings::shared_entropy`] 时主持人不能指定奖品位置
+    pub fn start(&mut self, prize: u32) -> Result<()> {
+        self.start_with(self.next_round_settings().doors, prize)
+    }
+
+    /// 同 [`Room::start`]，这一轮使用 `doors` 个门，不影响设置中其他轮次的门数。
+    /// 至少需要 2 个门，奖品数和留下的门数按这一轮的门数计算，结果记录在 [`RoundResult::doors`]
+    pub fn start_with(&mut self, doors: u32, prize: u32) -> Result<()> {
+        if self.settings.shared_entropy {
+            return Err(Error::InvalidOperation);
+        }
+        if doors < 2 {
+            return Err(Error::InvalidSettings);
+        }
+        let round = RoundSettings {
+            doors,
+            ..self.next_round_settings()
+        };
+        if prize >= round.doors {
+            return Err(Error::InvalidDoorIndex);
+        }
+        let mut prizes = random_doors(
+            &mut self.rng,
+            round.doors,
+            self.settings.prizes(round) - 1,
+            &[prize],
+        );
+        prizes.push(prize);
+        self.place(prizes, round)
+    }
+
+    /// 开始游戏并将所有奖品放到序号指定的门内，门数必须等于 [`Settings::prizes`]
+    pub fn start_with_prizes(&mut self, prizes: &[u32]) -> Result<()> {
+        if self.settings.shared_entropy {
+            return Err(Error::InvalidOperation);
+        }
+        let round = self.settings.round(self.next_round());
+        if prizes.iter().any(|door| *door >= round.doors) {
+            return Err(Error::InvalidDoorIndex);
+        }
+        let mut prizes = prizes.to_vec();
+        prizes.sort_unstable();
+        prizes.dedup();
+        if prizes.len() != self.settings.prizes(round) as usize {
+            return Err(Error::InvalidOperation);
+        }
+        self.place(prizes, round)
+    }
+
+    /// 开始第一轮或者下一轮，这一轮使用 `round` 的门数，把奖品放到 `prizes` 中的门内
+    fn place(&mut self, mut prizes: Vec<u32>, round: RoundSettings) -> Result<()> {
+        self.check_running()?;
+        prizes.sort_unstable();
+        let next = self.next_round();
+        let event = RoomEvent::RoundStarted {
+            round: next,
+            prizes: prizes.clone(),
+        };
+        let override_doors =
+            (round.doors != self.settings.round(next).doors).then_some(round.doors);
+        let prize = prizes.remove(0);
+        match &mut self.state {
+            RoomState::Joined { ready, contestant } if *ready => {
+                // 开始游戏时放弃还没有确认的提议
+                self.proposed_settings = None;
+                self.state = RoomState::Started {
+                    contestant: *contestant,
+                    current_round: 0,
+                    prize,
+                    other_prizes: prizes,
+                    results: new_results(&self.settings, self.reserve_rounds),
+                    stage: Stage::Choose,
+                    paused: false,
+                    deadline: None,
+                    doors: override_doors,
+                    score: 0,
+                };
+                self.history.clear();
+                self.emit(event);
+                Ok(())
+            }
+            RoomState::Started {
+                current_round,
+                prize: p,
+                other_prizes,
+                stage,
+                results,
+                doors,
+                ..
+            } if stage.is_end() && has_next_round(&self.settings, *current_round, results) => {
+                *current_round += 1;
+                *stage = Stage::Choose;
+                *p = prize;
+                *other_prizes = prizes;
+                *doors = override_doors;
+                self.history.clear();
+                self.emit(event);
+                Ok(())
+            }
+            // 重新开始放弃的一轮，奖品重新放置
+            RoomState::Started {
+                prize: p,
+                other_prizes,
+                stage: stage @ Stage::Aborted,
+                doors,
+                ..
+            } => {
+                *stage = Stage::Choose;
+                *p = prize;
+                *other_prizes = prizes;
+                *doors = override_doors;
+                self.history.clear();
+                self.emit(event);
+                Ok(())
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 挑战者随机选择
+    pub fn choose_random(&mut self) -> Result<u32> {
+        self.with_rng(|room, rng| room.choose_random_with_rng(rng))
+    }
+
+    /// 同 [`Room::choose_random`]，使用指定的随机数生成器
+    pub fn choose_random_with_rng<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<u32> {
+        self.check_running()?;
+        let round = self.round_settings();
+        let closed = self.settings.closed(round);
+        let evil = self.settings.evil;
+        match &mut self.state {
+            RoomState::Started { stage, .. } => {
+                if let Stage::Choose = stage {
+                    let chosen = rng.gen_range(0..round.doors);
+                    self.history.push(Stage::Choose);
+                    *stage = after_choose(round, closed, evil, chosen);
+                    let revealed = revealed(stage);
+                    self.emit(RoomEvent::Chosen { chosen });
+                    if let Some(event) = revealed {
+                        self.emit(event);
+                    }
+                    Ok(chosen)
+                } else {
+                    Err(Error::InvalidOperation)
+                }
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 挑战者做出选择
+    pub fn choose(&mut self, chosen: u32) -> Result<()> {
+        self.check_running()?;
+        let round = self.round_settings();
+        let closed = self.settings.closed(round);
+        let evil = self.settings.evil;
+        if chosen >= round.doors {
+            return Err(Error::InvalidDoorIndex);
+        }
+
+        match &mut self.state {
+            RoomState::Started { stage, .. } => {
+                if let Stage::Choose = stage {
+                    self.history.push(Stage::Choose);
+                    *stage = after_choose(round, closed, evil, chosen);
+                    let revealed = revealed(stage);
+                    self.emit(RoomEvent::Chosen { chosen });
+                    if let Some(event) = revealed {
+                        self.emit(event);
+                    }
+                    Ok(())
+                } else {
+                    Err(Error::InvalidOperation)
+                }
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 主持人随机揭示，返回留下的（序号最小的）门序号，其余留下的门见 [`Stage::others`]
+    pub fn reveal_random(&mut self) -> Result<u32> {
+        self.with_rng(|room, rng| room.reveal_random_with_rng(rng))
+    }
+
+    /// 同 [`Room::reveal_random`]，使用指定的随机数生成器
+    pub fn reveal_random_with_rng<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<u32> {
+        self.check_running()?;
+        let round = self.round_settings();
+        let closed = self.settings.closed(round);
+        let forgetful = self.settings.forgetful;
+        match &mut self.state {
+            RoomState::Started {
+                stage,
+                prize,
+                other_prizes,
+                ..
+            } => {
+                if let Stage::Reveal { chosen } = stage {
+                    let chosen = *chosen;
+                    let remaining = if forgetful {
+                        let mut remaining = random_doors(rng, round.doors, closed, &[chosen]);
+                        remaining.sort_unstable();
+                        remaining
+                    } else {
+                        let prizes = prize_set(*prize, other_prizes);
+                        keep_closed(rng, round.doors, chosen, &prizes, vec![], closed)
+                    };
+                    let left = remaining[0];
+                    self.open_doors(chosen, remaining);
+                    Ok(left)
+                } else {
+                    Err(Error::InvalidOperation)
+                }
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 主持人揭示（提供留下的门序号即可），需要留下多个门时其余的门随机选择
+    pub fn reveal(&mut self, left: u32) -> Result<()> {
+        self.check_running()?;
+        let round = self.round_settings();
+        let closed = self.settings.closed(round);
+        let forgetful = self.settings.forgetful;
+        if left >= round.doors {
+            return Err(Error::InvalidDoorIndex);
+        }
+
+        match &mut self.state {
+            RoomState::Started {
+                stage,
+                prize,
+                other_prizes,
+                ..
+            } => {
+                if let Stage::Reveal { chosen } = stage {
+                    let chosen = *chosen;
+                    let remaining = if forgetful {
+                        // 健忘的主持人不避开奖品，其余留下的门随机选择
+                        let mut remaining =
+                            random_doors(&mut self.rng, round.doors, closed - 1, &[chosen, left]);
+                        remaining.push(left);
+                        remaining.sort_unstable();
+                        remaining
+                    } else {
+                        let prizes = prize_set(*prize, other_prizes);
+                        keep_closed(
+                            &mut self.rng,
+                            round.doors,
+                            chosen,
+                            &prizes,
+                            vec![left],
+                            closed,
+                        )
+                    };
+                    // 1. 不可能留下挑战者已经选择的那个门；
+                    // 2. 挑战者没有选择的奖都必须留下，否则主持人打开的门中就有奖了
+                    if left == chosen || remaining.len() > closed as usize {
+                        Err(Error::InvalidOperation)
+                    } else {
+                        self.open_doors(chosen, remaining);
+                        Ok(())
+                    }
+                } else {
+                    Err(Error::InvalidOperation)
+                }
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 主持人揭示并指定所有留下的门，门数必须等于 [`Settings::closed`]
+    pub fn reveal_doors(&mut self, left: &[u32]) -> Result<()> {
+        self.check_running()?;
+        let round = self.round_settings();
+        let closed = self.settings.closed(round);
+        let forgetful = self.settings.forgetful;
+        if left.iter().any(|door| *door >= round.doors) {
+            return Err(Error::InvalidDoorIndex);
+        }
+
+        match &mut self.state {
+            RoomState::Started {
+                stage,
+                prize,
+                other_prizes,
+                ..
+            } => {
+                if let Stage::Reveal { chosen } = stage {
+                    let chosen = *chosen;
+                    let mut remaining = left.to_vec();
+                    remaining.sort_unstable();
+                    remaining.dedup();
+                    if remaining.len() != closed as usize
+                        || remaining.contains(&chosen)
+                        || !forgetful
+                            && prize_set(*prize, other_prizes)
+                                .iter()
+                                .any(|p| *p != chosen && !remaining.contains(p))
+                    {
+                        Err(Error::InvalidOperation)
+                    } else {
+                        self.open_doors(chosen, remaining);
+                        Ok(())
+                    }
+                } else {
+                    Err(Error::InvalidOperation)
+                }
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 主持人留下 `remaining`（已排序）之后进入抉择阶段，健忘的主持人打开了有奖的门时本轮作废
+    fn open_doors(&mut self, chosen: u32, remaining: Vec<u32>) {
+        let round = self.round_settings();
+        let value = self.settings.value(round);
+        let rounds = self.settings.rounds;
+        let endless = self.settings.endless;
+        let clock = self.clock;
+        let team = self.playing_team();
+        self.history.push(Stage::Reveal { chosen });
+        if let RoomState::Started {
+            current_round,
+            prize,
+            other_prizes,
+            results,
+            stage,
+            ..
+        } = &mut self.state
+        {
+            let prizes = prize_set(*prize, other_prizes);
+            let exposed = prizes
+                .iter()
+                .find(|door| **door != chosen && !remaining.contains(door));
+            let event = match exposed {
+                Some(exposed) => {
+                    let result = RoundResult {
+                        doors: round.doors,
+                        prize: *exposed,
+                        prizes: prizes.len() as u32,
+                        chosen,
+                        left: remaining[0],
+                        closed: remaining.len() as u32,
+                        decision: Decision::Stick,
+                        win: false,
+                        value,
+                        sudden_death: !endless && *current_round >= rounds,
+                        void: true,
+                        declined: false,
+                        offer: None,
+                        team,
+                        decision_ms: None,
+                        round_ms: None,
+                        estimate: None,
+                        started_at: clock.started,
+                        choose_ms: elapsed(clock.started, clock.chosen),
+                        reveal_ms: elapsed(clock.chosen, Some(protocol::timestamp())),
+                        decide_ms: None,
+                    };
+                    results.push(result);
+                    *stage = Stage::End;
+                    Some(RoomEvent::RoundVoided { result })
+                }
+                None => {
+                    *stage = decide_stage(chosen, remaining);
+                    revealed(stage)
+                }
+            };
+            if let Some(event) = event {
+                self.emit(event);
+            }
+        }
+    }
+
+    /// 邪恶的主持人在揭示阶段拒绝给改变选择的机会，按挑战者的选择直接结束这一轮，
+    /// 不是邪恶的主持人时返回 [`Error::InvalidOperation`]
+    pub fn decline(&mut self) -> Result<RoundResult> {
+        self.check_running()?;
+        if !self.settings.evil {
+            return Err(Error::InvalidOperation);
+        }
+        let round = self.round_settings();
+        let value = self.settings.value(round);
+        let rounds = self.settings.rounds;
+        let endless = self.settings.endless;
+        let clock = self.clock;
+        let team = self.playing_team();
+        if let RoomState::Started {
+            current_round,
+            prize,
+            other_prizes,
+            results,
+            stage,
+            score,
+            ..
+        } = &mut self.state
+        {
+            let chosen = match stage {
+                Stage::Reveal { chosen } => *chosen,
+                _ => return Err(Error::InvalidOperation),
+            };
+            let prizes = prize_set(*prize, other_prizes);
+            let win = prizes.contains(&chosen);
+            let result = RoundResult {
+                doors: round.doors,
+                prize: if win { chosen } else { *prize },
+                prizes: prizes.len() as u32,
+                chosen,
+                left: chosen,
+                closed: round.doors - 1,
+                decision: Decision::Stick,
+                win,
+                value,
+                sudden_death: !endless && *current_round >= rounds,
+                void: false,
+                declined: true,
+                offer: None,
+                team,
+                decision_ms: None,
+                round_ms: None,
+                estimate: None,
+                started_at: clock.started,
+                choose_ms: elapsed(clock.started, clock.chosen),
+                reveal_ms: elapsed(clock.chosen, Some(protocol::timestamp())),
+                decide_ms: None,
+            };
+            results.push(result);
+            *score += self.rules.score(&result);
+            *stage = Stage::End;
+            self.emit(RoomEvent::Decided { result });
+            Ok(result)
+        } else {
+            Err(Error::InvalidOperation)
+        }
+    }
+
+    /// 测验模式下挑战者在抉择前估计改变选择的中奖率（百分比），每轮只能估计一次
+    pub fn estimate(&mut self, percent: u32) -> Result<()> {
+        self.check_running()?;
+        if !self.settings.quiz {
+            return Err(Error::InvalidOperation);
+        }
+        if percent > 100 {
+            return Err(Error::InvalidEstimate);
+        }
+        match &mut self.state {
+            RoomState::Started { stage, .. }
+                if matches!(
+                    stage,
+                    Stage::Decide { estimate: None, .. } | Stage::Deal { estimate: None, .. }
+                ) =>
+            {
+                self.history.push(stage.clone());
+                if let Stage::Decide { estimate, .. } | Stage::Deal { estimate, .. } = stage {
+                    *estimate = Some(percent);
+                }
+                self.emit(RoomEvent::Estimated { percent });
+                Ok(())
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 主持人在揭示之后出价买断，挑战者抉择之前可以再次出价。没有开启 [`Settings::deals`] 时
+    /// 返回 [`Error::InvalidOperation`]
+    pub fn offer_deal(&mut self, offer: u32) -> Result<()> {
+        self.check_running()?;
+        if !self.settings.deals {
+            return Err(Error::InvalidOperation);
+        }
+        match &mut self.state {
+            RoomState::Started { stage, .. } => {
+                let deal = match stage.clone() {
+                    Stage::Decide {
+                        chosen,
+                        left,
+                        others,
+                        estimate,
+                    }
+                    | Stage::Deal {
+                        chosen,
+                        left,
+                        others,
+                        estimate,
+                        ..
+                    } => Stage::Deal {
+                        chosen,
+                        left,
+                        others,
+                        estimate,
+                        offer,
+                    },
+                    _ => return Err(Error::InvalidOperation),
+                };
+                self.history.push(std::mem::replace(stage, deal));
+                self.emit(RoomEvent::DealOffered { offer });
+                Ok(())
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 挑战者做出最终抉择，主持人留下多个门时 `Switch` 换到 `left`，`SwitchTo` 换到指定的门
+    pub fn decide(&mut self, decision: Decision) -> Result<RoundResult> {
+        self.make_decision(decision, None, self.is_overdue())
+    }
+
+    /// 挑战者做出最终抉择，`latency` 是从看到揭示结果到做出抉择的时间
+    pub fn decide_with_latency(
+        &mut self,
+        decision: Decision,
+        latency: Duration,
+    ) -> Result<RoundResult> {
+        self.make_decision(
+            decision,
+            Some(latency.as_millis() as u64),
+            self.is_overdue(),
+        )
+    }
+
+    /// 记录刚抉择完的一轮从开始到抉择经过的时间，返回更新后的结果
+    pub fn time_round(&mut self, elapsed: Duration) -> Result<RoundResult> {
+        match &mut self.state {
+            RoomState::Started {
+                stage: Stage::End,
+                results,
+                ..
+            } => match results.last_mut() {
+                Some(result) => {
+                    result.round_ms = Some(elapsed.as_millis() as u64);
+                    Ok(*result)
+                }
+                None => Err(Error::InvalidOperation),
+            },
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 当前阶段已经超时
+    fn is_overdue(&self) -> bool {
+        self.deadline()
+            .is_some_and(|deadline| deadline <= protocol::timestamp())
+    }
+
+    /// `overdue` 表示抉择阶段已经超时，测验模式下不再要求估计
+    fn make_decision(
+        &mut self,
+        decision: Decision,
+        decision_ms: Option<u64>,
+        overdue: bool,
+    ) -> Result<RoundResult> {
+        self.check_running()?;
+        let round = self.round_settings();
+        let doors = round.doors;
+        let value = self.settings.value(round);
+        let rounds = self.settings.rounds;
+        let endless = self.settings.endless;
+        let quiz = self.settings.quiz && !overdue;
+        let clock = self.clock;
+        let team = self.playing_team();
+        let now = protocol::timestamp();
+        if let RoomState::Started {
+            current_round,
+            prize,
+            other_prizes,
+            ref mut results,
+            stage,
+            score,
+            ..
+        } = &mut self.state
+        {
+            let offer = match stage {
+                Stage::Deal { offer, .. } => Some(*offer),
+                _ => None,
+            };
+            let result = match stage {
+                Stage::Decide { estimate: None, .. } | Stage::Deal { estimate: None, .. }
+                    if quiz =>
+                {
+                    return Err(Error::EstimateRequired)
+                }
+                Stage::Decide {
+                    chosen,
+                    left,
+                    others,
+                    estimate,
+                }
+                | Stage::Deal {
+                    chosen,
+                    left,
+                    others,
+                    estimate,
+                    ..
+                } => {
+                    // 换到指定的门只能是主持人留下的门，不能是打开的门或者原来选择的门；
+                    // 只能接受主持人当前的出价
+                    let (decision, left) = match decision {
+                        Decision::SwitchTo(door) if door >= doors => {
+                            return Err(Error::InvalidDoorIndex)
+                        }
+                        Decision::SwitchTo(door) if door == *left || others.contains(&door) => {
+                            (Decision::Switch, door)
+                        }
+                        Decision::SwitchTo(door) => return Err(Error::DoorNotLeft { door }),
+                        Decision::TakeDeal(amount) if offer != Some(amount) => {
+                            return Err(Error::InvalidOperation)
+                        }
+                        decision => (decision, *left),
+                    };
+                    let prizes = prize_set(*prize, other_prizes);
+                    let win_the_prize = match decision {
+                        Decision::Stick => prizes.contains(chosen),
+                        Decision::TakeDeal(_) => false,
+                        _ => prizes.contains(&left),
+                    };
+                    let prize = [*chosen, left]
+                        .into_iter()
+                        .find(|door| prizes.contains(door))
+                        .unwrap_or(*prize);
+                    RoundResult {
+                        doors,
+                        prize,
+                        prizes: prizes.len() as u32,
+                        chosen: *chosen,
+                        left,
+                        closed: 1 + others.len() as u32,
+                        decision,
+                        win: win_the_prize,
+                        value,
+                        sudden_death: !endless && *current_round >= rounds,
+                        void: false,
+                        declined: false,
+                        offer,
+                        team,
+                        decision_ms,
+                        round_ms: None,
+                        estimate: *estimate,
+                        started_at: clock.started,
+                        choose_ms: elapsed(clock.started, clock.chosen),
+                        reveal_ms: elapsed(clock.chosen, clock.revealed),
+                        decide_ms: elapsed(clock.revealed, Some(now)),
+                    }
+                }
+                _ => return Err(Error::InvalidOperation),
+            };
+
+            results.push(result);
+            *score += self.rules.score(&result);
+            *stage = Stage::End;
+            self.emit(RoomEvent::Decided { result });
+            Ok(result)
+        } else {
+            Err(Error::InvalidOperation)
+        }
+    }
+
+    /// 撤销本轮最近的一次操作（选择、揭示或者估计），恢复到操作之前的阶段，可以连续撤销到本轮开始。
+    /// 抉择之后本轮已经有了结果，不能再撤销
+    pub fn undo(&mut self) -> Result<()> {
+        self.check_running()?;
+        match &mut self.state {
+            RoomState::Started { stage, .. } if !stage.is_end() => {
+                let previous = self.history.pop().ok_or(Error::InvalidOperation)?;
+                *stage = previous.clone();
+                self.emit(RoomEvent::Undone { stage: previous });
+                Ok(())
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 放弃进行中的一轮，例如本轮有争议时。本轮不产生结果，也不影响已经完成的轮次，
+    /// 之后主持人重新开始的仍然是这一轮
+    pub fn abort_round(&mut self) -> Result<()> {
+        self.check_running()?;
+        match &mut self.state {
+            RoomState::Started {
+                current_round,
+                stage,
+                ..
+            } if !stage.is_end() && !matches!(stage, Stage::Aborted) => {
+                let round = *current_round;
+                *stage = Stage::Aborted;
+                self.history.clear();
+                self.emit(RoomEvent::RoundAborted { round });
+                Ok(())
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 把进行中的一轮退回挑战者选择，奖品位置不变，例如服务器崩溃后无法确认本轮的进度时。
+    /// 暂停中也可以退回，本轮还没有选择时返回 [`Error::InvalidOperation`]
+    pub fn rollback_round(&mut self) -> Result<()> {
+        match &mut self.state {
+            RoomState::Started {
+                current_round,
+                stage: stage @ (Stage::Reveal { .. } | Stage::Decide { .. } | Stage::Deal { .. }),
+                ..
+            } => {
+                let round = *current_round;
+                *stage = Stage::Choose;
+                self.history.clear();
+                self.emit(RoomEvent::RoundRolledBack { round });
+                Ok(())
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 暂停进行中的游戏，例如挑战者暂时断开连接时。暂停期间不能进行游戏操作，
+    /// 可以提前结束本局
+    pub fn pause(&mut self) -> Result<()> {
+        match &mut self.state {
+            RoomState::Started { paused, .. } if !*paused => {
+                *paused = true;
+                self.emit(RoomEvent::Paused);
+                Ok(())
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 继续暂停的游戏
+    pub fn resume(&mut self) -> Result<()> {
+        match &mut self.state {
+            RoomState::Started { paused, .. } if *paused => {
+                *paused = false;
+                self.emit(RoomEvent::Resumed);
+                Ok(())
+            }
+            _ => Err(Error::InvalidOperation),
+        }
+    }
+
+    /// 暂停中不能进行游戏操作
+    fn check_running(&self) -> Result<()> {
+        if self.is_paused() {
+            Err(Error::Paused)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// 完成本局游戏并输出每局结果，需要加赛时不能完成。无尽模式下每轮结束或者放弃后都可以完成
+    pub fn complete(&mut self, kick_contestant: bool) -> Result<Vec<RoundResult>> {
+        self.check_running()?;
+        let endless = self.settings.endless;
+        let new_state = match &mut self.state {
+            RoomState::Started {
+                contestant,
+                current_round,
+                stage,
+                results,
+                ..
+            } if (endless && matches!(stage, Stage::End | Stage::Aborted))
+                || (stage.is_end() && !has_next_round(&self.settings, *current_round, results)) =>
+            {
+                if kick_contestant {
+                    RoomState::Created
+                } else {
+                    RoomState::Joined {
+                        contestant: *contestant,
+                        ready: false,
+                    }
+                }
+            }
+            _ => return Err(Error::InvalidOperation),
+        };
+
+        match std::mem::replace(&mut self.state, new_state) {
+            RoomState::Started { results, .. } => {
+                self.emit(RoomEvent::Completed {
+                    rounds: results.len() as u32,
+                    forced: false,
+                });
+                if kick_contestant {
+                    self.emit(RoomEvent::ContestantKicked);
+                }
+                Ok(results)
+            }
+            _ => Err(Error::Impossible),
+        }
+    }
+
+    /// 在任意阶段强制结束本局游戏，只输出已经完成的轮次，挑战者保留在房间中
+    pub fn force_complete(&mut self) -> Result<Vec<RoundResult>> {
+        self.complete_early(EarlyEnd::Forced)
+    }
+
+    /// 在任意阶段提前结束本局游戏，只输出已经完成的轮次。
+    /// 挑战者离开时同时移出挑战者，其他原因时挑战者保留在房间中
+    pub fn complete_early(&mut self, reason: EarlyEnd) -> Result<Vec<RoundResult>> {
+        let new_state = match &self.state {
+            RoomState::Started { .. } if reason == EarlyEnd::ContestantLeft => RoomState::Created,
+            RoomState::Started { contestant, .. } => RoomState::Joined {
+                contestant: *contestant,
+                ready: false,
+            },
+            _ => return Err(Error::InvalidOperation),
+        };
+
+        match std::mem::replace(&mut self.state, new_state) {
+            RoomState::Started { results, .. } => {
+                self.history.clear();
+                self.emit(RoomEvent::Completed {
+                    rounds: results.len() as u32,
+                    forced: true,
+                });
+                if reason == EarlyEnd::ContestantLeft {
+                    self.emit(RoomEvent::ContestantKicked);
+                }
+                Ok(results)
+            }
+            _ => Err(Error::Impossible),
+        }
+    }
+}
+
+/// 房间的快照，恢复时检查不变量，不信任任意反序列化得到的状态
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct RoomSnapshot {
+    pub id: Uuid,
+    pub host: Uuid,
+    #[serde(default)]
+    pub co_hosts: Vec<Uuid>,
+    pub settings: Settings,
+    pub state: RoomState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposed_settings: Option<Settings>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exchange: Option<Exchange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teams: Vec<Team>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spectators: Vec<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl RoomSnapshot {
+    /// 检查快照是否是房间操作能够到达的状态
+    fn validate(&self) -> Result<()> {
+        let settings = &self.settings;
+        settings.validate().map_err(|_| Error::InvalidSnapshot)?;
+        if let Some(proposed) = &self.proposed_settings {
+            proposed.validate().map_err(|_| Error::InvalidSnapshot)?;
+            ensure(matches!(self.state, RoomState::Joined { .. }))?;
+        }
+        let mut co_hosts = self.co_hosts.clone();
+        co_hosts.sort_unstable();
+        co_hosts.dedup();
+        ensure(co_hosts.len() == self.co_hosts.len() && !co_hosts.contains(&self.host))?;
+        let valid_details = |text: &Option<String>, max| match text {
+            Some(text) => details_text(text, max).ok().as_ref() == Some(text),
+            None => true,
+        };
+        ensure(
+            valid_details(&self.name, MAX_ROOM_NAME_LEN)
+                && valid_details(&self.description, MAX_ROOM_DESCRIPTION_LEN),
+        )?;
+        let mut spectators = self.spectators.clone();
+        spectators.sort_unstable();
+        spectators.dedup();
+        ensure(
+            spectators.len() == self.spectators.len()
+                && spectators
+                    .iter()
+                    .all(|id| *id != self.host && !self.co_hosts.contains(id)),
+        )?;
+        self.validate_teams()?;
+        if let Some(exchange) = &self.exchange {
+            self.validate_exchange(exchange)?;
+        }
+
+        let (contestant, current_round, prize, other_prizes, results, stage, doors) = match &self
+            .state
+        {
+            RoomState::Created => return Ok(()),
+            RoomState::Joined { contestant, .. } => return self.validate_contestant(contestant),
+            RoomState::Started {
+                contestant,
+                current_round,
+                prize,
+                other_prizes,
+                results,
+                stage,
+                doors,
+                ..
+            } => (
+                contestant,
+                *current_round,
+                *prize,
+                other_prizes,
+                results,
+                stage,
+                *doors,
+            ),
+        };
+        self.validate_contestant(contestant)?;
+
+        ensure(current_round < settings.rounds || settings.sudden_death || settings.endless)?;
+        let finished = current_round as usize + usize::from(stage.is_end());
+        ensure(results.len() == finished)?;
+        // 主持人可以为某一轮指定门数，已经结束的轮次只检查结果本身
+        for result in results {
+            let doors = result.doors;
+            ensure(
+                doors >= 2
+                    && result.prize < doors
+                    && result.chosen < doors
+                    && result.left < doors
+                    && (result.chosen != result.left || result.declined)
+                    && !(result.void && (result.win || result.declined)),
+            )?;
+        }
+
+        let round = match doors {
+            Some(doors) => {
+                ensure(doors >= 2 && doors != settings.round(current_round).doors)?;
+                RoundSettings::new(doors)
+            }
+            None => settings.round(current_round),
+        };
+        if stage.is_end() {
+            ensure(
+                results
+                    .last()
+                    .is_some_and(|result| result.doors == round.doors),
+            )?;
+        }
+        // 插件规则的得分只有加载了插件的服务器能够计算，只检查内置规则的得分
+        let builtin = settings.rules.as_deref().map_or(true, |name| {
+            builtin_rules().iter().any(|rules| rules.name() == name)
+        });
+        if let RoomState::Started { score, .. } = &self.state {
+            ensure(!builtin || *score == results.iter().map(|result| result.score() as u64).sum())?;
+        }
+        let prizes = prize_set(prize, other_prizes);
+        ensure(prizes.len() == settings.prizes(round) as usize)?;
+        ensure(is_ascending_doors(&prizes, round.doors))?;
+
+        match stage {
+            Stage::Choose | Stage::End | Stage::Aborted => Ok(()),
+            Stage::Reveal { chosen } => ensure(
+                *chosen < round.doors
+                    && (settings.closed(round) + 1 < round.doors || settings.evil),
+            ),
+            Stage::Deal { .. } if !settings.deals => Err(Error::InvalidSnapshot),
+            Stage::Decide {
+                chosen,
+                left,
+                others,
+                estimate,
+            }
+            | Stage::Deal {
+                chosen,
+                left,
+                others,
+                estimate,
+                ..
+            } => {
+                let remaining = prize_set(*left, others);
+                ensure(
+                    *chosen < round.doors
+                        && is_ascending_doors(&remaining, round.doors)
+                        && remaining.len() == settings.closed(round) as usize
+                        && !remaining.contains(chosen)
+                        && prizes.iter().all(|p| p == chosen || remaining.contains(p))
+                        && !estimate.is_some_and(|percent| percent > 100),
+                )
+            }
+        }
+    }
+
+    /// 还没有放置奖品的交换属于下一轮，已经放置的交换属于进行中的一轮且和奖品位置一致
+    fn validate_exchange(&self, exchange: &Exchange) -> Result<()> {
+        ensure(self.settings.shared_entropy)?;
+        match &self.state {
+            RoomState::Created => Err(Error::InvalidSnapshot),
+            RoomState::Joined { .. } => ensure(!exchange.placed && exchange.round == 0),
+            RoomState::Started {
+                current_round,
+                prize,
+                other_prizes,
+                stage,
+                ..
+            } if exchange.placed => {
+                let round = self.settings.round(*current_round);
+                ensure(
+                    exchange.round == *current_round
+                        && !matches!(stage, Stage::End | Stage::Aborted)
+                        && exchange.prizes(round, self.settings.prizes(round))
+                            == Some(prize_set(*prize, other_prizes)),
+                )
+            }
+            RoomState::Started {
+                current_round,
+                stage,
+                ..
+            } => ensure(match stage {
+                Stage::End => exchange.round == *current_round + 1,
+                Stage::Aborted => exchange.round == *current_round,
+                _ => false,
+            }),
+        }
+    }
+
+    /// 团队名称不为空且互不相同，每个用户最多属于一个团队，主持人不属于任何团队
+    fn validate_teams(&self) -> Result<()> {
+        let mut names: Vec<&str> = self.teams.iter().map(|team| team.name.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+        let mut ids: Vec<Uuid> = self
+            .teams
+            .iter()
+            .flat_map(|team| std::iter::once(team.captain).chain(team.members.iter().copied()))
+            .collect();
+        let count = ids.len();
+        ids.sort_unstable();
+        ids.dedup();
+        ensure(
+            names.len() == self.teams.len()
+                && self.teams.iter().all(|team| !team.name.is_empty())
+                && ids.len() == count
+                && ids
+                    .iter()
+                    .all(|id| *id != self.host && !self.co_hosts.contains(id)),
+        )
+    }
+
+    fn validate_contestant(&self, contestant: &Uuid) -> Result<()> {
+        ensure(
+            (*contestant != self.host || self.settings.self_play)
+                && !self.co_hosts.contains(contestant)
+                && !self.spectators.contains(contestant),
+        )
+    }
+}
+
+/// 房间名称的最大字符数
+pub const MAX_ROOM_NAME_LEN: usize = 64;
+
+/// 房间介绍的最大字符数
+pub const MAX_ROOM_DESCRIPTION_LEN: usize = 500;
+
+/// 去掉首尾空白之后的房间名称或介绍，为空或者超过 `max` 个字符时无效
+fn details_text(text: &str, max: usize) -> Result<String> {
+    let text = text.trim();
+    if text.is_empty() || text.chars().count() > max {
+        Err(Error::InvalidDetails)
+    } else {
+        Ok(text.to_string())
+    }
+}
+
+/// 不满足不变量时快照无效
+fn ensure(valid: bool) -> Result<()> {
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidSnapshot)
+    }
+}
+
+/// 门序号严格递增且都小于门数
+fn is_ascending_doors(doors: &[u32], count: u32) -> bool {
+    doors.windows(2).all(|pair| pair[0] < pair[1]) && doors.iter().all(|door| *door < count)
+}
+
+/// 挑战者选择后的阶段，主持人没有可以打开的门时（例如 2 个门）跳过揭示，留下其余所有的门。
+/// 邪恶的主持人总是进入揭示阶段，由主持人决定是否给改变选择的机会
+fn after_choose(round: RoundSettings, closed: u32, evil: bool, chosen: u32) -> Stage {
+    if closed + 1 >= round.doors && !evil {
+        let remaining = (0..round.doors).filter(|door| *door != chosen).collect();
+        decide_stage(chosen, remaining)
+    } else {
+        Stage::Reveal { chosen }
+    }
+}
+
+/// 进入抉择阶段时的揭示事件
+fn revealed(stage: &Stage) -> Option<RoomEvent> {
+    match stage {
+        Stage::Decide { left, others, .. } => Some(RoomEvent::Revealed {
+            left: *left,
+            others: others.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// 主持人留下 `remaining`（已排序）之后的抉择阶段
+fn decide_stage(chosen: u32, mut remaining: Vec<u32>) -> Stage {
+    let left = remaining.remove(0);
+    Stage::Decide {
+        chosen,
+        left,
+        others: remaining,
+        estimate: None,
+    }
+}
+
+/// 主持人揭示后保持关闭的门（不包括挑战者选择的门）：先放入 `keep`，再放入挑战者没有选中的奖品，
+/// 不足 `closed` 个时从其余的门中随机补足，按序号排序
+fn keep_closed<R: Rng + ?Sized>(
+    rng: &mut R,
+    doors: u32,
+    chosen: u32,
+    prizes: &[u32],
+    mut keep: Vec<u32>,
+    closed: u32,
+) -> Vec<u32> {
+    for prize in prizes {
+        if *prize != chosen && !keep.contains(prize) {
+            keep.push(*prize);
+        }
+    }
+    let mut exclusive = keep.clone();
+    exclusive.push(chosen);
+    let count = (closed as usize).saturating_sub(keep.len()) as u32;
+    keep.extend(random_doors(rng, doors, count, &exclusive));
+    keep.sort_unstable();
+    keep
+}
+
+/// 从 `doors` 个门中随机选择 `count` 个不在 `exclusive` 中的门，没有那么多时全部选择
+fn random_doors<R: Rng + ?Sized>(
+    rng: &mut R,
+    doors: u32,
+    count: u32,
+    exclusive: &[u32],
+) -> Vec<u32> {
+    let mut candidates: Vec<u32> = (0..doors)
+        .filter(|door| !exclusive.contains(door))
+        .collect();
+    let mut chosen = vec![];
+    while chosen.len() < count as usize && !candidates.is_empty() {
+        let index = rng.gen_range(0..candidates.len());
+        chosen.push(candidates.swap_remove(index));
+    }
+    chosen
+}
+
+/// 一轮中所有奖品所在的门
+fn prize_set(prize: u32, other_prizes: &[u32]) -> Vec<u32> {
+    let mut prizes = vec![prize];
+    prizes.extend_from_slice(other_prizes);
+    prizes
+}
+
+/// 本轮结束后是否还有下一轮：无尽模式，规定轮数没打完，或者需要加赛
+fn has_next_round(settings: &Settings, current_round: u32, results: &[RoundResult]) -> bool {
+    settings.endless || current_round < settings.rounds - 1 || needs_tiebreak(settings, results)
+}
+
+/// 开启了加赛（无尽模式下不加赛），规定轮数已经打完，且挑战者赢的轮数与输的轮数相同，作废的轮次不算输赢
+fn needs_tiebreak(settings: &Settings, results: &[RoundResult]) -> bool {
+    let decided = results.iter().filter(|result| !result.void).count();
+    !settings.endless
+        && settings.sudden_death
+        && results.len() >= settings.rounds as usize
+        && results.iter().filter(|result| result.win).count() * 2 == decided
+}
+
+/// 新一局游戏的结果列表
+fn new_results(settings: &Settings, reserve: bool) -> Vec<RoundResult> {
+    if reserve {
+        Vec::with_capacity(settings.rounds as usize)
+    } else {
+        vec![]
+    }
+}
+
+/// 一组轮次的统计，作废的轮次只计入 `void`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct Stats {
+    /// 轮数
+    rounds: u32,
+    /// 健忘的主持人打开了有奖的门而作废的轮数
+    #[serde(default, skip_serializing_if = "is_zero")]
+    void: u32,
+    /// 邪恶的主持人没有给改变选择机会的轮数，这些轮次不计入改变或者坚持选择
+    #[serde(default, skip_serializing_if = "is_zero")]
+    declined: u32,
+    /// 其中赢的轮数
+    #[serde(default, skip_serializing_if = "is_zero")]
+    declined_win: u32,
+    /// 接受买断的轮数，这些轮次不计入改变或者坚持选择
+    #[serde(default, skip_serializing_if = "is_zero")]
+    deals: u32,
+    /// 接受的出价的总和
+    #[serde(default, skip_serializing_if = "is_zero")]
+    deal_value: u64,
+    /// 赢的轮数
+    win: u32,
+    /// 选择时就选了正确选项
+    chosen_win: u32,
+    /// 主持人留下的是正确选项
+    left_win: u32,
+    /// 主持人留下的门后的奖品数
+    #[serde(default, skip_serializing_if = "is_zero")]
+    left_prizes: u32,
+    /// 改变选择的次数
+    switch: u32,
+    /// 坚持选择的次数
+    stick: u32,
+    /// 改变选择后赢的次数
+    switch_win: u32,
+    /// 坚持选择后赢的次数
+    stick_win: u32,
+    /// 有用时记录的轮数
+    #[serde(default, skip_serializing_if = "is_zero")]
+    timed_rounds: u32,
+    /// 这些轮次从开始到抉择的总毫秒数
+    #[serde(default, skip_serializing_if = "is_zero")]
+    round_ms: u64,
+    /// 其中赢的轮数
+    #[serde(default, skip_serializing_if = "is_zero")]
+    timed_win: u32,
+}
+
+fn is_zero<T: Default + PartialEq>(n: &T) -> bool {
+    *n == T::default()
+}
+
+impl Stats {
+    /// 统计任意一组轮次，例如跨越多局或者多个房间的轮次
+    pub fn calculate<'a, I>(results: I) -> Self
+    where
+        I: IntoIterator<Item = &'a RoundResult>,
+    {
+        let mut stats = Stats::default();
+        for result in results {
+            stats.add(result);
+        }
+        stats
+    }
+
+    fn add(&mut self, result: &RoundResult) {
+        if result.void {
+            self.void += 1;
+            return;
+        }
+        self.rounds += 1;
+
+        if let Some(ms) = result.round_ms {
+            self.timed_rounds += 1;
+            self.round_ms += ms;
+            if result.win {
+                self.timed_win += 1;
+            }
+        }
+
+        if result.chosen == result.prize {
+            self.chosen_win += 1;
+        }
+
+        if result.declined {
+            self.declined += 1;
+            if result.win {
+                self.win += 1;
+                self.declined_win += 1;
+            }
+            return;
+        }
+
+        // 主持人不会打开有奖的门，挑战者没有选中的奖品一定在留下的门中
+        if result.left_prizes() > 0 {
+            self.left_win += 1;
+        }
+        self.left_prizes += result.left_prizes();
+
+        match result.decision {
+            Decision::Switch | Decision::SwitchTo(_) => {
+                self.switch += 1;
+                if result.win {
+                    self.win += 1;
+                    self.switch_win += 1;
+                }
+            }
+            Decision::Stick => {
+                self.stick += 1;
+                if result.win {
+                    self.win += 1;
+                    self.stick_win += 1;
+                }
+            }
+            Decision::TakeDeal(value) => {
+                self.deals += 1;
+                self.deal_value += value as u64;
+            }
+        }
+    }
+
+    /// 轮数，不包括作废的轮次
+    pub fn rounds(&self) -> u32 {
+        self.rounds
+    }
+
+    /// 作废的轮数
+    pub fn void(&self) -> u32 {
+        self.void
+    }
+
+    /// 邪恶的主持人没有给改变选择机会的轮数
+    pub fn declined(&self) -> u32 {
+        self.declined
+    }
+
+    /// 其中赢的轮数
+    pub fn declined_win(&self) -> u32 {
+        self.declined_win
+    }
+
+    /// 接受买断的轮数
+    pub fn deals(&self) -> u32 {
+        self.deals
+    }
+
+    /// 接受的出价的总和
+    pub fn deal_value(&self) -> u64 {
+        self.deal_value
+    }
+
+    /// 中奖率，没有轮次时为 0
+    pub fn win_rate(&self) -> f64 {
+        if self.rounds == 0 {
+            0.0
+        } else {
+            self.win as f64 / self.rounds as f64
+        }
+    }
+
+    /// 改变选择的比例，没有轮次时为 0
+    pub fn switch_rate(&self) -> f64 {
+        if self.rounds == 0 {
+            0.0
+        } else {
+            self.switch as f64 / self.rounds as f64
+        }
+    }
+
+    /// 赢的轮数
+    pub fn win(&self) -> u32 {
+        self.win
+    }
+
+    /// 有用时记录的轮次从开始到抉择的平均毫秒数，没有记录时为空
+    pub fn mean_round_ms(&self) -> Option<u64> {
+        self.round_ms.checked_div(u64::from(self.timed_rounds))
+    }
+
+    /// 每赢一轮平均花费的毫秒数（有用时记录的轮次的总用时除以其中赢的轮数），
+    /// 同时反映速度和准确率，越小越好，没有赢过时为空
+    pub fn ms_per_win(&self) -> Option<u64> {
+        self.round_ms.checked_div(u64::from(self.timed_win))
+    }
+
+    /// 选择时就选了正确选项
+    pub fn chosen_win(&self) -> u32 {
+        self.chosen_win
+    }
+
+    /// 主持人留下的是正确选项
+    pub fn left_win(&self) -> u32 {
+        self.left_win
+    }
+
+    /// 主持人留下的门后的奖品数
+    pub fn left_prizes(&self) -> u32 {
+        self.left_prizes
+    }
+
+    /// 改变选择的次数
+    pub fn switch(&self) -> u32 {
+        self.switch
+    }
+
+    /// 坚持选择的次数
+    pub fn stick(&self) -> u32 {
+        self.stick
+    }
+
+    /// 改变选择后赢的次数
+    pub fn switch_win(&self) -> u32 {
+        self.switch_win
+    }
+
+    /// 坚持选择后赢的次数
+    pub fn stick_win(&self) -> u32 {
+        self.stick_win
+    }
+}
+
+/// 门数相同的轮次的统计
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct DoorsStats {
+    /// 门数
+    doors: u32,
+    #[serde(flatten)]
+    stats: Stats,
+}
+
+impl DoorsStats {
+    /// 门数
+    pub fn doors(&self) -> u32 {
+        self.doors
+    }
+
+    /// 这些轮次的统计
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+}
+
+/// 团队模式下一个团队进行的轮次的统计
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct TeamStats {
+    /// 团队的序号
+    team: u32,
+    #[serde(flatten)]
+    stats: Stats,
+}
+
+impl TeamStats {
+    /// 团队的序号，见 [`Room::teams`]
+    pub fn team(&self) -> u32 {
+        self.team
+    }
+
+    /// 这些轮次的统计
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+}
+
+/// 按抉择用时分组的统计，以中位数为界分成快、慢两组
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct LatencyStats {
+    /// 抉择用时的中位数（毫秒），不超过它的轮次算作快
+    median_ms: u64,
+    /// 抉择快的轮次
+    fast: Stats,
+    /// 抉择慢的轮次
+    slow: Stats,
+}
+
+impl LatencyStats {
+    /// 统计有抉择用时的轮次，全都没有时返回 `None`
+    fn calculate(results: &[RoundResult]) -> Option<Self> {
+        let mut latencies: Vec<_> = results
+            .iter()
+            .filter_map(|result| result.decision_ms)
+            .collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort_unstable();
+        let median_ms = latencies[(latencies.len() - 1) / 2];
+
+        let mut stats = LatencyStats {
+            median_ms,
+            fast: Stats::default(),
+            slow: Stats::default(),
+        };
+        for result in results {
+            match result.decision_ms {
+                Some(ms) if ms <= median_ms => stats.fast.add(result),
+                Some(_) => stats.slow.add(result),
+                None => {}
+            }
+        }
+        Some(stats)
+    }
+
+    /// 抉择用时的中位数（毫秒）
+    pub fn median_ms(&self) -> u64 {
+        self.median_ms
+    }
+
+    /// 抉择快的轮次
+    pub fn fast(&self) -> &Stats {
+        &self.fast
+    }
+
+    /// 抉择慢的轮次
+    pub fn slow(&self) -> &Stats {
+        &self.slow
+    }
+}
+
+/// 测验模式下挑战者的估计与实际的对比
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct QuizReport {
+    /// 有估计的轮数
+    estimates: u32,
+    /// 估计的改变选择中奖率的平均值（0 到 1）
+    mean_estimate: f64,
+    /// 这些轮次改变选择中奖率的理论值的平均值，`n` 个门 `p` 个奖品留下 `k` 个时为 `p * (n - 1) / (n * k)`，
+    /// 健忘的主持人没有打开有奖的门时是这个条件下的概率，1 个奖品时为 `1 / (k + 1)`
+    expected: f64,
+    /// 这些轮次中改变选择会中奖的比例，即留下的门中有奖的比例
+    observed: f64,
+    /// 估计与理论值之差的绝对值的平均值
+    mean_error: f64,
+}
+
+impl QuizReport {
+    /// 统计有估计的轮次，全都没有时返回 `None`，`forgetful` 表示主持人是健忘的
+    fn calculate(forgetful: bool, results: &[RoundResult]) -> Option<Self> {
+        let mut report = QuizReport {
+            estimates: 0,
+            mean_estimate: 0.0,
+            expected: 0.0,
+            observed: 0.0,
+            mean_error: 0.0,
+        };
+        for result in results {
+            let estimate = match result.estimate {
+                Some(percent) => percent as f64 / 100.0,
+                None => continue,
+            };
+            let expected = if forgetful {
+                forgetful_switch_rate(result.doors, result.prizes, result.closed)
+            } else {
+                (result.prizes * (result.doors - 1)) as f64
+                    / (result.doors as f64 * result.closed as f64)
+            };
+            report.estimates += 1;
+            report.mean_estimate += estimate;
+            report.expected += expected;
+            report.observed += result.left_prizes() as f64 / result.closed as f64;
+            report.mean_error += (estimate - expected).abs();
+        }
+        if report.estimates == 0 {
+            return None;
+        }
+        let n = report.estimates as f64;
+        report.mean_estimate /= n;
+        report.expected /= n;
+        report.observed /= n;
+        report.mean_error /= n;
+        Some(report)
+    }
+
+    /// 有估计的轮数
+    pub fn estimates(&self) -> u32 {
+        self.estimates
+    }
+
+    /// 估计的平均值（0 到 1）
+    pub fn mean_estimate(&self) -> f64 {
+        self.mean_estimate
+    }
+
+    /// 理论值的平均值
+    pub fn expected(&self) -> f64 {
+        self.expected
+    }
+
+    /// 改变选择会中奖的实际比例
+    pub fn observed(&self) -> f64 {
+        self.observed
+    }
+
+    /// 估计与理论值的平均误差
+    pub fn mean_error(&self) -> f64 {
+        self.mean_error
+    }
+}
+
+/// 健忘的主持人随机留下 `k` 个门并且没有打开有奖的门时，`n` 个门 `p` 个奖品中换到一个留下的门的中奖率。
+/// 挑战者选中奖品时其余 `p - 1` 个、没有选中时全部 `p` 个奖品都要在留下的门中，
+/// 挑战者没有选中的 `u` 个奖品都被留下的概率是 `k! (n - 1 - u)! / ((k - u)! (n - 1)!)`
+fn forgetful_switch_rate(doors: u32, prizes: u32, closed: u32) -> f64 {
+    let kept = |unchosen: u32| {
+        (0..unchosen)
+            .map(|i| closed.saturating_sub(i) as f64 / (doors - 1 - i) as f64)
+            .product::<f64>()
+    };
+    let n = doors as f64;
+    let p = prizes as f64;
+    // 挑战者选中奖品，或者没有选中奖品，并且留下的门中没有被打开的奖品
+    let chosen = p / n * kept(prizes - 1);
+    let missed = (n - p) / n * kept(prizes);
+    let total = chosen + missed;
+    if total == 0.0 {
+        return 0.0;
+    }
+    (chosen * (p - 1.0) + missed * p) / closed as f64 / total
+}
+
+/// 设置了奖品价值或者允许买断时一局的得分
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ScoreReport {
+    /// 赢得的奖品和接受的出价的总价值
+    score: u64,
+    /// 所有没有作废的轮次奖品的总价值
+    stakes: u64,
+}
+
+impl ScoreReport {
+    /// 没有设置奖品价值、不允许买断也没有使用规则时返回 `None`
+    fn calculate(settings: &Settings, results: &[RoundResult], rules: &dyn Rules) -> Option<Self> {
+        if !settings.has_values() && !settings.deals && settings.rules.is_none() {
+            return None;
+        }
+        Some(ScoreReport {
+            score: rules.total(results),
+            stakes: results
+                .iter()
+                .filter(|result| !result.void)
+                .map(|result| result.value as u64)
+                .sum(),
+        })
+    }
+
+    /// 赢得的奖品的总价值
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    /// 所有没有作废的轮次奖品的总价值
+    pub fn stakes(&self) -> u64 {
+        self.stakes
+    }
+}
+
+/// 一局游戏结果
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct GameResult {
+    /// 游戏设置，轮数为实际进行的轮数
+    settings: Settings,
+    /// 所有轮次的统计
+    #[serde(flatten)]
+    stats: Stats,
+    /// 按门数从小到大分组的统计
+    by_doors: Vec<DoorsStats>,
+    /// 团队模式下按团队序号从小到大分组的统计，不是团队模式时为空
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    by_team: Vec<TeamStats>,
+    /// 按抉择用时分组的统计，没有用时数据时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    by_latency: Option<LatencyStats>,
+    /// 测验模式下估计与实际的对比，没有估计时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quiz: Option<QuizReport>,
+    /// 设置了奖品价值时的得分
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    score: Option<ScoreReport>,
+    /// 没有打完所有轮次就结束的原因，正常结束时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ended_early: Option<EarlyEnd>,
+}
+
+impl GameResult {
+    pub fn calculate<R>(settings: &Settings, results: R) -> Self
+    where
+        R: AsRef<[RoundResult]>,
+    {
+        Self::calculate_with_rules(settings, results, &Classic)
+    }
+
+    /// 同 [`GameResult::calculate`]，得分按 `rules` 计算
+    pub fn calculate_with_rules<R>(settings: &Settings, results: R, rules: &dyn Rules) -> Self
+    where
+        R: AsRef<[RoundResult]>,
+    {
+        let results = results.as_ref();
+        let mut game_result = GameResult {
+            settings: Settings {
+                rounds: results.len() as u32,
+                ..settings.clone()
+            },
+            stats: Stats::default(),
+            by_doors: vec![],
+            by_team: vec![],
+            by_latency: LatencyStats::calculate(results),
+            quiz: QuizReport::calculate(settings.forgetful, results),
+            score: ScoreReport::calculate(settings, results, rules),
+            ended_early: None,
+        };
+
+        for result in results {
+            game_result.stats.add(result);
+            let index = match game_result
+                .by_doors
+                .binary_search_by_key(&result.doors, |group| group.doors)
+            {
+                Ok(index) => index,
+                Err(index) => {
+                    let group = DoorsStats {
+                        doors: result.doors,
+                        stats: Stats::default(),
+                    };
+                    game_result.by_doors.insert(index, group);
+                    index
+                }
+            };
+            game_result.by_doors[index].stats.add(result);
+
+            if let Some(team) = result.team {
+                let index = match game_result
+                    .by_team
+                    .binary_search_by_key(&team, |group| group.team)
+                {
+                    Ok(index) => index,
+                    Err(index) => {
+                        let group = TeamStats {
+                            team,
+                            stats: Stats::default(),
+                        };
+                        game_result.by_team.insert(index, group);
+                        index
+                    }
+                };
+                game_result.by_team[index].stats.add(result);
+            }
+        }
+
+        game_result
+    }
+
+    /// 游戏设置
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// 所有轮次的统计
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// 按门数从小到大分组的统计
+    pub fn by_doors(&self) -> &[DoorsStats] {
+        &self.by_doors
+    }
+
+    /// 团队模式下按团队序号从小到大分组的统计
+    pub fn by_team(&self) -> &[TeamStats] {
+        &self.by_team
+    }
+
+    /// 按抉择用时分组的统计
+    pub fn by_latency(&self) -> Option<&LatencyStats> {
+        self.by_latency.as_ref()
+    }
+
+    /// 测验模式下估计与实际的对比
+    pub fn quiz(&self) -> Option<&QuizReport> {
+        self.quiz.as_ref()
+    }
+
+    /// 设置了奖品价值时的得分
+    pub fn score(&self) -> Option<&ScoreReport> {
+        self.score.as_ref()
+    }
+
+    /// 记录本局提前结束的原因
+    pub fn with_early_end(mut self, reason: EarlyEnd) -> Self {
+        self.ended_early = Some(reason);
+        self
+    }
+
+    /// 没有打完所有轮次就结束的原因
+    pub fn ended_early(&self) -> Option<EarlyEnd> {
+        self.ended_early
+    }
+
+    /// 挑战者是否弃权
+    pub fn is_forfeit(&self) -> bool {
+        self.ended_early.is_some_and(|reason| reason.is_forfeit())
+    }
+
+    /// 赢的轮数
+    pub fn win(&self) -> u32 {
+        self.stats.win
+    }
+
+    /// 选择时就选了正确选项
+    pub fn chosen_win(&self) -> u32 {
+        self.stats.chosen_win
+    }
+
+    /// 主持人留下的是正确选项
+    pub fn left_win(&self) -> u32 {
+        self.stats.left_win
+    }
+
+    /// 改变选择的次数
+    pub fn switch(&self) -> u32 {
+        self.stats.switch
+    }
+
+    /// 坚持选择的次数
+    pub fn stick(&self) -> u32 {
+        self.stats.stick
+    }
+
+    /// 改变选择后赢的次数
+    pub fn switch_win(&self) -> u32 {
+        self.stats.switch_win
+    }
+
+    /// 坚持选择后赢的次数
+    pub fn stick_win(&self) -> u32 {
+        self.stats.stick_win
+    }
+}
+
+// 在 [0, doors) 范围内生成 exclusive 之外的随机整数
+fn random_door(doors: u32, exclusive: u32) -> u32 {
+    assert!(
+        exclusive < doors,
+        "doors = {}, exclusive = {}",
+        doors,
+        exclusive
+    );
+
+    // 在排除 exclusive 之后剩下的 doors - 1 个门中选择
+    let random = rand::thread_rng().gen_range(0..doors - 1);
+
+    if random >= exclusive {
+        random + 1
+    } else {
+        random
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn co_host_() {
+        let host = Uuid::new_v4();
+        let contestant = Uuid::new_v4();
+        let co_host = Uuid::new_v4();
+        let mut room = Room::create(host, Settings::new(3, 1)).unwrap();
+        room.accept_contestant(contestant).unwrap();
+
+        assert!(room.add_co_host(host).is_err());
+        assert!(room.add_co_host(contestant).is_err());
+        room.add_co_host(co_host).unwrap();
+        assert!(room.add_co_host(co_host).is_err());
+        assert!(room.is_host(&co_host));
+        assert!(!room.is_host(&contestant));
+
+        room.remove_co_host(&co_host).unwrap();
+        assert!(!room.is_host(&co_host));
+        assert!(room.remove_co_host(&co_host).is_err());
+    }
+
+    #[test]
+    fn create_with_id_() {
+        let id = Uuid::from_u128(1);
+        let host = Uuid::from_u128(2);
+        let room = Room::create_with_id(id, host, Settings::new(3, 1)).unwrap();
+        assert_eq!(room.id(), &id);
+        assert_eq!(room.host(), &host);
+        assert!(Room::create_with_id(id, host, Settings::new(1, 1)).is_err());
+    }
+
+    #[test]
+    fn details_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 1)).unwrap();
+        assert_eq!(room.name(), None);
+        room.update_details(Some(" Friday quiz ".to_string()), None)
+            .unwrap();
+        assert_eq!(room.name(), Some("Friday quiz"));
+        assert_eq!(room.description(), None);
+
+        let long = "门".repeat(MAX_ROOM_NAME_LEN + 1);
+        assert!(room.update_details(Some(long), None).is_err());
+        assert!(room.update_details(Some("".to_string()), None).is_err());
+        assert_eq!(room.name(), Some("Friday quiz"));
+
+        let description = "门".repeat(MAX_ROOM_DESCRIPTION_LEN);
+        room.update_details(None, Some(description.clone()))
+            .unwrap();
+        assert_eq!(room.name(), None);
+        assert_eq!(room.description(), Some(description.as_str()));
+        let restored = Room::restore(room.snapshot()).unwrap();
+        assert_eq!(restored.description(), Some(description.as_str()));
+    }
+
+    #[test]
+    fn spectator_() {
+        let host = Uuid::new_v4();
+        let contestant = Uuid::new_v4();
+        let spectator = Uuid::new_v4();
+        let mut room = Room::create(host, Settings::new(3, 1)).unwrap();
+        room.add_spectator(contestant).unwrap();
+        assert!(room.accept_contestant(contestant).is_err());
+        room.remove_spectator(&contestant).unwrap();
+        room.accept_contestant(contestant).unwrap();
+
+        assert!(room.add_spectator(host).is_err());
+        assert!(room.add_spectator(contestant).is_err());
+        room.add_spectator(spectator).unwrap();
+        assert!(room.add_spectator(spectator).is_err());
+        assert!(room.add_co_host(spectator).is_err());
+        assert_eq!(room.spectators(), &[spectator]);
+
+        let restored = Room::restore(room.snapshot()).unwrap();
+        assert!(restored.is_spectator(&spectator));
+
+        room.remove_spectator(&spectator).unwrap();
+        assert!(room.spectators().is_empty());
+        assert!(room.remove_spectator(&spectator).is_err());
+        room.add_co_host(spectator).unwrap();
+        assert!(room.add_spectator(spectator).is_err());
+    }
+
+    #[test]
+    fn force_complete_() {
+        let host = Uuid::new_v4();
+        let contestant = Uuid::new_v4();
+        let mut room = Room::create(host, Settings::new(3, 3)).unwrap();
+        assert!(room.force_complete().is_err());
+
+        room.accept_contestant(contestant).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        room.choose(1).unwrap();
+        room.reveal(0).unwrap();
+        room.decide(Decision::Stick).unwrap();
+        room.start(2).unwrap();
+        room.choose(2).unwrap();
+
+        let results = room.force_complete().unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            room.state(),
+            RoomState::Joined { contestant: c, ready: false } if *c == contestant
+        ));
+    }
+
+    #[test]
+    fn complete_early_() {
+        let host = Uuid::new_v4();
+        let contestant = Uuid::new_v4();
+        let mut room = Room::create(host, Settings::new(3, 3)).unwrap();
+        room.record_events(true);
+        assert!(room.complete_early(EarlyEnd::ContestantLeft).is_err());
+
+        room.accept_contestant(contestant).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        room.choose(0).unwrap();
+        room.reveal(1).unwrap();
+        room.decide(Decision::Switch).unwrap();
+        room.start(1).unwrap();
+        room.take_events();
+
+        // 挑战者离开时输出已经完成的轮次并移出挑战者
+        let results = room.complete_early(EarlyEnd::ContestantLeft).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(matches!(room.state(), RoomState::Created));
+        assert!(matches!(
+            room.take_events()[..],
+            [
+                RoomEvent::Completed {
+                    rounds: 1,
+                    forced: true
+                },
+                RoomEvent::ContestantKicked,
+            ]
+        ));
+
+        let result = GameResult::calculate(room.settings(), &results)
+            .with_early_end(EarlyEnd::ContestantLeft);
+        assert_eq!(result.settings().rounds, 1);
+        assert!(result.is_forfeit());
+        assert!(!GameResult::calculate(room.settings(), &results)
+            .with_early_end(EarlyEnd::Forced)
+            .is_forfeit());
+        assert!(!GameResult::calculate(room.settings(), &results).is_forfeit());
+    }
+
+    #[test]
+    fn pause_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 2)).unwrap();
+        room.record_events(true);
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        // 游戏开始前不能暂停
+        assert!(room.pause().is_err());
+        room.start(0).unwrap();
+        room.choose(1).unwrap();
+
+        room.pause().unwrap();
+        assert!(room.is_paused());
+        assert!(room.pause().is_err());
+        assert!(matches!(room.reveal(0), Err(Error::Paused)));
+        assert!(matches!(room.reveal_random(), Err(Error::Paused)));
+        assert!(matches!(room.undo(), Err(Error::Paused)));
+        assert!(matches!(room.abort_round(), Err(Error::Paused)));
+
+        // 暂停状态保存在快照中
+        let restored = Room::restore(room.snapshot()).unwrap();
+        assert!(restored.is_paused());
+
+        room.resume().unwrap();
+        assert!(!room.is_paused());
+        assert!(room.resume().is_err());
+        room.reveal(0).unwrap();
+        room.decide(Decision::Stick).unwrap();
+
+        // 暂停时主持人不能开始下一轮，但可以提前结束本局
+        room.pause().unwrap();
+        assert!(matches!(room.start(0), Err(Error::Paused)));
+        assert_eq!(
+            room.complete_early(EarlyEnd::ContestantLeft).unwrap().len(),
+            1
+        );
+        assert!(!room.is_paused());
+        assert!(room
+            .take_events()
+            .iter()
+            .any(|event| matches!(event, RoomEvent::Resumed)));
+    }
+
+    #[test]
+    fn host_as_contestant_() {
+        let host = Uuid::new_v4();
+        let mut room = Room::create(host, Settings::new(3, 1)).unwrap();
+        assert!(matches!(
+            room.accept_contestant(host),
+            Err(Error::HostAsContestant)
+        ));
+        assert!(matches!(room.state(), RoomState::Created));
+
+        let mut room = Room::create(host, Settings::new(3, 1).with_self_play(true)).unwrap();
+        room.accept_contestant(host).unwrap();
+    }
+
+    #[test]
+    fn settings_proposal_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 1)).unwrap();
+        assert!(!room.update_settings(Settings::new(4, 1)).unwrap());
+        assert_eq!(room.settings().doors, 4);
+
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        assert!(room.answer_settings(true).is_err());
+        assert!(room.update_settings(Settings::new(5, 1)).unwrap());
+        assert_eq!(room.settings().doors, 4);
+        assert_eq!(room.proposed_settings().map(|s| s.doors), Some(5));
+
+        assert_eq!(room.answer_settings(false).unwrap().doors, 5);
+        assert_eq!(room.settings().doors, 4);
+        assert!(room.proposed_settings().is_none());
+
+        room.update_settings(Settings::new(5, 1)).unwrap();
+        room.answer_settings(true).unwrap();
+        assert_eq!(room.settings().doors, 5);
+        assert!(matches!(
+            room.state(),
+            RoomState::Joined { ready: true, .. }
+        ));
+
+        room.update_settings(Settings::new(6, 1)).unwrap();
+        assert!(!room.update_settings(Settings::new(5, 1)).unwrap());
+        assert!(room.proposed_settings().is_none());
+
+        room.update_settings(Settings::new(6, 1)).unwrap();
+        room.start(0).unwrap();
+        assert!(room.proposed_settings().is_none());
+        assert_eq!(room.settings().doors, 5);
+    }
+
+    #[test]
+    fn snapshot_restore_() {
+        let host = Uuid::new_v4();
+        let settings = Settings::new(5, 2).with_prizes(2);
+        let mut room = Room::create(host, settings).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start_with_prizes(&[1, 3]).unwrap();
+        room.choose(1).unwrap();
+
+        let mut restored = Room::restore(room.snapshot()).unwrap();
+        assert_eq!(restored, room);
+        assert_eq!(restored.state(), room.state());
+        restored.reveal(3).unwrap();
+        restored.decide(Decision::Stick).unwrap();
+
+        let tampered = |f: fn(&mut RoomSnapshot)| {
+            let mut snapshot = room.snapshot();
+            f(&mut snapshot);
+            matches!(Room::restore(snapshot), Err(Error::InvalidSnapshot))
+        };
+        assert!(tampered(|s| s.settings.doors = 1));
+        assert!(tampered(|s| s.co_hosts.push(s.host)));
+        assert!(tampered(|s| s.spectators.push(s.host)));
+        assert!(tampered(|s| s.name = Some(" ".to_string())));
+        assert!(tampered(|s| s.proposed_settings = Some(Settings::new(3, 1))));
+        assert!(tampered(|s| {
+            if let RoomState::Started { contestant, .. } = &mut s.state {
+                *contestant = s.host;
+            }
+        }));
+        assert!(tampered(|s| {
+            if let RoomState::Started { prize, .. } = &mut s.state {
+                *prize = 5;
+            }
+        }));
+        assert!(tampered(|s| {
+            if let RoomState::Started { other_prizes, .. } = &mut s.state {
+                other_prizes.clear();
+            }
+        }));
+        assert!(tampered(|s| {
+            if let RoomState::Started { current_round, .. } = &mut s.state {
+                *current_round = 2;
+            }
+        }));
+        assert!(tampered(|s| {
+            if let RoomState::Started { stage, .. } = &mut s.state {
+                *stage = Stage::End;
+            }
+        }));
+        assert!(tampered(|s| {
+            if let RoomState::Started { stage, .. } = &mut s.state {
+                *stage = Stage::Reveal { chosen: 7 };
+            }
+        }));
+        assert!(tampered(|s| {
+            if let RoomState::Started { stage, .. } = &mut s.state {
+                *stage = decide_stage(1, vec![2]);
+            }
+        }));
+    }
+
+    #[test]
+    fn clone_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 2)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+
+        let mut copy = room.clone();
+        assert_eq!(copy, room);
+        copy.choose(1).unwrap();
+        assert_ne!(copy, room);
+        assert!(matches!(
+            room.state(),
+            RoomState::Started {
+                stage: Stage::Choose,
+                ..
+            }
+        ));
+        room.choose(1).unwrap();
+        assert_eq!(copy, room);
     }
 
-    /// 主持人 ID
-    pub fn host(&self) -> &Uuid {
-        &self.host
+    #[test]
+    fn live_info_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 2)).unwrap();
+        assert_eq!(room.contestant(), None);
+        assert_eq!(room.progress(), (0, 2));
+
+        let contestant = Uuid::new_v4();
+        room.accept_contestant(contestant).unwrap();
+        room.contestant_ready(true).unwrap();
+        assert_eq!(room.contestant(), Some(contestant));
+        assert_eq!(room.current_round(), None);
+        assert_eq!(room.stage(), None);
+
+        room.start(0).unwrap();
+        assert_eq!(room.current_round(), Some(0));
+        assert_eq!(room.stage(), Some(&Stage::Choose));
+        assert_eq!(room.progress(), (0, 2));
+        room.choose(0).unwrap();
+        room.reveal(1).unwrap();
+        room.decide(Decision::Stick).unwrap();
+        assert_eq!(room.stage(), Some(&Stage::End));
+        assert_eq!(room.progress(), (1, 2));
+
+        room.start(2).unwrap();
+        assert_eq!(room.current_round(), Some(1));
+        assert_eq!(room.progress(), (1, 2));
     }
 
-    /// 当前游戏配置
-    pub fn settings(&self) -> Settings {
-        self.settings
+    #[test]
+    fn room_events_() {
+        let contestant = Uuid::new_v4();
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 1)).unwrap();
+        room.record_events(true);
+        room.accept_contestant(contestant).unwrap();
+        room.contestant_ready(true).unwrap();
+        assert!(room.choose(0).is_err());
+        room.start(2).unwrap();
+        room.choose(0).unwrap();
+        room.reveal(2).unwrap();
+        room.decide(Decision::Switch).unwrap();
+        room.complete(true).unwrap();
+
+        let events = room.take_events();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                RoomEvent::ContestantJoined { contestant: c },
+                RoomEvent::Ready { ready: true },
+                RoomEvent::RoundStarted { round: 0, prizes },
+                RoomEvent::Chosen { chosen: 0 },
+                RoomEvent::Revealed { left: 2, others },
+                RoomEvent::Decided { result },
+                RoomEvent::Completed { rounds: 1, forced: false },
+                RoomEvent::ContestantKicked,
+            ] if *c == contestant && prizes == &[2] && others.is_empty() && result.win
+        ));
+        assert!(room.take_events().is_empty());
+
+        // 两个门时没有揭示阶段，选择后紧跟揭示事件
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(2, 1)).unwrap();
+        room.record_events(true);
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(1).unwrap();
+        room.take_events();
+        room.choose(0).unwrap();
+        assert!(matches!(
+            room.take_events().as_slice(),
+            [
+                RoomEvent::Chosen { chosen: 0 },
+                RoomEvent::Revealed { left: 1, .. }
+            ]
+        ));
+
+        // 默认不记录事件，长时间使用的房间不会积累事件，`apply` 仍然返回操作产生的事件
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 1)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        assert!(room.take_events().is_empty());
+        assert!(matches!(
+            room.apply(Command::Ready { ready: true })
+                .unwrap()
+                .as_slice(),
+            [RoomEvent::Ready { ready: true }]
+        ));
+        assert!(room.take_events().is_empty());
     }
 
-    /// 当前房间状态
-    pub fn state(&self) -> &RoomState {
-        &self.state
+    #[test]
+    fn room_observer_() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl RoomObserver for Recorder {
+            fn on_round_started(&mut self, round: u32, prizes: &[u32]) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("start {round} {prizes:?}"));
+            }
+
+            fn on_chosen(&mut self, chosen: u32) {
+                self.0.lock().unwrap().push(format!("choose {chosen}"));
+            }
+
+            fn on_revealed(&mut self, left: u32, _others: &[u32]) {
+                self.0.lock().unwrap().push(format!("reveal {left}"));
+            }
+        }
+
+        let recorder = Recorder::default();
+        let log = recorder.0.clone();
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(2, 1)).unwrap();
+        room.record_events(true);
+        room.set_observer(recorder);
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(1).unwrap();
+        room.choose(0).unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["start 0 [1]", "choose 0", "reveal 1"]
+        );
+        // 观察者不影响事件记录
+        assert_eq!(room.take_events().len(), 5);
+
+        assert!(room.remove_observer().is_some());
+        room.decide(Decision::Stick).unwrap();
+        assert_eq!(log.lock().unwrap().len(), 3);
     }
 
-    /// 接收挑战者
-    pub fn accept_contestant(&mut self, contestant: Uuid) -> Result<()> {
-        if let RoomState::Created = self.state {
-            self.state = RoomState::Joined {
-                contestant,
-                ready: false,
-            };
-            Ok(())
-        } else {
+    #[test]
+    fn apply_command_() {
+        let contestant = Uuid::new_v4();
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 1)).unwrap();
+        room.record_events(true);
+        assert!(room.apply(Command::AcceptContestant { contestant }).is_ok());
+        room.apply(Command::Ready { ready: true }).unwrap();
+        assert!(matches!(
+            room.apply(Command::Choose { chosen: Some(0) }),
             Err(Error::InvalidOperation)
+        ));
+        assert!(matches!(
+            room.apply(Command::Start {
+                prize: Some(2),
+                others: vec![],
+                doors: None,
+            })
+            .unwrap()
+            .as_slice(),
+            [RoomEvent::RoundStarted { round: 0, prizes }] if prizes == &[2]
+        ));
+        let chosen = match room.apply(Command::Choose { chosen: None }).unwrap()[..] {
+            [RoomEvent::Chosen { chosen }] => chosen,
+            ref events => panic!("unexpected events {events:?}"),
+        };
+        let events = room
+            .apply(Command::Reveal {
+                left: None,
+                others: vec![],
+            })
+            .unwrap();
+        assert!(matches!(events[..], [RoomEvent::Revealed { .. }]));
+        let events = room
+            .apply(Command::Decide {
+                decision: Decision::Stick,
+                latency_ms: Some(1500),
+            })
+            .unwrap();
+        assert!(matches!(
+            events[..],
+            [RoomEvent::Decided { result }]
+                if result.chosen == chosen && result.decision_ms == Some(1500)
+        ));
+        assert!(matches!(
+            room.apply(Command::Complete {
+                kick_contestant: true
+            })
+            .unwrap()[..],
+            [
+                RoomEvent::Completed {
+                    rounds: 1,
+                    forced: false
+                },
+                RoomEvent::ContestantKicked
+            ]
+        ));
+        // 返回的事件同样记录在房间中
+        assert_eq!(room.take_events().len(), 8);
+    }
+
+    #[test]
+    fn undo_() {
+        let mut settings = Settings::new(3, 2);
+        settings.quiz = true;
+        let mut room = Room::create(Uuid::new_v4(), settings).unwrap();
+        room.record_events(true);
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        // 还没有开始时没有可以撤销的操作
+        assert!(room.undo().is_err());
+        room.start(2).unwrap();
+        assert!(room.undo().is_err());
+
+        room.choose(0).unwrap();
+        room.reveal(2).unwrap();
+        room.estimate(67).unwrap();
+        // 依次撤销估计、揭示和选择
+        room.undo().unwrap();
+        assert!(matches!(
+            room.state(),
+            RoomState::Started {
+                stage: Stage::Decide { estimate: None, .. },
+                ..
+            }
+        ));
+        room.undo().unwrap();
+        assert!(matches!(
+            room.state(),
+            RoomState::Started {
+                stage: Stage::Reveal { chosen: 0 },
+                ..
+            }
+        ));
+        room.undo().unwrap();
+        assert!(matches!(
+            room.state(),
+            RoomState::Started {
+                stage: Stage::Choose,
+                ..
+            }
+        ));
+        assert!(room.undo().is_err());
+
+        // 撤销后可以重新操作，抉择之后不能撤销
+        room.choose(1).unwrap();
+        room.reveal(2).unwrap();
+        room.estimate(50).unwrap();
+        let result = room.decide(Decision::Switch).unwrap();
+        assert!(result.win);
+        assert!(room.undo().is_err());
+
+        // 新的一轮开始时清空历史
+        room.start(0).unwrap();
+        assert!(room.undo().is_err());
+        let undone = room
+            .take_events()
+            .into_iter()
+            .filter(|event| matches!(event, RoomEvent::Undone { .. }))
+            .count();
+        assert_eq!(undone, 3);
+    }
+
+    #[test]
+    fn abort_round_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 2)).unwrap();
+        room.record_events(true);
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        // 还没有开始时不能放弃
+        assert!(room.abort_round().is_err());
+        room.start(0).unwrap();
+        room.choose(0).unwrap();
+        room.reveal(1).unwrap();
+        room.decide(Decision::Stick).unwrap();
+        // 一轮结束后没有可以放弃的轮次
+        assert!(room.abort_round().is_err());
+
+        room.start(1).unwrap();
+        room.choose(0).unwrap();
+        room.abort_round().unwrap();
+        assert!(room.abort_round().is_err());
+        assert!(room.has_next_round());
+        assert!(room.complete(false).is_err());
+        assert!(room.choose(1).is_err());
+        assert_eq!(room.next_round_settings().doors, 3);
+
+        // 重新开始的仍然是第二轮，已经完成的第一轮不受影响
+        room.start(2).unwrap();
+        match room.state() {
+            RoomState::Started {
+                current_round,
+                prize,
+                results,
+                stage,
+                ..
+            } => {
+                assert_eq!(*current_round, 1);
+                assert_eq!(*prize, 2);
+                assert_eq!(results.len(), 1);
+                assert!(matches!(stage, Stage::Choose));
+            }
+            state => panic!("unexpected state {:?}", state),
         }
+        assert!(room
+            .take_events()
+            .iter()
+            .any(|event| matches!(event, RoomEvent::RoundAborted { round: 1 })));
+        room.choose(2).unwrap();
+        room.reveal(0).unwrap();
+        room.decide(Decision::Stick).unwrap();
+        assert_eq!(room.complete(false).unwrap().len(), 2);
     }
 
-    /// 踢出挑战者
-    pub fn kick_contestant(&mut self) -> Result<()> {
-        if matches!(
-            self.state,
-            RoomState::Joined { .. } | RoomState::Started { .. }
-        ) {
-            self.state = RoomState::Created;
-            Ok(())
-        } else {
-            Err(Error::InvalidOperation)
+    #[test]
+    fn rollback_round_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 2)).unwrap();
+        room.record_events(true);
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        // 还没有选择时没有可以退回的进度
+        assert!(room.rollback_round().is_err());
+        room.choose(1).unwrap();
+        room.reveal(0).unwrap();
+        room.pause().unwrap();
+        // 暂停中也可以退回
+        room.rollback_round().unwrap();
+        room.resume().unwrap();
+        match room.state() {
+            RoomState::Started {
+                current_round,
+                prize,
+                stage,
+                ..
+            } => {
+                assert_eq!(*current_round, 0);
+                assert_eq!(*prize, 0);
+                assert!(matches!(stage, Stage::Choose));
+            }
+            state => panic!("unexpected state {:?}", state),
         }
+        assert!(room
+            .take_events()
+            .iter()
+            .any(|event| matches!(event, RoomEvent::RoundRolledBack { round: 0 })));
+        // 退回后不能撤销到退回之前
+        assert!(room.undo().is_err());
+        room.choose(2).unwrap();
+        room.reveal(0).unwrap();
+        room.decide(Decision::Stick).unwrap();
+        assert!(room.rollback_round().is_err());
     }
 
-    /// 挑战者就绪
-    pub fn contestant_ready(&mut self, ready: bool) -> Result<()> {
-        match &mut self.state {
-            RoomState::Joined { ready: r, .. } => {
-                *r = ready;
-                Ok(())
+    #[test]
+    fn stage_timeout_() {
+        let timeouts = Timeouts {
+            choose_secs: Some(30),
+            reveal_secs: None,
+            decide_secs: Some(60),
+        };
+        assert!(Settings::new(3, 1)
+            .with_timeouts(Timeouts {
+                reveal_secs: Some(0),
+                ..timeouts
+            })
+            .validate()
+            .is_err());
+        let settings = Settings::new(3, 1).with_quiz(true).with_timeouts(timeouts);
+        let mut room = Room::create(Uuid::new_v4(), settings).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        let deadline = room.deadline().unwrap();
+        assert!(deadline >= protocol::timestamp() + 29_000);
+        assert!(!room.check_timeout(deadline - 1).unwrap());
+
+        // 暂停中不计时，继续后重新计时
+        room.pause().unwrap();
+        assert_eq!(room.deadline(), None);
+        assert!(!room.check_timeout(u64::MAX).unwrap());
+        room.resume().unwrap();
+        assert!(room.deadline().is_some());
+
+        // 超时后随机选择，揭示阶段不限时
+        assert!(room.check_timeout(u64::MAX).unwrap());
+        assert!(matches!(
+            room.state(),
+            RoomState::Started {
+                stage: Stage::Reveal { .. },
+                deadline: None,
+                ..
             }
-            _ => Err(Error::InvalidOperation),
+        ));
+        assert!(!room.check_timeout(u64::MAX).unwrap());
+        room.reveal_random().unwrap();
+        assert!(room.deadline().is_some());
+
+        // 测验模式下超时的抉择不需要估计
+        assert!(room.check_timeout(u64::MAX).unwrap());
+        match room.state() {
+            RoomState::Started {
+                stage: Stage::End,
+                results,
+                deadline: None,
+                ..
+            } => assert_eq!(results[0].estimate, None),
+            state => panic!("unexpected state {:?}", state),
         }
+        assert!(!room.check_timeout(u64::MAX).unwrap());
     }
 
-    /// 更新设置，返回 `true` 表示需要通知挑战者重新选择就绪
-    pub fn update_settings(&mut self, settings: Settings) -> Result<bool> {
-        match &mut self.state {
-            RoomState::Created => {
-                self.settings = settings;
-                Ok(false)
+    #[test]
+    fn stage_times_() {
+        fn last_result(room: &Room) -> RoundResult {
+            match room.state() {
+                RoomState::Started { results, .. } => *results.last().unwrap(),
+                state => panic!("unexpected state {:?}", state),
             }
-            RoomState::Joined { ready, .. } => {
-                // 如果配置没有改变，不需要做任何事
-                let notify_contestant = self.settings != settings;
-                // 如果挑战者已经就绪，需要重置，让挑战者重新选择就绪
-                if notify_contestant {
-                    self.settings = settings;
-                    *ready = false;
-                }
-                Ok(notify_contestant)
+        }
+
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 2)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        let before = protocol::timestamp();
+        room.start(0).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        room.choose(1).unwrap();
+        room.reveal(0).unwrap();
+        room.decide(Decision::Switch).unwrap();
+        let result = last_result(&room);
+        assert!(result.started_at().unwrap() >= before);
+        assert!(result.choose_ms().unwrap() >= 20);
+        assert!(result.reveal_ms().is_some());
+        assert!(result.decide_ms().is_some());
+        assert_eq!(result.decision_ms(), None);
+
+        // 从快照恢复的房间没有恢复之前的时间
+        room.start(0).unwrap();
+        room.choose(1).unwrap();
+        let mut room = Room::restore(room.snapshot()).unwrap();
+        room.reveal(0).unwrap();
+        room.decide(Decision::Stick).unwrap();
+        let result = last_result(&room);
+        assert_eq!(result.started_at(), None);
+        assert_eq!(result.choose_ms(), None);
+        assert_eq!(result.reveal_ms(), None);
+        assert!(result.decide_ms().is_some());
+    }
+
+    #[test]
+    fn typestate_() {
+        use crate::typed::{self, AfterChoose, AfterDecide, AfterReveal};
+
+        let host = Uuid::new_v4();
+        let room = typed::Room::create(host, Settings::new(3, 1)).unwrap();
+        // 主持人不能作为挑战者进入，失败时房间保持原来的状态
+        let room = room.accept_contestant(host).unwrap_err().room;
+        let room = room.accept_contestant(Uuid::new_v4()).unwrap().ready();
+        let room = room.start(3).unwrap_err().room;
+        let room = match room.start(2).unwrap().choose(0).unwrap() {
+            AfterChoose::Revealing(room) => room,
+            AfterChoose::Deciding(_) => panic!("3 doors have a reveal stage"),
+        };
+        // 奖品在 2 号门，主持人只能留下 2 号门
+        let room = room.reveal(1).unwrap_err().room;
+        let room = match room.reveal(2).unwrap() {
+            AfterReveal::Deciding(room) => room,
+            AfterReveal::Voided(_) => panic!("only a forgetful host voids a round"),
+        };
+        let (room, result) = room.decide(Decision::Stick).unwrap();
+        assert!(!result.win);
+        let (room, results) = match room {
+            AfterDecide::GameOver(room) => room.complete(),
+            AfterDecide::RoundOver(_) => panic!("a single round"),
+        };
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            room.state(),
+            RoomState::Joined { ready: false, .. }
+        ));
+
+        // 两个门时选择后直接进入抉择
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(2, 2)).unwrap();
+        room.record_events(true);
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        let room = match typed::AnyRoom::from(room) {
+            typed::AnyRoom::Choosing(room) => room.choose_random(),
+            room => panic!("unexpected {room:?}"),
+        };
+        let (room, _) = match room {
+            AfterChoose::Deciding(room) => room.decide(Decision::Switch).unwrap(),
+            AfterChoose::Revealing(_) => panic!("2 doors have no reveal stage"),
+        };
+        let room = match room {
+            AfterDecide::RoundOver(room) => room.start_random(),
+            AfterDecide::GameOver(_) => panic!("2 rounds"),
+        };
+        let (room, results) = room.force_complete();
+        assert_eq!(results.len(), 1);
+        // 事件照常记录，可以取出来发送
+        assert!(!room.into_inner().take_events().is_empty());
+
+        // 健忘的主持人打开了奖品所在的门，本轮直接结束
+        let settings = Settings::new(3, 1).with_forgetful(true);
+        let room = typed::Room::create(Uuid::new_v4(), settings).unwrap();
+        let room = room.accept_contestant(Uuid::new_v4()).unwrap().ready();
+        let room = match room.start(0).unwrap().choose(1).unwrap() {
+            AfterChoose::Revealing(room) => room,
+            AfterChoose::Deciding(_) => panic!("3 doors have a reveal stage"),
+        };
+        match room.reveal(2).unwrap() {
+            AfterReveal::Voided(AfterDecide::GameOver(room)) => assert!(room.complete().1[0].void),
+            room => panic!("unexpected {room:?}"),
+        }
+    }
+
+    #[test]
+    fn seeded_rng_() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        fn play(seed: u64) -> Vec<(u32, u32, u32)> {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let settings = Settings::new(10, 5).with_prizes(2).with_closed(3);
+            let mut room = Room::create(Uuid::new_v4(), settings).unwrap();
+            room.accept_contestant(Uuid::new_v4()).unwrap();
+            room.contestant_ready(true).unwrap();
+            let mut rounds = vec![];
+            for _ in 0..5 {
+                let prize = room.start_random_with_rng(&mut rng).unwrap();
+                let chosen = room.choose_random_with_rng(&mut rng).unwrap();
+                let left = room.reveal_random_with_rng(&mut rng).unwrap();
+                room.decide(Decision::Switch).unwrap();
+                rounds.push((prize, chosen, left));
             }
-            RoomState::Started { .. } => Err(Error::InvalidOperation),
+            rounds
         }
+
+        assert_eq!(play(42), play(42));
     }
 
-    /// 开始游戏并将奖品随机放到一个门内
-    pub fn start_random(&mut self) -> Result<u32> {
-        match &mut self.state {
-            RoomState::Joined { ready, contestant } if *ready => {
-                let prize = rand::thread_rng().gen_range(0..self.settings.doors);
-                self.state = RoomState::Started {
-                    contestant: *contestant,
-                    current_round: 0,
-                    prize,
-                    results: vec![],
-                    stage: Stage::Choose,
-                };
-                Ok(prize)
+    #[test]
+    fn seeded_room_() {
+        fn play(seed: u64) -> Vec<RoundResult> {
+            let settings = Settings::new(10, 4).with_prizes(2).with_closed(3);
+            let mut room = Room::create_seeded(Uuid::new_v4(), settings, seed).unwrap();
+            room.accept_contestant(Uuid::new_v4()).unwrap();
+            room.contestant_ready(true).unwrap();
+            for round in 0..4 {
+                if round % 2 == 0 {
+                    room.start_random().unwrap();
+                    room.choose_random().unwrap();
+                    room.reveal_random().unwrap();
+                } else {
+                    room.start(round).unwrap();
+                    room.choose(round).unwrap();
+                    room.reveal(9).unwrap();
+                }
+                room.decide(Decision::Switch).unwrap();
             }
+            room.complete(false).unwrap()
+        }
+
+        assert_eq!(play(7), play(7));
+    }
+
+    #[test]
+    fn reseed_() {
+        fn play(room: &mut Room, seed: u64) -> (u32, u32, u32) {
+            room.reseed(Some(seed));
+            let prize = room.start_random().unwrap();
+            let chosen = room.choose_random().unwrap();
+            let left = room.reveal_random().unwrap();
+            room.decide(Decision::Stick).unwrap();
+            (prize, chosen, left)
+        }
+
+        let settings = Settings::new(10, 2).with_closed(3);
+        let mut first = Room::create(Uuid::new_v4(), settings.clone()).unwrap();
+        let mut second = Room::create_seeded(Uuid::new_v4(), settings, 1).unwrap();
+        for room in [&mut first, &mut second] {
+            room.accept_contestant(Uuid::new_v4()).unwrap();
+            room.contestant_ready(true).unwrap();
+        }
+        // 每轮重新设置种子后，随机操作只取决于本轮的种子
+        assert_eq!(first.next_round(), 0);
+        assert_eq!(play(&mut first, 3), play(&mut second, 3));
+        assert_eq!(first.next_round(), 1);
+        assert_eq!(play(&mut first, 5), play(&mut second, 5));
+    }
+
+    #[test]
+    fn placement_() {
+        use crate::fairness::{message, placement};
+
+        let bytes = [7u8; 32];
+        let prizes = placement(&bytes, 10, 3);
+        assert_eq!(prizes.len(), 3);
+        assert!(prizes.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(prizes.iter().all(|door| *door < 10));
+        // 相同的输入总是得到相同的位置
+        assert_eq!(placement(&bytes, 10, 3), prizes);
+        assert_eq!(placement(&bytes, 10, 10), (0..10).collect::<Vec<_>>());
+        assert_eq!(placement(&bytes, 3, 5).len(), 3);
+        // 门数来自不可信的证明时也不会按门数分配内存
+        let prizes = placement(&bytes, u32::MAX, 3);
+        assert_eq!(prizes.len(), 3);
+        assert!(prizes.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let room_id = Uuid::new_v4();
+        let message = message(&room_id, 1, "abc");
+        assert_eq!(message.len(), 16 + 4 + 3);
+        assert_eq!(&message[16..20], &[0, 0, 0, 1]);
+        assert_eq!(&message[20..], b"abc");
+    }
+
+    #[test]
+    fn shared_entropy_() {
+        use crate::fairness::{commitment, placement, shared_entropy};
+
+        let settings = Settings::new(5, 2).with_prizes(2).with_shared_entropy(true);
+        let mut room = Room::create(Uuid::new_v4(), settings).unwrap();
+        room.record_events(true);
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        // 主持人不能指定奖品位置，交换完成之前不能开始
+        assert!(matches!(room.start(0), Err(Error::InvalidOperation)));
+        assert!(matches!(
+            room.start_random(),
+            Err(Error::ExchangeIncomplete)
+        ));
+        assert!(matches!(
+            room.supply_nonce("n1".to_string()),
+            Err(Error::ExchangeIncomplete)
+        ));
+
+        room.commit_seed("s1".to_string()).unwrap();
+        // 挑战者提供随机串之前可以重新提交种子，之后不能再修改
+        room.commit_seed("s2".to_string()).unwrap();
+        room.supply_nonce("n1".to_string()).unwrap();
+        assert!(room.commit_seed("s3".to_string()).is_err());
+        assert!(room.supply_nonce("n2".to_string()).is_err());
+        assert!(room.take_events().iter().any(|event| matches!(
+            event,
+            RoomEvent::SeedCommitted { round: 0, commitment: c } if *c == commitment("s2")
+        )));
+        assert_eq!(room.exchange().unwrap().commitment(), commitment("s2"));
+
+        room.start_random().unwrap();
+        let expected = placement(&shared_entropy("s2", "n1"), 5, 2);
+        match room.state() {
             RoomState::Started {
-                current_round,
-                stage,
                 prize,
+                other_prizes,
+                ..
+            } => assert_eq!(prize_set(*prize, other_prizes), expected),
+            state => panic!("unexpected state {:?}", state),
+        }
+        let restored = Room::restore(room.snapshot()).unwrap();
+        assert!(restored.exchange().unwrap().is_placed());
+        // 本轮进行中序列化的房间和快照都不带种子，恢复后丢弃交换，本轮照常继续
+        let json = serde_json::to_string(&room).unwrap();
+        assert!(json.contains(r#""exchange":{"round":0,"nonce":"n1","placed":true}"#));
+        assert!(!json.contains("s2"));
+        let json = serde_json::to_string(&room.snapshot()).unwrap();
+        assert!(!json.contains("s2"));
+        let mut restored = Room::restore(serde_json::from_str(&json).unwrap()).unwrap();
+        assert!(restored.exchange().is_none());
+        restored.choose_random().unwrap();
+
+        // 本轮结束时公布种子
+        room.choose_random().unwrap();
+        room.reveal_random().unwrap();
+        room.decide(Decision::Stick).unwrap();
+        assert!(room.take_events().iter().any(|event| matches!(
+            event,
+            RoomEvent::SeedRevealed { round: 0, seed, nonce } if seed == "s2" && nonce == "n1"
+        )));
+        assert!(room.exchange().is_none());
+
+        // 放弃的一轮重新开始时需要新的交换
+        room.commit_seed("s4".to_string()).unwrap();
+        room.supply_nonce("n4".to_string()).unwrap();
+        room.start_random().unwrap();
+        room.abort_round().unwrap();
+        assert!(room
+            .take_events()
+            .iter()
+            .any(|event| matches!(event, RoomEvent::SeedRevealed { round: 1, .. })));
+        assert!(matches!(
+            room.start_random(),
+            Err(Error::ExchangeIncomplete)
+        ));
+    }
+
+    #[cfg(feature = "fairness")]
+    #[test]
+    fn fair_round_() {
+        use crate::fairness::{verify, FairnessKey};
+
+        let key = FairnessKey::from_hex(&"01".repeat(32)).unwrap();
+        assert!(FairnessKey::from_hex("01").is_err());
+        let room_id = Uuid::new_v4();
+        let proof = key.prove(&room_id, 0, 10, 2, "nonce");
+        assert_eq!(proof.prizes.len(), 2);
+        assert!(verify(&key.public_key(), &room_id, &proof).is_ok());
+        // 房间、随机串或者奖品位置不同都不能通过验证
+        assert!(verify(&key.public_key(), &Uuid::new_v4(), &proof).is_err());
+        let mut forged = proof.clone();
+        forged.nonce = "other".to_string();
+        assert!(verify(&key.public_key(), &room_id, &forged).is_err());
+        let mut forged = proof.clone();
+        forged.prizes = vec![(proof.prizes[0] + 1) % 10, proof.prizes[1]];
+        assert!(verify(&key.public_key(), &room_id, &forged).is_err());
+    }
+
+    #[test]
+    fn settings_builder_() {
+        assert_eq!(Settings::builder().build().unwrap(), Settings::new(3, 10));
+
+        let settings = Settings::builder()
+            .doors(5)
+            .rounds(3)
+            .closed(2)
+            .prizes(2)
+            .quiz(true)
+            .timeouts(Timeouts {
+                choose_secs: Some(30),
+                ..Timeouts::default()
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            settings,
+            Settings::new(5, 3)
+                .with_closed(2)
+                .with_prizes(2)
+                .with_quiz(true)
+                .with_timeouts(Timeouts {
+                    choose_secs: Some(30),
+                    ..Timeouts::default()
+                })
+        );
+
+        // 在已有设置的基础上修改，无效的设置在构建时报错
+        let builder = SettingsBuilder::from(settings);
+        assert_eq!(builder.clone().rounds(4).build().unwrap().rounds, 4);
+        assert!(matches!(
+            builder.clone().doors(1).build(),
+            Err(Error::InvalidSettings)
+        ));
+        assert!(builder.prizes(0).build().is_err());
+    }
+
+    #[test]
+    fn prize_values_() {
+        assert!(Settings::new(3, 3).with_prize_value(0).validate().is_err());
+        let mut settings = Settings::new(3, 3).with_prize_value(10);
+        settings.schedule = vec![RoundSettings::new(3), RoundSettings::new(3).with_value(50)];
+        let mut room = Room::create(Uuid::new_v4(), settings).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+
+        // 第 1 轮坚持赢 10，第 2 轮改变选择赢 50，第 3 轮坚持输
+        for (prize, left, decision) in [
+            (0, 1, Decision::Stick),
+            (2, 2, Decision::Switch),
+            (1, 1, Decision::Stick),
+        ] {
+            room.start(prize).unwrap();
+            room.choose(0).unwrap();
+            room.reveal(left).unwrap();
+            room.decide(decision).unwrap();
+        }
+        assert_eq!(room.score(), 60);
+        let snapshot = room.snapshot();
+        assert!(Room::restore(snapshot.clone()).is_ok());
+        let mut tampered = snapshot;
+        if let RoomState::Started { score, .. } = &mut tampered.state {
+            *score += 1;
+        }
+        assert!(matches!(
+            Room::restore(tampered),
+            Err(Error::InvalidSnapshot)
+        ));
+
+        let results = room.complete(false).unwrap();
+        let values: Vec<u32> = results.iter().map(RoundResult::value).collect();
+        assert_eq!(values, [10, 50, 10]);
+        let score = *GameResult::calculate(room.settings(), &results)
+            .score()
+            .unwrap();
+        assert_eq!((score.score(), score.stakes()), (60, 70));
+        assert!(GameResult::calculate(&Settings::new(3, 3), &results)
+            .score()
+            .is_none());
+    }
+
+    #[test]
+    fn encoded_message_() {
+        use protocol::{GameResponse, ServerMessage};
+        use std::sync::Arc;
+
+        let mut message = ServerMessage::from(GameResponse::Paused);
+        let json = message.to_json().unwrap();
+        message.encoded = Some(json.clone());
+        // 克隆共享同一份序列化结果，预先序列化的结果本身不会被序列化
+        let clone = message.clone();
+        assert!(Arc::ptr_eq(&clone.to_json().unwrap(), &json));
+        assert_eq!(serde_json::to_string(&clone).unwrap(), *json);
+    }
+
+    #[test]
+    fn start_with_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 2)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        assert!(matches!(room.start_with(1, 0), Err(Error::InvalidSettings)));
+        assert!(matches!(
+            room.start_with(5, 5),
+            Err(Error::InvalidDoorIndex)
+        ));
+
+        // 第一轮 10 个门，设置中的门数不变
+        room.start_with(10, 7).unwrap();
+        assert_eq!(room.round_settings().doors, 10);
+        assert_eq!(room.settings().doors, 3);
+        room.choose(9).unwrap();
+        let restored = Room::restore(room.snapshot()).unwrap();
+        assert_eq!(restored.round_settings().doors, 10);
+        room.reveal(7).unwrap();
+        let result = room.decide(Decision::Switch).unwrap();
+        assert_eq!(result.doors(), 10);
+        assert!(result.win());
+
+        // 第二轮回到设置中的 3 个门
+        assert_eq!(room.next_round_settings().doors, 3);
+        room.start(1).unwrap();
+        assert_eq!(room.round_settings().doors, 3);
+        room.choose(1).unwrap();
+        room.reveal(0).unwrap();
+        room.decide(Decision::Stick).unwrap();
+        assert!(Room::restore(room.snapshot()).is_ok());
+
+        let results = room.complete(false).unwrap();
+        let result = GameResult::calculate(room.settings(), &results);
+        let doors: Vec<u32> = result
+            .by_doors()
+            .iter()
+            .map(|group| group.doors())
+            .collect();
+        assert_eq!(doors, [3, 10]);
+    }
+
+    #[test]
+    fn endless_() {
+        let mut room =
+            Room::create(Uuid::new_v4(), Settings::new(3, 1).with_endless(true)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        assert!(room.complete(false).is_err());
+        // 超过 `rounds` 之后仍然可以继续，也不算加赛
+        for _ in 0..3 {
+            room.start(0).unwrap();
+            room.choose(1).unwrap();
+            room.reveal(0).unwrap();
+            let result = room.decide(Decision::Switch).unwrap();
+            assert!(!result.sudden_death());
+            assert!(room.has_next_round());
+            assert!(!room.needs_tiebreak());
+        }
+        // 进行中不能完成，放弃后可以完成，放弃的一轮没有结果
+        room.start(2).unwrap();
+        assert!(room.complete(false).is_err());
+        let restored = Room::restore(room.snapshot()).unwrap();
+        assert_eq!(restored.next_round(), 4);
+        room.abort_round().unwrap();
+        let results = room.complete(false).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|result| result.win()));
+    }
+
+    #[test]
+    fn forgetful_() {
+        let settings = Settings::new(3, 2).with_sudden_death(true);
+        let mut room = Room::create(Uuid::new_v4(), settings.clone()).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        room.choose(1).unwrap();
+        assert!(room.reveal(2).is_err());
+
+        let mut room = Room::create(Uuid::new_v4(), settings.with_forgetful(true)).unwrap();
+        room.record_events(true);
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        room.choose(1).unwrap();
+        room.take_events();
+        // 主持人打开了有奖的 0 号门，本轮作废，不能再抉择
+        room.reveal(2).unwrap();
+        let result = match room.take_events().as_slice() {
+            [RoomEvent::RoundVoided { result }] => *result,
+            events => panic!("{:?}", events),
+        };
+        assert!(result.void() && !result.win());
+        assert_eq!((result.prize(), result.left()), (0, 2));
+        assert!(room.decide(Decision::Stick).is_err());
+        assert!(Room::restore(room.snapshot()).is_ok());
+
+        room.start(0).unwrap();
+        room.choose(0).unwrap();
+        room.reveal(1).unwrap();
+        assert!(room.decide(Decision::Stick).unwrap().win());
+        // 作废的一轮不算输赢，赢了唯一有结果的一轮，不需要加赛
+        assert!(!room.needs_tiebreak());
+        let results = room.complete(false).unwrap();
+        let game = GameResult::calculate(room.settings(), &results);
+        assert_eq!((game.stats().rounds(), game.stats().void()), (1, 1));
+        assert_eq!(game.stats().win_rate(), 1.0);
+
+        assert!((forgetful_switch_rate(3, 1, 1) - 0.5).abs() < 1e-9);
+        assert!((forgetful_switch_rate(10, 1, 1) - 0.5).abs() < 1e-9);
+        assert!((forgetful_switch_rate(4, 1, 2) - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn puzzle_() {
+        use crate::puzzle::{Answer, Puzzle};
+
+        assert!(Puzzle::create(Settings::new(3, 5).with_evil(true), "seed").is_err());
+        let puzzle = Puzzle::create(Settings::new(4, 5).with_prizes(2), "seed").unwrap();
+        assert_eq!(puzzle.seed().unwrap(), "seed");
+        assert_eq!(puzzle.prizes(3).unwrap(), puzzle.prizes(3).unwrap());
+
+        let mut room = puzzle.room().unwrap();
+        while room.next_round() < 5 {
+            puzzle.start(&mut room).unwrap();
+            room.choose_random().unwrap();
+            room.reveal_random().unwrap();
+            let decision = match room.next_round() % 2 {
+                0 => Decision::Stick,
+                _ => Decision::Switch,
+            };
+            room.decide(decision).unwrap();
+        }
+        let mut answer = Answer {
+            commitment: puzzle.commitment.clone(),
+            results: room.complete(false).unwrap(),
+        };
+        let game = puzzle.verify(&answer).unwrap();
+        assert_eq!(game.stats().rounds(), 5);
+
+        // 改了输赢或者少交了轮次的答卷都不能通过验证
+        answer.results[0].win = !answer.results[0].win;
+        assert!(puzzle.verify(&answer).is_err());
+        answer.results[0].win = !answer.results[0].win;
+        answer.results.pop();
+        assert!(puzzle.verify(&answer).is_err());
+        let other = Puzzle::create(Settings::new(4, 5).with_prizes(2), "other").unwrap();
+        assert!(other.verify(&answer).is_err());
+    }
+
+    #[test]
+    fn evil_() {
+        let settings = Settings::new(2, 3);
+        let mut room = Room::create(Uuid::new_v4(), settings.clone()).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        room.choose(0).unwrap();
+        assert!(room.decline().is_err());
+
+        let mut room = Room::create(Uuid::new_v4(), settings.with_evil(true)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        // 两个门时也等待主持人决定是否给改变选择的机会
+        room.choose(0).unwrap();
+        assert!(matches!(
+            room.state(),
+            RoomState::Started {
+                stage: Stage::Reveal { chosen: 0 },
                 ..
-            } if stage.is_end() && *current_round < self.settings.rounds - 1 => {
-                let new_prize = rand::thread_rng().gen_range(0..self.settings.doors);
-                *current_round += 1;
-                *stage = Stage::Choose;
-                *prize = new_prize;
-                Ok(new_prize)
             }
-            _ => Err(Error::InvalidOperation),
+        ));
+        assert!(Room::restore(room.snapshot()).is_ok());
+        let result = room.decline().unwrap();
+        assert!(!result.offered() && result.win());
+        assert_eq!((result.left(), result.closed()), (0, 1));
+        assert!(room.decide(Decision::Switch).is_err());
+        assert!(Room::restore(room.snapshot()).is_ok());
+        let small = SmallRoundResult::<u8>::try_from(result).unwrap();
+        assert!(!RoundResult::from(small).offered());
+
+        room.start(1).unwrap();
+        room.choose(0).unwrap();
+        room.apply(Command::Decline).unwrap();
+        room.start(1).unwrap();
+        room.choose(0).unwrap();
+        room.reveal_random().unwrap();
+        assert!(room.decide(Decision::Switch).unwrap().offered());
+
+        let results = room.complete(false).unwrap();
+        let game = GameResult::calculate(room.settings(), &results);
+        let stats = game.stats();
+        assert_eq!((stats.rounds(), stats.win()), (3, 2));
+        assert_eq!((stats.declined(), stats.declined_win()), (2, 1));
+        assert_eq!(stats.switch_rate(), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn deals_() {
+        let settings = Settings::new(3, 2).with_prize_value(100);
+        let mut room = Room::create(Uuid::new_v4(), settings.clone()).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        room.choose(1).unwrap();
+        room.reveal(0).unwrap();
+        assert!(room.offer_deal(40).is_err());
+
+        let mut room = Room::create(Uuid::new_v4(), settings.with_deals(true)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        room.choose(1).unwrap();
+        assert!(room.offer_deal(40).is_err());
+        room.reveal(0).unwrap();
+        // 再次出价代替之前的出价，只能接受当前的出价
+        room.offer_deal(40).unwrap();
+        room.apply(Command::OfferDeal { offer: 60 }).unwrap();
+        assert!(matches!(
+            room.state(),
+            RoomState::Started {
+                stage: Stage::Deal { offer: 60, .. },
+                ..
+            }
+        ));
+        assert!(Room::restore(room.snapshot()).is_ok());
+        assert!(room.decide(Decision::TakeDeal(40)).is_err());
+        let result = room.decide(Decision::TakeDeal(60)).unwrap();
+        assert!(!result.win());
+        assert_eq!((result.offer(), result.score()), (Some(60), 60));
+        let small = SmallRoundResult::<u8>::try_from(result).unwrap();
+        let full = RoundResult::from(small);
+        assert_eq!(full.decision(), Decision::TakeDeal(60));
+        assert_eq!(SmallRoundResult::<u8>::try_from(full).unwrap(), small);
+
+        // 拒绝出价照常抉择，结果仍然记录出价
+        room.start(0).unwrap();
+        room.choose(1).unwrap();
+        room.reveal(0).unwrap();
+        room.offer_deal(30).unwrap();
+        let result = room.decide(Decision::Switch).unwrap();
+        assert!(result.win());
+        assert_eq!((result.offer(), result.score()), (Some(30), 100));
+
+        let results = room.complete(false).unwrap();
+        let game = GameResult::calculate(room.settings(), &results);
+        let stats = game.stats();
+        assert_eq!((stats.rounds(), stats.win()), (2, 1));
+        assert_eq!((stats.deals(), stats.deal_value()), (1, 60));
+        assert_eq!(stats.switch_rate(), 0.5);
+        assert_eq!(game.score().map(|score| score.score()), Some(160));
+    }
+
+    #[test]
+    fn teams_() {
+        let host = Uuid::new_v4();
+        let (red, blue, member) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let mut room = Room::create(host, Settings::new(3, 3)).unwrap();
+        assert_eq!(room.playing_team(), None);
+        assert_eq!(room.add_team("Red".to_string(), red).unwrap(), 0);
+        assert!(room.add_team("Red".to_string(), blue).is_err());
+        assert!(room.add_team("Blue".to_string(), host).is_err());
+        room.apply(Command::AddTeam {
+            name: "Blue".to_string(),
+            captain: blue,
+        })
+        .unwrap();
+        room.join_team(1, member).unwrap();
+        assert!(room.join_team(0, member).is_err());
+        assert!(room.add_co_host(member).is_err());
+        assert_eq!(room.team_of(&member), Some(1));
+        assert!(room.leave_team(&blue).is_err());
+
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        assert!(room.add_team("Green".to_string(), Uuid::new_v4()).is_err());
+        // 两个团队轮流进行，由各自的队长提交
+        for (round, captain) in [red, blue, red].into_iter().enumerate() {
+            assert_eq!(room.decider(), Some(captain));
+            room.start(0).unwrap();
+            assert_eq!(room.playing_team(), Some(round as u32 % 2));
+            room.choose(1).unwrap();
+            room.reveal(0).unwrap();
+            let decision = match round {
+                1 => Decision::Stick,
+                _ => Decision::Switch,
+            };
+            assert_eq!(
+                room.decide(decision).unwrap().team(),
+                Some(round as u32 % 2)
+            );
+        }
+        let restored = Room::restore(room.snapshot()).unwrap();
+        assert_eq!(restored.teams(), room.teams());
+
+        let results = room.complete(false).unwrap();
+        let game = GameResult::calculate(room.settings(), &results);
+        let by_team: Vec<_> = game
+            .by_team()
+            .iter()
+            .map(|group| (group.team(), group.stats().rounds(), group.stats().win()))
+            .collect();
+        assert_eq!(by_team, [(0, 2, 2), (1, 1, 0)]);
+    }
+
+    #[test]
+    fn two_doors_() {
+        assert!(Room::create(Uuid::new_v4(), Settings::new(1, 1)).is_err());
+        assert!(Room::create(Uuid::new_v4(), Settings::new(2, 0)).is_err());
+
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(2, 1)).unwrap();
+        assert!(room.update_settings(Settings::new(1, 1)).is_err());
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(1).unwrap();
+        room.choose(0).unwrap();
+        assert!(room.reveal(1).is_err());
+        let result = room.decide(Decision::Switch).unwrap();
+        assert!(result.win);
+        assert_eq!(result.left, 1);
+    }
+
+    #[test]
+    fn generalized_reveal_() {
+        assert!(Settings::new(5, 1).with_closed(0).validate().is_err());
+
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(5, 4).with_closed(3)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+
+        room.start(4).unwrap();
+        room.choose(0).unwrap();
+        assert!(room.reveal_doors(&[1, 2]).is_err());
+        assert!(room.reveal_doors(&[1, 2, 3]).is_err());
+        assert!(room.reveal_doors(&[0, 2, 4]).is_err());
+        room.reveal_doors(&[4, 1, 2]).unwrap();
+        assert!(matches!(
+            room.state(),
+            RoomState::Started { stage: Stage::Decide { left: 1, others, .. }, .. } if others == &[2, 4]
+        ));
+        assert!(matches!(
+            room.decide(Decision::SwitchTo(3)),
+            Err(Error::DoorNotLeft { door: 3 })
+        ));
+        assert!(matches!(
+            room.decide(Decision::SwitchTo(0)),
+            Err(Error::DoorNotLeft { door: 0 })
+        ));
+        assert!(room.decide(Decision::SwitchTo(5)).is_err());
+        let result = room.decide(Decision::SwitchTo(4)).unwrap();
+        assert_eq!(result.decision, Decision::Switch);
+        assert!(result.win);
+        assert_eq!((result.left, result.closed), (4, 3));
+
+        room.start(1).unwrap();
+        room.choose(3).unwrap();
+        room.reveal(0).unwrap();
+        if let RoomState::Started { stage, .. } = room.state() {
+            assert_eq!(stage.others().len(), 2);
+            assert!(stage.others().contains(&1));
+        }
+        assert!(!room.decide(Decision::Stick).unwrap().win);
+
+        for _ in 0..2 {
+            room.start_random().unwrap();
+            room.choose_random().unwrap();
+            room.reveal_random().unwrap();
+            room.decide(Decision::Switch).unwrap();
+        }
+        let results = room.complete(false).unwrap();
+        assert!(results.iter().all(|result| result.closed() == 3));
+
+        // 留下所有的门时跳过揭示
+        let settings = Settings::new(3, 1).with_closed(5);
+        let mut room = Room::create(Uuid::new_v4(), settings).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(2).unwrap();
+        room.choose(1).unwrap();
+        assert!(room.reveal(0).is_err());
+        assert!(room.decide(Decision::SwitchTo(2)).unwrap().win);
+    }
+
+    #[test]
+    fn multiple_prizes_() {
+        assert!(Settings::new(5, 1).with_prizes(0).validate().is_err());
+
+        let settings = Settings::new(6, 4).with_prizes(2);
+        let round = settings.round(0);
+        assert_eq!((settings.prizes(round), settings.closed(round)), (2, 2));
+        let mut room = Room::create(Uuid::new_v4(), settings).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+
+        assert!(room.start_with_prizes(&[1]).is_err());
+        assert!(room.start_with_prizes(&[1, 6]).is_err());
+        room.start_with_prizes(&[4, 1]).unwrap();
+        assert!(matches!(
+            room.state(),
+            RoomState::Started { prize: 1, other_prizes, .. } if other_prizes == &[4]
+        ));
+        room.choose(0).unwrap();
+        assert!(room.reveal(2).is_err());
+        assert!(room.reveal_doors(&[1, 2]).is_err());
+        room.reveal(4).unwrap();
+        assert_eq!(
+            room.decide(Decision::SwitchTo(4))
+                .map(|result| result.win)
+                .ok(),
+            Some(true)
+        );
+
+        room.start_with_prizes(&[2, 3]).unwrap();
+        room.choose(2).unwrap();
+        room.reveal_doors(&[0, 3]).unwrap();
+        let result = room.decide(Decision::SwitchTo(0)).unwrap();
+        assert!(!result.win);
+        assert_eq!(
+            (result.prize, result.prizes(), result.left_prizes()),
+            (2, 2, 1)
+        );
+
+        for _ in 0..2 {
+            room.start(5).unwrap();
+            room.choose_random().unwrap();
+            room.reveal_random().unwrap();
+            room.decide(Decision::Stick).unwrap();
         }
+        let results = room.complete(false).unwrap();
+        let stats = Stats::calculate(&results);
+        assert_eq!(stats.left_prizes(), 8 - stats.chosen_win());
+        assert_eq!(stats.left_win(), 4);
     }
 
-    /// 开始游戏并将奖品放到序号指定的门内
-    pub fn start(&mut self, prize: u32) -> Result<()> {
-        if prize >= self.settings.doors {
-            return Err(Error::InvalidDoorIndex);
+    #[test]
+    fn schedule_() {
+        let settings = Settings::new(3, 5).with_schedule(&[3, 5, 10]);
+        assert!(settings.clone().with_schedule(&[3, 1]).validate().is_err());
+
+        let mut room = Room::create(Uuid::new_v4(), settings.clone()).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        assert!(room.start(3).is_err());
+        room.start(2).unwrap();
+        room.choose(2).unwrap();
+        room.reveal(0).unwrap();
+        room.decide(Decision::Stick).unwrap();
+        assert!(room.start(5).is_err());
+        room.start(4).unwrap();
+        assert_eq!(room.round_settings().doors, 5);
+        room.choose(4).unwrap();
+        room.reveal(1).unwrap();
+        room.decide(Decision::Switch).unwrap();
+        for doors in [10, 3, 5] {
+            let prize = room.start_random().unwrap();
+            assert!(prize < doors);
+            assert!(room.choose_random().unwrap() < doors);
+            assert!(room.reveal_random().unwrap() < doors);
+            assert_eq!(room.decide(Decision::Stick).unwrap().doors, doors);
         }
-        match &mut self.state {
-            RoomState::Joined { ready, contestant } if *ready => {
-                self.state = RoomState::Started {
-                    contestant: *contestant,
-                    current_round: 0,
-                    prize,
-                    results: vec![],
-                    stage: Stage::Choose,
-                };
-                Ok(())
-            }
-            RoomState::Started {
-                current_round,
-                prize: p,
-                stage,
-                ..
-            } if stage.is_end() && *current_round < self.settings.rounds - 1 => {
-                *current_round += 1;
-                *stage = Stage::Choose;
-                *p = prize;
-                Ok(())
-            }
-            _ => Err(Error::InvalidOperation),
+
+        let result = GameResult::calculate(&settings, room.complete(false).unwrap());
+        assert_eq!(result.stats().rounds(), 5);
+        let groups: Vec<_> = result
+            .by_doors()
+            .iter()
+            .map(|group| (group.doors(), group.stats().rounds()))
+            .collect();
+        assert_eq!(groups, [(3, 2), (5, 2), (10, 1)]);
+        let win: u32 = result
+            .by_doors()
+            .iter()
+            .map(|group| group.stats().win())
+            .sum();
+        assert_eq!(win, result.win());
+    }
+
+    #[test]
+    fn sudden_death_() {
+        let settings = Settings::new(3, 2).with_sudden_death(true);
+        let mut room = Room::create(Uuid::new_v4(), settings).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        // 第一轮赢，第二轮输，打平
+        for decision in [Decision::Stick, Decision::Switch, Decision::Switch] {
+            room.start(0).unwrap();
+            room.choose(0).unwrap();
+            room.reveal(1).unwrap();
+            let result = room.decide(decision).unwrap();
+            assert_eq!(result.win, decision == Decision::Stick);
+        }
+        // 加赛一轮输了，分出胜负
+        assert!(!room.needs_tiebreak());
+        let results = room.complete(false).unwrap();
+        assert_eq!(results.len(), 3);
+        let flags: Vec<_> = results.iter().map(|result| result.sudden_death).collect();
+        assert_eq!(flags, [false, false, true]);
+
+        room.contestant_ready(true).unwrap();
+        for decision in [Decision::Stick, Decision::Switch] {
+            room.start(0).unwrap();
+            room.choose(0).unwrap();
+            room.reveal(1).unwrap();
+            room.decide(decision).unwrap();
         }
+        assert!(room.needs_tiebreak());
+        assert!(room.complete(false).is_err());
+        room.start_random().unwrap();
     }
 
-    /// 挑战者随机选择
-    pub fn choose_random(&mut self) -> Result<u32> {
-        match &mut self.state {
-            RoomState::Started { stage, .. } => {
-                if let Stage::Choose = stage {
-                    let chosen = rand::thread_rng().gen_range(0..self.settings.doors);
-                    *stage = Stage::Reveal { chosen };
-                    Ok(chosen)
-                } else {
-                    Err(Error::InvalidOperation)
-                }
-            }
-            _ => Err(Error::InvalidOperation),
+    #[test]
+    fn round_result_getters_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(4, 1)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(2).unwrap();
+        room.choose(0).unwrap();
+        room.reveal(2).unwrap();
+        let result = room
+            .decide_with_latency(Decision::Switch, Duration::from_millis(1200))
+            .unwrap();
+        assert_eq!(
+            (
+                result.doors(),
+                result.prize(),
+                result.chosen(),
+                result.left()
+            ),
+            (4, 2, 0, 2)
+        );
+        assert_eq!(result.decision(), Decision::Switch);
+        assert!(result.win());
+        assert!(!result.sudden_death());
+        assert_eq!(result.decision_ms(), Some(1200));
+        assert_eq!(result.estimate(), None);
+    }
+
+    #[test]
+    fn round_pace_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 4)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        assert!(room.time_round(Duration::from_secs(1)).is_err());
+        for (ms, decision) in [
+            (4000, Decision::Stick),
+            (2000, Decision::Switch),
+            (6000, Decision::Switch),
+        ] {
+            room.start(1).unwrap();
+            room.choose(0).unwrap();
+            assert!(room.time_round(Duration::from_secs(1)).is_err());
+            room.reveal(1).unwrap();
+            room.decide(decision).unwrap();
+            let result = room.time_round(Duration::from_millis(ms)).unwrap();
+            assert_eq!(result.round_ms(), Some(ms));
         }
+        room.start(1).unwrap();
+        room.choose(1).unwrap();
+        room.reveal(2).unwrap();
+        room.decide(Decision::Stick).unwrap();
+
+        let stats = Stats::calculate(&room.complete(false).unwrap());
+        assert_eq!(stats.rounds(), 4);
+        assert_eq!(stats.mean_round_ms(), Some(4000));
+        assert_eq!(stats.ms_per_win(), Some(6000));
+        assert_eq!(Stats::default().mean_round_ms(), None);
+        assert_eq!(Stats::default().ms_per_win(), None);
     }
 
-    /// 挑战者做出选择
-    pub fn choose(&mut self, chosen: u32) -> Result<()> {
-        if chosen >= self.settings.doors {
-            return Err(Error::InvalidDoorIndex);
+    #[test]
+    fn decision_latency_() {
+        let settings = Settings::new(3, 4);
+        let mut room = Room::create(Uuid::new_v4(), settings.clone()).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        // 快的两轮坚持选择并中奖，慢的两轮改变选择未中奖
+        for (ms, decision) in [
+            (300, Decision::Stick),
+            (5000, Decision::Switch),
+            (800, Decision::Stick),
+            (2000, Decision::Switch),
+        ] {
+            room.start(0).unwrap();
+            room.choose(0).unwrap();
+            room.reveal(2).unwrap();
+            room.decide_with_latency(decision, Duration::from_millis(ms))
+                .unwrap();
         }
 
-        match &mut self.state {
-            RoomState::Started { stage, .. } => {
-                if let Stage::Choose = stage {
-                    *stage = Stage::Reveal { chosen };
-                    Ok(())
-                } else {
-                    Err(Error::InvalidOperation)
-                }
+        let results = room.complete(false).unwrap();
+        let latency = *GameResult::calculate(&settings, &results)
+            .by_latency()
+            .unwrap();
+        assert_eq!(latency.median_ms(), 800);
+        assert_eq!(
+            (latency.fast().rounds(), latency.fast().win_rate()),
+            (2, 1.0)
+        );
+        assert_eq!(
+            (latency.slow().rounds(), latency.slow().win_rate()),
+            (2, 0.0)
+        );
+
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|result| RoundResult {
+                decision_ms: None,
+                ..result
+            })
+            .collect();
+        assert!(GameResult::calculate(&settings, results)
+            .by_latency()
+            .is_none());
+    }
+
+    #[test]
+    fn stats_calculate_() {
+        let play = |decision| {
+            let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 4)).unwrap();
+            room.accept_contestant(Uuid::new_v4()).unwrap();
+            room.contestant_ready(true).unwrap();
+            for _ in 0..4 {
+                room.start(0).unwrap();
+                room.choose(1).unwrap();
+                room.reveal(0).unwrap();
+                room.decide(decision).unwrap();
             }
-            _ => Err(Error::InvalidOperation),
+            room.complete(false).unwrap()
+        };
+        let switch = play(Decision::Switch);
+        let stick = play(Decision::Stick);
+
+        let stats = Stats::calculate(switch.iter().chain(&stick));
+        assert_eq!((stats.rounds(), stats.switch(), stats.win()), (8, 4, 4));
+        assert_eq!((stats.switch_rate(), stats.win_rate()), (0.5, 0.5));
+        assert_eq!(Stats::calculate(&[]).switch_rate(), 0.0);
+    }
+
+    #[test]
+    fn reserve_rounds_() {
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 50)).unwrap();
+        room.reserve_rounds();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        for _ in 0..50 {
+            room.start_random().unwrap();
+            room.choose_random().unwrap();
+            room.reveal_random().unwrap();
+            room.decide(Decision::Switch).unwrap();
+            assert!(matches!(
+                room.state(),
+                RoomState::Started { results, stage: Stage::End, .. } if results.capacity() == 50
+            ));
         }
+        assert_eq!(room.complete(false).unwrap().len(), 50);
     }
 
-    /// 主持人揭示（提供留下的门序号即可）
-    pub fn reveal_random(&mut self) -> Result<u32> {
-        match &mut self.state {
-            RoomState::Started { stage, prize, .. } => {
-                if let Stage::Reveal { chosen } = stage {
-                    let left = if *chosen == *prize {
-                        random_door(self.settings.doors, *chosen)
-                    } else {
-                        *prize
-                    };
+    #[test]
+    fn small_round_result_() {
+        let result = RoundResult {
+            doors: 201,
+            prize: 200,
+            prizes: 1,
+            chosen: 3,
+            left: 200,
+            closed: 1,
+            decision: Decision::Switch,
+            win: true,
+            value: 1,
+            sudden_death: true,
+            void: false,
+            declined: false,
+            offer: None,
+            team: None,
+            decision_ms: None,
+            round_ms: None,
+            estimate: None,
+            started_at: None,
+            choose_ms: None,
+            reveal_ms: None,
+            decide_ms: None,
+        };
+        let small = SmallRoundResult::<u8>::try_from(result).unwrap();
+        assert_eq!(std::mem::size_of_val(&small), 9);
+        let full = RoundResult::from(small);
+        assert_eq!(
+            (
+                full.doors,
+                full.prize,
+                full.chosen,
+                full.left,
+                full.decision,
+                full.win,
+                full.sudden_death
+            ),
+            (201, 200, 3, 200, Decision::Switch, true, true)
+        );
+        assert_eq!(SmallRoundResult::<u8>::try_from(full).unwrap(), small);
 
-                    *stage = Stage::Decide {
-                        chosen: *chosen,
-                        left,
-                    };
-                    Ok(left)
-                } else {
-                    Err(Error::InvalidOperation)
-                }
-            }
-            _ => Err(Error::InvalidOperation),
+        let result = RoundResult {
+            doors: 301,
+            prize: 300,
+            ..result
+        };
+        assert!(SmallRoundResult::<u8>::try_from(result).is_err());
+        assert!(SmallRoundResult::<u16>::try_from(result).is_ok());
+    }
+
+    /// 模型检查中的操作
+    #[derive(Debug, Clone)]
+    enum Act {
+        Accept,
+        Kick,
+        Ready(bool),
+        Update(Settings),
+        Answer(bool),
+        Start(u32),
+        StartRandom,
+        Choose(u32),
+        ChooseRandom,
+        Reveal(u32),
+        RevealRandom,
+        Decide(Decision),
+        Complete(bool),
+        ForceComplete,
+    }
+
+    fn acts(settings: &Settings) -> Vec<Act> {
+        let mut acts = vec![
+            Act::Accept,
+            Act::Kick,
+            Act::Ready(true),
+            Act::Ready(false),
+            Act::Update(Settings::new(settings.doors + 1, settings.rounds)),
+            Act::Answer(true),
+            Act::Answer(false),
+            Act::StartRandom,
+            Act::ChooseRandom,
+            Act::RevealRandom,
+            Act::Decide(Decision::Switch),
+            Act::Decide(Decision::Stick),
+            Act::Complete(false),
+            Act::Complete(true),
+            Act::ForceComplete,
+        ];
+        let doors = settings.round(0).doors.max(settings.round(1).doors);
+        for door in 0..=doors {
+            acts.extend([Act::Start(door), Act::Choose(door), Act::Reveal(door)]);
         }
+        acts
     }
 
-    /// 主持人揭示（提供留下的门序号即可）
-    pub fn reveal(&mut self, left: u32) -> Result<()> {
-        if left >= self.settings.doors {
-            return Err(Error::InvalidDoorIndex);
+    /// 执行一个操作并检查不变量，返回操作是否成功
+    fn step(room: &mut Room, act: &Act) -> bool {
+        let before = format!("{:?}", room);
+        let final_round_ended = matches!(
+            room.state(),
+            RoomState::Started { current_round, stage: Stage::End, .. }
+                if *current_round + 1 == room.settings().rounds
+        );
+
+        let result = match *act {
+            Act::Accept => room.accept_contestant(Uuid::new_v4()),
+            Act::Kick => room.kick_contestant(),
+            Act::Ready(ready) => room.contestant_ready(ready),
+            Act::Update(ref settings) => room.update_settings(settings.clone()).map(|_| ()),
+            Act::Answer(accept) => room.answer_settings(accept).map(|_| ()),
+            Act::Start(prize) => room.start(prize),
+            Act::StartRandom => room.start_random().map(|_| ()),
+            Act::Choose(door) => room.choose(door),
+            Act::ChooseRandom => room.choose_random().map(|_| ()),
+            Act::Reveal(door) => room.reveal(door),
+            Act::RevealRandom => room.reveal_random().map(|_| ()),
+            Act::Decide(decision) => room.decide(decision).map(|_| ()),
+            Act::Complete(kick) => room.complete(kick).map(|results| {
+                assert!(final_round_ended, "completed before the final round ended");
+                assert_eq!(results.len() as u32, room.settings().rounds);
+            }),
+            Act::ForceComplete => room.force_complete().map(|results| {
+                assert!(results.len() as u32 <= room.settings().rounds);
+            }),
+        };
+
+        if result.is_err() {
+            assert_eq!(
+                before,
+                format!("{:?}", room),
+                "failed {:?} changed the room",
+                act
+            );
         }
 
-        match &mut self.state {
-            RoomState::Started { stage, prize, .. } => {
-                if let Stage::Reveal { chosen } = stage {
-                    // 1. 不可能留下挑战者已经选择的那个门；
-                    // 2. 如果挑战者选择的不是奖，则留下的必须是奖，否则主持人打开的门中就有奖了
-                    if left == *chosen || (*chosen != *prize && left != *prize) {
-                        Err(Error::InvalidOperation)
-                    } else {
-                        *stage = Stage::Decide {
-                            chosen: *chosen,
-                            left,
-                        };
-                        Ok(())
-                    }
-                } else {
-                    Err(Error::InvalidOperation)
-                }
-            }
-            _ => Err(Error::InvalidOperation),
+        if room.proposed_settings().is_some() {
+            assert!(matches!(room.state(), RoomState::Joined { .. }));
         }
-    }
+        assert!(
+            Room::restore(room.snapshot()).is_ok(),
+            "snapshot rejected after {:?}: {:?}",
+            act,
+            room
+        );
 
-    /// 挑战者做出最终抉择
-    pub fn decide(&mut self, decision: Decision) -> Result<RoundResult> {
+        let doors = room.round_settings().doors;
         if let RoomState::Started {
+            current_round,
             prize,
-            ref mut results,
+            results,
             stage,
             ..
-        } = &mut self.state
+        } = room.state()
         {
-            let result = match stage {
-                Stage::Decide { chosen, left } => {
-                    let win_the_prize = matches!((*chosen, *left, decision), (p, _, Decision::Stick) | (_, p, Decision::Switch) if p == *prize);
-                    RoundResult {
-                        prize: *prize,
-                        chosen: *chosen,
-                        left: *left,
-                        decision,
-                        win: win_the_prize,
-                    }
+            assert!(*prize < doors, "prize {} out of {} doors", prize, doors);
+            assert!(*current_round < room.settings().rounds);
+            let finished = *current_round as usize + usize::from(stage.is_end());
+            assert_eq!(results.len(), finished);
+            match *stage {
+                Stage::Choose | Stage::End | Stage::Aborted => {}
+                Stage::Reveal { chosen } => assert!(chosen < doors),
+                Stage::Decide { chosen, left, .. } => {
+                    assert!(chosen < doors && left < doors, "{:?}", stage);
+                    assert_ne!(chosen, left);
+                    assert!(chosen == *prize || left == *prize);
                 }
-                _ => return Err(Error::InvalidOperation),
-            };
-
-            results.push(result);
-            *stage = Stage::End { result };
-            Ok(result)
-        } else {
-            Err(Error::InvalidOperation)
+            }
         }
+
+        result.is_ok()
     }
 
-    /// 完成本局游戏并输出每局结果
-    pub fn complete(&mut self, kick_contestant: bool) -> Result<Vec<RoundResult>> {
-        let new_state = match &mut self.state {
-            RoomState::Started {
-                contestant,
-                current_round,
-                stage,
-                ..
-            } if stage.is_end() && *current_round >= self.settings.rounds - 1 => {
-                if kick_contestant {
-                    RoomState::Created
-                } else {
-                    RoomState::Joined {
-                        contestant: *contestant,
-                        ready: false,
-                    }
-                }
+    /// 从新房间开始执行操作序列，所有操作都成功时返回 `true`
+    fn run(settings: &Settings, acts: &[Act]) -> bool {
+        let mut room = Room::create(Uuid::new_v4(), settings.clone()).unwrap();
+        acts.iter().all(|act| step(&mut room, act))
+    }
+
+    /// 深度优先枚举操作序列。失败的操作不改变房间状态，所以只延伸全部成功的序列
+    fn explore(settings: &Settings, prefix: &mut Vec<Act>, depth: usize) {
+        for act in acts(settings) {
+            prefix.push(act);
+            if run(settings, prefix) && depth > 1 {
+                explore(settings, prefix, depth - 1);
             }
-            _ => return Err(Error::InvalidOperation),
-        };
+            prefix.pop();
+        }
+    }
 
-        match std::mem::replace(&mut self.state, new_state) {
-            RoomState::Started { results, .. } => Ok(results),
-            _ => Err(Error::Impossible),
+    #[test]
+    fn model_check_() {
+        for doors in 2..=3 {
+            for rounds in 1..=2 {
+                explore(&Settings::new(doors, rounds), &mut vec![], 7);
+            }
         }
+        explore(&Settings::new(3, 2).with_schedule(&[2, 3]), &mut vec![], 7);
     }
-}
 
-/// 一局游戏结果
-#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
-pub struct GameResult {
-    /// 游戏设置
-    settings: Settings,
-    /// 赢的轮数
-    win: u32,
-    /// 选择时就选了正确选项
-    chosen_win: u32,
-    /// 主持人留下的是正确选项
-    left_win: u32,
-    /// 改变选择的次数
-    switch: u32,
-    /// 坚持选择的次数
-    stick: u32,
-    /// 改变选择后赢的次数
-    switch_win: u32,
-    /// 坚持选择后赢的次数
-    stick_win: u32,
-}
+    #[test]
+    fn rules_() {
+        /// 不支持每轮不同的门数
+        struct Fixed;
 
-impl GameResult {
-    pub fn calculate<R>(doors: u32, results: R) -> Self
-    where
-        R: AsRef<[RoundResult]>,
-    {
-        let results = results.as_ref();
-        let settings = Settings::new(doors, results.len() as u32);
-        let mut game_result = GameResult {
-            settings,
-            win: 0,
-            chosen_win: 0,
-            left_win: 0,
-            switch: 0,
-            stick: 0,
-            switch_win: 0,
-            stick_win: 0,
-        };
+        impl Rules for Fixed {
+            fn name(&self) -> &str {
+                "fixed"
+            }
 
-        for result in results {
-            if result.chosen == result.prize {
-                game_result.chosen_win += 1;
+            fn configure(&self, settings: Settings) -> Result<Settings> {
+                if !settings.schedule.is_empty() {
+                    return Err(Error::InvalidSettings);
+                }
+                Ok(settings)
             }
+        }
+
+        let settings = Settings::new(3, 2).with_sudden_death(true).with_deals(true);
+        let classic = apply_rules(&Classic, settings.clone()).unwrap();
+        assert!(!classic.sudden_death && !classic.deals);
+        assert_eq!(classic.rules.as_deref(), Some("classic"));
+        let sudden_death = apply_rules(&SuddenDeath, Settings::new(3, 2)).unwrap();
+        assert!(sudden_death.sudden_death);
+        let forgetful = apply_rules(&Forgetful, settings.clone().with_evil(true)).unwrap();
+        assert!(forgetful.forgetful && !forgetful.evil && forgetful.sudden_death);
+        assert!(!apply_rules(&Classic, forgetful).unwrap().forgetful);
+        let evil = apply_rules(&Evil, settings.clone().with_forgetful(true)).unwrap();
+        assert!(evil.evil && !evil.forgetful);
+        assert_eq!(
+            builtin_rules()
+                .iter()
+                .map(|rules| rules.name())
+                .collect::<Vec<_>>(),
+            ["classic", "sudden-death", "forgetful", "evil", "deals"]
+        );
+        assert!(apply_rules(&Fixed, settings.clone()).is_ok());
+        assert!(apply_rules(&Fixed, settings.with_schedule(&[3, 5])).is_err());
+        let mut invalid = Settings::new(3, 2);
+        invalid.rules = Some("Bad Name".to_string());
+        assert!(invalid.validate().is_err());
+        invalid.rules = Some("a".repeat(MAX_RULES_NAME_LEN + 1));
+        assert!(invalid.validate().is_err());
+
+        /// 改变选择赢了得双倍
+        struct Doubled;
 
-            if result.left == result.prize {
-                game_result.left_win += 1;
+        impl Rules for Doubled {
+            fn name(&self) -> &str {
+                "doubled"
             }
 
-            match result.decision {
-                Decision::Switch => {
-                    game_result.switch += 1;
-                    if result.win {
-                        game_result.win += 1;
-                        game_result.switch_win += 1;
-                    }
+            fn configure(&self, settings: Settings) -> Result<Settings> {
+                if settings.deals {
+                    return Err(Error::InvalidSettings);
                 }
-                Decision::Stick => {
-                    game_result.stick += 1;
-                    if result.win {
-                        game_result.win += 1;
-                        game_result.stick_win += 1;
-                    }
+                Ok(settings)
+            }
+
+            fn score(&self, result: &RoundResult) -> u32 {
+                match result.decision() {
+                    Decision::Switch | Decision::SwitchTo(_) => result.score() * 2,
+                    _ => result.score(),
                 }
             }
         }
 
-        game_result
-    }
-
-    /// 游戏设置
-    pub fn settings(&self) -> Settings {
-        self.settings
+        assert!(apply_rules(&Doubled, Settings::new(3, 2).with_deals(true)).is_err());
+        let settings = apply_rules(&Doubled, Settings::new(3, 2)).unwrap();
+        let mut room = Room::create(Uuid::new_v4(), settings.clone()).unwrap();
+        room.set_rules(Arc::new(Doubled));
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        room.choose(1).unwrap();
+        room.reveal(0).unwrap();
+        assert!(room.decide(Decision::Switch).unwrap().win());
+        assert_eq!(room.score(), 2);
+        room.start(0).unwrap();
+        room.choose(0).unwrap();
+        room.reveal(1).unwrap();
+        assert!(room.decide(Decision::Stick).unwrap().win());
+        assert_eq!(room.score(), 3);
+        // 插件规则的得分不能按默认计分检查
+        let restored = Room::restore(room.snapshot()).unwrap();
+        assert_eq!(restored.score(), 3);
+        let results = room.complete(false).unwrap();
+        let score = *GameResult::calculate_with_rules(&settings, &results, &Doubled)
+            .score()
+            .unwrap();
+        assert_eq!((score.score(), score.stakes()), (3, 2));
+        // 没有按规则计算时使用默认计分
+        let score = *GameResult::calculate(&settings, &results).score().unwrap();
+        assert_eq!(score.score(), 2);
+        assert!(GameResult::calculate(&Settings::new(3, 2), &results)
+            .score()
+            .is_none());
     }
 
-    /// 赢的轮数
-    pub fn win(&self) -> u32 {
-        self.win
-    }
+    #[test]
+    fn test_vectors_() {
+        let vectors = test_vectors();
+        let names: std::collections::HashSet<_> = vectors.iter().map(|v| &v.name).collect();
+        assert_eq!(names.len(), vectors.len());
+        for vector in &vectors {
+            let json = serde_json::to_string(&vector.message).unwrap();
+            assert_eq!(vector.check(&json), Ok(()), "{}", vector.name);
+        }
 
-    /// 选择时就选了正确选项
-    pub fn chosen_win(&self) -> u32 {
-        self.chosen_win
+        let decide = vectors.iter().find(|v| v.name == "decide").unwrap();
+        let mismatch = decide
+            .check(r#"{"action": "Decide", "decision": "switch"}"#)
+            .unwrap_err();
+        assert_eq!(mismatch.path, "$.decision");
+        let mismatch = decide
+            .check(r#"{"action": "Decide", "decision": "Switch", "seq": 1}"#)
+            .unwrap_err();
+        assert_eq!(mismatch.reason, "unexpected field `seq`");
+        assert!(decide.check("{").is_err());
     }
 
-    /// 主持人留下的是正确选项
-    pub fn left_win(&self) -> u32 {
-        self.left_win
+    #[test]
+    fn request_name_() {
+        let mut names = std::collections::HashSet::new();
+        for vector in test_vectors() {
+            if vector.direction == Direction::Request {
+                let request: protocol::GameRequest =
+                    serde_json::from_value(vector.message.clone()).unwrap();
+                assert_eq!(request.name(), vector.message["action"], "{}", vector.name);
+                names.insert(request.name());
+            }
+        }
+        for (deprecated, replacement) in protocol::DEPRECATED_REQUESTS {
+            assert!(names.contains(deprecated), "{}", deprecated);
+            assert!(!replacement.is_empty());
+        }
     }
 
-    /// 改变选择的次数
-    pub fn switch(&self) -> u32 {
-        self.switch
-    }
+    #[test]
+    fn contestant_strategy_() {
+        fn play(strategy: &mut impl ContestantStrategy) -> GameResult {
+            let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 3000)).unwrap();
+            room.accept_contestant(Uuid::new_v4()).unwrap();
+            room.contestant_ready(true).unwrap();
+            for _ in 0..room.settings().rounds {
+                room.start_random().unwrap();
+                let chosen = strategy.choose(3);
+                room.choose(chosen).unwrap();
+                let left = room.reveal_random().unwrap();
+                room.decide(strategy.decide(3, chosen, left)).unwrap();
+            }
+            let results = room.complete(false).unwrap();
+            GameResult::calculate(room.settings(), results)
+        }
 
-    /// 坚持选择的次数
-    pub fn stick(&self) -> u32 {
-        self.stick
-    }
+        let switch = play(&mut FixedDecision(Decision::Switch));
+        assert_eq!(switch.switch(), 3000);
+        let stick = play(&mut FixedDecision(Decision::Stick));
+        assert_eq!(stick.stick(), 3000);
+        // 改变选择的中奖率约为 2/3，坚持选择约为 1/3
+        assert!(switch.win() > 1800 && stick.win() < 1200);
 
-    /// 改变选择后赢的次数
-    pub fn switch_win(&self) -> u32 {
-        self.switch_win
+        let random = play(&mut RandomDecision);
+        assert!(random.switch() > 0 && random.stick() > 0);
     }
 
-    /// 坚持选择后赢的次数
-    pub fn stick_win(&self) -> u32 {
-        self.stick_win
+    #[test]
+    fn host_strategy_() {
+        for host in [&mut RandomHost as &mut dyn HostStrategy, &mut LazyHost] {
+            for _ in 0..1000 {
+                let doors = rand::thread_rng().gen_range(3..10);
+                let prize = host.hide(doors);
+                let chosen = rand::thread_rng().gen_range(0..doors);
+                let left = host.leave(doors, prize, chosen);
+                assert!(prize < doors && left < doors);
+                assert_ne!(left, chosen);
+                assert!(chosen == prize || left == prize);
+            }
+        }
+        assert_eq!(LazyHost.leave(5, 0, 0), 1);
+        assert_eq!(LazyHost.leave(5, 3, 3), 0);
     }
-}
 
-// 在 [0, doors) 范围内生成 exclusive 之外的随机整数
-fn random_door(doors: u32, exclusive: u32) -> u32 {
-    assert!(
-        exclusive < doors,
-        "doors = {}, exclusive = {}",
-        doors,
-        exclusive
-    );
+    #[test]
+    fn quiz_() {
+        let settings = Settings::new(3, 2).with_quiz(true);
+        let mut room = Room::create(Uuid::new_v4(), settings.clone()).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        for (chosen, left, percent) in [(0, 2, 50), (1, 0, 70)] {
+            room.start(0).unwrap();
+            assert!(room.estimate(50).is_err());
+            room.choose(chosen).unwrap();
+            room.reveal(left).unwrap();
+            assert!(matches!(
+                room.decide(Decision::Switch),
+                Err(Error::EstimateRequired)
+            ));
+            assert!(matches!(room.estimate(101), Err(Error::InvalidEstimate)));
+            room.estimate(percent).unwrap();
+            assert!(room.estimate(percent).is_err());
+            let result = room.decide(Decision::Switch).unwrap();
+            assert_eq!(result.estimate(), Some(percent));
+        }
 
-    let random = rand::thread_rng().gen_range(0..doors);
+        let results = room.complete(false).unwrap();
+        let quiz = *GameResult::calculate(&settings, &results).quiz().unwrap();
+        assert_eq!(quiz.estimates(), 2);
+        assert!((quiz.mean_estimate() - 0.6).abs() < 1e-9);
+        assert!((quiz.expected() - 2.0 / 3.0).abs() < 1e-9);
+        assert!((quiz.observed() - 0.5).abs() < 1e-9);
+        assert!((quiz.mean_error() - (1.0 / 6.0 + 1.0 / 30.0) / 2.0).abs() < 1e-9);
 
-    if random >= exclusive {
-        random + 1
-    } else {
-        random
+        let mut room = Room::create(Uuid::new_v4(), Settings::new(3, 1)).unwrap();
+        room.accept_contestant(Uuid::new_v4()).unwrap();
+        room.contestant_ready(true).unwrap();
+        room.start(0).unwrap();
+        room.choose(0).unwrap();
+        room.reveal(1).unwrap();
+        assert!(room.estimate(50).is_err());
+        assert!(room.decide(Decision::Stick).unwrap().estimate().is_none());
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::random_door;
-    use rand::Rng;
 
     #[test]
     fn random_door_() {
@@ -595,6 +6514,7 @@ mod test {
             let exclusive = rand::thread_rng().gen_range(0..doors);
             let door = random_door(doors, exclusive);
             assert_ne!(door, exclusive);
+            assert!(door < doors);
         }
     }
 }
@@ -0,0 +1,914 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+use crate::fairness::RoundProof;
+use crate::*;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(tag = "action")]
+pub enum GameRequest {
+    ListRooms {
+        page: u32,
+        size: u32,
+    },
+    EnterRoom {
+        id: Uuid,
+    },
+    Spectate {
+        id: Uuid,
+    },
+    ExitRoom {
+        id: Uuid,
+    },
+    Ready {
+        ready: bool,
+    },
+    Choose {
+        chosen: Index,
+    },
+    Decide {
+        decision: Decision,
+    },
+    /// 测验模式下估计改变选择的中奖率（百分比）
+    Estimate {
+        percent: u32,
+    },
+    CreateRoom {
+        settings: Option<Settings>,
+        pacing: Option<Pacing>,
+        /// 房间所属的班级，老师可以在班级看板上查看同一班级所有房间的汇总
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        class: Option<String>,
+        /// 使用服务器上的课程包，课程包的设置、节奏和脚本优先
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lesson: Option<String>,
+        /// 创建通信对局房间，每个阶段可以持续很长时间，需要服务器配置 `correspondence_dir`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        correspondence: Option<Correspondence>,
+        /// 创建公平模式房间，奖品位置由服务器私钥的 VRF 决定，需要服务器配置 `fairness_key`
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        fair: bool,
+        /// 向观众发送这种语言（例如 `en`、`zh`）的解说
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        commentary: Option<String>,
+        /// 房间名称，见 [`Room::update_details`]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        /// 房间介绍
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+    },
+    /// 挑战者进入房间后为设置提议，挑战者确认后才生效
+    UpdateSettings {
+        settings: Settings,
+    },
+    /// 挑战者确认或者拒绝主持人提议的设置
+    AnswerSettings {
+        accept: bool,
+    },
+    /// 修改房间名称和介绍，为空时清除
+    UpdateDetails {
+        name: Option<String>,
+        description: Option<String>,
+    },
+    UpdatePacing {
+        pacing: Pacing,
+    },
+    Start {
+        prize: Index,
+        /// 房间设置有多个奖品时其余奖品所在的门，不指定时由服务器随机放置
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        others: Vec<u32>,
+        /// 只在这一轮使用的门数，不指定时使用设置中这一轮的门数
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doors: Option<u32>,
+    },
+    Reveal {
+        left: Index,
+        /// 房间设置留下多个门时其余留下的门，不指定时由服务器随机选择
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        others: Vec<u32>,
+    },
+    /// 邪恶的主持人不揭示，按挑战者的选择直接结束这一轮
+    Decline,
+    /// 主持人在揭示之后出价买断，再次出价时代替之前的出价
+    OfferDeal {
+        offer: u32,
+    },
+    /// 撤销本轮最近的一次操作，例如主持人揭示时点错了门
+    Undo,
+    /// 放弃进行中的一轮，之后重新开始这一轮
+    AbortRound,
+    Complete {
+        kick_contestant: bool,
+    },
+    KickContestant,
+    PromoteCoHost {
+        user_id: Uuid,
+    },
+    /// 主持人在游戏开始前添加团队，`captain` 提交这个团队的选择和抉择
+    AddTeam {
+        name: String,
+        captain: Uuid,
+    },
+    /// 挑战者或者观众加入团队，`team` 是团队的序号
+    JoinTeam {
+        team: u32,
+    },
+    /// 离开所在的团队，队长不能离开
+    LeaveTeam,
+    Chat {
+        text: String,
+    },
+    /// 观众的反应，服务器汇总后定期广播次数
+    Reaction {
+        emoji: String,
+    },
+    Moderate {
+        room_id: Uuid,
+        command: Moderation,
+    },
+    /// 设置主持人脚本，为空时移除脚本
+    SetScript {
+        script: Option<String>,
+    },
+    /// 邀请机器人作为挑战者，`bot` 是内置策略的名称或者上传的机器人 ID
+    InviteBot {
+        bot: String,
+    },
+    /// 注册接收轮次通知的推送设备，为空时移除设备
+    RegisterDevice {
+        device: Option<Device>,
+    },
+    /// 公平模式下挑战者在开始前提供的随机串，和房间 ID、轮次一起决定奖品位置
+    SetNonce {
+        nonce: String,
+    },
+    /// 双方共同决定奖品位置时主持人为下一轮提交种子，其他成员只能看到种子的承诺
+    CommitSeed {
+        seed: String,
+    },
+    /// 双方共同决定奖品位置时挑战者在主持人提交种子之后为下一轮提供随机串
+    SupplyNonce {
+        nonce: String,
+    },
+    /// 为房间中已完成的一局游戏生成分享卡片，`game` 为空时是最近完成的一局
+    ShareGame {
+        #[serde(default)]
+        game: Option<u64>,
+    },
+}
+
+/// 客户端发来的消息
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct ClientMessage {
+    #[serde(flatten)]
+    pub request: GameRequest,
+    /// 客户端看到的房间事件序号（`RoomInfo::seq`），房间已经有新的事件时拒绝修改操作
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_seq: Option<u64>,
+}
+
+impl GameRequest {
+    /// 请求中指定的房间
+    pub fn room_id(&self) -> Option<Uuid> {
+        match self {
+            GameRequest::EnterRoom { id }
+            | GameRequest::Spectate { id }
+            | GameRequest::ExitRoom { id } => Some(*id),
+            GameRequest::Moderate { room_id, .. } => Some(*room_id),
+            _ => None,
+        }
+    }
+
+    /// 请求的 `action` 标签
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameRequest::ListRooms { .. } => "ListRooms",
+            GameRequest::EnterRoom { .. } => "EnterRoom",
+            GameRequest::Spectate { .. } => "Spectate",
+            GameRequest::ExitRoom { .. } => "ExitRoom",
+            GameRequest::Ready { .. } => "Ready",
+            GameRequest::Choose { .. } => "Choose",
+            GameRequest::Decide { .. } => "Decide",
+            GameRequest::Estimate { .. } => "Estimate",
+            GameRequest::CreateRoom { .. } => "CreateRoom",
+            GameRequest::UpdateSettings { .. } => "UpdateSettings",
+            GameRequest::AnswerSettings { .. } => "AnswerSettings",
+            GameRequest::UpdateDetails { .. } => "UpdateDetails",
+            GameRequest::UpdatePacing { .. } => "UpdatePacing",
+            GameRequest::Start { .. } => "Start",
+            GameRequest::Reveal { .. } => "Reveal",
+            GameRequest::Decline => "Decline",
+            GameRequest::OfferDeal { .. } => "OfferDeal",
+            GameRequest::Undo => "Undo",
+            GameRequest::AbortRound => "AbortRound",
+            GameRequest::Complete { .. } => "Complete",
+            GameRequest::KickContestant => "KickContestant",
+            GameRequest::PromoteCoHost { .. } => "PromoteCoHost",
+            GameRequest::AddTeam { .. } => "AddTeam",
+            GameRequest::JoinTeam { .. } => "JoinTeam",
+            GameRequest::LeaveTeam => "LeaveTeam",
+            GameRequest::Chat { .. } => "Chat",
+            GameRequest::Reaction { .. } => "Reaction",
+            GameRequest::Moderate { .. } => "Moderate",
+            GameRequest::SetScript { .. } => "SetScript",
+            GameRequest::InviteBot { .. } => "InviteBot",
+            GameRequest::RegisterDevice { .. } => "RegisterDevice",
+            GameRequest::SetNonce { .. } => "SetNonce",
+            GameRequest::CommitSeed { .. } => "CommitSeed",
+            GameRequest::SupplyNonce { .. } => "SupplyNonce",
+            GameRequest::ShareGame { .. } => "ShareGame",
+        }
+    }
+
+    /// 废弃的请求应该改用的请求或者用法，见 [`DEPRECATED_REQUESTS`]，没有废弃时为空
+    pub fn replacement(&self) -> Option<&'static str> {
+        let name = self.name();
+        DEPRECATED_REQUESTS
+            .iter()
+            .find(|(deprecated, _)| *deprecated == name)
+            .map(|(_, replacement)| *replacement)
+    }
+}
+
+/// 废弃的请求（`action` 标签）和应该改用的请求或者用法。服务器仍然正常处理废弃的请求，
+/// 之后另外回复 [`GameResponse::Deprecated`] 并记录使用情况，确认没有客户端使用之后才能移除。
+/// 修改响应时保留原来的变体，同时发送新的变体，不在这里列出
+pub const DEPRECATED_REQUESTS: &[(&str, &str)] = &[];
+
+/// 门序号，指定时为 `{"type": "Specified", "value": 2}`
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(tag = "type", content = "value")]
+pub enum Index {
+    Random,
+    Specified(u32),
+}
+
+impl Index {
+    /// 指定的门序号，随机时为空
+    pub fn door(&self) -> Option<u32> {
+        match self {
+            Index::Random => None,
+            Index::Specified(door) => Some(*door),
+        }
+    }
+}
+
+/// 版主的管理操作，可以作用于任意房间
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum Moderation {
+    /// 悄悄进入房间旁观，不通知房间成员
+    Watch,
+    Mute {
+        user_id: Uuid,
+    },
+    Unmute {
+        user_id: Uuid,
+    },
+    /// 踢出主持人以外的任意成员
+    Kick {
+        user_id: Uuid,
+    },
+    /// 强制结束卡住的游戏
+    ForceComplete,
+}
+
+#[derive(thiserror::Error, Debug, Serialize, Deserialize, Clone)]
+pub enum ServerError {
+    #[error("Room not found: {}", .id)]
+    RoomNotFound { id: Uuid },
+    #[error("Permission denied: {:?}", .action)]
+    PermissionDenied { action: Action },
+    #[error("You have been muted")]
+    Muted,
+    #[error("Room quarantined after an internal error: {}", .id)]
+    RoomQuarantined { id: Uuid },
+    #[error("Internal server error")]
+    Internal,
+    #[error("Server is draining, no new rooms can be created")]
+    Draining,
+    #[error("Quota exceeded: {:?}", .kind)]
+    QuotaExceeded { kind: QuotaKind },
+    #[error("Host script error: {}", .message)]
+    Script { message: String },
+    #[error("Rules not found: {}", .name)]
+    RulesNotFound { name: String },
+    #[error("Rules {} do not support these settings", .name)]
+    RulesRejected { name: String },
+    #[error("Bot not found: {}", .name)]
+    BotNotFound { name: String },
+    #[error("Lesson not found: {}", .name)]
+    LessonNotFound { name: String },
+    #[error("Commentary not available in: {}", .locale)]
+    CommentaryNotFound { locale: String },
+    #[error("Correspondence games are not enabled on this server")]
+    CorrespondenceDisabled,
+    #[error("Reactions must be a short emoji")]
+    InvalidReaction,
+    #[error("Fair rooms are not enabled on this server")]
+    FairnessDisabled,
+    #[error("No such completed game in this room")]
+    GameNotFound,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "resp")]
+pub enum GameResponse {
+    UserCreated {
+        id: Uuid,
+        /// 断开连接后用 `resume` 查询参数恢复会话，服务器没有开启会话恢复时为空
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        resume_token: Option<Uuid>,
+    },
+    /// 会话已恢复，用户仍在房间中时附带房间信息
+    SessionResumed {
+        id: Uuid,
+        info: Option<RoomInfo>,
+    },
+    RoomList {
+        rooms: Vec<RoomInfo>,
+        page: u32,
+        size: u32,
+        total: u32,
+    },
+    RoomCreated {
+        info: RoomInfo,
+    },
+    Exited {
+        user_id: Uuid,
+    },
+    RoomEntered {
+        contestant_id: Uuid,
+    },
+    ContestantRoomEntered {
+        info: RoomInfo,
+    },
+    SpectatorEntered {
+        user_id: Uuid,
+    },
+    Spectating {
+        info: RoomInfo,
+    },
+    CoHostPromoted {
+        user_id: Uuid,
+    },
+    /// 团队有变化，`teams` 是所有团队
+    TeamsUpdated {
+        teams: Vec<Team>,
+    },
+    SettingsUpdated {
+        notify: bool,
+        settings: Settings,
+    },
+    /// 主持人提议的设置，等待挑战者确认
+    SettingsProposed {
+        settings: Settings,
+    },
+    /// 挑战者拒绝了提议的设置
+    SettingsRejected {
+        settings: Settings,
+    },
+    PacingUpdated {
+        pacing: Pacing,
+    },
+    Ready {
+        ready: bool,
+    },
+    Started {
+        prize: u32,
+        /// 有多个奖品时其余奖品所在的门
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        others: Vec<u32>,
+        /// 主持人只为这一轮指定的门数，为空时使用设置中这一轮的门数
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doors: Option<u32>,
+        random: bool,
+        /// 供屏幕阅读器朗读的描述
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        announcement: Option<String>,
+    },
+    ContestantStarted {
+        /// 主持人只为这一轮指定的门数，为空时使用设置中这一轮的门数
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        doors: Option<u32>,
+        random: bool,
+        /// 供屏幕阅读器朗读的描述
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        announcement: Option<String>,
+    },
+    Chosen {
+        chosen: u32,
+        random: bool,
+        /// 供屏幕阅读器朗读的描述
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        announcement: Option<String>,
+    },
+    Revealed {
+        left: u32,
+        /// 房间设置留下多个门时其余留下的门
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        others: Vec<u32>,
+        random: bool,
+        /// 供屏幕阅读器朗读的描述
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        announcement: Option<String>,
+    },
+    /// 房间名称和介绍已经修改
+    DetailsUpdated {
+        name: Option<String>,
+        description: Option<String>,
+    },
+    /// 测验模式下挑战者需要先估计改变选择的中奖率才能抉择
+    EstimateRequested {
+        /// 课程包中本轮的问题
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        question: Option<String>,
+    },
+    Estimated {
+        percent: u32,
+    },
+    /// 主持人出价买断，挑战者可以用 `TakeDeal` 接受出价
+    DealOffered {
+        offer: u32,
+    },
+    Decided {
+        result: RoundResult,
+        /// 供屏幕阅读器朗读的描述
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        announcement: Option<String>,
+    },
+    /// 健忘的主持人打开了有奖的门，这一轮作废，代替 `Revealed`，挑战者不再抉择
+    RoundVoided {
+        result: RoundResult,
+        /// 供屏幕阅读器朗读的描述
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        announcement: Option<String>,
+    },
+    /// 撤销了本轮最近的一次操作，`stage` 是恢复后的阶段
+    Undone {
+        stage: Stage,
+    },
+    /// 放弃了进行中的一轮，主持人重新开始时仍然是这一轮
+    RoundAborted {
+        round: u32,
+    },
+    /// 服务器重启后进行中的一轮回到挑战者选择，奖品位置不变
+    RoundRolledBack {
+        round: u32,
+    },
+    /// 挑战者暂时断开连接，游戏暂停，恢复会话后继续
+    Paused,
+    /// 挑战者恢复会话，游戏继续
+    Resumed,
+    /// 规定轮数打完后打平，需要加赛
+    SuddenDeath {
+        round: u32,
+        /// 供屏幕阅读器朗读的描述
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        announcement: Option<String>,
+    },
+    Completed {
+        result: Box<GameResult>,
+        /// 公平模式下每轮的证明，可以用服务器公钥验证
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        proofs: Vec<RoundProof>,
+        /// 供屏幕阅读器朗读的描述
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        announcement: Option<String>,
+    },
+    /// 课程包按报告模板生成的本局报告，紧跟在 `Completed` 之后
+    LessonReport {
+        text: String,
+    },
+    GameError {
+        cause: Error,
+    },
+    ServerError {
+        cause: ServerError,
+    },
+    ChatMessage {
+        user_id: Uuid,
+        text: String,
+    },
+    /// 最近一段时间内观众每种反应的次数，不计入房间事件序号
+    Reactions {
+        counts: BTreeMap<String, u32>,
+    },
+    Muted {
+        user_id: Uuid,
+        muted: bool,
+    },
+    Watching {
+        info: RoomInfo,
+    },
+    Moderated {
+        room_id: Uuid,
+        command: Moderation,
+    },
+    Conflict {
+        current_seq: u64,
+        info: RoomInfo,
+    },
+    /// 主持人脚本已设置或移除
+    ScriptUpdated {
+        enabled: bool,
+    },
+    /// 推送设备已注册或者移除
+    DeviceUpdated {
+        registered: bool,
+    },
+    /// 挑战者提供了公平模式的随机串
+    NonceSet {
+        nonce: String,
+    },
+    /// 主持人为 `round` 提交了种子，`commitment` 是种子的 SHA-256（十六进制）
+    SeedCommitted {
+        round: u32,
+        commitment: String,
+    },
+    /// 挑战者为 `round` 提供了随机串
+    NonceSupplied {
+        round: u32,
+        nonce: String,
+    },
+    /// 按种子和随机串放置奖品的一轮结束，公布种子供挑战者验证承诺和奖品位置
+    SeedRevealed {
+        round: u32,
+        seed: String,
+        nonce: String,
+    },
+    /// 同一用户的新连接接管了会话，这个连接随后关闭
+    ConnectionReplaced,
+    /// 刚处理的 `request` 已经废弃，客户端应该改用 `replacement`，见 [`DEPRECATED_REQUESTS`]
+    Deprecated {
+        request: String,
+        replacement: String,
+    },
+    /// 只发给观众的解说，不计入房间事件序号
+    Commentary {
+        text: String,
+    },
+    /// 房间的生命周期变化
+    LifecycleChanged {
+        lifecycle: RoomLifecycle,
+    },
+    /// 分享卡片已生成，只发给请求的用户。`/s/{code}` 返回 JSON 摘要，`/s/{code}/card.svg` 返回图片
+    GameShared {
+        game: u64,
+        code: String,
+    },
+}
+
+impl GameResponse {
+    /// 是否是一轮游戏的阶段变化，受房间节奏控制
+    pub fn is_transition(&self) -> bool {
+        matches!(
+            self,
+            GameResponse::Started { .. }
+                | GameResponse::ContestantStarted { .. }
+                | GameResponse::Chosen { .. }
+                | GameResponse::Revealed { .. }
+                | GameResponse::Decided { .. }
+                | GameResponse::RoundVoided { .. }
+                | GameResponse::SuddenDeath { .. }
+                | GameResponse::RoundAborted { .. }
+                | GameResponse::RoundRolledBack { .. }
+                | GameResponse::Completed { .. }
+        )
+    }
+}
+
+/// 服务器发给客户端的消息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerMessage {
+    #[serde(flatten)]
+    pub response: GameResponse,
+    /// 发出消息时的服务器时间（Unix 时间戳，毫秒），客户端用来校正本地时钟
+    pub server_time: u64,
+    /// 房间广播发出时本局游戏已经进行的毫秒数，游戏开始前和非广播消息没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_clock: Option<u64>,
+    /// 广播给多个成员时预先序列化的消息，所有克隆共享，见 [`ServerMessage::to_json`]
+    #[serde(skip)]
+    pub encoded: Option<Arc<str>>,
+}
+
+impl ServerMessage {
+    /// 序列化为 JSON，已经预先序列化时直接返回共享的结果
+    pub fn to_json(&self) -> serde_json::Result<Arc<str>> {
+        match &self.encoded {
+            Some(encoded) => Ok(encoded.clone()),
+            None => serde_json::to_string(self).map(Arc::from),
+        }
+    }
+}
+
+impl From<GameResponse> for ServerMessage {
+    fn from(response: GameResponse) -> Self {
+        ServerMessage {
+            response,
+            server_time: timestamp(),
+            game_clock: None,
+            encoded: None,
+        }
+    }
+}
+
+impl From<RoomPage> for GameResponse {
+    fn from(page: RoomPage) -> Self {
+        GameResponse::RoomList {
+            rooms: page.rooms,
+            page: page.page,
+            size: page.size,
+            total: page.total,
+        }
+    }
+}
+
+impl From<Result<GameResponse>> for GameResponse {
+    fn from(result: Result<GameResponse>) -> Self {
+        match result {
+            Ok(response) => response,
+            Err(cause) => GameResponse::GameError { cause },
+        }
+    }
+}
+
+impl From<std::result::Result<GameResponse, ServerError>> for GameResponse {
+    fn from(result: std::result::Result<GameResponse, ServerError>) -> Self {
+        match result {
+            Ok(response) => response,
+            Err(cause) => GameResponse::ServerError { cause },
+        }
+    }
+}
+
+/// 房间节奏预设，控制两次阶段变化广播之间的最短间隔
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum Pacing {
+    /// 立即广播（默认）
+    #[default]
+    Instant,
+
+    /// 正常节奏
+    Normal,
+
+    /// 综艺节目般的戏剧节奏
+    Dramatic,
+}
+
+impl Pacing {
+    /// 两次阶段变化广播之间的最短间隔
+    pub fn delay(&self) -> Duration {
+        match self {
+            Pacing::Instant => Duration::ZERO,
+            Pacing::Normal => Duration::from_millis(800),
+            Pacing::Dramatic => Duration::from_millis(2500),
+        }
+    }
+}
+
+/// 需要检查权限的操作
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+pub enum Action {
+    ListRooms,
+    CreateRoom,
+    EnterRoom,
+    Spectate,
+    ExitRoom,
+    Ready,
+    Choose,
+    Decide,
+    Estimate,
+    UpdateSettings,
+    AnswerSettings,
+    UpdateDetails,
+    UpdatePacing,
+    Start,
+    Reveal,
+    Undo,
+    AbortRound,
+    Complete,
+    Kick,
+    PromoteCoHost,
+    ManageTeams,
+    JoinTeam,
+    Chat,
+    Reaction,
+    Moderate,
+    SetScript,
+    InviteBot,
+    RegisterDevice,
+    SetNonce,
+    CommitSeed,
+    SupplyNonce,
+    ShareGame,
+}
+
+impl From<&GameRequest> for Action {
+    fn from(request: &GameRequest) -> Self {
+        match request {
+            GameRequest::ListRooms { .. } => Action::ListRooms,
+            GameRequest::EnterRoom { .. } => Action::EnterRoom,
+            GameRequest::Spectate { .. } => Action::Spectate,
+            GameRequest::ExitRoom { .. } => Action::ExitRoom,
+            GameRequest::Ready { .. } => Action::Ready,
+            GameRequest::Choose { .. } => Action::Choose,
+            GameRequest::Decide { .. } => Action::Decide,
+            GameRequest::Estimate { .. } => Action::Estimate,
+            GameRequest::CreateRoom { .. } => Action::CreateRoom,
+            GameRequest::UpdateSettings { .. } => Action::UpdateSettings,
+            GameRequest::AnswerSettings { .. } => Action::AnswerSettings,
+            GameRequest::UpdateDetails { .. } => Action::UpdateDetails,
+            GameRequest::UpdatePacing { .. } => Action::UpdatePacing,
+            GameRequest::Start { .. } => Action::Start,
+            GameRequest::Reveal { .. } | GameRequest::Decline | GameRequest::OfferDeal { .. } => {
+                Action::Reveal
+            }
+            GameRequest::Undo => Action::Undo,
+            GameRequest::AbortRound => Action::AbortRound,
+            GameRequest::Complete { .. } => Action::Complete,
+            GameRequest::KickContestant => Action::Kick,
+            GameRequest::PromoteCoHost { .. } => Action::PromoteCoHost,
+            GameRequest::AddTeam { .. } => Action::ManageTeams,
+            GameRequest::JoinTeam { .. } | GameRequest::LeaveTeam => Action::JoinTeam,
+            GameRequest::Chat { .. } => Action::Chat,
+            GameRequest::Reaction { .. } => Action::Reaction,
+            GameRequest::Moderate { .. } => Action::Moderate,
+            GameRequest::SetScript { .. } => Action::SetScript,
+            GameRequest::InviteBot { .. } => Action::InviteBot,
+            GameRequest::RegisterDevice { .. } => Action::RegisterDevice,
+            GameRequest::SetNonce { .. } => Action::SetNonce,
+            GameRequest::CommitSeed { .. } => Action::CommitSeed,
+            GameRequest::SupplyNonce { .. } => Action::SupplyNonce,
+            GameRequest::ShareGame { .. } => Action::ShareGame,
+        }
+    }
+}
+
+impl Action {
+    /// 是否会修改房间状态，这些操作可以携带 `expected_seq`
+    pub fn is_mutating(&self) -> bool {
+        matches!(
+            self,
+            Action::Ready
+                | Action::Choose
+                | Action::Decide
+                | Action::Estimate
+                | Action::UpdateSettings
+                | Action::AnswerSettings
+                | Action::UpdateDetails
+                | Action::UpdatePacing
+                | Action::Start
+                | Action::Reveal
+                | Action::Undo
+                | Action::AbortRound
+                | Action::Complete
+                | Action::Kick
+                | Action::PromoteCoHost
+                | Action::ManageTeams
+                | Action::JoinTeam
+                | Action::InviteBot
+                | Action::SetNonce
+                | Action::CommitSeed
+                | Action::SupplyNonce
+        )
+    }
+}
+
+/// 推送服务
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+pub enum Platform {
+    /// Apple Push Notification service
+    Apns,
+    /// Firebase Cloud Messaging
+    Fcm,
+}
+
+/// 客户端注册的推送设备
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Device {
+    pub platform: Platform,
+    /// APNs 的设备令牌或者 FCM 的注册令牌
+    pub token: String,
+}
+
+/// 配额项
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum QuotaKind {
+    Rooms,
+    RoundsPerDay,
+}
+
+/// 通信对局选项：每个阶段可以持续几个小时甚至几天，成员可以随时断开，回来时恢复会话继续操作
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+pub struct Correspondence {
+    /// 每个阶段的时限（秒），默认一天。主持人超时由服务器随机操作，挑战者超时本局按已完成的轮次结束
+    #[serde(default = "default_turn_secs")]
+    pub turn_secs: u64,
+}
+
+fn default_turn_secs() -> u64 {
+    24 * 60 * 60
+}
+
+/// 房间在大厅中显示的状态
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum RoomStatus {
+    /// 等待挑战者加入
+    Waiting,
+    /// 挑战者已加入，尚未开始
+    Joined { ready: bool },
+    /// 游戏进行中
+    Playing { round: u32 },
+}
+
+impl From<&RoomState> for RoomStatus {
+    fn from(state: &RoomState) -> Self {
+        match state {
+            RoomState::Created => RoomStatus::Waiting,
+            RoomState::Joined { ready, .. } => RoomStatus::Joined { ready: *ready },
+            RoomState::Started { current_round, .. } => RoomStatus::Playing {
+                round: *current_round,
+            },
+        }
+    }
+}
+
+/// 房间的生命周期，和一轮游戏的阶段无关，变化时广播 `LifecycleChanged`
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub enum RoomLifecycle {
+    /// 没有进行中的游戏，可以开始
+    #[default]
+    Open,
+    /// 游戏进行中
+    InGame,
+    /// 挑战者暂时断开连接，游戏暂停
+    Paused,
+    /// 刚完成一局游戏，挑战者还在房间中
+    Completed,
+    /// 房间被隔离，只保留用于导出调试信息，不再处理游戏请求
+    Archived,
+    /// 主持人离开或者房间被删除
+    Closed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct RoomInfo {
+    pub id: Uuid,
+    /// 房间名称
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// 房间介绍
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub settings: Settings,
+    pub pacing: Pacing,
+    pub status: RoomStatus,
+    #[serde(default)]
+    pub lifecycle: RoomLifecycle,
+    pub spectators: u32,
+    /// 房间已经广播的事件数，每次广播加一
+    pub seq: u64,
+    /// 当前阶段的截止时间（Unix 时间戳，毫秒），来自通信对局或者设置的阶段时限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<u64>,
+    /// 主持人提议、等待挑战者确认的设置
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposed_settings: Option<Settings>,
+    /// 公平模式房间
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fair: bool,
+    /// 团队模式下的团队
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teams: Vec<Team>,
+}
+
+/// 大厅中的一页房间
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct RoomPage {
+    pub rooms: Vec<RoomInfo>,
+    pub page: u32,
+    pub size: u32,
+    pub total: u32,
+}
+
+/// Unix 时间戳（毫秒）
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
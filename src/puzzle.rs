@@ -0,0 +1,149 @@
+//! 离线谜题：出题人用种子生成一局游戏，每轮的奖品位置由种子决定，谜题中公开种子的承诺。
+//! 挑战者不需要服务器，在本地按谜题玩完一局后交出答卷，任何人都可以用谜题重放答卷来验证
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::fairness::{self, decode, encode};
+use crate::{
+    Decision, Error, GameResult, Result, Room, RoomState, RoundResult, Settings, Stage, Uuid,
+};
+
+/// 谜题文件的格式版本
+pub const PUZZLE_VERSION: u32 = 1;
+
+/// 一局离线游戏的设置和决定奖品位置的种子
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Puzzle {
+    pub version: u32,
+    pub settings: Settings,
+    /// 种子的承诺，见 [`fairness::commitment`]
+    pub commitment: String,
+    /// 遮掩后的种子（十六进制），只是不让奖品位置一眼就能看出来，挑战者有意解开时无法阻止
+    sealed: String,
+}
+
+/// 挑战者玩完谜题后交出的答卷
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Answer {
+    /// 谜题的承诺
+    pub commitment: String,
+    /// 每一轮的结果
+    pub results: Vec<RoundResult>,
+}
+
+impl Puzzle {
+    /// 用种子生成谜题。本地游戏由程序担任主持人，需要主持人做决定的健忘、邪恶、出价买断的主持人，
+    /// 以及无尽模式、共同决定奖品位置和指定规则（规则可能来自本地没有的插件）的设置都不能用于谜题
+    pub fn create(settings: Settings, seed: &str) -> Result<Self> {
+        settings.validate()?;
+        if settings.endless
+            || settings.forgetful
+            || settings.evil
+            || settings.deals
+            || settings.shared_entropy
+            || settings.rules.is_some()
+        {
+            return Err(Error::InvalidSettings);
+        }
+        let commitment = fairness::commitment(seed);
+        let sealed = encode(&mask(seed.as_bytes(), &commitment));
+        Ok(Self {
+            version: PUZZLE_VERSION,
+            settings,
+            commitment,
+            sealed,
+        })
+    }
+
+    /// 解开种子，种子和承诺不符时返回 [`Error::InvalidPuzzle`]
+    pub fn seed(&self) -> Result<String> {
+        let sealed = decode(&self.sealed).ok_or(Error::InvalidPuzzle)?;
+        let seed =
+            String::from_utf8(mask(&sealed, &self.commitment)).map_err(|_| Error::InvalidPuzzle)?;
+        if self.version != PUZZLE_VERSION || fairness::commitment(&seed) != self.commitment {
+            return Err(Error::InvalidPuzzle);
+        }
+        Ok(seed)
+    }
+
+    /// 一轮的奖品位置：以轮次的十进制字符串作为随机串，见 [`fairness::shared_entropy`]
+    pub fn prizes(&self, round: u32) -> Result<Vec<u32>> {
+        let entropy = fairness::shared_entropy(&self.seed()?, &round.to_string());
+        let round = self.settings.round(round);
+        let count = self.settings.prizes(round);
+        Ok(fairness::placement(&entropy, round.doors, count))
+    }
+
+    /// 挑战者已经准备好的房间
+    pub fn room(&self) -> Result<Room> {
+        self.seed()?;
+        let mut room = Room::create(Uuid::new_v4(), self.settings.clone())?;
+        room.accept_contestant(Uuid::new_v4())?;
+        room.contestant_ready(true)?;
+        Ok(room)
+    }
+
+    /// 按谜题放置奖品，开始下一轮
+    pub fn start(&self, room: &mut Room) -> Result<()> {
+        room.start_with_prizes(&self.prizes(room.next_round())?)
+    }
+
+    /// 重放答卷，每一轮的奖品、主持人留下的门和输赢都必须和谜题一致，而且一局已经完成。
+    /// 不一致时返回 [`Error::InvalidPuzzle`]，一致时返回重放得到的结果
+    pub fn verify(&self, answer: &Answer) -> Result<GameResult> {
+        if answer.commitment != self.commitment {
+            return Err(Error::InvalidPuzzle);
+        }
+        let mut room = self.room()?;
+        let mut replay = || {
+            for claimed in &answer.results {
+                let prizes = self.prizes(room.next_round())?;
+                room.start_with_prizes(&prizes)?;
+                room.choose(claimed.chosen())?;
+                if let RoomState::Started {
+                    stage: Stage::Reveal { .. },
+                    ..
+                } = room.state()
+                {
+                    room.reveal(claimed.left())?;
+                }
+                if let Some(percent) = claimed.estimate() {
+                    room.estimate(percent)?;
+                }
+                // 答卷只记录了一个留下的门，其余留下的门随机补足，换到的门和输赢不受影响
+                let decision = match claimed.decision() {
+                    Decision::Stick => Decision::Stick,
+                    _ => Decision::SwitchTo(claimed.left()),
+                };
+                let result = room.decide(decision)?;
+                if result.win() != claimed.win() || !prizes.contains(&claimed.prize()) {
+                    return Err(Error::InvalidPuzzle);
+                }
+            }
+            room.complete(false)
+        };
+        let results = replay().map_err(|_| Error::InvalidPuzzle)?;
+        Ok(GameResult::calculate(&self.settings, results))
+    }
+}
+
+/// 用承诺派生的字节流遮掩或者解开种子：第 i 块是 SHA-256（`ndoors puzzle`、承诺、i（4 个字节，大端））
+fn mask(bytes: &[u8], commitment: &str) -> Vec<u8> {
+    bytes
+        .chunks(32)
+        .enumerate()
+        .flat_map(|(i, chunk)| {
+            let mut hasher = Sha256::new();
+            hasher.update(b"ndoors puzzle");
+            hasher.update(commitment.as_bytes());
+            hasher.update((i as u32).to_be_bytes());
+            let block: [u8; 32] = hasher.finalize().into();
+            chunk
+                .iter()
+                .zip(block)
+                .map(|(byte, key)| byte ^ key)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
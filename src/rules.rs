@@ -0,0 +1,126 @@
+use crate::{Result, RoundResult, Settings};
+
+/// 规则名称的最大长度
+pub const MAX_RULES_NAME_LEN: usize = 32;
+
+/// 游戏变体的规则。规则在创建房间或者修改设置时调整设置（例如开启加赛），
+/// 在一局结束时为每一轮计分。设置中的 [`Settings::rules`] 记录使用的规则名称，恢复的房间按名称找回规则
+pub trait Rules {
+    /// 规则名称，只能包含小写字母、数字和 `-`
+    fn name(&self) -> &str;
+
+    /// 按规则调整设置，规则不支持这组设置时返回 [`Error::InvalidSettings`](crate::Error::InvalidSettings)
+    fn configure(&self, settings: Settings) -> Result<Settings>;
+
+    /// 一轮的得分，默认赢了得到奖品价值、接受买断得到出价，见 [`RoundResult::score`]
+    fn score(&self, result: &RoundResult) -> u32 {
+        result.score()
+    }
+
+    /// 一局所有轮次的总得分，默认逐轮调用 [`Rules::score`] 相加。每次计分都有额外开销的规则（例如插件）可以一次算完
+    fn total(&self, results: &[RoundResult]) -> u64 {
+        results.iter().map(|result| self.score(result) as u64).sum()
+    }
+}
+
+/// 经典的三门问题：主持人知道奖品的位置，总是揭示并给出改变选择的机会，不能买断，打平时不加赛
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Classic;
+
+impl Rules for Classic {
+    fn name(&self) -> &str {
+        "classic"
+    }
+
+    fn configure(&self, settings: Settings) -> Result<Settings> {
+        Ok(settings
+            .with_sudden_death(false)
+            .with_forgetful(false)
+            .with_evil(false)
+            .with_deals(false))
+    }
+}
+
+/// 打平时加赛，见 [`Settings::sudden_death`]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SuddenDeath;
+
+impl Rules for SuddenDeath {
+    fn name(&self) -> &str {
+        "sudden-death"
+    }
+
+    fn configure(&self, settings: Settings) -> Result<Settings> {
+        Ok(settings.with_sudden_death(true))
+    }
+}
+
+/// 健忘的主持人（Monty Fall），见 [`Settings::forgetful`]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Forgetful;
+
+impl Rules for Forgetful {
+    fn name(&self) -> &str {
+        "forgetful"
+    }
+
+    fn configure(&self, settings: Settings) -> Result<Settings> {
+        Ok(settings.with_forgetful(true).with_evil(false))
+    }
+}
+
+/// 邪恶的主持人（Evil Monty），见 [`Settings::evil`]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Evil;
+
+impl Rules for Evil {
+    fn name(&self) -> &str {
+        "evil"
+    }
+
+    fn configure(&self, settings: Settings) -> Result<Settings> {
+        Ok(settings.with_evil(true).with_forgetful(false))
+    }
+}
+
+/// 主持人可以出价买断，见 [`Settings::deals`]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Deals;
+
+impl Rules for Deals {
+    fn name(&self) -> &str {
+        "deals"
+    }
+
+    fn configure(&self, settings: Settings) -> Result<Settings> {
+        Ok(settings.with_deals(true))
+    }
+}
+
+/// 内置的规则
+pub fn builtin_rules() -> Vec<Box<dyn Rules + Send + Sync>> {
+    vec![
+        Box::new(Classic),
+        Box::new(SuddenDeath),
+        Box::new(Forgetful),
+        Box::new(Evil),
+        Box::new(Deals),
+    ]
+}
+
+/// 规则名称不为空，不超过 [`MAX_RULES_NAME_LEN`]，只包含小写字母、数字和 `-`
+pub fn valid_rules_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_RULES_NAME_LEN
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-')
+}
+
+/// 按规则调整并检查设置，设置中记录规则名称。规则名称无效时返回 [`Error::InvalidSettings`](crate::Error::InvalidSettings)
+pub fn apply_rules(rules: &dyn Rules, settings: Settings) -> Result<Settings> {
+    let mut settings = rules.configure(settings)?;
+    settings.rules = Some(rules.name().to_string());
+    settings.validate()?;
+    Ok(settings)
+}
@@ -0,0 +1,184 @@
+use ndoors::{GameResult, RoundResult, Settings, Uuid};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::room::RoomAgent;
+use crate::timestamp;
+
+/// 缓冲的事件数，接收端跟不上时丢弃新事件，不影响游戏
+const BUFFER: usize = 1024;
+
+/// 匿名的分析事件，不包含用户和房间 ID。
+/// `game` 由房间 ID 和对局序号加上每次启动随机生成的盐派生，只用于关联同一局的事件
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AnalyticsEvent {
+    GameStarted {
+        game: Uuid,
+        tenant: String,
+        settings: Settings,
+    },
+    RoundDecided {
+        game: Uuid,
+        /// 轮次序号，从 0 开始
+        round: u32,
+        #[serde(flatten)]
+        result: RoundResult,
+    },
+    GameCompleted {
+        game: Uuid,
+        /// 是否被版主强制结束
+        forced: bool,
+        #[serde(flatten)]
+        result: Box<GameResult>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct AnalyticsRecord {
+    /// Unix 时间戳（毫秒）
+    timestamp: u64,
+    #[serde(flatten)]
+    event: AnalyticsEvent,
+}
+
+/// 分析事件的输出位置
+#[derive(Debug, Deserialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnalyticsConfig {
+    /// 不输出（默认）
+    #[default]
+    Disabled,
+    /// 每个事件一行 JSON 输出到标准输出
+    Stdout,
+    /// 每个事件一个 JSON 请求体 POST 到 `url`，只支持 HTTP
+    Http { url: String },
+    /// 发送到 Kafka 的 `topic`，键是 `game`，需要 `kafka` feature
+    Kafka { brokers: String, topic: String },
+}
+
+enum Sink {
+    Stdout,
+    Http {
+        client: hyper::Client<hyper::client::HttpConnector>,
+        uri: hyper::Uri,
+    },
+    #[cfg(feature = "kafka")]
+    Kafka {
+        producer: rdkafka::producer::FutureProducer,
+        topic: String,
+    },
+}
+
+impl Sink {
+    fn new(config: AnalyticsConfig) -> anyhow::Result<Option<Self>> {
+        let sink = match config {
+            AnalyticsConfig::Disabled => return Ok(None),
+            AnalyticsConfig::Stdout => Sink::Stdout,
+            AnalyticsConfig::Http { url } => Sink::Http {
+                client: hyper::Client::new(),
+                uri: url.parse()?,
+            },
+            #[cfg(feature = "kafka")]
+            AnalyticsConfig::Kafka { brokers, topic } => Sink::Kafka {
+                producer: rdkafka::ClientConfig::new()
+                    .set("bootstrap.servers", brokers)
+                    .create()?,
+                topic,
+            },
+            #[cfg(not(feature = "kafka"))]
+            AnalyticsConfig::Kafka { .. } => {
+                anyhow::bail!("The Kafka analytics sink requires the `kafka` feature.")
+            }
+        };
+        Ok(Some(sink))
+    }
+
+    async fn send(&self, record: &AnalyticsRecord) -> anyhow::Result<()> {
+        let json = serde_json::to_string(record)?;
+        match self {
+            Sink::Stdout => println!("{}", json),
+            Sink::Http { client, uri } => {
+                let request = hyper::Request::builder()
+                    .method(hyper::Method::POST)
+                    .uri(uri.clone())
+                    .header(hyper::header::CONTENT_TYPE, "application/json")
+                    .body(hyper::Body::from(json))?;
+                let response = client.request(request).await?;
+                if !response.status().is_success() {
+                    anyhow::bail!("Analytics endpoint responded {}.", response.status());
+                }
+            }
+            #[cfg(feature = "kafka")]
+            Sink::Kafka { producer, topic } => {
+                let key = record.event.game().to_string();
+                let record = rdkafka::producer::FutureRecord::to(topic)
+                    .key(&key)
+                    .payload(&json);
+                producer
+                    .send(record, std::time::Duration::from_secs(5))
+                    .await
+                    .map_err(|(cause, _)| cause)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 分析事件流，事件在后台任务中逐个发送，发送失败只记录日志
+#[derive(Debug)]
+pub struct Analytics {
+    sender: Option<mpsc::Sender<AnalyticsRecord>>,
+    salt: Uuid,
+}
+
+impl Analytics {
+    pub fn start(config: AnalyticsConfig) -> anyhow::Result<Self> {
+        let sender = Sink::new(config)?.map(|sink| {
+            let (sender, mut receiver) = mpsc::channel::<AnalyticsRecord>(BUFFER);
+            tokio::spawn(async move {
+                while let Some(record) = receiver.recv().await {
+                    if let Err(cause) = sink.send(&record).await {
+                        tracing::error!(%cause, "Failed to send analytics event.");
+                    }
+                }
+            });
+            sender
+        });
+        Ok(Self {
+            sender,
+            salt: Uuid::new_v4(),
+        })
+    }
+
+    /// 房间中进行中的一局的匿名 ID
+    pub fn game_id(&self, ra: &RoomAgent) -> Uuid {
+        let name = format!("{}/{}", ra.room.id(), ra.game_number());
+        Uuid::new_v5(&self.salt, name.as_bytes())
+    }
+
+    pub fn emit(&self, event: AnalyticsEvent) {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return,
+        };
+        let record = AnalyticsRecord {
+            timestamp: timestamp(),
+            event,
+        };
+        if sender.try_send(record).is_err() {
+            tracing::warn!("Analytics event dropped.");
+        }
+    }
+}
+
+impl AnalyticsEvent {
+    #[cfg(feature = "kafka")]
+    fn game(&self) -> Uuid {
+        match self {
+            AnalyticsEvent::GameStarted { game, .. }
+            | AnalyticsEvent::RoundDecided { game, .. }
+            | AnalyticsEvent::GameCompleted { game, .. } => *game,
+        }
+    }
+}
@@ -0,0 +1,1006 @@
+use axum::body::Bytes;
+use axum::extract::{Extension, Path, Query};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, HeaderName, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use futures::Stream;
+use ndoors::fairness::RoundProof;
+use ndoors::{
+    Decision, DoorsStats, EarlyEnd, GameResult, LatencyStats, QuizReport, RoomState, RoundResult,
+    RoundSettings, ScoreReport, Settings, Stats, Team, TeamStats, Timeouts, Uuid,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tower_http::cors::CorsLayer;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+
+use crate::auth::bearer_token;
+use crate::batch::{self, CreatedRoom, RoomBatch};
+use crate::class::{ClassReport, RoomProgress};
+use crate::exhibition::{self, Exhibition};
+use crate::footprint::RoomFootprint;
+use crate::lesson::{LessonPack, LessonSummary};
+use crate::pacing::Pacing;
+use crate::protocol::GameResponse;
+use crate::quota::Quota;
+use crate::room::{CompletedGame, RoomDump, RoomInfo, RoomLifecycle, RoomPage, RoomStatus};
+use crate::share::ShareCard;
+use crate::{Credentials, Server};
+
+/// HTTP 接口的 OpenAPI 文档
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        lobby,
+        games,
+        rounds,
+        lessons,
+        lesson,
+        fairness,
+        class_report,
+        class_events,
+        dataset,
+        share_card,
+        share_card_svg,
+        metrics,
+        grant_moderator,
+        revoke_moderator,
+        dump_room,
+        drain_status,
+        start_drain,
+        stop_drain,
+        reload,
+        usage,
+        room_footprints,
+        create_rooms,
+        start_exhibition,
+        stop_exhibition,
+        upload_bot,
+        delete_bot
+    ),
+    components(schemas(
+        RoomPage,
+        RoomInfo,
+        RoomStatus,
+        RoomLifecycle,
+        Team,
+        Pacing,
+        Settings,
+        RoundSettings,
+        Timeouts,
+        GamePage,
+        GameSummary,
+        GameResult,
+        EarlyEnd,
+        Stats,
+        DoorsStats,
+        TeamStats,
+        LatencyStats,
+        QuizReport,
+        ScoreReport,
+        RoundPage,
+        RoundResult,
+        Decision,
+        DrainStatus,
+        ClassReport,
+        RoomProgress,
+        LessonPack,
+        LessonSummary,
+        TenantUsage,
+        Quota,
+        RoomFootprint,
+        Exhibition,
+        RoomBatch,
+        CreatedRoom,
+        ExhibitionStarted,
+        BotUploaded,
+        FairnessKey,
+        RoundProof,
+        ShareCard
+    )),
+    modifiers(&AdminToken),
+    tags(
+        (name = "rooms", description = "大厅和房间"),
+        (name = "history", description = "房间最近完成的游戏"),
+        (name = "share", description = "分享的游戏卡片"),
+        (name = "classes", description = "班级看板"),
+        (name = "lessons", description = "课程包"),
+        (name = "admin", description = "需要管理令牌的管理接口")
+    )
+)]
+struct ApiDoc;
+
+/// 管理接口使用的 Bearer 令牌
+struct AdminToken;
+
+impl Modify for AdminToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "admin_token",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+        }
+    }
+}
+
+/// HTTP 接口，供静态页面和第三方网站在不建立 WebSocket 连接的情况下读取数据
+pub fn router() -> Router {
+    Router::new()
+        .route("/api/openapi.json", get(openapi))
+        .route("/api/lobby", get(lobby))
+        .route("/api/rooms/:id/games", get(games))
+        .route("/api/rooms/:id/games/:game/rounds", get(rounds))
+        .route("/api/lessons", get(lessons))
+        .route("/api/lessons/:name", get(lesson))
+        .route("/api/fairness", get(fairness))
+        .route("/api/classes/:class", get(class_report))
+        .route("/api/classes/:class/events", get(class_events))
+        .route("/api/dataset.csv", get(dataset))
+        .route("/s/:code", get(share_card))
+        .route("/s/:code/card.svg", get(share_card_svg))
+        .layer(CorsLayer::permissive())
+        .route("/api/bots", post(upload_bot))
+        .route("/api/bots/:id", delete(delete_bot))
+        .route("/metrics", get(metrics))
+        .merge(admin_router())
+}
+
+/// 管理接口，需要在 `Authorization: Bearer` 请求头中提供配置的管理令牌
+fn admin_router() -> Router {
+    Router::new()
+        .route(
+            "/api/admin/moderators/:id",
+            put(grant_moderator).delete(revoke_moderator),
+        )
+        .route("/api/admin/rooms/:id/dump", get(dump_room))
+        .route("/api/admin/reload", post(reload))
+        .route("/api/admin/usage", get(usage))
+        .route("/api/admin/rooms/footprint", get(room_footprints))
+        .route("/api/admin/rooms", post(create_rooms))
+        .route("/api/admin/exhibitions", post(start_exhibition))
+        .route("/api/admin/exhibitions/:id", delete(stop_exhibition))
+        .route(
+            "/api/admin/drain",
+            get(drain_status).put(start_drain).delete(stop_drain),
+        )
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LobbyQuery {
+    /// 页码，从 0 开始
+    #[serde(default)]
+    page: u32,
+    /// 每页房间数，默认 20，最多 100
+    #[serde(default = "default_page_size")]
+    size: u32,
+}
+
+/// 请求者所属的租户，由令牌（通常来自 `Authorization` 头）认证得到，匿名访问时是默认租户。
+/// 房间的租户与请求者不符时视为房间不存在
+fn tenant(server: &Server, token: Option<&str>) -> Result<String, StatusCode> {
+    server
+        .auth
+        .authenticate(token)
+        .map(|identity| identity.tenant)
+        .map_err(|_| StatusCode::UNAUTHORIZED)
+}
+
+fn default_page_size() -> u32 {
+    20
+}
+
+/// 游标分页参数，`cursor` 使用上一页返回的 `next_cursor`
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CursorQuery {
+    /// 上一页返回的 `next_cursor`，从头开始时为 0
+    #[serde(default)]
+    cursor: u64,
+    /// 每页条数，默认 100，最多 1000
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+const MAX_LIMIT: usize = 1000;
+
+/// 一页数据，`next_cursor` 为空表示没有更多数据
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(GamePage = CursorPage<GameSummary>, RoundPage = CursorPage<RoundResult>)]
+pub struct CursorPage<T> {
+    items: Vec<T>,
+    next_cursor: Option<u64>,
+}
+
+/// 一局已经完成的游戏的统计，不含每一轮的结果
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GameSummary {
+    id: u64,
+    completed_at: u64,
+    /// 从第一轮开始到完成的毫秒数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+    result: GameResult,
+    rounds: u32,
+}
+
+impl From<&CompletedGame> for GameSummary {
+    fn from(game: &CompletedGame) -> Self {
+        Self {
+            id: game.id,
+            completed_at: game.completed_at,
+            duration_ms: game.duration_ms,
+            result: game.result.clone(),
+            rounds: game.rounds.len() as u32,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/lobby",
+    tag = "rooms",
+    params(LobbyQuery),
+    responses(
+        (status = 200, description = "大厅中的一页房间", body = RoomPage),
+        (status = 401, description = "认证失败")
+    )
+)]
+async fn lobby(
+    Query(query): Query<LobbyQuery>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Json<RoomPage>, StatusCode> {
+    let tenant = tenant(&server, bearer_token(&headers))?;
+    Ok(Json(server.lobby.page(&tenant, query.page, query.size)))
+}
+
+/// 房间最近完成的游戏，按完成顺序排列，游标是游戏编号
+#[utoipa::path(
+    get,
+    path = "/api/rooms/{id}/games",
+    tag = "history",
+    params(("id" = Uuid, Path, description = "房间 ID"), CursorQuery),
+    responses(
+        (status = 200, description = "一页游戏", body = GamePage),
+        (status = 401, description = "认证失败"),
+        (status = 404, description = "房间不存在")
+    )
+)]
+async fn games(
+    Path(id): Path<Uuid>,
+    Query(query): Query<CursorQuery>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Json<CursorPage<GameSummary>>, StatusCode> {
+    let tenant = tenant(&server, bearer_token(&headers))?;
+    let ra = server
+        .rooms
+        .get(&id)
+        .filter(|ra| ra.tenant == tenant)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let limit = query.limit.clamp(1, MAX_LIMIT);
+    let mut items: Vec<GameSummary> = ra
+        .history
+        .iter()
+        .filter(|game| game.id >= query.cursor)
+        .take(limit + 1)
+        .map(GameSummary::from)
+        .collect();
+    let next_cursor = if items.len() > limit {
+        items.pop().map(|game| game.id)
+    } else {
+        None
+    };
+    Ok(Json(CursorPage { items, next_cursor }))
+}
+
+/// 一局游戏每一轮的结果，游标是轮次序号
+#[utoipa::path(
+    get,
+    path = "/api/rooms/{id}/games/{game}/rounds",
+    tag = "history",
+    params(
+        ("id" = Uuid, Path, description = "房间 ID"),
+        ("game" = u64, Path, description = "游戏编号"),
+        CursorQuery
+    ),
+    responses(
+        (status = 200, description = "一页轮次结果", body = RoundPage),
+        (status = 401, description = "认证失败"),
+        (status = 404, description = "房间或游戏不存在")
+    )
+)]
+async fn rounds(
+    Path((id, game)): Path<(Uuid, u64)>,
+    Query(query): Query<CursorQuery>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Json<CursorPage<RoundResult>>, StatusCode> {
+    let tenant = tenant(&server, bearer_token(&headers))?;
+    let ra = server
+        .rooms
+        .get(&id)
+        .filter(|ra| ra.tenant == tenant)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let game = ra
+        .history
+        .iter()
+        .find(|g| g.id == game)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let limit = query.limit.clamp(1, MAX_LIMIT);
+    let len = game.rounds.len();
+    let start = (query.cursor as usize).min(len);
+    let end = (start + limit).min(len);
+    Ok(Json(CursorPage {
+        items: game.rounds[start..end].to_vec(),
+        next_cursor: (end < len).then_some(end as u64),
+    }))
+}
+
+/// 服务器上的课程包
+#[utoipa::path(
+    get,
+    path = "/api/lessons",
+    tag = "lessons",
+    responses((status = 200, description = "按名称排序的课程包", body = [LessonSummary]))
+)]
+async fn lessons(Extension(server): Extension<Server>) -> Json<Vec<LessonSummary>> {
+    Json(server.lessons.list())
+}
+
+/// 导出课程包，保存为 `*.json` 文件放到其他服务器的课程包目录即可使用
+#[utoipa::path(
+    get,
+    path = "/api/lessons/{name}",
+    tag = "lessons",
+    params(("name" = String, Path, description = "课程名称")),
+    responses(
+        (status = 200, description = "课程包", body = LessonPack),
+        (status = 404, description = "课程包不存在")
+    )
+)]
+async fn lesson(
+    Path(name): Path<String>,
+    Extension(server): Extension<Server>,
+) -> Result<Json<LessonPack>, StatusCode> {
+    let lesson = server.lessons.get(&name).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(LessonPack::clone(&lesson)))
+}
+
+/// 公平模式的公钥
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FairnessKey {
+    /// VRF 公钥（十六进制），用来验证 `Completed` 中每轮的证明
+    public_key: String,
+}
+
+/// 公平模式的公钥，验证证明时使用
+#[utoipa::path(
+    get,
+    path = "/api/fairness",
+    tag = "rooms",
+    responses(
+        (status = 200, description = "服务器的公钥", body = FairnessKey),
+        (status = 404, description = "服务器没有开启公平模式")
+    )
+)]
+async fn fairness(Extension(server): Extension<Server>) -> Result<Json<FairnessKey>, StatusCode> {
+    let public_key = server.fairness.public_key().ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(FairnessKey { public_key }))
+}
+
+/// 班级所有房间的实时汇总
+#[utoipa::path(
+    get,
+    path = "/api/classes/{class}",
+    tag = "classes",
+    params(("class" = String, Path, description = "班级名称")),
+    responses(
+        (status = 200, description = "班级汇总，没有房间时为空", body = ClassReport),
+        (status = 401, description = "认证失败")
+    )
+)]
+async fn class_report(
+    Path(class): Path<String>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Json<ClassReport>, StatusCode> {
+    let tenant = tenant(&server, bearer_token(&headers))?;
+    Ok(Json(ClassReport::collect(&server.rooms, &tenant, &class)))
+}
+
+/// 班级看板的刷新间隔，汇总没有变化时不推送
+const CLASS_REFRESH: Duration = Duration::from_secs(1);
+
+/// 用 SSE 推送班级汇总：连接后立即推送一次，之后汇总变化时推送，事件名是 `report`
+#[utoipa::path(
+    get,
+    path = "/api/classes/{class}/events",
+    tag = "classes",
+    params(
+        ("class" = String, Path, description = "班级名称"),
+        ("token" = Option<String>, Query, description = "认证令牌，`EventSource` 不能设置 `Authorization` 头时使用")
+    ),
+    responses(
+        (status = 200, description = "`report` 事件流，数据是班级汇总", body = ClassReport, content_type = "text/event-stream"),
+        (status = 401, description = "认证失败")
+    )
+)]
+async fn class_events(
+    Path(class): Path<String>,
+    Query(credentials): Query<Credentials>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, StatusCode> {
+    let token = credentials
+        .token
+        .as_deref()
+        .or_else(|| bearer_token(&headers));
+    let tenant = tenant(&server, token)?;
+    let refresh = tokio::time::interval(CLASS_REFRESH);
+    let state = (server, tenant, class, None, refresh);
+    let reports = futures::stream::unfold(
+        state,
+        |(server, tenant, class, mut last, mut refresh)| async move {
+            loop {
+                refresh.tick().await;
+                let report = ClassReport::collect(&server.rooms, &tenant, &class);
+                let json = match serde_json::to_string(&report) {
+                    Ok(json) => json,
+                    Err(cause) => {
+                        tracing::error!(%cause, "Failed to serialize class report.");
+                        continue;
+                    }
+                };
+                if last.as_ref() != Some(&json) {
+                    let event = Event::default().event("report").data(&json);
+                    last = Some(json);
+                    return Some((Ok(event), (server, tenant, class, last, refresh)));
+                }
+            }
+        },
+    );
+    Ok(Sse::new(reports).keep_alive(KeepAlive::new()))
+}
+
+/// 下载公开数据集，每行是真人完成的一轮游戏
+#[utoipa::path(
+    get,
+    path = "/api/dataset.csv",
+    tag = "history",
+    responses(
+        (status = 200, description = "CSV 格式的轮次结果，第一行是列名", body = String, content_type = "text/csv"),
+        (status = 404, description = "服务器没有开启数据集")
+    )
+)]
+async fn dataset(
+    Extension(server): Extension<Server>,
+) -> Result<([(HeaderName, &'static str); 1], Vec<u8>), StatusCode> {
+    let path = server.dataset.path().ok_or(StatusCode::NOT_FOUND)?;
+    match tokio::fs::read(path).await {
+        Ok(csv) => Ok(([(CONTENT_TYPE, "text/csv")], csv)),
+        Err(cause) => {
+            tracing::error!(%cause, "Failed to read dataset.");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// 分享的游戏卡片
+#[utoipa::path(
+    get,
+    path = "/s/{code}",
+    tag = "share",
+    params(("code" = String, Path, description = "分享时返回的短代码")),
+    responses(
+        (status = 200, description = "游戏摘要", body = ShareCard),
+        (status = 404, description = "卡片不存在或者已经过期")
+    )
+)]
+async fn share_card(
+    Path(code): Path<String>,
+    Extension(server): Extension<Server>,
+) -> Result<Json<ShareCard>, StatusCode> {
+    let card = server.shares.get(&code).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(ShareCard::clone(&card)))
+}
+
+/// 分享的游戏卡片图片，可以直接发到社交网站
+#[utoipa::path(
+    get,
+    path = "/s/{code}/card.svg",
+    tag = "share",
+    params(("code" = String, Path, description = "分享时返回的短代码")),
+    responses(
+        (status = 200, description = "1200×630 的 SVG 图片", body = String, content_type = "image/svg+xml"),
+        (status = 404, description = "卡片不存在或者已经过期")
+    )
+)]
+async fn share_card_svg(
+    Path(code): Path<String>,
+    Extension(server): Extension<Server>,
+) -> Result<([(HeaderName, &'static str); 1], String), StatusCode> {
+    let card = server.shares.get(&code).ok_or(StatusCode::NOT_FOUND)?;
+    Ok(([(CONTENT_TYPE, "image/svg+xml")], card.svg()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses((status = 200, description = "Prometheus 文本格式的计数器，房间数按租户分组", body = String, content_type = "text/plain"))
+)]
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+async fn metrics(Extension(server): Extension<Server>) -> String {
+    let mut rooms = BTreeMap::new();
+    for ra in server.rooms.iter() {
+        *rooms.entry(ra.tenant.clone()).or_insert(0) += 1;
+    }
+    server.metrics.render(&rooms, &footprints(&server))
+}
+
+/// 所有房间占用的内存，按估算的字节数从大到小排序
+fn footprints(server: &Server) -> Vec<RoomFootprint> {
+    let limits = server.tunables().room_size_limits;
+    let mut footprints: Vec<_> = server
+        .rooms
+        .iter()
+        .map(|ra| RoomFootprint::measure(&ra, &limits))
+        .collect();
+    footprints.sort_by_key(|footprint| std::cmp::Reverse(footprint.bytes));
+    footprints
+}
+
+fn authorize(server: &Server, headers: &HeaderMap) -> Result<(), StatusCode> {
+    match (&server.admin_token, bearer_token(headers)) {
+        (None, _) => Err(StatusCode::NOT_FOUND),
+        (Some(expected), Some(token)) if expected.as_ref() == token => Ok(()),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/moderators/{id}",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "用户 ID")),
+    responses(
+        (status = 204, description = "已设为版主"),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌")
+    ),
+    security(("admin_token" = []))
+)]
+async fn grant_moderator(
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> StatusCode {
+    if let Err(status) = authorize(&server, &headers) {
+        return status;
+    }
+    server.moderators.insert(id);
+    tracing::info!(user = %id, "Moderator granted.");
+    StatusCode::NO_CONTENT
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/moderators/{id}",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "用户 ID")),
+    responses(
+        (status = 204, description = "已取消版主"),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌")
+    ),
+    security(("admin_token" = []))
+)]
+async fn revoke_moderator(
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> StatusCode {
+    if let Err(status) = authorize(&server, &headers) {
+        return status;
+    }
+    server.moderators.remove(&id);
+    tracing::info!(user = %id, "Moderator revoked.");
+    StatusCode::NO_CONTENT
+}
+
+/// 导出单个房间的调试信息，用于排查卡住的房间
+#[utoipa::path(
+    get,
+    path = "/api/admin/rooms/{id}/dump",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "房间 ID")),
+    responses(
+        (status = 200, description = "房间状态快照、事件记录和最近的请求"),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌或房间不存在")
+    ),
+    security(("admin_token" = []))
+)]
+async fn dump_room(
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    authorize(&server, &headers)?;
+    let ra = server.rooms.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let dump = serde_json::to_value(RoomDump::from(&*ra)).map_err(|cause| {
+        tracing::error!(%cause, room = %id, "Failed to dump room.");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(dump))
+}
+
+/// 排空进度，`drained` 为真时已经没有进行中的游戏，可以切换流量
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DrainStatus {
+    draining: bool,
+    connections: u64,
+    rooms: u32,
+    games_in_progress: u32,
+    drained: bool,
+}
+
+impl From<&Server> for DrainStatus {
+    fn from(server: &Server) -> Self {
+        let games_in_progress = server
+            .rooms
+            .iter()
+            .filter(|ra| matches!(ra.room.state(), RoomState::Started { .. }))
+            .count() as u32;
+        let draining = server.is_draining();
+        Self {
+            draining,
+            connections: server.metrics.connections.load(Ordering::Relaxed),
+            rooms: server.rooms.len() as u32,
+            games_in_progress,
+            drained: draining && games_in_progress == 0,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/drain",
+    tag = "admin",
+    responses(
+        (status = 200, description = "排空进度", body = DrainStatus),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌")
+    ),
+    security(("admin_token" = []))
+)]
+async fn drain_status(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Json<DrainStatus>, StatusCode> {
+    authorize(&server, &headers)?;
+    Ok(Json(DrainStatus::from(&server)))
+}
+
+/// 开始排空：拒绝新的 WebSocket 连接和创建房间，已有的连接和进行中的游戏不受影响
+#[utoipa::path(
+    put,
+    path = "/api/admin/drain",
+    tag = "admin",
+    responses(
+        (status = 200, description = "已开始排空，返回排空进度", body = DrainStatus),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌")
+    ),
+    security(("admin_token" = []))
+)]
+async fn start_drain(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Json<DrainStatus>, StatusCode> {
+    authorize(&server, &headers)?;
+    server.draining.store(true, Ordering::Relaxed);
+    tracing::warn!("Draining started.");
+    Ok(Json(DrainStatus::from(&server)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/drain",
+    tag = "admin",
+    responses(
+        (status = 200, description = "已停止排空", body = DrainStatus),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌")
+    ),
+    security(("admin_token" = []))
+)]
+async fn stop_drain(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Json<DrainStatus>, StatusCode> {
+    authorize(&server, &headers)?;
+    server.draining.store(false, Ordering::Relaxed);
+    tracing::warn!("Draining stopped.");
+    Ok(Json(DrainStatus::from(&server)))
+}
+
+/// 重新读取配置文件，只有默认设置、过滤规则和压缩阈值会生效
+#[utoipa::path(
+    post,
+    path = "/api/admin/reload",
+    tag = "admin",
+    responses(
+        (status = 204, description = "已重新加载"),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌"),
+        (status = 422, description = "配置文件有误，保留原来的配置")
+    ),
+    security(("admin_token" = []))
+)]
+async fn reload(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    authorize(&server, &headers).map_err(|status| (status, String::new()))?;
+    server.reload().map_err(|cause| {
+        tracing::error!(%cause, "Failed to reload config.");
+        (StatusCode::UNPROCESSABLE_ENTITY, cause.to_string())
+    })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 租户的配额和用量
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TenantUsage {
+    tenant: String,
+    rooms: u32,
+    rounds_today: u64,
+    quota: Quota,
+}
+
+/// 有房间或者配置了配额的租户的用量，按租户排序
+#[utoipa::path(
+    get,
+    path = "/api/admin/usage",
+    tag = "admin",
+    responses(
+        (status = 200, description = "每个租户的配额和用量", body = [TenantUsage]),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌")
+    ),
+    security(("admin_token" = []))
+)]
+async fn usage(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Json<Vec<TenantUsage>>, StatusCode> {
+    authorize(&server, &headers)?;
+    let mut rooms = BTreeMap::new();
+    for ra in server.rooms.iter() {
+        *rooms.entry(ra.tenant.clone()).or_insert(0) += 1;
+    }
+    let tenants: BTreeSet<_> = rooms
+        .keys()
+        .chain(server.quotas.tenants())
+        .cloned()
+        .collect();
+    let usage = tenants
+        .into_iter()
+        .map(|tenant| TenantUsage {
+            rooms: rooms.get(&tenant).copied().unwrap_or(0),
+            rounds_today: server.quotas.rounds_today(&tenant),
+            quota: server.quotas.quota(&tenant),
+            tenant,
+        })
+        .collect();
+    Ok(Json(usage))
+}
+
+/// 每个房间占用的内存，按估算的字节数从大到小排序，`warnings` 列出超过 `room_size_limits` 的项
+#[utoipa::path(
+    get,
+    path = "/api/admin/rooms/footprint",
+    tag = "admin",
+    responses(
+        (status = 200, description = "每个房间占用的内存", body = [RoomFootprint]),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌")
+    ),
+    security(("admin_token" = []))
+)]
+async fn room_footprints(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<Json<Vec<RoomFootprint>>, StatusCode> {
+    authorize(&server, &headers)?;
+    Ok(Json(footprints(&server)))
+}
+
+/// 创建的机器人对战房间
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExhibitionStarted {
+    /// 房间 ID
+    id: Uuid,
+}
+
+/// 一次创建一批设置相同的房间，返回房间 ID，真人主持时还有主持人的恢复令牌
+#[utoipa::path(
+    post,
+    path = "/api/admin/rooms",
+    tag = "admin",
+    request_body = RoomBatch,
+    responses(
+        (status = 201, description = "已创建，按创建顺序排列", body = [CreatedRoom]),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌"),
+        (status = 422, description = "房间数、策略、设置或者班级有误，或者超出租户配额"),
+        (status = 503, description = "服务器正在排空")
+    ),
+    security(("admin_token" = []))
+)]
+async fn create_rooms(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+    Json(request): Json<RoomBatch>,
+) -> Result<(StatusCode, Json<Vec<CreatedRoom>>), (StatusCode, String)> {
+    authorize(&server, &headers).map_err(|status| (status, String::new()))?;
+    if server.is_draining() {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, String::new()));
+    }
+    let rooms = batch::create(&server, request)
+        .map_err(|cause| (StatusCode::UNPROCESSABLE_ENTITY, cause.to_string()))?;
+    Ok((StatusCode::CREATED, Json(rooms)))
+}
+
+/// 创建机器人对战房间，服务器按节奏推进，观众可以通过 `Spectate` 旁观
+#[utoipa::path(
+    post,
+    path = "/api/admin/exhibitions",
+    tag = "admin",
+    request_body = Exhibition,
+    responses(
+        (status = 201, description = "已创建", body = ExhibitionStarted),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌"),
+        (status = 422, description = "策略不存在、设置或者脚本有误")
+    ),
+    security(("admin_token" = []))
+)]
+async fn start_exhibition(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+    Json(request): Json<Exhibition>,
+) -> Result<(StatusCode, Json<ExhibitionStarted>), (StatusCode, String)> {
+    authorize(&server, &headers).map_err(|status| (status, String::new()))?;
+    let id = exhibition::start(&server, request)
+        .map_err(|cause| (StatusCode::UNPROCESSABLE_ENTITY, cause.to_string()))?;
+    Ok((StatusCode::CREATED, Json(ExhibitionStarted { id })))
+}
+
+/// 关闭机器人对战房间，观众收到主持人离开的通知
+#[utoipa::path(
+    delete,
+    path = "/api/admin/exhibitions/{id}",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "房间 ID")),
+    responses(
+        (status = 204, description = "已关闭"),
+        (status = 401, description = "管理令牌错误"),
+        (status = 404, description = "没有配置管理令牌或者不是机器人对战房间")
+    ),
+    security(("admin_token" = []))
+)]
+async fn stop_exhibition(
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Extension(server): Extension<Server>,
+) -> Result<StatusCode, StatusCode> {
+    authorize(&server, &headers)?;
+    match server.rooms.get_mut(&id) {
+        Some(mut ra) if ra.exhibition => {
+            let user_id = *ra.room.host();
+            ra.publish(GameResponse::Exited { user_id });
+            ra.close();
+        }
+        _ => return Err(StatusCode::NOT_FOUND),
+    }
+    server.rooms.remove(&id);
+    tracing::info!(room = %id, "Exhibition closed.");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// 上传的机器人
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BotUploaded {
+    /// 在 `InviteBot` 中使用的机器人 ID
+    id: Uuid,
+}
+
+/// 上传和删除机器人的用户，匿名访问时没有持久的身份，不能上传
+fn bot_owner(server: &Server, headers: &HeaderMap) -> Result<Uuid, (StatusCode, String)> {
+    if server.auth.is_anonymous() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Uploading bots requires authentication.".to_string(),
+        ));
+    }
+    server
+        .auth
+        .authenticate(bearer_token(headers))
+        .map(|identity| identity.id)
+        .map_err(|cause| (StatusCode::UNAUTHORIZED, cause.to_string()))
+}
+
+/// 上传 WASM 机器人，请求体是模块的二进制内容，使用和 WebSocket 相同的认证令牌。
+/// 模块不能有导入，需要导出 `choose(doors: i32) -> i32` 和 `decide(doors: i32, chosen: i32, left: i32) -> i32`。
+/// 每个用户最多保存 10 个机器人，超过时删除最早上传的
+#[utoipa::path(
+    post,
+    path = "/api/bots",
+    request_body(content = Vec<u8>, content_type = "application/wasm"),
+    responses(
+        (status = 201, description = "已上传", body = BotUploaded),
+        (status = 401, description = "认证失败"),
+        (status = 403, description = "服务器使用匿名访问"),
+        (status = 422, description = "模块无效、超出大小限制、机器人太多或者没有启用 `bots` feature")
+    )
+)]
+async fn upload_bot(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+    wasm: Bytes,
+) -> Result<(StatusCode, Json<BotUploaded>), (StatusCode, String)> {
+    let owner = bot_owner(&server, &headers)?;
+    // 编译模块可能需要几百毫秒，不能阻塞异步运行时
+    let bots = server.bots.clone();
+    let id = tokio::task::spawn_blocking(move || bots.upload(owner, &wasm))
+        .await
+        .map_err(|cause| (StatusCode::INTERNAL_SERVER_ERROR, cause.to_string()))?
+        .map_err(|cause| {
+            tracing::warn!(user = %owner, %cause, "Invalid bot.");
+            (StatusCode::UNPROCESSABLE_ENTITY, cause.to_string())
+        })?;
+    tracing::info!(user = %owner, bot = %id, "Bot uploaded.");
+    Ok((StatusCode::CREATED, Json(BotUploaded { id })))
+}
+
+/// 删除自己上传的机器人，已经在房间中的机器人继续运行到离开为止
+#[utoipa::path(
+    delete,
+    path = "/api/bots/{id}",
+    params(("id" = Uuid, Path, description = "机器人 ID")),
+    responses(
+        (status = 204, description = "已删除"),
+        (status = 401, description = "认证失败"),
+        (status = 403, description = "服务器使用匿名访问"),
+        (status = 404, description = "机器人不存在或者不是自己上传的")
+    )
+)]
+async fn delete_bot(
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let owner = bot_owner(&server, &headers)?;
+    if !server.bots.delete(owner, id) {
+        return Err((StatusCode::NOT_FOUND, "Bot not found.".to_string()));
+    }
+    tracing::info!(user = %owner, bot = %id, "Bot deleted.");
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,77 @@
+use ndoors::Uuid;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::filter::{TextKind, Violation};
+use crate::random::RandomRecord;
+use crate::timestamp;
+
+/// 需要审计的事件
+#[derive(Debug, Serialize)]
+#[serde(tag = "event")]
+pub enum AuditEvent {
+    /// 用户输入的文本被过滤
+    FilterViolation {
+        user_id: Uuid,
+        kind: TextKind,
+        violations: Vec<Violation>,
+    },
+    /// 房间或者一轮游戏使用的随机数来源，`round` 从 0 开始
+    Randomness {
+        room_id: Uuid,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        round: Option<u32>,
+        source: RandomRecord,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    /// Unix 时间戳（毫秒）
+    timestamp: u64,
+    #[serde(flatten)]
+    event: &'a AuditEvent,
+}
+
+/// 审计日志，总是输出到 `audit` target，配置了文件时同时以 JSON Lines 格式追加到文件
+#[derive(Debug)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+}
+
+impl AuditLog {
+    pub fn open(path: Option<&Path>) -> anyhow::Result<Self> {
+        let file = match path {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+        Ok(Self { file })
+    }
+
+    pub fn record(&self, event: AuditEvent) {
+        tracing::warn!(target: "audit", ?event, "Audit.");
+
+        let file = match &self.file {
+            Some(file) => file,
+            None => return,
+        };
+        let record = AuditRecord {
+            timestamp: timestamp(),
+            event: &event,
+        };
+        let result = serde_json::to_string(&record)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| match file.lock() {
+                Ok(mut file) => Ok(writeln!(file, "{}", line)?),
+                Err(_) => Err(anyhow::anyhow!("Audit log lock poisoned.")),
+            });
+        if let Err(cause) = result {
+            tracing::error!(%cause, "Failed to write audit log.");
+        }
+    }
+}
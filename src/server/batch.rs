@@ -0,0 +1,136 @@
+use ndoors::{Room, Settings, Uuid};
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+use utoipa::ToSchema;
+
+use crate::class::MAX_CLASS_LEN;
+use crate::exhibition::{drive, unattended};
+use crate::pacing::Pacing;
+use crate::permission::Role;
+use crate::room::{RoomAgent, RoomDropper};
+use crate::{Server, User};
+
+/// 一次最多创建的房间数
+const MAX_BATCH: u32 = 100;
+
+/// 一次创建一批设置相同的房间，例如研究者在实验课开始前为每个受试者准备一个房间
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct RoomBatch {
+    /// 房间数，最多 100
+    pub count: u32,
+    /// 房间所属租户，默认是默认租户
+    #[serde(default)]
+    pub tenant: String,
+    /// 不指定时使用默认设置
+    #[serde(default)]
+    pub settings: Option<Settings>,
+    #[serde(default)]
+    pub pacing: Pacing,
+    /// 房间所属的班级
+    #[serde(default)]
+    pub class: Option<String>,
+    /// 机器人主持人的策略：内置的 `random` 或者 `lazy`。不指定时由真人主持
+    #[serde(default)]
+    pub host: Option<String>,
+    /// 机器人主持人两次操作之间的间隔（毫秒），不会小于节奏的间隔，默认 1500
+    #[serde(default = "default_step_ms")]
+    pub step_ms: u64,
+}
+
+fn default_step_ms() -> u64 {
+    1500
+}
+
+/// 创建的一个房间
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedRoom {
+    /// 房间 ID，挑战者用 `EnterRoom` 进入房间
+    id: Uuid,
+    /// 真人主持时主持人会话的恢复令牌，用它连接 `/ws?resume=` 即成为房间的主持人
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host_token: Option<Uuid>,
+}
+
+/// 创建一批房间。机器人主持的房间由服务器按节奏推进，挑战者准备好后开始；
+/// 真人主持的房间保留一个主持人会话，房间一直保留到主持人恢复会话后离开
+pub fn create(server: &Server, batch: RoomBatch) -> anyhow::Result<Vec<CreatedRoom>> {
+    if batch.count == 0 || batch.count > MAX_BATCH {
+        anyhow::bail!("`count` must be between 1 and {}.", MAX_BATCH);
+    }
+    if batch
+        .class
+        .as_ref()
+        .is_some_and(|class| class.is_empty() || class.len() > MAX_CLASS_LEN)
+    {
+        anyhow::bail!("Invalid class name.");
+    }
+    if let Some(host) = &batch.host {
+        if server.bots.host(host).is_none() {
+            anyhow::bail!("Unknown host strategy `{}`.", host);
+        }
+    }
+    let settings = batch
+        .settings
+        .clone()
+        .unwrap_or_else(|| server.tunables().default_settings.clone());
+    let settings = server.rules.configure(settings)?;
+    settings.validate()?;
+    // 没有用完的名额（中途出错时）在返回时归还
+    let mut reservation = server
+        .quotas
+        .reserve_rooms(&batch.tenant, batch.count, || {
+            server
+                .rooms
+                .iter()
+                .filter(|ra| ra.tenant == batch.tenant)
+                .count() as u32
+        })
+        .map_err(|kind| anyhow::anyhow!("Quota exceeded: {:?}.", kind))?;
+
+    let step = Duration::from_millis(batch.step_ms).max(batch.pacing.delay());
+    let mut created = Vec::with_capacity(batch.count as usize);
+    for _ in 0..batch.count {
+        let mut room = Room::create(Uuid::new_v4(), settings.clone())?;
+        server.rules.attach(&mut room);
+        let room_id = *room.id();
+        let mut ra = RoomAgent::new(
+            room,
+            batch.tenant.clone(),
+            unattended(),
+            batch.pacing,
+            server.bridge.clone(),
+            server.push.clone(),
+            server.lobby.clone(),
+        );
+        ra.class = batch.class.clone();
+
+        let host_token = match batch
+            .host
+            .as_deref()
+            .and_then(|host| server.bots.host(host))
+        {
+            Some(host) => {
+                reservation.fulfil(|| server.rooms.insert(room_id, ra));
+                tokio::spawn(drive(server.clone(), room_id, host, step));
+                None
+            }
+            None => {
+                let mut user = User::new(*ra.room.host(), ra.host.clone());
+                user.tenant = batch.tenant.clone();
+                user.role = Role::Host { room_id };
+                let token = user.resume_token;
+                reservation.fulfil(|| server.rooms.insert(room_id, ra));
+                let mut room_dropper = RoomDropper::new(server.rooms.clone());
+                room_dropper.set_room(room_id);
+                server.sessions.park(user, room_dropper, false);
+                Some(token)
+            }
+        };
+        created.push(CreatedRoom {
+            id: room_id,
+            host_token,
+        });
+    }
+    tracing::info!(count = batch.count, tenant = %batch.tenant, "Room batch created.");
+    Ok(created)
+}
@@ -0,0 +1,216 @@
+use ndoors::{
+    ContestantStrategy, Decision, FixedDecision, HostStrategy, LazyHost, RandomDecision,
+    RandomHost, Uuid,
+};
+use std::fmt::{Debug, Formatter};
+
+/// 房间中的机器人挑战者
+pub struct Bot {
+    /// 内置策略的名称或者上传的机器人 ID
+    pub name: String,
+    /// 机器人在房间中的用户 ID
+    pub id: Uuid,
+    pub strategy: Box<dyn ContestantStrategy + Send + Sync>,
+}
+
+impl Debug for Bot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bot")
+            .field("name", &self.name)
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+/// 内置策略和用户上传的机器人
+#[derive(Debug, Default)]
+pub struct Bots {
+    #[cfg(feature = "bots")]
+    modules: dashmap::DashMap<Uuid, std::sync::Arc<wasm::WasmBot>>,
+    /// 每个用户上传的机器人，按上传顺序排列。上传和删除在锁内同时修改 `modules`，保证数量限制
+    #[cfg(feature = "bots")]
+    owners: std::sync::Mutex<std::collections::HashMap<Uuid, std::collections::VecDeque<Uuid>>>,
+}
+
+impl Bots {
+    /// 按名称创建机器人：内置的 `switch`、`stick`、`random`，或者上传的机器人 ID
+    pub fn get(&self, name: &str) -> Option<Bot> {
+        let strategy: Box<dyn ContestantStrategy + Send + Sync> = match name {
+            "switch" => Box::new(FixedDecision(Decision::Switch)),
+            "stick" => Box::new(FixedDecision(Decision::Stick)),
+            "random" => Box::new(RandomDecision),
+            name => self.uploaded(name.parse().ok()?)?,
+        };
+        Some(Bot {
+            name: name.to_string(),
+            id: Uuid::new_v4(),
+            strategy,
+        })
+    }
+
+    /// 按名称创建内置的主持人策略：`random`、`lazy`
+    pub fn host(&self, name: &str) -> Option<Box<dyn HostStrategy + Send + Sync>> {
+        match name {
+            "random" => Some(Box::new(RandomHost)),
+            "lazy" => Some(Box::new(LazyHost)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(feature = "bots"))]
+impl Bots {
+    pub fn upload(&self, _owner: Uuid, _wasm: &[u8]) -> anyhow::Result<Uuid> {
+        anyhow::bail!("Uploading bots requires the `bots` feature.")
+    }
+
+    pub fn delete(&self, _owner: Uuid, _id: Uuid) -> bool {
+        false
+    }
+
+    fn uploaded(&self, _id: Uuid) -> Option<Box<dyn ContestantStrategy + Send + Sync>> {
+        None
+    }
+}
+
+#[cfg(feature = "bots")]
+mod wasm {
+    use super::*;
+    use crate::sandbox::WasmModule;
+    use std::collections::VecDeque;
+    use std::sync::Arc;
+
+    /// 模块的最大字节数
+    const MAX_MODULE_SIZE: usize = 256 * 1024;
+    /// 最多保存的机器人数
+    const MAX_BOTS: usize = 1000;
+    /// 每个用户最多保存的机器人数，超过时删除这个用户最早上传的机器人
+    const MAX_BOTS_PER_OWNER: usize = 10;
+
+    /// 编译好的机器人模块。
+    ///
+    /// 模块不能有导入，需要导出 `choose(doors: i32) -> i32` 和
+    /// `decide(doors: i32, chosen: i32, left: i32) -> i32`（0 坚持，1 改变）。
+    /// 每次调用都在新的实例中进行，调用之间不保留状态
+    pub struct WasmBot {
+        module: WasmModule,
+    }
+
+    impl Debug for WasmBot {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            f.write_str("WasmBot")
+        }
+    }
+
+    impl WasmBot {
+        pub fn new(wasm: &[u8]) -> anyhow::Result<Self> {
+            let bot = Self {
+                module: WasmModule::compile(wasm, MAX_MODULE_SIZE)?,
+            };
+            bot.choose(3)?;
+            bot.decide(3, 0, 1)?;
+            Ok(bot)
+        }
+
+        fn choose(&self, doors: u32) -> anyhow::Result<u32> {
+            let mut instance = self.module.instantiate()?;
+            let choose = instance
+                .func::<i32, i32>("choose")?
+                .ok_or_else(|| anyhow::anyhow!("Missing `choose` export."))?;
+            Ok(instance.call(&choose, doors as i32)? as u32)
+        }
+
+        fn decide(&self, doors: u32, chosen: u32, left: u32) -> anyhow::Result<Decision> {
+            let mut instance = self.module.instantiate()?;
+            let decide = instance
+                .func::<(i32, i32, i32), i32>("decide")?
+                .ok_or_else(|| anyhow::anyhow!("Missing `decide` export."))?;
+            let decision = instance.call(&decide, (doors as i32, chosen as i32, left as i32))?;
+            Ok(if decision == 0 {
+                Decision::Stick
+            } else {
+                Decision::Switch
+            })
+        }
+    }
+
+    /// 运行上传的模块，模块出错（包括燃料耗尽）时随机选择，并记录日志
+    #[derive(Debug)]
+    pub struct WasmStrategy {
+        id: Uuid,
+        bot: Arc<WasmBot>,
+    }
+
+    impl ContestantStrategy for WasmStrategy {
+        fn choose(&mut self, doors: u32) -> u32 {
+            match self.bot.choose(doors) {
+                Ok(chosen) if chosen < doors => chosen,
+                result => {
+                    tracing::warn!(bot = %self.id, ?result, "Bot failed to choose.");
+                    RandomDecision.choose(doors)
+                }
+            }
+        }
+
+        fn decide(&mut self, doors: u32, chosen: u32, left: u32) -> Decision {
+            self.bot
+                .decide(doors, chosen, left)
+                .unwrap_or_else(|cause| {
+                    tracing::warn!(bot = %self.id, %cause, "Bot failed to decide.");
+                    rand::random()
+                })
+        }
+    }
+
+    impl Bots {
+        /// 编译并保存 `owner` 上传的模块，返回机器人 ID。编译比较慢，异步上下文中在阻塞线程上调用
+        pub fn upload(&self, owner: Uuid, wasm: &[u8]) -> anyhow::Result<Uuid> {
+            let bot = Arc::new(WasmBot::new(wasm)?);
+            let id = Uuid::new_v4();
+            let mut owners = self.owners.lock().unwrap();
+            let full = owners.get(&owner).map_or(0, VecDeque::len) >= MAX_BOTS_PER_OWNER;
+            if !full && self.modules.len() >= MAX_BOTS {
+                anyhow::bail!("Too many bots.");
+            }
+            let uploaded = owners.entry(owner).or_default();
+            if full {
+                if let Some(evicted) = uploaded.pop_front() {
+                    self.modules.remove(&evicted);
+                    tracing::info!(user = %owner, bot = %evicted, "Bot evicted.");
+                }
+            }
+            uploaded.push_back(id);
+            self.modules.insert(id, bot);
+            Ok(id)
+        }
+
+        /// 删除 `owner` 上传的机器人，机器人不存在或者不属于 `owner` 时返回 `false`。
+        /// 已经邀请到房间中的机器人继续运行到离开为止
+        pub fn delete(&self, owner: Uuid, id: Uuid) -> bool {
+            let mut owners = self.owners.lock().unwrap();
+            let uploaded = match owners.get_mut(&owner) {
+                Some(uploaded) => uploaded,
+                None => return false,
+            };
+            match uploaded.iter().position(|bot| *bot == id) {
+                Some(index) => {
+                    uploaded.remove(index);
+                    if uploaded.is_empty() {
+                        owners.remove(&owner);
+                    }
+                    self.modules.remove(&id);
+                    true
+                }
+                None => false,
+            }
+        }
+
+        pub(super) fn uploaded(
+            &self,
+            id: Uuid,
+        ) -> Option<Box<dyn ContestantStrategy + Send + Sync>> {
+            let bot = self.modules.get(&id)?.clone();
+            Some(Box::new(WasmStrategy { id, bot }))
+        }
+    }
+}
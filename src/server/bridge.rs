@@ -0,0 +1,335 @@
+use ndoors::Uuid;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "webhooks")]
+use std::sync::Arc;
+use tokio::sync::mpsc;
+#[cfg(feature = "webhooks")]
+use tokio::sync::Semaphore;
+#[cfg(feature = "webhooks")]
+use tokio::time::Duration;
+
+#[cfg(feature = "webhooks")]
+use crate::http::{self, HttpClient};
+use crate::protocol::GameResponse;
+use crate::timestamp;
+
+/// 缓冲的事件数，消息系统跟不上时丢弃新事件，不影响游戏
+const BUFFER: usize = 4096;
+
+/// 转发给外部消息系统的房间事件，和主持人看到的事件相同
+#[derive(Debug, Serialize)]
+struct BridgeMessage {
+    /// Unix 时间戳（毫秒）
+    timestamp: u64,
+    tenant: String,
+    room_id: Uuid,
+    /// 房间事件序号
+    seq: u64,
+    event: GameResponse,
+}
+
+/// 房间事件转发配置
+#[derive(Debug, Deserialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BridgeConfig {
+    /// 不转发（默认）
+    #[default]
+    Disabled,
+    /// 发送到 Kafka 的 `topic`，键是房间 ID，需要 `kafka` feature
+    Kafka { brokers: String, topic: String },
+    /// 发布到 NATS 主题 `<prefix>.<房间 ID>`，需要 `nats` feature
+    Nats {
+        url: String,
+        #[serde(default = "default_prefix")]
+        prefix: String,
+    },
+    /// POST 到 `url`（`http://` 或 `https://`），请求头 `X-Ndoors-Signature` 是用房间密钥计算的 HMAC-SHA256 签名，
+    /// 失败时按指数退避重试 `retries` 次，需要 `webhooks` feature。多个事件同时发送，重试不阻塞新事件，
+    /// 所以同一个房间的事件可能乱序到达，接收方按 `seq` 排序
+    Webhook {
+        url: String,
+        /// 派生每个房间密钥的服务器密钥
+        secret: String,
+        #[serde(default = "default_retries")]
+        retries: u32,
+    },
+}
+
+fn default_prefix() -> String {
+    "ndoors.rooms".to_string()
+}
+
+fn default_retries() -> u32 {
+    5
+}
+
+/// 第一次重试前等待的时间，之后每次加倍
+#[cfg(feature = "webhooks")]
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+/// 单个请求的超时时间
+#[cfg(feature = "webhooks")]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// 同时进行的第一次发送数，都在进行时新事件留在缓冲中
+#[cfg(feature = "webhooks")]
+const MAX_SENDING: usize = 16;
+/// 同时等待重试的事件数，重试队列满时放弃新的失败事件
+#[cfg(feature = "webhooks")]
+const MAX_RETRYING: usize = 256;
+
+enum Target {
+    #[cfg(feature = "kafka")]
+    Kafka {
+        producer: rdkafka::producer::FutureProducer,
+        topic: String,
+    },
+    /// 第一次转发时连接
+    #[cfg(feature = "nats")]
+    Nats {
+        url: String,
+        prefix: String,
+        client: Option<async_nats::Client>,
+    },
+    #[cfg(feature = "webhooks")]
+    Webhook(Arc<Webhook>),
+}
+
+#[cfg(feature = "webhooks")]
+struct Webhook {
+    client: HttpClient,
+    uri: hyper::Uri,
+    secret: String,
+    retries: u32,
+    sending: Arc<Semaphore>,
+    retrying: Arc<Semaphore>,
+}
+
+#[cfg(feature = "webhooks")]
+impl Webhook {
+    /// POST 一次，失败时返回是否可以重试。网络错误、超时、5xx 和 429 可以重试，其他错误说明请求本身被拒绝
+    async fn post(&self, json: &str, signature: &str, attempt: u32) -> Result<(), bool> {
+        let request = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri(self.uri.clone())
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .header(signature::HEADER, signature)
+            .body(hyper::Body::from(json.to_string()))
+            .map_err(|cause| {
+                tracing::error!(%cause, "Invalid webhook request.");
+                false
+            })?;
+        match tokio::time::timeout(REQUEST_TIMEOUT, self.client.request(request)).await {
+            Ok(Ok(response)) if response.status().is_success() => Ok(()),
+            Ok(Ok(response)) => {
+                let status = response.status();
+                tracing::warn!(%status, attempt, "Webhook rejected room event.");
+                Err(status.is_server_error() || status == hyper::StatusCode::TOO_MANY_REQUESTS)
+            }
+            Ok(Err(cause)) => {
+                tracing::warn!(%cause, attempt, "Webhook unreachable.");
+                Err(true)
+            }
+            Err(_) => {
+                tracing::warn!(attempt, "Webhook timed out.");
+                Err(true)
+            }
+        }
+    }
+
+    /// 在后台发送一个事件。第一次发送最多同时进行 [`MAX_SENDING`] 个，都在进行时等待；
+    /// 失败后进入重试队列，释放发送的名额，重试队列满时放弃
+    async fn deliver(self: &Arc<Self>, room_id: Uuid, json: String) -> anyhow::Result<()> {
+        let key = signature::room_key(&self.secret, &room_id);
+        let signature = format!("sha256={}", signature::sign(&key, json.as_bytes()));
+        let sending = self.sending.clone().acquire_owned().await?;
+        let webhook = self.clone();
+        tokio::spawn(async move {
+            let retryable = match webhook.post(&json, &signature, 0).await {
+                Ok(()) => return,
+                Err(retryable) => retryable,
+            };
+            drop(sending);
+            if !retryable || webhook.retries == 0 {
+                tracing::error!(%room_id, "Webhook gave up on room event.");
+                return;
+            }
+            let _retrying = match webhook.retrying.clone().try_acquire_owned() {
+                Ok(retrying) => retrying,
+                Err(_) => {
+                    tracing::error!(%room_id, "Webhook retry queue full, room event dropped.");
+                    return;
+                }
+            };
+            let mut delay = RETRY_DELAY;
+            for attempt in 1..=webhook.retries {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                match webhook.post(&json, &signature, attempt).await {
+                    Ok(()) => return,
+                    Err(false) => break,
+                    Err(true) => {}
+                }
+            }
+            tracing::error!(%room_id, "Webhook gave up on room event.");
+        });
+        Ok(())
+    }
+}
+
+impl Target {
+    fn new(config: BridgeConfig) -> anyhow::Result<Option<Self>> {
+        match config {
+            BridgeConfig::Disabled => Ok(None),
+            #[cfg(feature = "kafka")]
+            BridgeConfig::Kafka { brokers, topic } => Ok(Some(Target::Kafka {
+                producer: rdkafka::ClientConfig::new()
+                    .set("bootstrap.servers", brokers)
+                    .create()?,
+                topic,
+            })),
+            #[cfg(not(feature = "kafka"))]
+            BridgeConfig::Kafka { .. } => {
+                anyhow::bail!("The Kafka event bridge requires the `kafka` feature.")
+            }
+            #[cfg(feature = "nats")]
+            BridgeConfig::Nats { url, prefix } => Ok(Some(Target::Nats {
+                url,
+                prefix,
+                client: None,
+            })),
+            #[cfg(not(feature = "nats"))]
+            BridgeConfig::Nats { .. } => {
+                anyhow::bail!("The NATS event bridge requires the `nats` feature.")
+            }
+            #[cfg(feature = "webhooks")]
+            BridgeConfig::Webhook {
+                url,
+                secret,
+                retries,
+            } => Ok(Some(Target::Webhook(Arc::new(Webhook {
+                client: http::client(),
+                uri: url.parse()?,
+                secret,
+                retries,
+                sending: Arc::new(Semaphore::new(MAX_SENDING)),
+                retrying: Arc::new(Semaphore::new(MAX_RETRYING)),
+            })))),
+            #[cfg(not(feature = "webhooks"))]
+            BridgeConfig::Webhook { .. } => {
+                anyhow::bail!("The webhook event bridge requires the `webhooks` feature.")
+            }
+        }
+    }
+
+    #[cfg(not(any(feature = "kafka", feature = "nats", feature = "webhooks")))]
+    async fn send(&mut self, _message: BridgeMessage) -> anyhow::Result<()> {
+        match *self {}
+    }
+
+    #[cfg(any(feature = "kafka", feature = "nats", feature = "webhooks"))]
+    async fn send(&mut self, message: BridgeMessage) -> anyhow::Result<()> {
+        match self {
+            #[cfg(feature = "kafka")]
+            Target::Kafka { producer, topic } => {
+                let json = serde_json::to_string(&message)?;
+                let key = message.room_id.to_string();
+                let record = rdkafka::producer::FutureRecord::to(topic)
+                    .key(&key)
+                    .payload(&json);
+                producer
+                    .send(record, std::time::Duration::from_secs(5))
+                    .await
+                    .map_err(|(cause, _)| cause)?;
+            }
+            #[cfg(feature = "nats")]
+            Target::Nats {
+                url,
+                prefix,
+                client,
+            } => {
+                let json = serde_json::to_string(&message)?;
+                let client = match client {
+                    Some(client) => client,
+                    None => client.insert(async_nats::connect(url.as_str()).await?),
+                };
+                let subject = format!("{}.{}", prefix, message.room_id);
+                client.publish(subject, json.into()).await?;
+            }
+            #[cfg(feature = "webhooks")]
+            Target::Webhook(webhook) => {
+                let json = serde_json::to_string(&message)?;
+                webhook.deliver(message.room_id, json).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// webhook 签名：房间密钥 = HMAC-SHA256(服务器密钥, 房间 ID)，签名 = HMAC-SHA256(房间密钥, 请求体)，
+/// 都用小写十六进制表示。接收方用同样的方法验证，泄露一个房间的密钥不影响其他房间
+#[cfg(feature = "webhooks")]
+mod signature {
+    use hmac::{Hmac, Mac};
+    use ndoors::Uuid;
+    use sha2::Sha256;
+
+    pub const HEADER: &str = "x-ndoors-signature";
+
+    fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn room_key(secret: &str, room_id: &Uuid) -> String {
+        hex(&hmac(secret.as_bytes(), room_id.to_string().as_bytes()))
+    }
+
+    pub fn sign(room_key: &str, payload: &[u8]) -> String {
+        hex(&hmac(room_key.as_bytes(), payload))
+    }
+}
+
+/// 把房间事件实时转发到 Kafka、NATS 或 webhook，供排行榜、机器学习等外部服务消费。
+/// 发送在后台任务中进行，失败只记录日志。Kafka 和 NATS 按顺序发送，webhook 见 [`BridgeConfig::Webhook`]
+#[derive(Debug, Default)]
+pub struct EventBridge {
+    sender: Option<mpsc::Sender<BridgeMessage>>,
+}
+
+impl EventBridge {
+    pub fn start(config: BridgeConfig) -> anyhow::Result<Self> {
+        let sender = Target::new(config)?.map(|mut target| {
+            let (sender, mut receiver) = mpsc::channel::<BridgeMessage>(BUFFER);
+            tokio::spawn(async move {
+                while let Some(message) = receiver.recv().await {
+                    if let Err(cause) = target.send(message).await {
+                        tracing::error!(%cause, "Failed to forward room event.");
+                    }
+                }
+            });
+            sender
+        });
+        Ok(Self { sender })
+    }
+
+    pub fn forward(&self, tenant: &str, room_id: Uuid, seq: u64, event: &GameResponse) {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return,
+        };
+        let message = BridgeMessage {
+            timestamp: timestamp(),
+            tenant: tenant.to_string(),
+            room_id,
+            seq,
+            event: event.clone(),
+        };
+        if sender.try_send(message).is_err() {
+            tracing::warn!(%room_id, "Room event dropped by the event bridge.");
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use dashmap::DashMap;
+use ndoors::{RoomState, Stats, Uuid};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::room::{RoomAgent, RoomStatus};
+
+/// 班级名称的最大长度
+pub const MAX_CLASS_LEN: usize = 64;
+
+/// 班级中一个房间的进度
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RoomProgress {
+    id: Uuid,
+    status: RoomStatus,
+    /// 进行中的一局已经完成的轮数
+    round: u32,
+    /// 每局的轮数
+    rounds: u32,
+    /// 已经完成的局数（最多保留最近 20 局）
+    games: u32,
+    /// 房间中所有轮次的统计，包括进行中的一局
+    #[serde(flatten)]
+    stats: Stats,
+    /// 每轮从开始到抉择的平均毫秒数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean_round_ms: Option<u64>,
+    /// 每赢一轮平均花费的毫秒数，越小说明又快又准
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ms_per_win: Option<u64>,
+}
+
+/// 一个班级（同一租户中创建时指定了相同 `class` 的房间）的实时汇总
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ClassReport {
+    class: String,
+    /// 按房间 ID 排序
+    rooms: Vec<RoomProgress>,
+    /// 所有房间所有轮次的统计
+    #[serde(flatten)]
+    stats: Stats,
+    /// 改变选择的比例
+    switch_rate: f64,
+    /// 中奖率
+    win_rate: f64,
+    /// 每轮从开始到抉择的平均毫秒数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean_round_ms: Option<u64>,
+}
+
+impl ClassReport {
+    /// 汇总租户中属于班级的房间，不包括被隔离的房间
+    pub fn collect(rooms: &DashMap<Uuid, RoomAgent>, tenant: &str, class: &str) -> Self {
+        let mut progress = Vec::new();
+        let mut results = Vec::new();
+        for ra in rooms.iter() {
+            if ra.tenant != tenant || ra.class.as_deref() != Some(class) || ra.quarantined {
+                continue;
+            }
+            let current = match ra.room.state() {
+                RoomState::Started { results, .. } => results.as_slice(),
+                _ => &[],
+            };
+            let rounds = ra.history.iter().flat_map(|game| &game.rounds);
+            let stats = Stats::calculate(rounds.clone().chain(current));
+            progress.push(RoomProgress {
+                id: *ra.room.id(),
+                status: RoomStatus::from(ra.room.state()),
+                round: current.len() as u32,
+                rounds: ra.room.settings().rounds,
+                games: ra.history.len() as u32,
+                mean_round_ms: stats.mean_round_ms(),
+                ms_per_win: stats.ms_per_win(),
+                stats,
+            });
+            results.extend(rounds.chain(current).copied());
+        }
+        progress.sort_by_key(|room| room.id);
+
+        let stats = Stats::calculate(&results);
+        Self {
+            class: class.to_string(),
+            rooms: progress,
+            switch_rate: stats.switch_rate(),
+            win_rate: stats.win_rate(),
+            mean_round_ms: stats.mean_round_ms(),
+            stats,
+        }
+    }
+}
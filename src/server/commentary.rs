@@ -0,0 +1,186 @@
+use ndoors::{Decision, Room, RoomState, Stage};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::protocol::GameResponse;
+
+/// 一种语言的解说模板，占位符替换为门的编号（从 1 开始）或者轮次。
+///
+/// 服务器内置 `en` 和 `zh`，启动时还会读取 `commentary_dir` 目录中的 `<语言>.json`，同名时替换内置的模板
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Templates {
+    /// 新的一轮开始：`{round}`、`{doors}`
+    pub started: String,
+    /// 挑战者选了门：`{chosen}`
+    pub chosen: String,
+    /// 主持人打开了其他的门：`{chosen}`、`{left}`
+    pub revealed: String,
+    /// 改变选择并且赢了：`{door}`（最终的门）、`{prize}`
+    pub switched_won: String,
+    /// 改变选择但是输了：`{door}`、`{prize}`
+    pub switched_lost: String,
+    /// 坚持选择并且赢了：`{door}`、`{prize}`
+    pub stuck_won: String,
+    /// 坚持选择但是输了：`{door}`、`{prize}`
+    pub stuck_lost: String,
+    /// 规定轮数打完后打平：`{round}`
+    pub sudden_death: String,
+    /// 本局结束：`{win}`、`{rounds}`
+    pub completed: String,
+}
+
+impl Templates {
+    fn english() -> Self {
+        Self {
+            started: "Round {round} is on, with {doors} doors to pick from.".to_string(),
+            chosen: "The contestant points at door {chosen}. Bold choice!".to_string(),
+            revealed: "The host throws the other doors open. Only door {left} stays shut \
+                       next to door {chosen}. Switch or stick?"
+                .to_string(),
+            switched_won: "The contestant switched to door {door} and there it is, the prize!"
+                .to_string(),
+            switched_lost:
+                "The contestant switched and the prize was behind door {prize} all along!"
+                    .to_string(),
+            stuck_won: "The contestant held firm on door {door} and it pays off!".to_string(),
+            stuck_lost:
+                "The contestant stuck with door {door}, but the prize was behind door {prize}."
+                    .to_string(),
+            sudden_death: "All square! Sudden death, round {round}.".to_string(),
+            completed: "That's the game: {win} prizes out of {rounds} rounds.".to_string(),
+        }
+    }
+
+    fn chinese() -> Self {
+        Self {
+            started: "第 {round} 轮开始，一共 {doors} 扇门。".to_string(),
+            chosen: "挑战者指向了 {chosen} 号门，果断！".to_string(),
+            revealed:
+                "主持人打开了其他的门，除了 {chosen} 号门只剩 {left} 号门还关着。换还是不换？"
+                    .to_string(),
+            switched_won: "挑战者换到了 {door} 号门，大奖就在这里！".to_string(),
+            switched_lost: "挑战者换了门，可是大奖一直就在 {prize} 号门后面！".to_string(),
+            stuck_won: "挑战者坚持选择 {door} 号门，坚持得到了回报！".to_string(),
+            stuck_lost: "挑战者坚持选择 {door} 号门，可惜大奖在 {prize} 号门后面。".to_string(),
+            sudden_death: "双方打平！加赛第 {round} 轮。".to_string(),
+            completed: "本局结束：{rounds} 轮中赢得了 {win} 份奖品。".to_string(),
+        }
+    }
+
+    /// 根据广播给观众的响应生成一句解说，不会透露观众还看不到的奖品位置
+    pub fn comment(&self, response: &GameResponse, room: &Room) -> Option<String> {
+        let door = |index: u32| (index + 1).to_string();
+        let (template, values) = match response {
+            GameResponse::ContestantStarted { .. } => {
+                let round = match room.state() {
+                    RoomState::Started { current_round, .. } => current_round + 1,
+                    _ => return None,
+                };
+                (
+                    &self.started,
+                    vec![
+                        ("round", round.to_string()),
+                        ("doors", room.round_settings().doors.to_string()),
+                    ],
+                )
+            }
+            GameResponse::Chosen { chosen, .. } => (&self.chosen, vec![("chosen", door(*chosen))]),
+            GameResponse::Revealed { left, .. } => {
+                let chosen = match room.state() {
+                    RoomState::Started {
+                        stage: Stage::Decide { chosen, .. },
+                        ..
+                    } => *chosen,
+                    _ => return None,
+                };
+                (
+                    &self.revealed,
+                    vec![("chosen", door(chosen)), ("left", door(*left))],
+                )
+            }
+            GameResponse::Decided { result, .. } => {
+                let (template, final_door) = match (result.decision(), result.win()) {
+                    (Decision::Stick, true) => (&self.stuck_won, result.chosen()),
+                    (Decision::Stick, false) => (&self.stuck_lost, result.chosen()),
+                    (_, true) => (&self.switched_won, result.left()),
+                    (_, false) => (&self.switched_lost, result.left()),
+                };
+                (
+                    template,
+                    vec![("door", door(final_door)), ("prize", door(result.prize()))],
+                )
+            }
+            GameResponse::SuddenDeath { round, .. } => {
+                (&self.sudden_death, vec![("round", (round + 1).to_string())])
+            }
+            GameResponse::Completed { result, .. } => {
+                let stats = result.stats();
+                (
+                    &self.completed,
+                    vec![
+                        ("win", stats.win().to_string()),
+                        ("rounds", stats.rounds().to_string()),
+                    ],
+                )
+            }
+            _ => return None,
+        };
+        let text = values
+            .into_iter()
+            .fold(template.clone(), |text, (key, value)| {
+                text.replace(&format!("{{{}}}", key), &value)
+            });
+        Some(text)
+    }
+}
+
+/// 加载的解说模板，按语言索引
+#[derive(Debug)]
+pub struct Commentary {
+    locales: BTreeMap<String, Arc<Templates>>,
+}
+
+impl Commentary {
+    /// 内置的模板加上目录中的 `<语言>.json`，有误的文件记录日志后跳过
+    pub fn load(dir: Option<&Path>) -> anyhow::Result<Self> {
+        let mut commentary = Commentary {
+            locales: BTreeMap::from([
+                ("en".to_string(), Arc::new(Templates::english())),
+                ("zh".to_string(), Arc::new(Templates::chinese())),
+            ]),
+        };
+        let dir = match dir {
+            Some(dir) => dir,
+            None => return Ok(commentary),
+        };
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+            let locale = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(locale) => locale.to_string(),
+                None => continue,
+            };
+            let loaded = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(serde_json::from_str::<Templates>(&content)?));
+            match loaded {
+                Ok(templates) => {
+                    tracing::info!(path = %path.display(), %locale, "Commentary loaded.");
+                    commentary.locales.insert(locale, Arc::new(templates));
+                }
+                Err(cause) => {
+                    tracing::error!(path = %path.display(), %cause, "Invalid commentary.")
+                }
+            }
+        }
+        Ok(commentary)
+    }
+
+    pub fn get(&self, locale: &str) -> Option<Arc<Templates>> {
+        self.locales.get(locale).cloned()
+    }
+}
@@ -0,0 +1,49 @@
+use axum::extract::ws::Message;
+use flate2::write::{DeflateEncoder, GzEncoder};
+use serde::Deserialize;
+use std::io::Write;
+
+/// 客户端支持的压缩格式，连接时通过 `compress` 查询参数指定
+#[derive(Debug, Deserialize, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+/// 压缩超过阈值的响应，压缩后的消息以二进制帧发送，小消息仍然是文本帧
+#[derive(Debug, Copy, Clone)]
+pub struct Compressor {
+    encoding: Encoding,
+    threshold: usize,
+}
+
+impl Compressor {
+    pub fn new(encoding: Encoding, threshold: usize) -> Self {
+        Self {
+            encoding,
+            threshold,
+        }
+    }
+
+    pub fn frame(&self, text: String) -> std::io::Result<Message> {
+        if text.len() < self.threshold {
+            return Ok(Message::Text(text));
+        }
+
+        let level = flate2::Compression::default();
+        let compressed = match self.encoding {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), level);
+                encoder.write_all(text.as_bytes())?;
+                encoder.finish()?
+            }
+            Encoding::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), level);
+                encoder.write_all(text.as_bytes())?;
+                encoder.finish()?
+            }
+        };
+        Ok(Message::Binary(compressed))
+    }
+}
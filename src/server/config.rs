@@ -0,0 +1,143 @@
+use ndoors::Settings;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::analytics::AnalyticsConfig;
+use crate::auth::AuthConfig;
+use crate::bridge::BridgeConfig;
+use crate::connection::DuplicatePolicy;
+use crate::correspondence::Recovery;
+use crate::exhibition::Exhibition;
+use crate::filter::FilterConfig;
+use crate::footprint::SizeLimits;
+use crate::netsim::NetworkSimulation;
+use crate::push::PushConfig;
+use crate::quota::{Quota, QuotaHookConfig};
+use crate::random::RandomSource;
+
+/// 服务器配置，启动时从 `NDOORS_CONFIG` 指定的 JSON 文件读取，未指定时使用默认配置。
+/// 收到 SIGHUP 或调用管理接口时重新读取，只有 [`Tunables`](crate::tunables::Tunables) 中的配置会生效
+#[derive(Debug, Deserialize, Default)]
+pub struct Config {
+    /// 认证方式
+    #[serde(default)]
+    pub auth: AuthConfig,
+
+    /// 管理接口 `/api/admin` 的令牌，未设置时管理接口不可用
+    #[serde(default)]
+    pub admin_token: Option<String>,
+
+    /// 创建房间时没有指定设置使用的默认设置，默认 3 个门 10 轮
+    #[serde(default)]
+    pub default_settings: Option<Settings>,
+
+    /// 聊天消息和显示名称的过滤规则
+    #[serde(default)]
+    pub filter: FilterConfig,
+
+    /// 审计日志文件
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+
+    /// 房间的随机数来源：`os`（默认）、`seeded` 或者 `beacon`，来源记录在审计日志中
+    #[serde(default)]
+    pub random_source: RandomSource,
+
+    /// 公平模式的 VRF 私钥（32 个字节，十六进制），未设置时不能创建公平模式房间，需要 `fairness` 特性
+    pub fairness_key: Option<String>,
+
+    /// 调试模式：为测试用户模拟延迟、乱序和断线，不要在生产环境开启
+    #[serde(default)]
+    pub network_simulation: Option<NetworkSimulation>,
+
+    /// 记录每个 WebSocket 会话的目录，可以用 `ndoors-server replay` 回放
+    #[serde(default)]
+    pub record_dir: Option<PathBuf>,
+
+    /// 公开数据集文件，设置后把真人完成的每局游戏的轮次匿名追加到 CSV 文件，
+    /// 可以从 `/api/dataset.csv` 下载，默认不收集
+    #[serde(default)]
+    pub dataset: Option<PathBuf>,
+
+    /// 匿名分析事件的输出位置，默认不输出
+    #[serde(default)]
+    pub analytics: AnalyticsConfig,
+
+    /// 把房间事件实时转发到 Kafka 或 NATS，默认不转发
+    #[serde(default)]
+    pub event_bridge: BridgeConfig,
+
+    /// 每个租户的配额，没有列出的租户不限制
+    #[serde(default)]
+    pub quotas: HashMap<String, Quota>,
+
+    /// 配额事件的处理方式，默认输出到 `quota` 日志 target
+    #[serde(default)]
+    pub quota_hook: QuotaHookConfig,
+
+    /// 课程包目录，启动时读取其中所有的 `*.json` 文件
+    #[serde(default)]
+    pub lesson_dir: Option<PathBuf>,
+
+    /// 解说模板目录，启动时读取其中所有的 `<语言>.json` 文件，补充或者替换内置的 `en` 和 `zh`
+    #[serde(default)]
+    pub commentary_dir: Option<PathBuf>,
+
+    /// 轮到已经断开连接或者长时间没有操作的用户时推送通知，默认不推送
+    #[serde(default)]
+    pub push: PushConfig,
+
+    /// 断开连接的会话保留的秒数，期间可以用恢复令牌恢复，默认不保留
+    #[serde(default)]
+    pub session_resume_secs: Option<u64>,
+
+    /// 同一个已认证用户再次连接时的处理方式：`takeover`（默认）、`reject` 或 `allow`
+    #[serde(default)]
+    pub duplicate_connections: DuplicatePolicy,
+
+    /// 通信对局房间的保存目录，设置后才能创建通信对局房间，服务器重启后恢复其中的房间
+    #[serde(default)]
+    pub correspondence_dir: Option<PathBuf>,
+
+    /// 服务器重启后如何恢复进行中的一轮：`resume`（默认）从保存的阶段继续，
+    /// `rollback` 退回挑战者选择并通知成员
+    #[serde(default)]
+    pub correspondence_recovery: Recovery,
+
+    /// 启动时创建的机器人对战房间
+    #[serde(default)]
+    pub exhibitions: Vec<Exhibition>,
+
+    /// 客户端要求压缩时，达到这个字节数的响应才会被压缩，默认 16 KiB
+    #[serde(default)]
+    pub compression_threshold: Option<usize>,
+
+    /// 规则插件目录，启动时读取其中所有的 `<规则名称>.wasm` 文件，需要 `plugins` feature
+    #[serde(default)]
+    pub plugin_dir: Option<PathBuf>,
+
+    /// 房间占用内存的告警阈值，见 `/api/admin/rooms/footprint`
+    #[serde(default)]
+    pub room_size_limits: SizeLimits,
+
+    /// 每局游戏结束后按记录的操作重放并核对的轮次比例（0 到 1），结果不一致时输出错误日志并计入
+    /// `ndoors_round_audit_mismatches_total`，默认不核对
+    #[serde(default)]
+    pub round_audit_rate: Option<f64>,
+}
+
+impl Config {
+    pub fn load() -> anyhow::Result<Self> {
+        match std::env::var_os("NDOORS_CONFIG") {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                let content = std::fs::read_to_string(&path)?;
+                let config = serde_json::from_str(&content)?;
+                tracing::info!(path = %path.display(), "Config loaded.");
+                Ok(config)
+            }
+            None => Ok(Config::default()),
+        }
+    }
+}
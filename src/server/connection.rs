@@ -0,0 +1,89 @@
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use ndoors::Uuid;
+use serde::Deserialize;
+use tokio::sync::oneshot;
+
+use crate::room::RoomDropper;
+use crate::User;
+
+/// 同一个用户再次连接时的处理方式。匿名用户每次连接都是新用户，不受影响
+#[derive(Debug, Deserialize, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// 新连接接管原来的会话（包括房间中的角色），原来的连接收到通知后关闭（默认）
+    #[default]
+    Takeover,
+    /// 拒绝新连接
+    Reject,
+    /// 每个连接都是独立的会话
+    Allow,
+}
+
+/// 接管时原来的连接交出会话的通道
+pub type Handoff = oneshot::Sender<(User, RoomDropper)>;
+
+/// 一个用户当前的连接
+#[derive(Debug)]
+struct Live {
+    /// 连接的序号，用来区分同一用户前后两个连接
+    serial: Uuid,
+    /// 向连接的请求处理任务索要会话
+    takeover: oneshot::Sender<Handoff>,
+}
+
+/// 已认证用户的连接，用来检测同一用户同时打开多个连接
+#[derive(Debug)]
+pub struct Connections {
+    policy: DuplicatePolicy,
+    live: DashMap<Uuid, Live>,
+}
+
+impl Connections {
+    pub fn new(policy: DuplicatePolicy) -> Self {
+        Self {
+            policy,
+            live: DashMap::new(),
+        }
+    }
+
+    /// 按照配置拒绝重复连接
+    pub fn rejects(&self, user_id: &Uuid) -> bool {
+        self.policy == DuplicatePolicy::Reject && self.live.contains_key(user_id)
+    }
+
+    /// 接管用户原来的连接，返回交出的会话。原来的连接已经结束时返回 `None`
+    pub async fn take_over(&self, user_id: &Uuid) -> Option<(User, RoomDropper)> {
+        if self.policy != DuplicatePolicy::Takeover {
+            return None;
+        }
+        let (_, live) = self.live.remove(user_id)?;
+        let (handoff, receiver) = oneshot::channel();
+        live.takeover.send(handoff).ok()?;
+        let session = receiver.await.ok()?;
+        tracing::info!(user = %user_id, "Connection taken over.");
+        Some(session)
+    }
+
+    /// 登记新的连接，返回连接序号和接管请求的接收端。`Allow` 时不登记，接收端永远不会收到请求
+    pub fn register(&self, user_id: Uuid) -> (Uuid, oneshot::Receiver<Handoff>) {
+        let serial = Uuid::new_v4();
+        let (takeover, receiver) = oneshot::channel();
+        if self.policy != DuplicatePolicy::Allow {
+            match self.live.entry(user_id) {
+                // 拒绝时两个连接同时通过了检查，后来的连接不再能被接管
+                Entry::Occupied(_) => {}
+                Entry::Vacant(entry) => {
+                    entry.insert(Live { serial, takeover });
+                }
+            }
+        }
+        (serial, receiver)
+    }
+
+    /// 连接结束，已经被接管时不影响新的连接
+    pub fn unregister(&self, user_id: &Uuid, serial: Uuid) {
+        self.live
+            .remove_if(user_id, |_, live| live.serial == serial);
+    }
+}
@@ -0,0 +1,355 @@
+use ndoors::protocol::Correspondence;
+use ndoors::{EarlyEnd, Room, RoomSnapshot, RoomState, Stage, Uuid};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, Sender};
+use tokio::time::Duration;
+
+use crate::fair::FairRoom;
+use crate::pacing::Pacing;
+use crate::permission::Role;
+use crate::protocol::{GameResponse, Index, ServerMessage};
+use crate::room::{RoomAgent, RoomDropper};
+use crate::{
+    complete_game, force_complete_game, reveal_door, run_automation, start_round, timestamp,
+    Server, User,
+};
+
+/// 缓冲的快照删除请求数，跟不上时下次检查再删除
+const BUFFER: usize = 1024;
+
+/// 检查阶段时限的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 通信对局房间的状态，和房间快照一起保存
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CorrespondenceState {
+    pub turn_secs: u64,
+    /// 当前阶段的截止时间（Unix 时间戳，毫秒）
+    pub deadline: Option<u64>,
+    /// 成员的恢复令牌，服务器重启后用来恢复主持人和挑战者的会话
+    pub tokens: HashMap<Uuid, Uuid>,
+    /// 服务器重启时退回挑战者选择的轮次，成员恢复会话时收到通知，下一次阶段变化后清除
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rolled_back: Option<u32>,
+}
+
+/// 服务器重启后恢复进行中的一轮的方式。快照在每次广播之前同步写入，
+/// 成员看到的阶段都已经保存，写入失败时快照停留在之前的阶段
+#[derive(Debug, Deserialize, Copy, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Recovery {
+    /// 从保存的阶段继续（默认）
+    #[default]
+    Resume,
+    /// 主持人揭示或者挑战者抉择中的一轮退回挑战者选择，奖品位置不变，
+    /// 成员恢复会话时收到 `RoundRolledBack`
+    Rollback,
+}
+
+impl CorrespondenceState {
+    /// `host` 是主持人的 ID，`resume_token` 是主持人会话的恢复令牌
+    pub fn new(options: Correspondence, host: Uuid, resume_token: Uuid) -> Self {
+        Self {
+            turn_secs: options.turn_secs,
+            deadline: None,
+            tokens: HashMap::from([(host, resume_token)]),
+            rolled_back: None,
+        }
+    }
+
+    /// 阶段变化后重新计时，之前退回的轮次不再通知
+    pub fn restart_clock(&mut self) {
+        self.deadline = Some(timestamp() + self.turn_secs * 1000);
+        self.rolled_back = None;
+    }
+}
+
+/// 保存的房间快照，读取后检查房间的不变量再恢复房间
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot<R> {
+    room: R,
+    tenant: String,
+    pacing: Pacing,
+    #[serde(default)]
+    class: Option<String>,
+    seq: u64,
+    correspondence: CorrespondenceState,
+    #[serde(default)]
+    fairness: Option<FairRoom>,
+}
+
+impl Snapshot<RoomSnapshot> {
+    fn restore(self) -> ndoors::Result<Snapshot<Room>> {
+        Ok(Snapshot {
+            room: Room::restore(self.room)?,
+            tenant: self.tenant,
+            pacing: self.pacing,
+            class: self.class,
+            seq: self.seq,
+            correspondence: self.correspondence,
+            fairness: self.fairness,
+        })
+    }
+}
+
+/// 通信对局房间的存储，每个房间一个 `<房间 ID>.json` 文件，每次广播事件之前同步覆盖写入。
+/// 通信对局的阶段变化很慢，同步写入的开销可以接受；删除文件在后台任务中进行
+#[derive(Debug)]
+pub struct RoomStore {
+    dir: PathBuf,
+    recovery: Recovery,
+    sender: mpsc::Sender<Uuid>,
+    /// 已经保存的房间，房间删除后删除文件
+    saved: Mutex<HashSet<Uuid>>,
+}
+
+impl RoomStore {
+    pub fn open(dir: PathBuf, recovery: Recovery) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        let (sender, mut receiver) = mpsc::channel::<Uuid>(BUFFER);
+        let remover_dir = dir.clone();
+        tokio::spawn(async move {
+            while let Some(id) = receiver.recv().await {
+                let path = remover_dir.join(format!("{}.json", id));
+                if let Err(cause) = tokio::fs::remove_file(&path).await {
+                    tracing::error!(path = %path.display(), %cause, "Failed to update room store.");
+                }
+            }
+        });
+        Ok(Self {
+            dir,
+            recovery,
+            sender,
+            saved: Default::default(),
+        })
+    }
+
+    /// 保存通信对局房间的快照
+    pub fn save(&self, ra: &RoomAgent) {
+        let correspondence = match &ra.correspondence {
+            Some(correspondence) => correspondence.clone(),
+            None => return,
+        };
+        let id = *ra.room.id();
+        let snapshot = Snapshot {
+            room: ra.room.snapshot(),
+            tenant: ra.tenant.clone(),
+            pacing: ra.outbox.pacing(),
+            class: ra.class.clone(),
+            seq: ra.seq,
+            correspondence,
+            fairness: ra.fairness.clone(),
+        };
+        let json = match serde_json::to_string(&snapshot) {
+            Ok(json) => json,
+            Err(cause) => {
+                tracing::error!(room = %id, %cause, "Failed to serialize room snapshot.");
+                return;
+            }
+        };
+        let path = self.dir.join(format!("{}.json", id));
+        if let Err(cause) = write_snapshot(&path, &json) {
+            tracing::error!(path = %path.display(), %cause, "Failed to save room snapshot.");
+            return;
+        }
+        self.saved.lock().unwrap().insert(id);
+    }
+
+    /// 删除已经不存在的房间的快照
+    fn prune(&self, server: &Server) {
+        let mut saved = self.saved.lock().unwrap();
+        saved.retain(|id| {
+            if server.rooms.contains_key(id) {
+                return true;
+            }
+            if self.sender.try_send(*id).is_err() {
+                tracing::warn!(room = %id, "Room snapshot removal dropped.");
+                return true;
+            }
+            false
+        });
+    }
+
+    /// 读取所有快照，有误的快照记录日志后跳过
+    fn load(&self) -> anyhow::Result<Vec<Snapshot<Room>>> {
+        let mut snapshots = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+            let loaded = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| {
+                    let snapshot = serde_json::from_str::<Snapshot<RoomSnapshot>>(&content)?;
+                    Ok(snapshot.restore()?)
+                });
+            match loaded {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(cause) => {
+                    tracing::error!(path = %path.display(), %cause, "Invalid room snapshot.")
+                }
+            }
+        }
+        Ok(snapshots)
+    }
+}
+
+/// 先写临时文件并刷到磁盘再改名，写到一半时崩溃不会损坏之前的快照
+fn write_snapshot(path: &Path, json: &str) -> std::io::Result<()> {
+    let temp = path.with_extension("json.tmp");
+    let mut file = std::fs::File::create(&temp)?;
+    file.write_all(json.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)
+}
+
+/// 已经关闭的发送端，成员恢复会话之前发给他们的消息直接丢弃
+fn disconnected() -> Sender<ServerMessage> {
+    mpsc::channel(1).0
+}
+
+/// 启动时恢复保存的通信对局房间，主持人和挑战者的会话可以用原来的恢复令牌恢复
+pub fn restore(server: &Server, store: &Arc<RoomStore>) -> anyhow::Result<()> {
+    for snapshot in store.load()? {
+        let room_id = *snapshot.room.id();
+        let host = *snapshot.room.host();
+        let contestant = snapshot.room.contestant();
+        let mut room = snapshot.room;
+        server.rules.attach(&mut room);
+        let mut ra = RoomAgent::new(
+            room,
+            snapshot.tenant,
+            disconnected(),
+            snapshot.pacing,
+            server.bridge.clone(),
+            server.push.clone(),
+            server.lobby.clone(),
+        );
+        ra.contestant = contestant.map(|_| disconnected());
+        ra.class = snapshot.class;
+        ra.seq = snapshot.seq;
+        ra.fairness = snapshot.fairness;
+        ra.store = Some(store.clone());
+
+        for (user_id, token) in &snapshot.correspondence.tokens {
+            let role = if *user_id == host {
+                Role::Host { room_id }
+            } else if Some(*user_id) == contestant {
+                Role::Contestant { room_id }
+            } else {
+                continue;
+            };
+            let mut user = User::new(*user_id, disconnected());
+            user.tenant = ra.tenant.clone();
+            user.role = role;
+            user.resume_token = *token;
+            server
+                .sessions
+                .park(user, RoomDropper::new(server.rooms.clone()), false);
+        }
+        ra.correspondence = Some(snapshot.correspondence);
+        if store.recovery == Recovery::Rollback {
+            roll_back(&mut ra);
+        }
+        ra.relist();
+        store.saved.lock().unwrap().insert(room_id);
+        server.rooms.insert(room_id, ra);
+        tracing::info!(room = %room_id, "Correspondence room restored.");
+    }
+    Ok(())
+}
+
+/// 把重启前进行到一半的一轮退回挑战者选择，重新计时
+fn roll_back(ra: &mut RoomAgent) {
+    let round = match ra.room.state() {
+        RoomState::Started { current_round, .. } => *current_round,
+        _ => return,
+    };
+    if ra.room.rollback_round().is_err() {
+        return;
+    }
+    tracing::info!(room = %ra.room.id(), round, "Interrupted round rolled back.");
+    ra.publish(GameResponse::RoundRolledBack { round });
+    if let Some(correspondence) = &mut ra.correspondence {
+        correspondence.rolled_back = Some(round);
+    }
+    ra.persist();
+}
+
+/// 在后台检查阶段时限，清理已经结束的房间的快照和会话
+pub fn watch(server: Server, store: Arc<RoomStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let now = timestamp();
+            // 不能在遍历的同时 get_mut，会导致死锁
+            let expired: Vec<Uuid> = server
+                .rooms
+                .iter()
+                .filter(|ra| !ra.quarantined)
+                .filter(|ra| {
+                    ra.correspondence
+                        .as_ref()
+                        .and_then(|correspondence| correspondence.deadline)
+                        .is_some_and(|deadline| deadline <= now)
+                })
+                .map(|ra| *ra.room.id())
+                .collect();
+            for room_id in expired {
+                if let Some(mut ra) = server.rooms.get_mut(&room_id) {
+                    time_out(&server, &mut ra);
+                }
+            }
+            store.prune(&server);
+            server.sessions.prune(&server.rooms);
+        }
+    });
+}
+
+/// 阶段超时：轮到主持人时随机操作，轮到挑战者时按已完成的轮次结束本局
+fn time_out(server: &Server, ra: &mut RoomAgent) {
+    tracing::info!(room = %ra.room.id(), state = ?ra.room.state(), "Turn timed out.");
+    if let Some(correspondence) = &mut ra.correspondence {
+        correspondence.deadline = None;
+    }
+    match ra.room.state() {
+        RoomState::Joined { ready: true, .. }
+        | RoomState::Started {
+            stage: Stage::Aborted,
+            ..
+        } => next_round(server, ra),
+        RoomState::Started {
+            stage: Stage::End, ..
+        } if ra.room.has_next_round() => next_round(server, ra),
+        RoomState::Started {
+            stage: Stage::End, ..
+        } => {
+            complete_game(server, ra, false);
+        }
+        RoomState::Started {
+            stage: Stage::Reveal { .. },
+            ..
+        } => reveal_door(ra, Index::Random, vec![]),
+        RoomState::Started { .. } => {
+            if let Err(cause) = force_complete_game(server, ra, EarlyEnd::TimedOut) {
+                tracing::error!(room = %ra.room.id(), %cause, "Failed to end the timed out game.");
+            }
+        }
+        _ => {}
+    }
+    run_automation(server, ra);
+    ra.persist();
+}
+
+/// 主持人超时，随机放置奖品开始下一轮
+fn next_round(server: &Server, ra: &mut RoomAgent) {
+    if let Some(response) = start_round(server, ra, Index::Random, vec![], None) {
+        tracing::warn!(room = %ra.room.id(), ?response, "Failed to start a timed out round.");
+    }
+}
@@ -0,0 +1,95 @@
+use ndoors::{Decision, RoundResult, Uuid};
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// CSV 文件的第一行
+const HEADER: &str = "game,round,doors,prizes,closed,chosen,prize,left,decision,win,sudden_death,decision_ms,round_ms,estimate,choose_ms,reveal_ms,decide_ms";
+
+/// 公开数据集，配置了文件时把真人完成的每局游戏的轮次追加到 CSV 文件。
+/// 每一行只有轮次本身的结果和各阶段的用时，`game` 是每局随机生成的 ID，不包含用户、房间、租户和时间
+#[derive(Debug)]
+pub struct Dataset {
+    path: Option<PathBuf>,
+    file: Option<Mutex<File>>,
+}
+
+impl Dataset {
+    pub fn open(path: Option<&Path>) -> anyhow::Result<Self> {
+        let file = match path {
+            Some(path) => {
+                let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                if file.metadata()?.len() == 0 {
+                    writeln!(file, "{}", HEADER)?;
+                }
+                Some(Mutex::new(file))
+            }
+            None => None,
+        };
+        Ok(Self {
+            path: path.map(Path::to_path_buf),
+            file,
+        })
+    }
+
+    /// 数据集文件，未配置时为空
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// 追加一局游戏的所有轮次，一局的轮次一次写入，下载时不会看到半局。作废的轮次没有抉择，不收集
+    pub fn append(&self, results: &[RoundResult]) {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return,
+        };
+        let game = Uuid::new_v4();
+        let mut rows = String::new();
+        for (round, result) in results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| !result.void())
+        {
+            let _ = writeln!(
+                rows,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                game,
+                round,
+                result.doors(),
+                result.prizes(),
+                result.closed(),
+                result.chosen(),
+                result.prize(),
+                result.left(),
+                match result.decision() {
+                    _ if !result.offered() => "declined",
+                    Decision::Stick => "stick",
+                    Decision::Switch | Decision::SwitchTo(_) => "switch",
+                    Decision::TakeDeal(_) => "deal",
+                },
+                result.win(),
+                result.sudden_death(),
+                optional(result.decision_ms()),
+                optional(result.round_ms()),
+                optional(result.estimate()),
+                optional(result.choose_ms()),
+                optional(result.reveal_ms()),
+                optional(result.decide_ms()),
+            );
+        }
+        let result = match file.lock() {
+            Ok(mut file) => file.write_all(rows.as_bytes()).map_err(anyhow::Error::from),
+            Err(_) => Err(anyhow::anyhow!("Dataset lock poisoned.")),
+        };
+        if let Err(cause) = result {
+            tracing::error!(%cause, "Failed to append to dataset.");
+        }
+    }
+}
+
+/// 没有数据时为空字段
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
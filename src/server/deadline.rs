@@ -0,0 +1,58 @@
+use ndoors::{RoomState, Stage, Uuid};
+use tokio::time::Duration;
+
+use crate::protocol::Index;
+use crate::room::RoomAgent;
+use crate::{choose_door, decide, reveal_door, run_automation, timestamp, Server};
+
+/// 检查阶段时限的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 在后台检查房间设置的阶段时限，超时后替超时的一方随机操作并广播
+pub fn watch(server: Server) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let now = timestamp();
+            // 不能在遍历的同时 get_mut，会导致死锁
+            let expired: Vec<Uuid> = server
+                .rooms
+                .iter()
+                .filter(|ra| !ra.quarantined && is_expired(ra, now))
+                .map(|ra| *ra.room.id())
+                .collect();
+            for room_id in expired {
+                if let Some(mut ra) = server.rooms.get_mut(&room_id) {
+                    time_out(&server, &mut ra, now);
+                }
+            }
+        }
+    });
+}
+
+fn is_expired(ra: &RoomAgent, now: u64) -> bool {
+    ra.room.deadline().is_some_and(|deadline| deadline <= now)
+}
+
+/// 阶段超时：挑战者随机选择或者抉择，主持人随机揭示。测验模式下超时的抉择不需要估计
+fn time_out(server: &Server, ra: &mut RoomAgent, now: u64) {
+    // 等待锁的时候房间可能已经进入下一阶段
+    if !is_expired(ra, now) {
+        return;
+    }
+    let stage = match ra.room.state() {
+        RoomState::Started { stage, .. } => stage.clone(),
+        _ => return,
+    };
+    tracing::info!(room = %ra.room.id(), ?stage, "Stage timed out.");
+    match stage {
+        Stage::Choose => choose_door(ra, Index::Random),
+        Stage::Reveal { .. } => reveal_door(ra, Index::Random, vec![]),
+        Stage::Decide { .. } | Stage::Deal { .. } => {
+            decide(server, ra, rand::random());
+        }
+        Stage::End | Stage::Aborted => {}
+    }
+    run_automation(server, ra);
+}
@@ -0,0 +1,169 @@
+use ndoors::{HostStrategy, Room, RoomState, Settings, Stage, Uuid};
+use serde::Deserialize;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::time::Duration;
+use utoipa::ToSchema;
+
+use crate::pacing::Pacing;
+use crate::protocol::{Index, ServerMessage};
+use crate::room::RoomAgent;
+use crate::script::HostScript;
+use crate::{complete_game, reveal_door, run_bot, run_host_script, start_round, Server};
+
+/// 机器人对战房间，主持人和挑战者都由策略操作，观众可以旁观
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct Exhibition {
+    /// 房间所属租户，默认是默认租户
+    #[serde(default)]
+    pub tenant: String,
+    /// 主持人策略：内置的 `random`（默认）或者 `lazy`
+    #[serde(default = "default_host")]
+    pub host: String,
+    /// 主持人脚本，需要 `scripting` feature，脚本没有操作时由 `host` 策略操作
+    #[serde(default)]
+    pub script: Option<String>,
+    /// 挑战者：内置的 `switch`、`stick`、`random` 或者上传的机器人 ID
+    pub contestant: String,
+    /// 不指定时使用默认设置
+    #[serde(default)]
+    pub settings: Option<Settings>,
+    /// 默认 `Normal`
+    #[serde(default = "default_pacing")]
+    pub pacing: Pacing,
+    /// 两次操作之间的间隔（毫秒），不会小于节奏的间隔，默认 1500
+    #[serde(default = "default_step_ms")]
+    pub step_ms: u64,
+}
+
+fn default_host() -> String {
+    "random".to_string()
+}
+
+fn default_pacing() -> Pacing {
+    Pacing::Normal
+}
+
+fn default_step_ms() -> u64 {
+    1500
+}
+
+/// 创建机器人对战房间并在后台按节奏推进，房间被删除时停止，返回房间 ID
+pub fn start(server: &Server, exhibition: Exhibition) -> anyhow::Result<Uuid> {
+    let host = server
+        .bots
+        .host(&exhibition.host)
+        .ok_or_else(|| anyhow::anyhow!("Unknown host strategy `{}`.", exhibition.host))?;
+    let bot = server
+        .bots
+        .get(&exhibition.contestant)
+        .ok_or_else(|| anyhow::anyhow!("Unknown bot `{}`.", exhibition.contestant))?;
+    let script = exhibition
+        .script
+        .as_deref()
+        .map(HostScript::compile)
+        .transpose()?;
+    let settings = exhibition
+        .settings
+        .clone()
+        .unwrap_or_else(|| server.tunables().default_settings.clone());
+    let settings = server.rules.configure(settings)?;
+
+    let mut room = Room::create(Uuid::new_v4(), settings)?;
+    server.rules.attach(&mut room);
+    room.accept_contestant(bot.id)?;
+    room.contestant_ready(true)?;
+
+    let mut ra = RoomAgent::new(
+        room,
+        exhibition.tenant.clone(),
+        unattended(),
+        exhibition.pacing,
+        server.bridge.clone(),
+        server.push.clone(),
+        server.lobby.clone(),
+    );
+    ra.bot = Some(bot);
+    ra.script = script;
+    ra.exhibition = true;
+    server.random.create_room(&mut ra, &server.audit);
+    let room_id = *ra.room.id();
+    server.rooms.insert(room_id, ra);
+
+    let step = Duration::from_millis(exhibition.step_ms).max(exhibition.pacing.delay());
+    tokio::spawn(drive(server.clone(), room_id, host, step));
+    tracing::info!(room = %room_id, ?exhibition, "Exhibition started.");
+    Ok(room_id)
+}
+
+/// 没有真正的主持人连接时使用，发给主持人的消息直接丢弃
+pub fn unattended() -> Sender<ServerMessage> {
+    let (sender, mut receiver) = channel(16);
+    tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+    sender
+}
+
+/// 每隔 `step` 推进一步，直到房间被删除
+pub async fn drive(
+    server: Server,
+    room_id: Uuid,
+    mut host: Box<dyn HostStrategy + Send + Sync>,
+    step: Duration,
+) {
+    let mut interval = tokio::time::interval(step);
+    loop {
+        interval.tick().await;
+        match server.rooms.get_mut(&room_id) {
+            Some(mut ra) if !ra.quarantined => advance(&server, &mut ra, &mut *host),
+            Some(_) => {}
+            None => break,
+        }
+    }
+    tracing::info!(room = %room_id, "Exhibition stopped.");
+}
+
+/// 推进一步：主持人脚本优先，然后是机器人挑战者，最后是主持人策略，局与局之间不停顿
+fn advance(server: &Server, ra: &mut RoomAgent, host: &mut dyn HostStrategy) {
+    let seq = ra.seq;
+    run_host_script(server, ra);
+    if ra.seq != seq {
+        return;
+    }
+    run_bot(server, ra);
+    if ra.seq != seq {
+        return;
+    }
+
+    let doors = ra.room.round_settings().doors;
+    match ra.room.state() {
+        RoomState::Joined { ready: true, .. }
+        | RoomState::Started {
+            stage: Stage::Aborted,
+            ..
+        } => next_round(server, ra, host),
+        RoomState::Started {
+            stage: Stage::End, ..
+        } if ra.room.has_next_round() => next_round(server, ra, host),
+        RoomState::Started {
+            stage: Stage::End, ..
+        } => {
+            complete_game(server, ra, false);
+        }
+        RoomState::Started {
+            stage: Stage::Reveal { chosen },
+            prize,
+            ..
+        } => {
+            let left = host.leave(doors, *prize, *chosen);
+            reveal_door(ra, Index::Specified(left), vec![]);
+        }
+        _ => {}
+    }
+}
+
+/// 主持人放置奖品并开始下一轮
+fn next_round(server: &Server, ra: &mut RoomAgent, host: &mut dyn HostStrategy) {
+    let prize = host.hide(ra.room.next_round_settings().doors);
+    if let Some(response) = start_round(server, ra, Index::Specified(prize), vec![], None) {
+        tracing::warn!(room = %ra.room.id(), ?response, "Exhibition failed to start a round.");
+    }
+}
@@ -0,0 +1,126 @@
+use ndoors::fairness::RoundProof;
+use ndoors::{Error, Uuid};
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{GameResponse, Index, ServerError};
+use crate::room::RoomAgent;
+
+/// 挑战者随机串的最大长度
+pub const MAX_NONCE_LEN: usize = 64;
+
+/// 公平模式房间的状态，随通信对局快照保存
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FairRoom {
+    /// 挑战者本局提供的随机串，完成游戏或者新的挑战者进入后清空
+    pub nonce: Option<String>,
+    /// 本局已经开始的轮次的证明，完成时随 `Completed` 公开
+    pub proofs: Vec<RoundProof>,
+}
+
+/// 服务器的 VRF 私钥，未配置时不能创建公平模式房间
+#[derive(Debug, Default)]
+pub struct Fairness {
+    #[cfg(feature = "fairness")]
+    key: Option<ndoors::fairness::FairnessKey>,
+}
+
+#[cfg(feature = "fairness")]
+impl Fairness {
+    /// 从配置的私钥（32 个字节，十六进制）加载
+    pub fn load(secret: Option<&str>) -> anyhow::Result<Self> {
+        let key = secret
+            .map(ndoors::fairness::FairnessKey::from_hex)
+            .transpose()
+            .map_err(|_| anyhow::anyhow!("`fairness_key` must be 32 bytes in hex."))?;
+        Ok(Self { key })
+    }
+
+    /// 公钥（十六进制）
+    pub fn public_key(&self) -> Option<String> {
+        self.key.as_ref().map(|key| key.public_key())
+    }
+
+    fn prove(
+        &self,
+        room_id: &Uuid,
+        round: u32,
+        doors: u32,
+        count: u32,
+        nonce: &str,
+    ) -> Option<RoundProof> {
+        let key = self.key.as_ref()?;
+        Some(key.prove(room_id, round, doors, count, nonce))
+    }
+}
+
+#[cfg(not(feature = "fairness"))]
+impl Fairness {
+    pub fn load(secret: Option<&str>) -> anyhow::Result<Self> {
+        if secret.is_some() {
+            anyhow::bail!("`fairness_key` requires the `fairness` feature.")
+        }
+        Ok(Self {})
+    }
+
+    pub fn public_key(&self) -> Option<String> {
+        None
+    }
+
+    fn prove(&self, _: &Uuid, _: u32, _: u32, _: u32, _: &str) -> Option<RoundProof> {
+        None
+    }
+}
+
+impl Fairness {
+    pub fn enabled(&self) -> bool {
+        self.public_key().is_some()
+    }
+
+    /// 公平模式房间开始新的一轮前由 VRF 决定奖品位置，返回本轮的证明。主持人不能指定奖品位置，
+    /// 挑战者必须先提供随机串；不是公平模式的房间返回 `None`
+    pub fn place(
+        &self,
+        ra: &RoomAgent,
+        prize: &Index,
+        others: &[u32],
+    ) -> Result<Option<RoundProof>, Box<GameResponse>> {
+        let fair = match &ra.fairness {
+            Some(fair) => fair,
+            None => return Ok(None),
+        };
+        if *prize != Index::Random || !others.is_empty() {
+            return Err(Box::new(GameResponse::GameError {
+                cause: Error::InvalidOperation,
+            }));
+        }
+        let nonce = fair.nonce.as_deref().ok_or_else(|| {
+            Box::new(GameResponse::GameError {
+                cause: Error::NonceRequired,
+            })
+        })?;
+        let round = ra.room.next_round();
+        let settings = ra.room.next_round_settings();
+        let count = ra.room.settings().prizes(settings);
+        self.prove(ra.room.id(), round, settings.doors, count, nonce)
+            .map(Some)
+            .ok_or_else(|| {
+                Box::new(GameResponse::ServerError {
+                    cause: ServerError::FairnessDisabled,
+                })
+            })
+    }
+}
+
+impl FairRoom {
+    /// 记录本轮的证明，重新开始的轮次替换之前的证明
+    pub fn record(&mut self, proof: RoundProof) {
+        self.proofs.retain(|previous| previous.round != proof.round);
+        self.proofs.push(proof);
+    }
+
+    /// 完成游戏时取出所有证明，下一局需要新的随机串
+    pub fn finish(&mut self) -> Vec<RoundProof> {
+        self.nonce = None;
+        std::mem::take(&mut self.proofs)
+    }
+}
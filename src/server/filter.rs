@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+
+/// 文本过滤配置
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FilterConfig {
+    /// 屏蔽词，不区分大小写，命中的部分替换为 `*`
+    pub deny_list: Vec<String>,
+    /// 聊天消息的最大字符数
+    pub max_chat_len: usize,
+    /// 显示名称的最大字符数
+    pub max_name_len: usize,
+    /// 隐藏邮箱和电话号码
+    pub mask_pii: bool,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            deny_list: vec![],
+            max_chat_len: 200,
+            max_name_len: 32,
+            mask_pii: true,
+        }
+    }
+}
+
+/// 被过滤的文本类型
+#[derive(Debug, Serialize, Copy, Clone)]
+pub enum TextKind {
+    Chat,
+    Name,
+}
+
+/// 过滤时发现的问题
+#[derive(Debug, Serialize, Copy, Clone, Eq, PartialEq)]
+pub enum Violation {
+    /// 包含控制字符或不可见的格式字符
+    Unsanitized,
+    /// 超出长度限制
+    TooLong,
+    /// 包含屏蔽词
+    Denied,
+    /// 包含个人信息
+    Pii,
+}
+
+#[derive(Debug)]
+pub struct Filtered {
+    pub text: String,
+    pub violations: Vec<Violation>,
+}
+
+/// 在广播和保存之前依次清理、截断、屏蔽用户输入的文本
+#[derive(Debug)]
+pub struct Filter {
+    deny_list: Vec<Vec<char>>,
+    max_chat_len: usize,
+    max_name_len: usize,
+    mask_pii: bool,
+}
+
+impl Filter {
+    pub fn new(config: FilterConfig) -> Self {
+        Self {
+            deny_list: config
+                .deny_list
+                .iter()
+                .map(|word| word.chars().map(lowercase).collect())
+                .filter(|word: &Vec<char>| !word.is_empty())
+                .collect(),
+            max_chat_len: config.max_chat_len,
+            max_name_len: config.max_name_len,
+            mask_pii: config.mask_pii,
+        }
+    }
+
+    pub fn apply(&self, kind: TextKind, text: &str) -> Filtered {
+        let mut violations = vec![];
+
+        let sanitized: String = text.chars().filter(|c| !is_hidden(*c)).collect();
+        if sanitized.len() != text.len() {
+            violations.push(Violation::Unsanitized);
+        }
+        let mut text = sanitized.trim().to_string();
+
+        let max_len = match kind {
+            TextKind::Chat => self.max_chat_len,
+            TextKind::Name => self.max_name_len,
+        };
+        if let Some((index, _)) = text.char_indices().nth(max_len) {
+            text.truncate(index);
+            violations.push(Violation::TooLong);
+        }
+
+        if let Some(masked) = self.mask_denied(&text) {
+            text = masked;
+            violations.push(Violation::Denied);
+        }
+
+        if self.mask_pii {
+            if let Some(masked) = mask_pii(&text) {
+                text = masked;
+                violations.push(Violation::Pii);
+            }
+        }
+
+        Filtered { text, violations }
+    }
+
+    fn mask_denied(&self, text: &str) -> Option<String> {
+        let mut chars: Vec<char> = text.chars().collect();
+        let lower: Vec<char> = chars.iter().copied().map(lowercase).collect();
+        let mut found = false;
+        for word in &self.deny_list {
+            let mut i = 0;
+            while i + word.len() <= lower.len() {
+                if lower[i..i + word.len()] == word[..] {
+                    chars[i..i + word.len()].fill('*');
+                    found = true;
+                    i += word.len();
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        found.then(|| chars.into_iter().collect())
+    }
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// 控制字符、零宽字符以及双向文本控制字符
+fn is_hidden(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2069}' | '\u{FEFF}'
+        )
+}
+
+/// 将看起来像邮箱或电话号码的词替换为 `***`
+fn mask_pii(text: &str) -> Option<String> {
+    let mut found = false;
+    let masked = text
+        .split_inclusive(char::is_whitespace)
+        .map(|piece| {
+            let word = piece.trim_end();
+            if is_email(word) || is_phone(word) {
+                found = true;
+                format!("***{}", &piece[word.len()..])
+            } else {
+                piece.to_string()
+            }
+        })
+        .collect();
+    found.then_some(masked)
+}
+
+fn is_email(word: &str) -> bool {
+    match word.split_once('@') {
+        Some((name, domain)) => !name.is_empty() && domain.contains('.'),
+        None => false,
+    }
+}
+
+fn is_phone(word: &str) -> bool {
+    word.chars().filter(char::is_ascii_digit).count() >= 7
+        && word
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '(' | ')' | '.'))
+}
@@ -0,0 +1,121 @@
+use ndoors::{RoomState, RoundResult, Uuid};
+use serde::{Deserialize, Serialize};
+use std::mem::size_of;
+use tokio::sync::mpsc::Sender;
+use utoipa::ToSchema;
+
+use crate::journal::{Entry, Traffic};
+use crate::protocol::{GameResponse, ServerMessage};
+use crate::room::RoomAgent;
+
+/// 房间占用内存的告警阈值，超过时在管理接口中给出警告，未设置的项使用默认值
+#[derive(Debug, Serialize, Deserialize, Copy, Clone)]
+#[serde(default)]
+pub struct SizeLimits {
+    /// 设置的轮数，默认 1000
+    pub rounds: u32,
+    /// 本局已经保存的结果数，默认 1000
+    pub results: usize,
+    /// 观众人数，默认 500
+    pub spectators: usize,
+    /// 估算的字节数，默认 8 MiB
+    pub bytes: u64,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            rounds: 1000,
+            results: 1000,
+            spectators: 500,
+            bytes: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// 房间占用的内存，`bytes` 只按各个集合的元素大小估算，不包括元素内部的堆内存
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RoomFootprint {
+    /// 房间 ID
+    pub id: Uuid,
+    pub tenant: String,
+    /// 设置的轮数
+    pub rounds: u32,
+    /// 本局已经保存的结果数
+    pub results: usize,
+    /// 最近完成的游戏数
+    pub history_games: usize,
+    /// 最近完成的游戏中保存的结果数
+    pub history_rounds: usize,
+    /// 调试记录中的事件数
+    pub events: usize,
+    /// 调试记录中的请求数
+    pub traffic: usize,
+    /// 观众人数
+    pub spectators: usize,
+    /// 估算的字节数
+    pub bytes: u64,
+    /// 超过的阈值
+    pub warnings: Vec<String>,
+}
+
+impl RoomFootprint {
+    pub fn measure(ra: &RoomAgent, limits: &SizeLimits) -> Self {
+        let results = match ra.room.state() {
+            RoomState::Started { results, .. } => results.len(),
+            _ => 0,
+        };
+        let history_rounds = ra.history.iter().map(|game| game.rounds.len()).sum();
+        let events = ra.journal.events().len();
+        let traffic = ra.journal.traffic().len();
+        let spectators = ra.spectators.len() + ra.moderators.len();
+        let bytes = (results + history_rounds) * size_of::<RoundResult>()
+            + events * size_of::<Entry<GameResponse>>()
+            + traffic * size_of::<Entry<Traffic>>()
+            + spectators * size_of::<(Uuid, Sender<ServerMessage>)>();
+        let mut footprint = Self {
+            id: *ra.room.id(),
+            tenant: ra.tenant.clone(),
+            rounds: ra.room.settings().rounds,
+            results,
+            history_games: ra.history.len(),
+            history_rounds,
+            events,
+            traffic,
+            spectators,
+            bytes: bytes as u64,
+            warnings: Vec::new(),
+        };
+        footprint.warnings = limits.check(&footprint);
+        footprint
+    }
+}
+
+impl SizeLimits {
+    /// 超过阈值的项
+    pub fn check(&self, footprint: &RoomFootprint) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if footprint.rounds > self.rounds {
+            warnings.push(format!(
+                "rounds {} exceeds {}",
+                footprint.rounds, self.rounds
+            ));
+        }
+        if footprint.results > self.results {
+            warnings.push(format!(
+                "results {} exceeds {}",
+                footprint.results, self.results
+            ));
+        }
+        if footprint.spectators > self.spectators {
+            warnings.push(format!(
+                "spectators {} exceeds {}",
+                footprint.spectators, self.spectators
+            ));
+        }
+        if footprint.bytes > self.bytes {
+            warnings.push(format!("bytes {} exceeds {}", footprint.bytes, self.bytes));
+        }
+        warnings
+    }
+}
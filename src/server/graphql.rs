@@ -0,0 +1,435 @@
+use async_graphql::http::ALL_WEBSOCKET_PROTOCOLS;
+use async_graphql::{
+    ComplexObject, Context, Data, EmptyMutation, Enum, Json, Object, Schema, SimpleObject,
+    Subscription,
+};
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
+use axum::extract::ws::WebSocketUpgrade;
+use axum::extract::Extension;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::Router;
+use futures::Stream;
+use ndoors::{GameResult, RoomState, RoundResult, Settings, Stats, Uuid};
+use tokio::sync::broadcast::error::RecvError;
+use tower_http::cors::CorsLayer;
+
+use crate::auth::bearer_token;
+use crate::class::ClassReport;
+use crate::pacing::Pacing;
+use crate::protocol::GameResponse;
+use crate::room::{CompletedGame, RoomAgent, RoomLifecycle, RoomStatus};
+use crate::Server;
+
+/// 一次查询最多返回的房间数或游戏数
+const MAX_LIMIT: usize = 1000;
+
+/// 查询的最大嵌套深度
+const MAX_DEPTH: usize = 8;
+
+pub type GraphQLSchema = Schema<Query, EmptyMutation, RoomEvents>;
+
+/// GraphQL 接口，供看板之类的页面按需查询房间、成员、历史和统计，并订阅房间事件。
+/// 查询使用 `POST /api/graphql`，订阅使用 `/api/graphql/ws` 上的 WebSocket。
+/// 查询和订阅都只能看到认证得到的租户中的房间
+pub fn router(server: Server) -> Router {
+    let schema = Schema::build(Query, EmptyMutation, RoomEvents)
+        .data(server)
+        .limit_depth(MAX_DEPTH)
+        .finish();
+    Router::new()
+        .route("/api/graphql", post(graphql))
+        .route("/api/graphql/ws", get(graphql_ws))
+        .layer(Extension(schema))
+        .layer(CorsLayer::permissive())
+}
+
+/// 认证得到的租户，查询和订阅都按它筛选房间
+struct Tenant(String);
+
+fn tenant<'a>(ctx: &'a Context<'_>) -> &'a str {
+    &ctx.data_unchecked::<Tenant>().0
+}
+
+async fn graphql(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+    Extension(schema): Extension<GraphQLSchema>,
+    request: GraphQLRequest,
+) -> Result<GraphQLResponse, StatusCode> {
+    let identity = server
+        .auth
+        .authenticate(bearer_token(&headers))
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let request = request.into_inner().data(Tenant(identity.tenant));
+    Ok(schema.execute(request).await.into())
+}
+
+/// 订阅的令牌来自升级请求的 `Authorization` 头，或者 `connection_init` 消息中的 `token`
+/// （浏览器不能为 WebSocket 设置请求头），认证失败时关闭连接
+async fn graphql_ws(
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+    Extension(schema): Extension<GraphQLSchema>,
+    protocol: GraphQLProtocol,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let header = bearer_token(&headers).map(str::to_string);
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| {
+            GraphQLWebSocket::new(stream, schema, protocol)
+                .on_connection_init(move |payload| async move {
+                    let token = payload
+                        .get("token")
+                        .and_then(|token| token.as_str())
+                        .map(str::to_string)
+                        .or(header);
+                    let identity = server
+                        .auth
+                        .authenticate(token.as_deref())
+                        .map_err(|cause| async_graphql::Error::new(cause.to_string()))?;
+                    let mut data = Data::default();
+                    data.insert(Tenant(identity.tenant));
+                    Ok(data)
+                })
+                .serve()
+        })
+}
+
+/// 和大厅一样，只能看到同一租户中没有被隔离的房间
+fn listed(ra: &RoomAgent, tenant: &str) -> bool {
+    ra.tenant == tenant && !ra.quarantined
+}
+
+/// 房间中所有轮次，包括进行中的一局
+fn all_rounds(ra: &RoomAgent) -> impl Iterator<Item = &RoundResult> + Clone {
+    let current = match ra.room.state() {
+        RoomState::Started { results, .. } => results.as_slice(),
+        _ => &[],
+    };
+    ra.history
+        .iter()
+        .flat_map(|game| &game.rounds)
+        .chain(current)
+}
+
+/// 房间所处的阶段，用来筛选房间
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum Phase {
+    Waiting,
+    Joined,
+    Playing,
+}
+
+impl From<&RoomStatus> for Phase {
+    fn from(status: &RoomStatus) -> Self {
+        match status {
+            RoomStatus::Waiting => Phase::Waiting,
+            RoomStatus::Joined { .. } => Phase::Joined,
+            RoomStatus::Playing { .. } => Phase::Playing,
+        }
+    }
+}
+
+/// 一个房间
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct RoomNode {
+    id: Uuid,
+    class: Option<String>,
+    phase: Phase,
+    status: Json<RoomStatus>,
+    lifecycle: Json<RoomLifecycle>,
+    settings: Json<Settings>,
+    pacing: Json<Pacing>,
+    spectators: u32,
+    /// 房间已经广播的事件数
+    seq: u64,
+    /// 机器人对战房间
+    exhibition: bool,
+    #[graphql(skip)]
+    tenant: String,
+}
+
+impl From<&RoomAgent> for RoomNode {
+    fn from(ra: &RoomAgent) -> Self {
+        let status = RoomStatus::from(ra.room.state());
+        Self {
+            id: *ra.room.id(),
+            class: ra.class.clone(),
+            phase: Phase::from(&status),
+            status: Json(status),
+            lifecycle: Json(ra.lifecycle()),
+            settings: Json(ra.room.settings().clone()),
+            pacing: Json(ra.outbox.pacing()),
+            spectators: ra.room.spectators().len() as u32,
+            seq: ra.seq,
+            exhibition: ra.exhibition,
+            tenant: ra.tenant.clone(),
+        }
+    }
+}
+
+#[ComplexObject]
+impl RoomNode {
+    /// 最近完成的游戏，按完成顺序排列，从编号 `since` 开始
+    async fn games(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] since: u64,
+        #[graphql(default = 20)] limit: usize,
+    ) -> Vec<GameNode> {
+        let server = ctx.data_unchecked::<Server>();
+        server
+            .rooms
+            .get(&self.id)
+            .filter(|ra| listed(ra, &self.tenant))
+            .map(|ra| {
+                ra.history
+                    .iter()
+                    .filter(|game| game.id >= since)
+                    .take(limit.min(MAX_LIMIT))
+                    .map(GameNode::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 房间中的成员，不包括版主
+    async fn players(&self, ctx: &Context<'_>) -> Vec<Player> {
+        let server = ctx.data_unchecked::<Server>();
+        server
+            .rooms
+            .get(&self.id)
+            .filter(|ra| listed(ra, &self.tenant))
+            .map(|ra| Player::collect(&ra))
+            .unwrap_or_default()
+    }
+
+    /// 房间中所有轮次的统计，包括进行中的一局
+    async fn stats(&self, ctx: &Context<'_>) -> Option<Json<Stats>> {
+        let server = ctx.data_unchecked::<Server>();
+        let ra = server
+            .rooms
+            .get(&self.id)
+            .filter(|ra| listed(ra, &self.tenant))?;
+        Some(Json(Stats::calculate(all_rounds(&ra))))
+    }
+}
+
+/// 一局已经完成的游戏
+#[derive(SimpleObject)]
+pub struct GameNode {
+    /// 房间内从 0 开始递增的编号
+    id: u64,
+    /// 完成时的 Unix 时间戳（毫秒）
+    completed_at: u64,
+    /// 从第一轮开始到完成的毫秒数
+    duration_ms: Option<u64>,
+    result: Json<GameResult>,
+    rounds: Vec<Json<RoundResult>>,
+}
+
+impl From<&CompletedGame> for GameNode {
+    fn from(game: &CompletedGame) -> Self {
+        Self {
+            id: game.id,
+            completed_at: game.completed_at,
+            duration_ms: game.duration_ms,
+            result: Json(game.result.clone()),
+            rounds: game.rounds.iter().copied().map(Json).collect(),
+        }
+    }
+}
+
+/// 成员在房间中的角色
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum PlayerRole {
+    Host,
+    CoHost,
+    Contestant,
+    Spectator,
+}
+
+/// 房间中的一个成员
+#[derive(SimpleObject)]
+pub struct Player {
+    id: Uuid,
+    room: Uuid,
+    role: PlayerRole,
+}
+
+impl Player {
+    /// 房间中的成员，不包括悄悄旁观的版主
+    fn collect(ra: &RoomAgent) -> Vec<Self> {
+        let room = *ra.room.id();
+        let player = |id: &Uuid, role| Player {
+            id: *id,
+            room,
+            role,
+        };
+        let mut players = vec![player(ra.room.host(), PlayerRole::Host)];
+        players.extend(
+            ra.room
+                .co_hosts()
+                .iter()
+                .map(|id| player(id, PlayerRole::CoHost)),
+        );
+        if let RoomState::Joined { contestant, .. } | RoomState::Started { contestant, .. } =
+            ra.room.state()
+        {
+            players.push(player(contestant, PlayerRole::Contestant));
+        }
+        players.extend(
+            ra.room
+                .spectators()
+                .iter()
+                .map(|id| player(id, PlayerRole::Spectator)),
+        );
+        players
+    }
+}
+
+/// 一组房间的汇总
+#[derive(SimpleObject)]
+pub struct Statistics {
+    rooms: u32,
+    /// 游戏进行中的房间数
+    playing: u32,
+    spectators: u32,
+    /// 已经完成的局数（每个房间最多保留最近 20 局）
+    games: u32,
+    /// 所有房间所有轮次的统计，包括进行中的游戏
+    stats: Json<Stats>,
+    /// 改变选择的比例
+    switch_rate: f64,
+    /// 中奖率
+    win_rate: f64,
+    /// 每轮从开始到抉择的平均毫秒数
+    mean_round_ms: Option<u64>,
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// 租户中的房间，按 ID 排序，可以按班级和阶段筛选
+    async fn rooms(
+        &self,
+        ctx: &Context<'_>,
+        class: Option<String>,
+        phase: Option<Phase>,
+        #[graphql(default)] offset: usize,
+        #[graphql(default = 20)] limit: usize,
+    ) -> Vec<RoomNode> {
+        let server = ctx.data_unchecked::<Server>();
+        let mut rooms: Vec<RoomNode> = server
+            .rooms
+            .iter()
+            .filter(|ra| listed(ra, tenant(ctx)))
+            .filter(|ra| class.is_none() || ra.class == class)
+            .map(|ra| RoomNode::from(&*ra))
+            .filter(|room| phase.is_none() || phase == Some(room.phase))
+            .collect();
+        rooms.sort_by_key(|room| room.id);
+        rooms
+            .into_iter()
+            .skip(offset)
+            .take(limit.min(MAX_LIMIT))
+            .collect()
+    }
+
+    /// 一个房间，不存在或者不属于租户时为空
+    async fn room(&self, ctx: &Context<'_>, id: Uuid) -> Option<RoomNode> {
+        let server = ctx.data_unchecked::<Server>();
+        let ra = server.rooms.get(&id).filter(|ra| listed(ra, tenant(ctx)))?;
+        Some(RoomNode::from(&*ra))
+    }
+
+    /// 租户所有房间中的成员，可以按角色筛选
+    async fn players(&self, ctx: &Context<'_>, role: Option<PlayerRole>) -> Vec<Player> {
+        let server = ctx.data_unchecked::<Server>();
+        server
+            .rooms
+            .iter()
+            .filter(|ra| listed(ra, tenant(ctx)))
+            .flat_map(|ra| Player::collect(&ra))
+            .filter(|player| role.is_none() || role == Some(player.role))
+            .collect()
+    }
+
+    /// 租户（或者其中一个班级）所有房间的汇总
+    async fn statistics(&self, ctx: &Context<'_>, class: Option<String>) -> Statistics {
+        let server = ctx.data_unchecked::<Server>();
+        let mut rooms = 0;
+        let mut playing = 0;
+        let mut spectators = 0;
+        let mut games = 0;
+        let mut results = Vec::new();
+        for ra in server.rooms.iter() {
+            if !listed(&ra, tenant(ctx)) || (class.is_some() && ra.class != class) {
+                continue;
+            }
+            rooms += 1;
+            if let RoomState::Started { .. } = ra.room.state() {
+                playing += 1;
+            }
+            spectators += ra.room.spectators().len() as u32;
+            games += ra.history.len() as u32;
+            results.extend(all_rounds(&ra).copied());
+        }
+        let stats = Stats::calculate(&results);
+        Statistics {
+            rooms,
+            playing,
+            spectators,
+            games,
+            switch_rate: stats.switch_rate(),
+            win_rate: stats.win_rate(),
+            mean_round_ms: stats.mean_round_ms(),
+            stats: Json(stats),
+        }
+    }
+
+    /// 班级看板，和 `/api/classes/{class}` 相同
+    async fn class(&self, ctx: &Context<'_>, name: String) -> Json<ClassReport> {
+        let server = ctx.data_unchecked::<Server>();
+        Json(ClassReport::collect(&server.rooms, tenant(ctx), &name))
+    }
+}
+
+pub struct RoomEvents;
+
+#[Subscription]
+impl RoomEvents {
+    /// 房间广播的事件，和观众看到的相同，房间关闭后结束。跟不上时跳过积压的事件
+    async fn room_events(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+    ) -> async_graphql::Result<impl Stream<Item = Json<GameResponse>>> {
+        let server = ctx.data_unchecked::<Server>();
+        let receiver = server
+            .rooms
+            .get(&id)
+            .filter(|ra| listed(ra, tenant(ctx)))
+            .map(|ra| ra.watchers.subscribe())
+            .ok_or_else(|| async_graphql::Error::new("Room not found."))?;
+        Ok(futures::stream::unfold(
+            receiver,
+            |mut receiver| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(response) => return Some((Json(response), receiver)),
+                        Err(RecvError::Lagged(skipped)) => {
+                            tracing::debug!(skipped, "GraphQL subscriber lagged.");
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        ))
+    }
+}
@@ -0,0 +1,14 @@
+use hyper::client::HttpConnector;
+use hyper_rustls::HttpsConnector;
+
+/// 向外发送请求的 HTTP 客户端，支持 `http://` 和 `https://`，按系统的根证书验证服务器证书
+pub type HttpClient = hyper::Client<HttpsConnector<HttpConnector>>;
+
+pub fn client() -> HttpClient {
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .https_or_http()
+        .enable_http1()
+        .build();
+    hyper::Client::builder().build(connector)
+}
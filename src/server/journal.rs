@@ -0,0 +1,65 @@
+use ndoors::Uuid;
+use serde::Serialize;
+use std::collections::VecDeque;
+
+use crate::protocol::{GameRequest, GameResponse};
+use crate::timestamp;
+
+/// 保留的房间事件数量
+const MAX_EVENTS: usize = 1000;
+/// 保留的请求数量
+const MAX_TRAFFIC: usize = 100;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Entry<T> {
+    /// Unix 时间戳（毫秒）
+    timestamp: u64,
+    #[serde(flatten)]
+    item: T,
+}
+
+/// 房间成员发来的请求
+#[derive(Debug, Serialize, Clone)]
+pub struct Traffic {
+    user_id: Uuid,
+    request: GameRequest,
+}
+
+/// 房间的调试记录：广播过的事件以及最近收到的请求，超出上限后丢弃最早的记录
+#[derive(Debug, Default)]
+pub struct Journal {
+    events: VecDeque<Entry<GameResponse>>,
+    traffic: VecDeque<Entry<Traffic>>,
+}
+
+impl Journal {
+    pub fn event(&mut self, response: &GameResponse) {
+        push(&mut self.events, MAX_EVENTS, response.clone());
+    }
+
+    pub fn request(&mut self, user_id: Uuid, request: &GameRequest) {
+        let traffic = Traffic {
+            user_id,
+            request: request.clone(),
+        };
+        push(&mut self.traffic, MAX_TRAFFIC, traffic);
+    }
+
+    pub fn events(&self) -> &VecDeque<Entry<GameResponse>> {
+        &self.events
+    }
+
+    pub fn traffic(&self) -> &VecDeque<Entry<Traffic>> {
+        &self.traffic
+    }
+}
+
+fn push<T>(queue: &mut VecDeque<Entry<T>>, max: usize, item: T) {
+    if queue.len() >= max {
+        queue.pop_front();
+    }
+    queue.push_back(Entry {
+        timestamp: timestamp(),
+        item,
+    });
+}
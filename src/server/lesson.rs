@@ -0,0 +1,147 @@
+use ndoors::{GameResult, Settings};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::pacing::Pacing;
+use crate::script::HostScript;
+
+/// 课程包：游戏设置、主持人脚本、测验问题和报告模板，可以导出分享给其他老师。
+///
+/// 服务器启动时从 `lesson_dir` 目录读取所有 `*.json` 课程包，主持人创建房间时指定 `lesson` 使用
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct LessonPack {
+    /// 课程名称，创建房间时使用
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// 游戏设置，有测验问题时自动开启测验模式
+    pub settings: Settings,
+    #[serde(default)]
+    pub pacing: Pacing,
+    /// 主持人脚本，需要 `scripting` feature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// 每轮请挑战者估计中奖率时的问题，轮数超过问题数时从头循环
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub questions: Vec<String>,
+    /// 每局结束后发送的报告，`{win_rate}` 等占位符替换为本局的结果，见 [`render`](LessonPack::render)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<String>,
+}
+
+/// 课程列表中的一项
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LessonSummary {
+    name: String,
+    description: String,
+}
+
+impl LessonPack {
+    /// 检查设置和脚本
+    fn validate(&mut self) -> anyhow::Result<()> {
+        self.settings.quiz |= !self.questions.is_empty();
+        self.settings.validate()?;
+        if let Some(script) = &self.script {
+            HostScript::compile(script)?;
+        }
+        Ok(())
+    }
+
+    /// 第 `round` 轮（从 0 开始）的问题
+    pub fn question(&self, round: u32) -> Option<&str> {
+        if self.questions.is_empty() {
+            return None;
+        }
+        let question = &self.questions[round as usize % self.questions.len()];
+        Some(question.as_str())
+    }
+
+    /// 用本局结果替换报告模板中的占位符：`{rounds}`、`{win}`、`{win_rate}`、`{switch}`、
+    /// `{switch_rate}`、`{switch_win}`、`{stick}`、`{stick_win}`，测验模式下还有
+    /// `{mean_estimate}`、`{expected}`、`{observed}`，设置了奖品价值时还有 `{score}`、`{stakes}`，
+    /// 比例都是百分数
+    pub fn render(&self, result: &GameResult) -> Option<String> {
+        let template = self.report.as_ref()?;
+        let percent = |rate: f64| format!("{:.0}%", rate * 100.0);
+        let stats = result.stats();
+        let mut values = vec![
+            ("rounds", stats.rounds().to_string()),
+            ("win", stats.win().to_string()),
+            ("win_rate", percent(stats.win_rate())),
+            ("switch", stats.switch().to_string()),
+            ("switch_rate", percent(stats.switch_rate())),
+            ("switch_win", stats.switch_win().to_string()),
+            ("stick", stats.stick().to_string()),
+            ("stick_win", stats.stick_win().to_string()),
+        ];
+        if let Some(quiz) = result.quiz() {
+            values.push(("mean_estimate", percent(quiz.mean_estimate())));
+            values.push(("expected", percent(quiz.expected())));
+            values.push(("observed", percent(quiz.observed())));
+        }
+        if let Some(score) = result.score() {
+            values.push(("score", score.score().to_string()));
+            values.push(("stakes", score.stakes().to_string()));
+        }
+        let text = values
+            .into_iter()
+            .fold(template.clone(), |text, (key, value)| {
+                text.replace(&format!("{{{}}}", key), &value)
+            });
+        Some(text)
+    }
+}
+
+/// 加载的课程包，按名称排序
+#[derive(Debug, Default)]
+pub struct Lessons {
+    packs: BTreeMap<String, Arc<LessonPack>>,
+}
+
+impl Lessons {
+    /// 读取目录中的所有课程包，有误的课程包记录日志后跳过
+    pub fn load(dir: Option<&Path>) -> anyhow::Result<Self> {
+        let mut lessons = Lessons::default();
+        let dir = match dir {
+            Some(dir) => dir,
+            None => return Ok(lessons),
+        };
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+            let loaded = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(serde_json::from_str::<LessonPack>(&content)?))
+                .and_then(|mut pack| pack.validate().map(|_| pack));
+            match loaded {
+                Ok(pack) => {
+                    tracing::info!(path = %path.display(), name = %pack.name, "Lesson loaded.");
+                    lessons.packs.insert(pack.name.clone(), Arc::new(pack));
+                }
+                Err(cause) => {
+                    tracing::error!(path = %path.display(), %cause, "Invalid lesson pack.")
+                }
+            }
+        }
+        Ok(lessons)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<LessonPack>> {
+        self.packs.get(name).cloned()
+    }
+
+    pub fn list(&self) -> Vec<LessonSummary> {
+        self.packs
+            .values()
+            .map(|pack| LessonSummary {
+                name: pack.name.clone(),
+                description: pack.description.clone(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,67 @@
+use arc_swap::ArcSwap;
+use im::OrdMap;
+use ndoors::Uuid;
+
+use crate::room::{RoomInfo, RoomPage};
+
+/// 按租户和房间 ID 排序的房间信息。持久化的有序映射复制时共享结构，修改一个房间只复制 O(log n) 个节点
+type Index = OrdMap<(String, Uuid), RoomInfo>;
+
+/// 每页最多的房间数，更大的 `size` 按这个值处理
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// 大厅的只读索引
+///
+/// 房间创建、删除或者列出的信息变化时复制索引并整体替换，列出房间时只读取当前的索引，
+/// 不会和游戏中修改房间的请求争用 `rooms` 的锁。每一轮房间的进度都会变化，所以复制必须足够便宜
+#[derive(Debug, Default)]
+pub struct Lobby {
+    index: ArcSwap<Index>,
+}
+
+impl Lobby {
+    /// 添加或者更新房间
+    pub fn list(&self, tenant: &str, info: RoomInfo) {
+        let key = (tenant.to_string(), info.id);
+        self.index.rcu(|index| {
+            let mut index = Index::clone(index);
+            index.insert(key.clone(), info.clone());
+            index
+        });
+    }
+
+    /// 移除房间
+    pub fn unlist(&self, tenant: &str, id: Uuid) {
+        let key = (tenant.to_string(), id);
+        if !self.index.load().contains_key(&key) {
+            return;
+        }
+        self.index.rcu(|index| {
+            let mut index = Index::clone(index);
+            index.remove(&key);
+            index
+        });
+    }
+
+    /// 租户的一页房间，WebSocket 和 HTTP 接口共用。`size` 最多 [`MAX_PAGE_SIZE`]，返回的页中是实际使用的值
+    pub fn page(&self, tenant: &str, page: u32, size: u32) -> RoomPage {
+        let size = size.min(MAX_PAGE_SIZE);
+        let index = self.index.load();
+        let listed = || {
+            index
+                .range((tenant.to_string(), Uuid::nil())..)
+                .take_while(|((t, _), _)| t == tenant)
+                .map(|(_, info)| info)
+        };
+        RoomPage {
+            rooms: listed()
+                .skip(page.saturating_mul(size) as usize)
+                .take(size as usize)
+                .cloned()
+                .collect(),
+            page,
+            size,
+            total: listed().count() as u32,
+        }
+    }
+}
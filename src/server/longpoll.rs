@@ -0,0 +1,232 @@
+use axum::extract::{Extension, Path, Query};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, HeaderName, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use dashmap::DashMap;
+use ndoors::Uuid;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::auth::bearer_token;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::{Credentials, Link, Server};
+
+/// 会话在没有轮询时保留的时间，超过后按断开连接处理，之后只能用恢复令牌恢复
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// 一次轮询最长等待新消息的时间
+const MAX_WAIT: Duration = Duration::from_secs(25);
+/// 还没有确认的消息最多保留的条数，超过后丢弃最早的消息
+const MAX_PENDING: usize = 1000;
+
+/// 不能使用 WebSocket 的网络中的备用传输：`POST /poll` 建立会话，`POST /poll/{session}` 发送请求，
+/// `GET /poll/{session}?after={seq}` 取回序号大于 `after` 的消息。请求和响应与 WebSocket 相同
+pub fn router() -> Router {
+    Router::new()
+        .route("/poll", post(open))
+        .route("/poll/:session", post(send).get(receive))
+}
+
+/// 长轮询的会话
+#[derive(Debug, Default)]
+pub struct Polls {
+    sessions: DashMap<Uuid, Arc<PollSession>>,
+}
+
+#[derive(Debug)]
+struct PollSession {
+    requests: Sender<ClientMessage>,
+    pending: Mutex<Pending>,
+    /// 有新消息或者连接结束
+    notify: Notify,
+    last_poll: Mutex<Instant>,
+}
+
+/// 发给客户端、还没有确认的消息
+#[derive(Debug, Default)]
+struct Pending {
+    /// 最后一条消息的序号，第一条消息的序号是 1
+    seq: u64,
+    messages: VecDeque<(u64, Arc<str>)>,
+    /// 连接已经结束（被新连接接管或者会话过期），不会再有新消息
+    closed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct PollOptions {
+    /// 恢复断开连接前的会话，值是 `UserCreated` 中的 `resume_token`
+    resume: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+struct PollOpened {
+    /// 会话令牌，之后的请求都在路径中带上
+    session: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReceiveQuery {
+    /// 客户端已经收到的最后一条消息的序号，序号不大于它的消息视为已确认并丢弃
+    #[serde(default)]
+    after: u64,
+    /// 没有新消息时等待的秒数，最多 25 秒
+    #[serde(default = "default_wait")]
+    wait: u64,
+}
+
+fn default_wait() -> u64 {
+    MAX_WAIT.as_secs()
+}
+
+/// 建立会话，认证和恢复会话与 WebSocket 相同。问候（`UserCreated` 或者 `SessionResumed`）是第一条消息
+async fn open(
+    Query(credentials): Query<Credentials>,
+    Query(options): Query<PollOptions>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Response {
+    let token = credentials
+        .token
+        .as_deref()
+        .or_else(|| bearer_token(&headers));
+    let (identity, resumed) = match server.admit(token, options.resume) {
+        Ok(admitted) => admitted,
+        Err(rejected) => return rejected.into_response(),
+    };
+    let link = match server.open_session(identity, resumed).await {
+        Some(link) => link,
+        None => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let Link {
+        user_id,
+        serial,
+        requests,
+        responses,
+    } = link;
+    let id = Uuid::new_v4();
+    let session = Arc::new(PollSession {
+        requests,
+        pending: Default::default(),
+        notify: Notify::new(),
+        last_poll: Mutex::new(Instant::now()),
+    });
+    server.polls.sessions.insert(id, session.clone());
+    tracing::info!(user = %user_id, session = %id, "Long-poll session opened.");
+
+    tokio::spawn(async move {
+        pump(&session, responses).await;
+        server.polls.sessions.remove(&id);
+        server.close_session(&user_id, serial);
+        tracing::info!(user = %user_id, session = %id, "Long-poll session closed.");
+    });
+    Json(PollOpened { session: id }).into_response()
+}
+
+/// 把发给用户的消息编上序号放进待确认的队列，直到客户端停止轮询
+async fn pump(session: &PollSession, mut responses: Receiver<ServerMessage>) {
+    loop {
+        let idle_at = *session.last_poll.lock().unwrap() + IDLE_TIMEOUT;
+        let closed = session.pending.lock().unwrap().closed;
+        tokio::select! {
+            response = responses.recv(), if !closed => {
+                let mut pending = session.pending.lock().unwrap();
+                match response.map(|response| response.to_json()) {
+                    Some(Ok(text)) => {
+                        pending.seq += 1;
+                        let seq = pending.seq;
+                        pending.messages.push_back((seq, text));
+                        if pending.messages.len() > MAX_PENDING {
+                            pending.messages.pop_front();
+                        }
+                    }
+                    Some(Err(cause)) => {
+                        tracing::error!(%cause, "Failed to serialize response.");
+                        continue;
+                    }
+                    // 会话被新连接接管，客户端取完剩下的消息后结束
+                    None => pending.closed = true,
+                }
+                session.notify.notify_waiters();
+            }
+            _ = tokio::time::sleep_until(idle_at) => {
+                if session.last_poll.lock().unwrap().elapsed() >= IDLE_TIMEOUT {
+                    break;
+                }
+            }
+        }
+    }
+    // 请求通道随会话一起释放，请求处理任务随后按断开连接结束会话
+    session.pending.lock().unwrap().closed = true;
+    session.notify.notify_waiters();
+}
+
+/// 发送一个请求，和 WebSocket 中的文本消息相同
+async fn send(
+    Path(id): Path<Uuid>,
+    Extension(server): Extension<Server>,
+    Json(message): Json<ClientMessage>,
+) -> StatusCode {
+    let requests = match server.polls.sessions.get(&id) {
+        Some(session) => session.requests.clone(),
+        None => return StatusCode::NOT_FOUND,
+    };
+    match requests.send(message).await {
+        Ok(_) => StatusCode::ACCEPTED,
+        Err(_) => StatusCode::GONE,
+    }
+}
+
+/// 取回序号大于 `after` 的消息，没有时等到有新消息或者超时。
+/// 返回 `{"messages": [{"seq": 1, "message": {...}}], "closed": false}`，
+/// 上一次轮询的响应丢失时用同一个 `after` 重新轮询即可再次取回
+async fn receive(
+    Path(id): Path<Uuid>,
+    Query(query): Query<ReceiveQuery>,
+    Extension(server): Extension<Server>,
+) -> Result<([(HeaderName, &'static str); 1], String), StatusCode> {
+    let session = server
+        .polls
+        .sessions
+        .get(&id)
+        .map(|session| session.clone())
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let deadline = Instant::now() + Duration::from_secs(query.wait).min(MAX_WAIT);
+    loop {
+        *session.last_poll.lock().unwrap() = Instant::now();
+        let notified = session.notify.notified();
+        {
+            let mut pending = session.pending.lock().unwrap();
+            while pending
+                .messages
+                .front()
+                .is_some_and(|(seq, _)| *seq <= query.after)
+            {
+                pending.messages.pop_front();
+            }
+            if !pending.messages.is_empty() || pending.closed || Instant::now() >= deadline {
+                return Ok(([(CONTENT_TYPE, "application/json")], batch(&pending)));
+            }
+        }
+        let _ = tokio::time::timeout_at(deadline, notified).await;
+    }
+}
+
+/// 消息已经是序列化好的 JSON，直接拼接，不再解析
+fn batch(pending: &Pending) -> String {
+    let messages: Vec<String> = pending
+        .messages
+        .iter()
+        .map(|(seq, text)| format!(r#"{{"seq":{},"message":{}}}"#, seq, text))
+        .collect();
+    format!(
+        r#"{{"messages":[{}],"closed":{}}}"#,
+        messages.join(","),
+        pending.closed
+    )
+}
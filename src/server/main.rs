@@ -0,0 +1,2222 @@
+use axum::extract::ws::{Message, WebSocket};
+use axum::extract::{Extension, Query, WebSocketUpgrade};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, get_service};
+use axum::Router;
+use dashmap::{DashMap, DashSet};
+use ndoors::protocol::timestamp;
+use ndoors::*;
+use rand::Rng;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tower_http::services::ServeDir;
+use tower_http::trace::TraceLayer;
+use tracing::Level;
+use tracing_subscriber::fmt::writer::MakeWriterExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::analytics::{Analytics, AnalyticsEvent};
+use crate::audit::*;
+use crate::auth::*;
+use crate::bot::Bots;
+use crate::bridge::EventBridge;
+use crate::class::MAX_CLASS_LEN;
+use crate::commentary::Commentary;
+use crate::compress::*;
+use crate::config::Config;
+use crate::connection::{Connections, Handoff};
+use crate::correspondence::{CorrespondenceState, RoomStore};
+use crate::dataset::Dataset;
+use crate::fair::{FairRoom, Fairness, MAX_NONCE_LEN};
+use crate::filter::*;
+use crate::lesson::Lessons;
+use crate::lobby::Lobby;
+use crate::longpoll::Polls;
+use crate::metrics::Metrics;
+use crate::netsim::NetworkSimulation;
+use crate::permission::*;
+use crate::protocol::*;
+use crate::push::Notifier;
+use crate::quota::Quotas;
+use crate::random::Randomness;
+use crate::reaction::{Reactions, REACTION_WINDOW};
+use crate::record::Recorder;
+use crate::room::*;
+use crate::rules::Rulebook;
+use crate::script::{HostScript, ScriptAction};
+use crate::session::Sessions;
+use crate::share::ShareCards;
+use crate::supervisor::*;
+use crate::tunables::Tunables;
+
+mod analytics;
+mod api;
+mod audit;
+mod auth;
+mod batch;
+mod bot;
+mod bridge;
+mod class;
+mod commentary;
+mod compress;
+mod config;
+mod connection;
+mod correspondence;
+mod dataset;
+mod deadline;
+mod exhibition;
+mod fair;
+mod filter;
+mod footprint;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod http;
+mod journal;
+mod lesson;
+mod lobby;
+mod longpoll;
+mod metrics;
+mod moderation;
+mod narration;
+mod netsim;
+mod pacing;
+mod permission;
+mod protocol;
+mod push;
+mod quota;
+mod random;
+mod reaction;
+mod record;
+mod replay;
+mod room;
+mod rules;
+mod sampling;
+#[cfg(any(feature = "bots", feature = "plugins"))]
+mod sandbox;
+mod script;
+mod session;
+mod share;
+mod stress;
+mod supervisor;
+mod tunables;
+mod vectors;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // `ndoors-server replay <记录文件>...` 回放录制的会话，不启动服务器
+    // `ndoors-server vectors` 检查协议测试向量，不启动服务器
+    // `ndoors-server stress [对数] [轮数]` 在进程内进行压力测试，不启动服务器
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = args.split_first().map(|(c, p)| (c.as_str(), p));
+
+    // 压力测试每个请求的日志太多，只输出错误
+    let level = match command {
+        Some(("stress", _)) => Level::ERROR,
+        _ => Level::INFO,
+    };
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stdout.with_max_level(level)))
+        .init();
+
+    match command {
+        Some(("replay", paths)) => return replay::run(paths).await,
+        Some(("vectors", _)) => return vectors::check(),
+        Some(("stress", args)) => {
+            println!("{}", stress::run(args)?);
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut config = Config::load()?;
+    let exhibitions = std::mem::take(&mut config.exhibitions);
+    let server = Server::new(config)?;
+    for exhibition in exhibitions {
+        exhibition::start(&server, exhibition)?;
+    }
+    if let Some(store) = &server.store {
+        correspondence::restore(&server, store)?;
+        correspondence::watch(server.clone(), store.clone());
+    }
+    deadline::watch(server.clone());
+    reload_on_hangup(server.clone());
+
+    let addr = SocketAddr::new([0, 0, 0, 0].into(), 7654);
+
+    let app = Router::new()
+        .route("/ws", get(ws_handler))
+        .merge(longpoll::router())
+        .merge(api::router());
+    #[cfg(feature = "graphql")]
+    let app = app.merge(graphql::router(server.clone()));
+    let app = app
+        .fallback(get_service(ServeDir::new("./html")).handle_error(
+            |error: std::io::Error| async move {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Unhandled internal error: {}", error),
+                )
+            },
+        ))
+        .layer(TraceLayer::new_for_http())
+        .layer(Extension(server));
+
+    tracing::info!(%addr, "Server started.");
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct Credentials {
+    token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConnectOptions {
+    /// 客户端能够解压的格式，不指定时不压缩
+    compress: Option<Encoding>,
+    /// 恢复断开连接前的会话，值是 `UserCreated` 中的 `resume_token`
+    resume: Option<Uuid>,
+}
+
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    Query(credentials): Query<Credentials>,
+    Query(options): Query<ConnectOptions>,
+    headers: HeaderMap,
+    Extension(server): Extension<Server>,
+) -> Response {
+    // 浏览器的 WebSocket 无法设置请求头，所以令牌也可以放在 `token` 查询参数中
+    let token = credentials
+        .token
+        .as_deref()
+        .or_else(|| bearer_token(&headers));
+    let (identity, resumed) = match server.admit(token, options.resume) {
+        Ok(admitted) => admitted,
+        Err(rejected) => return rejected.into_response(),
+    };
+
+    ws.on_upgrade(move |socket| async move {
+        let link = match server.open_session(identity, resumed).await {
+            Some(link) => link,
+            None => return,
+        };
+        let compressor = options
+            .compress
+            .map(|encoding| Compressor::new(encoding, server.tunables().compression_threshold));
+        let recorder = server.record_dir.as_deref().and_then(|dir| {
+            Recorder::create(dir, link.user_id)
+                .map_err(|cause| tracing::error!(%cause, "Failed to create session record."))
+                .ok()
+        });
+        let (user_id, serial) = (link.user_id, link.serial);
+        if let Err(cause) =
+            websocket_loop(socket, link.requests, link.responses, recorder, compressor).await
+        {
+            tracing::error!(%cause, "Websocket loop error.");
+        }
+        server.close_session(&user_id, serial);
+    })
+}
+
+/// 一个连接的两端，传输层（WebSocket、长轮询）把客户端的请求发到 `requests`，
+/// 把 `responses` 中的消息发给客户端，结束后调用 [`Server::close_session`]
+struct Link {
+    user_id: Uuid,
+    /// 连接序号，见 [`Connections::register`]
+    serial: Uuid,
+    requests: Sender<ClientMessage>,
+    responses: Receiver<ServerMessage>,
+}
+
+#[derive(Debug, Clone)]
+struct Server {
+    rooms: Arc<DashMap<Uuid, RoomAgent>>,
+    /// 大厅的只读索引，列出房间时不访问 `rooms`
+    lobby: Arc<Lobby>,
+    auth: Arc<dyn AuthProvider>,
+    /// 版主，来自认证信息或者管理接口的授权
+    moderators: Arc<DashSet<Uuid>>,
+    admin_token: Option<Arc<str>>,
+    audit: Arc<AuditLog>,
+    /// 房间的随机数来源
+    random: Arc<Randomness>,
+    /// 公平模式的 VRF 私钥
+    fairness: Arc<Fairness>,
+    metrics: Arc<Metrics>,
+    network_simulation: Option<Arc<NetworkSimulation>>,
+    record_dir: Option<PathBuf>,
+    /// 可以重新加载的配置
+    tunables: Arc<RwLock<Arc<Tunables>>>,
+    /// 排空模式：不再接受新连接和新房间，进行中的游戏可以继续
+    draining: Arc<AtomicBool>,
+    /// 租户配额和用量
+    quotas: Arc<Quotas>,
+    analytics: Arc<Analytics>,
+    /// 公开数据集
+    dataset: Arc<Dataset>,
+    bridge: Arc<EventBridge>,
+    /// 内置的规则和规则插件
+    rules: Arc<Rulebook>,
+    /// 内置策略和上传的机器人
+    bots: Arc<Bots>,
+    lessons: Arc<Lessons>,
+    /// 发给观众的解说模板
+    commentary: Arc<Commentary>,
+    /// 轮到离开的用户操作时推送通知
+    push: Arc<Notifier>,
+    /// 断开连接后可以恢复的会话
+    sessions: Arc<Sessions>,
+    /// 通信对局房间的存储，未配置时不能创建通信对局房间
+    store: Option<Arc<RoomStore>>,
+    /// 已认证用户当前的连接
+    connections: Arc<Connections>,
+    /// 分享的游戏卡片
+    shares: Arc<ShareCards>,
+    /// 长轮询的会话
+    polls: Arc<Polls>,
+}
+
+impl Server {
+    fn new(config: Config) -> anyhow::Result<Self> {
+        if let Some(simulation) = &config.network_simulation {
+            tracing::warn!(?simulation, "Network simulation enabled.");
+        }
+        let tunables = Tunables::new(&config)?;
+        Ok(Self {
+            rooms: Default::default(),
+            lobby: Default::default(),
+            auth: config.auth.build()?,
+            moderators: Default::default(),
+            admin_token: config.admin_token.map(Arc::from),
+            audit: Arc::new(AuditLog::open(config.audit_log.as_deref())?),
+            random: Arc::new(Randomness::start(config.random_source)?),
+            fairness: Arc::new(Fairness::load(config.fairness_key.as_deref())?),
+            metrics: Default::default(),
+            network_simulation: config.network_simulation.map(Arc::new),
+            record_dir: config.record_dir,
+            tunables: Arc::new(RwLock::new(Arc::new(tunables))),
+            draining: Default::default(),
+            quotas: Arc::new(Quotas::new(config.quotas, config.quota_hook.build())),
+            analytics: Arc::new(Analytics::start(config.analytics)?),
+            dataset: Arc::new(Dataset::open(config.dataset.as_deref())?),
+            bridge: Arc::new(EventBridge::start(config.event_bridge)?),
+            rules: Arc::new(Rulebook::load(config.plugin_dir.as_deref())?),
+            bots: Default::default(),
+            lessons: Arc::new(Lessons::load(config.lesson_dir.as_deref())?),
+            commentary: Arc::new(Commentary::load(config.commentary_dir.as_deref())?),
+            push: Arc::new(Notifier::new(config.push)?),
+            sessions: Arc::new(Sessions::new(
+                config.session_resume_secs.map(Duration::from_secs),
+            )),
+            store: config
+                .correspondence_dir
+                .map(|dir| RoomStore::open(dir, config.correspondence_recovery))
+                .transpose()?
+                .map(Arc::new),
+            connections: Arc::new(Connections::new(config.duplicate_connections)),
+            shares: Default::default(),
+            polls: Default::default(),
+        })
+    }
+
+    /// 检查新连接：排空模式、认证、重复连接和要恢复的会话，失败时返回给客户端的 HTTP 状态和原因
+    fn admit(
+        &self,
+        token: Option<&str>,
+        resume: Option<Uuid>,
+    ) -> Result<(Identity, Option<(User, RoomDropper)>), (StatusCode, String)> {
+        if self.is_draining() {
+            return Err((StatusCode::SERVICE_UNAVAILABLE, "Server is draining".into()));
+        }
+
+        let mut identity = match self.auth.authenticate(token) {
+            Ok(identity) => identity,
+            Err(cause) => {
+                tracing::warn!(%cause, "Authentication failed.");
+                return Err((StatusCode::UNAUTHORIZED, cause.to_string()));
+            }
+        };
+
+        identity.name = identity
+            .name
+            .map(|name| self.filter_text(identity.id, TextKind::Name, &name));
+        if identity.moderator {
+            self.moderators.insert(identity.id);
+        }
+
+        if self.connections.rejects(&identity.id) {
+            tracing::warn!(user = %identity.id, "Duplicate connection rejected.");
+            return Err((StatusCode::CONFLICT, "Already connected".into()));
+        }
+
+        let resumed = match resume {
+            Some(token) => match self.sessions.resume(token, &identity.tenant) {
+                Some(session) => Some(session),
+                None => return Err((StatusCode::NOT_FOUND, "Session not found or expired".into())),
+            },
+            None => None,
+        };
+        Ok((identity, resumed))
+    }
+
+    /// 为通过检查的连接建立或者恢复会话，发送问候并启动请求处理任务。发送问候失败时返回 `None`
+    async fn open_session(
+        &self,
+        identity: Identity,
+        resumed: Option<(User, RoomDropper)>,
+    ) -> Option<Link> {
+        let (resp_sender, resp_receiver) = channel(16);
+        let (req_sender, req_receiver) = channel(16);
+        let resp_receiver = match &self.network_simulation {
+            Some(simulation) if simulation.applies_to(&identity.id) => {
+                simulation.link(resp_receiver)
+            }
+            _ => resp_receiver,
+        };
+        // 同一用户已经连接时接管原来的会话
+        let resumed = match resumed {
+            Some(session) => Some(session),
+            None => self.connections.take_over(&identity.id).await,
+        };
+        let (user, room_dropper, greeting) = match resumed {
+            Some((mut user, room_dropper)) => {
+                // 断开期间房间发给用户的消息已经丢失，客户端根据房间信息重新同步
+                user.sender = resp_sender;
+                let info = user
+                    .role
+                    .room_id()
+                    .and_then(|room_id| self.rooms.get_mut(&room_id))
+                    .map(|mut ra| {
+                        ra.rebind(user.id, &user.role, user.sender.clone());
+                        // 挑战者回来后继续暂停的游戏
+                        if matches!(user.role, Role::Contestant { .. }) && ra.room.resume().is_ok()
+                        {
+                            ra.publish(GameResponse::Resumed);
+                        }
+                        // 服务器重启时退回的一轮
+                        let rolled_back = ra
+                            .correspondence
+                            .as_ref()
+                            .and_then(|correspondence| correspondence.rolled_back);
+                        if let Some(round) = rolled_back {
+                            ra.reply(user.sender.clone(), GameResponse::RoundRolledBack { round });
+                        }
+                        RoomInfo::from(&*ra)
+                    });
+                tracing::info!(user = %user.id, "Session resumed.");
+                let greeting = GameResponse::SessionResumed { id: user.id, info };
+                (user, room_dropper, greeting)
+            }
+            None => {
+                let mut user = User::new(identity.id, resp_sender);
+                user.tenant = identity.tenant;
+                tracing::info!(user = %user.id, name = ?identity.name, "User created.");
+                let greeting = GameResponse::UserCreated {
+                    id: user.id,
+                    resume_token: self.is_resumable().then_some(user.resume_token),
+                };
+                (user, RoomDropper::new(self.rooms.clone()), greeting)
+            }
+        };
+        if user.sender.send(greeting.into()).await.is_err() {
+            tracing::error!("Failed to send greeting response.");
+            self.end_session(user, room_dropper);
+            return None;
+        }
+        self.push.active(user.id);
+        let user_id = user.id;
+        let (serial, takeover) = self.connections.register(user_id);
+
+        let s = self.clone();
+        let handle = tokio::spawn(async move {
+            if let Err(cause) = request_handler(user, room_dropper, s, req_receiver, takeover).await
+            {
+                tracing::error!(%cause, "Request handler error.");
+            }
+        });
+        supervise(handle, self.metrics.clone());
+        self.metrics.connections.fetch_add(1, Ordering::Relaxed);
+        Some(Link {
+            user_id,
+            serial,
+            requests: req_sender,
+            responses: resp_receiver,
+        })
+    }
+
+    /// 传输层的连接结束
+    fn close_session(&self, user_id: &Uuid, serial: Uuid) {
+        self.connections.unregister(user_id, serial);
+        self.metrics.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// 是否给用户发恢复令牌
+    fn is_resumable(&self) -> bool {
+        self.sessions.window().is_some() || self.store.is_some()
+    }
+
+    /// 连接断开后保留会话，过期后丢弃（包括用户创建的房间）
+    fn end_session(&self, user: User, room_dropper: RoomDropper) {
+        self.push.disconnected(user.id);
+        // 通信对局的成员随时可以回来继续
+        let correspondence = user
+            .role
+            .room_id()
+            .and_then(|room_id| self.rooms.get(&room_id))
+            .is_some_and(|ra| ra.correspondence.is_some());
+        if correspondence {
+            self.sessions.park(user, room_dropper, false);
+            return;
+        }
+        let window = match self.sessions.window() {
+            Some(window) => window,
+            None => {
+                self.push.forget(user.id);
+                return;
+            }
+        };
+        let (user_id, token) = (user.id, user.resume_token);
+        // 挑战者暂时断开时暂停游戏，主持人不能趁机推进
+        if let Role::Contestant { room_id } = user.role {
+            if let Some(mut ra) = self.rooms.get_mut(&room_id) {
+                if ra.room.pause().is_ok() {
+                    tracing::info!(room = %room_id, "Game paused.");
+                    ra.publish(GameResponse::Paused);
+                }
+            }
+        }
+        self.sessions.park(user, room_dropper, true);
+        let server = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(window).await;
+            if let Some(role) = server.sessions.expire(token) {
+                server.push.forget(user_id);
+                tracing::info!(user = %user_id, "Session expired.");
+                // 挑战者没有回来，按弃权结束本局
+                if let Role::Contestant { room_id } = role {
+                    server.forfeit(room_id, user_id);
+                }
+            }
+        });
+    }
+
+    /// 挑战者在游戏中途离开后按弃权结束本局，并移出挑战者
+    fn forfeit(&self, room_id: Uuid, user_id: Uuid) {
+        let mut ra = match self.rooms.get_mut(&room_id) {
+            Some(ra) => ra,
+            None => return,
+        };
+        if !matches!(ra.room.state(), RoomState::Started { contestant, .. } if *contestant == user_id)
+        {
+            return;
+        }
+        if let Err(cause) = force_complete_game(self, &mut ra, EarlyEnd::ContestantLeft) {
+            tracing::error!(room = %room_id, %cause, "Failed to end the forfeited game.");
+            return;
+        }
+        ra.publish(GameResponse::Exited { user_id });
+        ra.contestant = None;
+    }
+
+    /// 当前的可重新加载配置
+    fn tunables(&self) -> Arc<Tunables> {
+        self.tunables.read().unwrap().clone()
+    }
+
+    /// 重新读取配置文件并替换可重新加载的配置，配置有误时保留原来的配置
+    fn reload(&self) -> anyhow::Result<()> {
+        let tunables = Tunables::new(&Config::load()?)?;
+        tracing::info!(?tunables, "Config reloaded.");
+        *self.tunables.write().unwrap() = Arc::new(tunables);
+        Ok(())
+    }
+
+    /// 过滤用户输入的文本，违规内容记录到审计日志
+    fn filter_text(&self, user_id: Uuid, kind: TextKind, text: &str) -> String {
+        let filtered = self.tunables().filter.apply(kind, text);
+        if !filtered.violations.is_empty() {
+            self.audit.record(AuditEvent::FilterViolation {
+                user_id,
+                kind,
+                violations: filtered.violations,
+            });
+        }
+        filtered.text
+    }
+
+    /// 隔离状态可能不一致的房间：保留用于导出调试信息，但不再处理游戏请求
+    fn quarantine(&self, id: Uuid) {
+        if let Some(mut ra) = self.rooms.get_mut(&id) {
+            if !ra.quarantined {
+                ra.quarantined = true;
+                ra.publish(GameResponse::ServerError {
+                    cause: ServerError::RoomQuarantined { id },
+                });
+                self.metrics
+                    .quarantined_rooms
+                    .fetch_add(1, Ordering::Relaxed);
+                tracing::error!(room = %id, "Room quarantined.");
+            }
+        }
+    }
+
+    /// 按配置的比例随机抽取刚完成的一局游戏的轮次重放核对，防范状态被意外破坏的问题。
+    /// 核对结果不一致时只输出错误日志和指标，不影响已经公布的结果
+    fn audit_rounds(&self, ra: &mut RoomAgent) {
+        let rounds = ra.tape.take_completed();
+        let rate = self.tunables().round_audit_rate;
+        if rate <= 0.0 {
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        for taped in rounds.iter().filter(|_| rng.gen_bool(rate)) {
+            self.metrics.round_audits.fetch_add(1, Ordering::Relaxed);
+            if let Err(cause) = sampling::verify(ra.room.settings(), taped) {
+                self.metrics
+                    .round_audit_mismatches
+                    .fetch_add(1, Ordering::Relaxed);
+                tracing::error!(
+                    room = %ra.room.id(),
+                    round = taped.round(),
+                    %cause,
+                    "Round audit mismatch."
+                );
+            }
+        }
+    }
+
+    fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    fn is_moderator(&self, id: &Uuid) -> bool {
+        self.moderators.contains(id)
+    }
+
+    /// 用户所在房间是团队模式时，当前由谁提交选择、估计和抉择
+    fn team_decider(&self, role: &Role) -> Option<Uuid> {
+        let ra = self.rooms.get(&role.room_id()?)?;
+        if ra.room.teams().is_empty() {
+            None
+        } else {
+            ra.room.decider()
+        }
+    }
+}
+
+#[derive(Debug)]
+struct User {
+    id: Uuid,
+    role: Role,
+    sender: Sender<ServerMessage>,
+    /// 所属租户，只能看到和进入同一租户的房间
+    tenant: String,
+    /// 断开连接后恢复会话的令牌
+    resume_token: Uuid,
+}
+
+impl User {
+    pub fn new(id: Uuid, sender: Sender<ServerMessage>) -> Self {
+        Self {
+            id,
+            role: Role::Guest,
+            sender,
+            tenant: String::new(),
+            resume_token: Uuid::new_v4(),
+        }
+    }
+}
+
+impl Drop for User {
+    fn drop(&mut self) {
+        tracing::info!(user = %self.id, "User disconnected.");
+    }
+}
+
+#[tracing::instrument(skip_all, fields(user = %user.id, role = ?user.role))]
+async fn request_handler(
+    mut user: User,
+    mut room_dropper: RoomDropper,
+    server: Server,
+    mut receiver: Receiver<ClientMessage>,
+    mut takeover: tokio::sync::oneshot::Receiver<Handoff>,
+) -> anyhow::Result<()> {
+    let mut result = Ok(());
+    let mut can_take_over = true;
+    loop {
+        tokio::select! {
+            message = receiver.recv() => {
+                match message {
+                    Some(message) => {
+                        result = process_request(&server, &mut user, &mut room_dropper, message).await;
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                    None => break,
+                }
+            }
+            handoff = &mut takeover, if can_take_over => {
+                match handoff {
+                    // 交出会话后房间改为发到新的连接，这个连接的响应通道关闭后连接随之结束
+                    Ok(handoff) => {
+                        let _ = user.sender.send(GameResponse::ConnectionReplaced.into()).await;
+                        if let Err((user, room_dropper)) = handoff.send((user, room_dropper)) {
+                            server.end_session(user, room_dropper);
+                        }
+                        return Ok(());
+                    }
+                    Err(_) => can_take_over = false,
+                }
+            }
+        }
+    }
+    server.end_session(user, room_dropper);
+    result
+}
+
+/// 处理用户的一个请求，包括角色刷新、权限检查和 panic 隔离
+async fn process_request(
+    server: &Server,
+    user: &mut User,
+    room_dropper: &mut RoomDropper,
+    message: ClientMessage,
+) -> anyhow::Result<()> {
+    let ClientMessage {
+        request,
+        expected_seq,
+    } = message;
+    server.push.active(user.id);
+
+    // 其他成员可能改变了用户在房间中的角色（踢出、提升为副主持人）
+    if let Some(notice) = refresh_role(server, user) {
+        user.sender.send(notice.into()).await.map_err(send_error)?;
+        return Ok(());
+    }
+
+    // 其他租户的房间对用户来说不存在
+    if let Some(id) = request.room_id() {
+        if server
+            .rooms
+            .get(&id)
+            .is_some_and(|ra| ra.tenant != user.tenant)
+        {
+            let response = GameResponse::ServerError {
+                cause: ServerError::RoomNotFound { id },
+            };
+            user.sender
+                .send(response.into())
+                .await
+                .map_err(send_error)?;
+            return Ok(());
+        }
+    }
+
+    // 记录到目标房间，用于导出调试信息
+    if let Some(room_id) = request.room_id().or_else(|| user.role.room_id()) {
+        if let Some(mut ra) = server.rooms.get_mut(&room_id) {
+            ra.journal.request(user.id, &request);
+        }
+    }
+
+    let action = Action::from(&request);
+    let allowed = match action {
+        // 管理操作不依赖用户在房间中的角色
+        Action::Moderate => server.is_moderator(&user.id),
+        // 团队模式下只有本轮团队的队长可以选择、估计和抉择
+        Action::Choose | Action::Decide | Action::Estimate => {
+            match server.team_decider(&user.role) {
+                Some(decider) => decider == user.id,
+                None => user.role.can(action),
+            }
+        }
+        action => user.role.can(action),
+    };
+    if !allowed {
+        let response = GameResponse::ServerError {
+            cause: ServerError::PermissionDenied { action },
+        };
+        tracing::warn!(?request, ?user.role, "Permission denied.");
+        user.sender
+            .send(response.into())
+            .await
+            .map_err(send_error)?;
+        return Ok(());
+    }
+
+    // 废弃的请求照常处理，之后提醒客户端改用新的请求
+    let deprecated = request
+        .replacement()
+        .map(|replacement| (request.name(), replacement));
+
+    // 请求处理中的 panic 只影响这一个请求，相关房间的状态可能已经不一致，需要隔离
+    let room_id = request.room_id().or_else(|| user.role.room_id());
+    let handled = CatchUnwind::new(handle_request(
+        server,
+        user,
+        room_dropper,
+        request,
+        expected_seq,
+    ))
+    .await;
+    match handled {
+        Ok(result) => result?,
+        Err(panic) => {
+            server
+                .metrics
+                .supervised_restarts
+                .fetch_add(1, Ordering::Relaxed);
+            tracing::error!(
+                cause = panic_message(&*panic),
+                ?room_id,
+                "Request handler panicked."
+            );
+            if let Some(room_id) = room_id {
+                server.quarantine(room_id);
+            }
+            let response = GameResponse::ServerError {
+                cause: ServerError::Internal,
+            };
+            user.sender
+                .send(response.into())
+                .await
+                .map_err(send_error)?;
+        }
+    }
+
+    if let Some((request, replacement)) = deprecated {
+        server
+            .metrics
+            .deprecated_requests
+            .fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(user = %user.id, request, replacement, "Deprecated request.");
+        let response = GameResponse::Deprecated {
+            request: request.to_string(),
+            replacement: replacement.to_string(),
+        };
+        user.sender
+            .send(response.into())
+            .await
+            .map_err(send_error)?;
+    }
+    Ok(())
+}
+
+/// 处理一个已经通过权限检查的请求
+async fn handle_request(
+    server: &Server,
+    user: &mut User,
+    room_dropper: &mut RoomDropper,
+    request: GameRequest,
+    expected_seq: Option<u64>,
+) -> anyhow::Result<()> {
+    // 被隔离的房间只允许离开和管理操作
+    if !matches!(
+        request,
+        GameRequest::ExitRoom { .. } | GameRequest::Moderate { .. }
+    ) {
+        if let Some(id) = request.room_id().or_else(|| user.role.room_id()) {
+            let quarantined = server.rooms.get(&id).is_some_and(|ra| ra.quarantined);
+            if quarantined {
+                let response = GameResponse::ServerError {
+                    cause: ServerError::RoomQuarantined { id },
+                };
+                user.sender
+                    .send(response.into())
+                    .await
+                    .map_err(send_error)?;
+                return Ok(());
+            }
+        }
+    }
+
+    match request {
+        GameRequest::ListRooms { page, size } => {
+            let response = GameResponse::from(server.lobby.page(&user.tenant, page, size));
+            tracing::info!(?response, "List rooms.");
+            user.sender
+                .send(response.into())
+                .await
+                .map_err(send_error)?;
+        }
+        GameRequest::EnterRoom { id } => match server.rooms.get_mut(&id) {
+            None => {
+                let response = GameResponse::ServerError {
+                    cause: ServerError::RoomNotFound { id },
+                };
+                user.sender
+                    .send(response.into())
+                    .await
+                    .map_err(send_error)?;
+            }
+            Some(mut ra) => {
+                if let Err(cause) = ra.room.accept_contestant(user.id) {
+                    ra.reply(user.sender.clone(), GameResponse::GameError { cause });
+                    return Ok(());
+                }
+                ra.contestant = Some(user.sender.clone());
+                // 新的挑战者需要提供自己的随机串
+                if let Some(fair) = &mut ra.fairness {
+                    fair.nonce = None;
+                }
+                if let Some(correspondence) = &mut ra.correspondence {
+                    correspondence.tokens.insert(user.id, user.resume_token);
+                }
+                ra.persist();
+
+                user.role = Role::Contestant {
+                    room_id: *ra.room.id(),
+                };
+
+                let host_resp = GameResponse::RoomEntered {
+                    contestant_id: user.id,
+                };
+
+                let contestant_resp = GameResponse::ContestantRoomEntered {
+                    info: RoomInfo::from(&*ra),
+                };
+
+                tracing::info!(?host_resp, "Enter rooms.");
+                let host = ra.host.clone();
+                ra.reply(host, host_resp);
+                ra.reply(user.sender.clone(), contestant_resp);
+            }
+        },
+        GameRequest::Spectate { id } => match server.rooms.get_mut(&id) {
+            None => {
+                let response = GameResponse::ServerError {
+                    cause: ServerError::RoomNotFound { id },
+                };
+                user.sender
+                    .send(response.into())
+                    .await
+                    .map_err(send_error)?;
+            }
+            Some(mut ra) => {
+                if let Err(cause) = ra.room.add_spectator(user.id) {
+                    ra.reply(user.sender.clone(), GameResponse::GameError { cause });
+                    return Ok(());
+                }
+                ra.spectators.insert(user.id, user.sender.clone());
+                user.role = Role::Spectator { room_id: id };
+
+                let response = GameResponse::SpectatorEntered { user_id: user.id };
+                tracing::info!(?response, "Spectate.");
+                ra.publish(response);
+                let response = GameResponse::Spectating {
+                    info: RoomInfo::from(&*ra),
+                };
+                ra.reply(user.sender.clone(), response);
+            }
+        },
+        GameRequest::CreateRoom { .. } if server.is_draining() => {
+            let response = GameResponse::ServerError {
+                cause: ServerError::Draining,
+            };
+            user.sender
+                .send(response.into())
+                .await
+                .map_err(send_error)?;
+        }
+        GameRequest::CreateRoom {
+            settings,
+            pacing,
+            class,
+            lesson,
+            correspondence,
+            fair,
+            commentary,
+            name,
+            description,
+        } => {
+            // 预留在创建失败提前返回时归还
+            let reserved = server.quotas.reserve_rooms(&user.tenant, 1, || {
+                server
+                    .rooms
+                    .iter()
+                    .filter(|ra| ra.tenant == user.tenant)
+                    .count() as u32
+            });
+            let mut reservation = match reserved {
+                Ok(reservation) => reservation,
+                Err(kind) => {
+                    let response = GameResponse::ServerError {
+                        cause: ServerError::QuotaExceeded { kind },
+                    };
+                    user.sender
+                        .send(response.into())
+                        .await
+                        .map_err(send_error)?;
+                    return Ok(());
+                }
+            };
+
+            let lesson = match lesson {
+                Some(name) => match server.lessons.get(&name) {
+                    Some(lesson) => Some(lesson),
+                    None => {
+                        let response = GameResponse::ServerError {
+                            cause: ServerError::LessonNotFound { name },
+                        };
+                        user.sender
+                            .send(response.into())
+                            .await
+                            .map_err(send_error)?;
+                        return Ok(());
+                    }
+                },
+                None => None,
+            };
+
+            let commentary = match commentary {
+                Some(locale) => match server.commentary.get(&locale) {
+                    Some(templates) => Some(templates),
+                    None => {
+                        let response = GameResponse::ServerError {
+                            cause: ServerError::CommentaryNotFound { locale },
+                        };
+                        user.sender
+                            .send(response.into())
+                            .await
+                            .map_err(send_error)?;
+                        return Ok(());
+                    }
+                },
+                None => None,
+            };
+
+            let store = match (correspondence, &server.store) {
+                (Some(_), None) => {
+                    let response = GameResponse::ServerError {
+                        cause: ServerError::CorrespondenceDisabled,
+                    };
+                    user.sender
+                        .send(response.into())
+                        .await
+                        .map_err(send_error)?;
+                    return Ok(());
+                }
+                (Some(_), Some(store)) => Some(store.clone()),
+                (None, _) => None,
+            };
+
+            if fair && !server.fairness.enabled() {
+                let response = GameResponse::ServerError {
+                    cause: ServerError::FairnessDisabled,
+                };
+                user.sender
+                    .send(response.into())
+                    .await
+                    .map_err(send_error)?;
+                return Ok(());
+            }
+
+            let settings = match (&lesson, settings) {
+                (Some(lesson), _) => lesson.settings.clone(),
+                (None, None) => server.tunables().default_settings.clone(),
+                (None, Some(settings)) => settings,
+            };
+            let settings = match server.rules.configure(settings) {
+                Ok(settings) => settings,
+                Err(cause) => {
+                    let response = GameResponse::ServerError { cause };
+                    user.sender
+                        .send(response.into())
+                        .await
+                        .map_err(send_error)?;
+                    return Ok(());
+                }
+            };
+
+            let name = name.map(|name| server.filter_text(user.id, TextKind::Name, &name));
+            let description = description
+                .map(|description| server.filter_text(user.id, TextKind::Chat, &description));
+            let created = Room::create(user.id, settings).and_then(|mut room| {
+                room.update_details(name, description)?;
+                Ok(room)
+            });
+            let mut room = match created {
+                Ok(room) => room,
+                Err(cause) => {
+                    let response = GameResponse::GameError { cause };
+                    user.sender
+                        .send(response.into())
+                        .await
+                        .map_err(send_error)?;
+                    return Ok(());
+                }
+            };
+            server.rules.attach(&mut room);
+            let room_id = *room.id();
+            let limits = server.tunables().room_size_limits;
+            if room.settings().rounds > limits.rounds {
+                tracing::warn!(
+                    room = %room_id,
+                    rounds = room.settings().rounds,
+                    limit = limits.rounds,
+                    "Room created with too many rounds."
+                );
+            }
+            let mut ra = RoomAgent::new(
+                room,
+                user.tenant.clone(),
+                user.sender.clone(),
+                lesson
+                    .as_ref()
+                    .map(|lesson| lesson.pacing)
+                    .or(pacing)
+                    .unwrap_or_default(),
+                server.bridge.clone(),
+                server.push.clone(),
+                server.lobby.clone(),
+            );
+            if let Some(script) = lesson.as_ref().and_then(|lesson| lesson.script.as_deref()) {
+                // 加载课程包时已经检查过脚本
+                ra.script = HostScript::compile(script).ok();
+            }
+            ra.lesson = lesson;
+            ra.commentary = commentary;
+            ra.correspondence = correspondence
+                .map(|options| CorrespondenceState::new(options, user.id, user.resume_token));
+            ra.store = store;
+            ra.fairness = fair.then(FairRoom::default);
+            server.random.create_room(&mut ra, &server.audit);
+            ra.persist();
+            ra.class = class
+                .filter(|class| !class.is_empty())
+                .map(|class| class.chars().take(MAX_CLASS_LEN).collect());
+            let response = GameResponse::RoomCreated {
+                info: RoomInfo::from(&ra),
+            };
+            user.role = Role::Host { room_id };
+            let correspondence = ra.correspondence.is_some();
+            reservation.fulfil(|| server.rooms.insert(room_id, ra));
+            // 通信对局房间不随主持人断开而删除
+            if !correspondence {
+                room_dropper.set_room(room_id);
+            }
+
+            tracing::info!(?response, "Create room.");
+            user.sender
+                .send(response.into())
+                .await
+                .map_err(send_error)?;
+        }
+        GameRequest::Moderate { room_id, command } => {
+            moderation::moderate(server, user, room_id, command).await?;
+        }
+        GameRequest::RegisterDevice { device } => {
+            let registered = server.push.register(user.id, device);
+            let response = GameResponse::DeviceUpdated { registered };
+            tracing::info!(?response, "Register device.");
+            user.sender
+                .send(response.into())
+                .await
+                .map_err(send_error)?;
+        }
+        request => {
+            // 其余操作都在用户所在的房间内进行，权限检查保证了用户在房间中
+            let room_id = match user.role.room_id() {
+                Some(room_id) => room_id,
+                None => return Ok(()),
+            };
+
+            let remove = match server.rooms.get_mut(&room_id) {
+                Some(mut ra) => {
+                    let remove =
+                        room_request_handler(server, user, &mut ra, request, expected_seq)?;
+                    if !remove && !ra.exhibition {
+                        run_automation(server, &mut ra);
+                    }
+                    remove
+                }
+                None => {
+                    let response = GameResponse::ServerError {
+                        cause: ServerError::RoomNotFound { id: room_id },
+                    };
+                    tracing::warn!(%room_id, "Room not found.");
+                    user.sender
+                        .send(response.into())
+                        .await
+                        .map_err(send_error)?;
+
+                    tracing::error!(user = %user.id, "Room not found, user role changed to guest.");
+                    user.role = Role::Guest;
+                    return Ok(());
+                }
+            };
+
+            if remove {
+                // 这个删除不能在 get_mut 之后的上下文进行，会导致死锁
+                server.rooms.remove(&room_id);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 处理房间内的请求，返回 `true` 表示房间需要被删除。
+/// 回复和广播都经过房间的发件箱，和房间事件的顺序一致
+fn room_request_handler(
+    server: &Server,
+    user: &mut User,
+    ra: &mut RoomAgent,
+    request: GameRequest,
+    expected_seq: Option<u64>,
+) -> anyhow::Result<bool> {
+    // 网络不稳定时重复提交的终结操作直接返回之前的结果，而不是 InvalidOperation
+    if let Some(response) = ra.repeated_terminal(user.id, &request) {
+        tracing::info!(?request, "Repeated terminal action.");
+        ra.reply(user.sender.clone(), response);
+        return Ok(false);
+    }
+
+    // 客户端基于过期的房间状态发起修改操作时拒绝，并返回最新的房间信息
+    match expected_seq {
+        Some(expected_seq) if Action::from(&request).is_mutating() && expected_seq != ra.seq => {
+            let response = GameResponse::Conflict {
+                current_seq: ra.seq,
+                info: RoomInfo::from(&*ra),
+            };
+            tracing::warn!(?request, %expected_seq, ?response, "Conflict.");
+            ra.reply(user.sender.clone(), response);
+            return Ok(false);
+        }
+        _ => {}
+    }
+
+    let mut remove = false;
+    let room = &mut ra.room;
+    match request {
+        GameRequest::ExitRoom { id } => {
+            if id != *room.id() {
+                tracing::error!("exit room error: {} != {}.", id, room.id())
+            }
+
+            let response = GameResponse::Exited { user_id: user.id };
+            match user.role {
+                Role::Host { .. } => {
+                    tracing::info!(?response, "Host exit room.");
+                    ra.publish(response);
+                    ra.close();
+                    remove = true;
+                }
+                Role::CoHost { .. } => {
+                    room.remove_co_host(&user.id).unwrap_or_default();
+
+                    tracing::info!(?response, "Co-host exit room.");
+                    ra.publish(response);
+                    ra.co_hosts.remove(&user.id);
+                }
+                Role::Spectator { .. } => {
+                    room.remove_spectator(&user.id).unwrap_or_default();
+
+                    tracing::info!(?response, "Spectator exit room.");
+                    ra.publish(response);
+                    ra.spectators.remove(&user.id);
+                }
+                Role::Moderator { .. } => {
+                    // 版主悄悄离开，不通知其他成员
+                    tracing::info!(?response, "Moderator exit room.");
+                    ra.moderators.remove(&user.id);
+                    ra.reply(user.sender.clone(), response);
+                }
+                _ => {
+                    // 游戏中途离开按弃权结束本局，已经完成的轮次照常记录
+                    if matches!(room.state(), RoomState::Started { .. }) {
+                        force_complete_game(server, ra, EarlyEnd::ContestantLeft)
+                            .unwrap_or_default();
+                    }
+                    // infallible
+                    ra.room.kick_contestant().unwrap_or_default();
+
+                    tracing::info!(?response, "Contestant exit room.");
+                    ra.publish(response);
+                    ra.contestant = None;
+                }
+            }
+            user.role = Role::Guest;
+        }
+        GameRequest::KickContestant => {
+            let contestant = room.contestant();
+            match (room.kick_contestant(), contestant) {
+                (Ok(_), Some(user_id)) => {
+                    let response = GameResponse::Exited { user_id };
+                    tracing::info!(?response, "Kick contestant.");
+                    ra.publish(response);
+                    ra.contestant = None;
+                    ra.bot = None;
+                }
+                _ => {
+                    ra.reply(
+                        user.sender.clone(),
+                        GameResponse::GameError {
+                            cause: Error::InvalidOperation,
+                        },
+                    );
+                }
+            }
+        }
+        GameRequest::PromoteCoHost { user_id } => match ra.spectators.remove(&user_id) {
+            Some(sender) => match room
+                .remove_spectator(&user_id)
+                .and_then(|_| room.add_co_host(user_id))
+            {
+                Ok(_) => {
+                    ra.co_hosts.insert(user_id, sender);
+                    let response = GameResponse::CoHostPromoted { user_id };
+                    tracing::info!(?response, "Promote co-host.");
+                    ra.publish(response);
+                }
+                Err(cause) => {
+                    // 队员不能成为副主持人，恢复观众身份
+                    room.add_spectator(user_id).unwrap_or_default();
+                    ra.spectators.insert(user_id, sender);
+                    ra.reply(user.sender.clone(), GameResponse::GameError { cause });
+                }
+            },
+            None => {
+                ra.reply(
+                    user.sender.clone(),
+                    GameResponse::GameError {
+                        cause: Error::InvalidOperation,
+                    },
+                );
+            }
+        },
+        GameRequest::AddTeam { name, captain } => {
+            let response = room
+                .add_team(name, captain)
+                .map(|_| GameResponse::TeamsUpdated {
+                    teams: room.teams().to_vec(),
+                });
+            match response {
+                Ok(response) => {
+                    tracing::info!(?response, "Add team.");
+                    ra.publish(response);
+                }
+                Err(cause) => ra.reply(user.sender.clone(), GameResponse::GameError { cause }),
+            }
+        }
+        GameRequest::JoinTeam { team } => {
+            let response = room
+                .join_team(team, user.id)
+                .map(|_| GameResponse::TeamsUpdated {
+                    teams: room.teams().to_vec(),
+                });
+            match response {
+                Ok(response) => {
+                    tracing::info!(?response, "Join team.");
+                    ra.publish(response);
+                }
+                Err(cause) => ra.reply(user.sender.clone(), GameResponse::GameError { cause }),
+            }
+        }
+        GameRequest::LeaveTeam => {
+            let response = room
+                .leave_team(&user.id)
+                .map(|_| GameResponse::TeamsUpdated {
+                    teams: room.teams().to_vec(),
+                });
+            match response {
+                Ok(response) => {
+                    tracing::info!(?response, "Leave team.");
+                    ra.publish(response);
+                }
+                Err(cause) => ra.reply(user.sender.clone(), GameResponse::GameError { cause }),
+            }
+        }
+        GameRequest::UpdateSettings { settings } => match server.rules.configure(settings) {
+            Ok(settings) => {
+                let withdrawn = room.proposed_settings().is_some();
+                match room.update_settings(settings.clone()) {
+                    Ok(true) => {
+                        let response = GameResponse::SettingsProposed { settings };
+                        tracing::info!(?response, "Propose settings.");
+                        ra.publish(response);
+                    }
+                    Ok(_) => {
+                        server.rules.attach(room);
+                        // 撤回提议时通知挑战者设置没有变化
+                        let response = GameResponse::SettingsUpdated {
+                            settings,
+                            notify: withdrawn,
+                        };
+                        tracing::info!(?response, "Update settings.");
+                        if withdrawn {
+                            ra.publish(response);
+                        } else {
+                            ra.reply(user.sender.clone(), response);
+                        }
+                    }
+                    Err(cause) => {
+                        ra.reply(user.sender.clone(), GameResponse::GameError { cause });
+                    }
+                }
+            }
+            Err(cause) => ra.reply(user.sender.clone(), GameResponse::ServerError { cause }),
+        },
+        GameRequest::AnswerSettings { accept } => match room.answer_settings(accept) {
+            Ok(settings) => {
+                server.rules.attach(room);
+                let response = if accept {
+                    GameResponse::SettingsUpdated {
+                        settings,
+                        notify: true,
+                    }
+                } else {
+                    GameResponse::SettingsRejected { settings }
+                };
+                tracing::info!(?response, "Answer settings.");
+                ra.publish(response);
+            }
+            Err(cause) => {
+                ra.reply(user.sender.clone(), GameResponse::GameError { cause });
+            }
+        },
+        GameRequest::UpdateDetails { name, description } => {
+            let name = name.map(|name| server.filter_text(user.id, TextKind::Name, &name));
+            let description = description
+                .map(|description| server.filter_text(user.id, TextKind::Chat, &description));
+            match room.update_details(name, description) {
+                Ok(_) => {
+                    let response = GameResponse::DetailsUpdated {
+                        name: room.name().map(str::to_string),
+                        description: room.description().map(str::to_string),
+                    };
+                    tracing::info!(?response, "Update details.");
+                    ra.publish(response);
+                }
+                Err(cause) => {
+                    ra.reply(user.sender.clone(), GameResponse::GameError { cause });
+                }
+            }
+        }
+        GameRequest::UpdatePacing { pacing } => {
+            ra.outbox.set_pacing(pacing);
+            let response = GameResponse::PacingUpdated { pacing };
+            tracing::info!(?response, "Update pacing.");
+            ra.publish(response);
+        }
+        GameRequest::Start {
+            prize,
+            others,
+            doors,
+        } => {
+            if let Some(response) = start_round(server, ra, prize, others, doors) {
+                ra.reply(user.sender.clone(), response);
+            }
+        }
+        GameRequest::Reveal { left, others } => reveal_door(ra, left, others),
+        GameRequest::Decline => {
+            let response = decline_offer(server, ra);
+            if let GameResponse::Decided { .. } = response {
+                ra.remember_terminal(user.id, request, response);
+            }
+        }
+        GameRequest::Undo => {
+            let response = match room.apply(Command::Undo) {
+                Ok(events) => match events.into_iter().next() {
+                    Some(RoomEvent::Undone { stage }) => GameResponse::Undone { stage },
+                    _ => GameResponse::GameError {
+                        cause: Error::Impossible,
+                    },
+                },
+                Err(cause) => GameResponse::GameError { cause },
+            };
+            tracing::info!(?response, "Undo.");
+            ra.publish(response);
+        }
+        GameRequest::AbortRound => {
+            let response = match room.apply(Command::AbortRound) {
+                Ok(events) => match events.into_iter().next() {
+                    Some(RoomEvent::RoundAborted { round }) => GameResponse::RoundAborted { round },
+                    _ => GameResponse::GameError {
+                        cause: Error::Impossible,
+                    },
+                },
+                Err(cause) => GameResponse::GameError { cause },
+            };
+            tracing::info!(?response, "Round aborted.");
+            ra.publish(response);
+        }
+        GameRequest::InviteBot { bot } => match server.bots.get(&bot) {
+            Some(bot) => match room
+                .accept_contestant(bot.id)
+                .and_then(|_| room.contestant_ready(true))
+            {
+                Ok(_) => {
+                    let contestant_id = bot.id;
+                    tracing::info!(?bot, "Invite bot.");
+                    ra.bot = Some(bot);
+                    ra.publish(GameResponse::RoomEntered { contestant_id });
+                    ra.publish(GameResponse::Ready { ready: true });
+                }
+                Err(cause) => {
+                    ra.reply(user.sender.clone(), GameResponse::GameError { cause });
+                }
+            },
+            None => {
+                let response = GameResponse::ServerError {
+                    cause: ServerError::BotNotFound { name: bot },
+                };
+                ra.reply(user.sender.clone(), response);
+            }
+        },
+        GameRequest::SetScript { script } => {
+            let response = match script.as_deref().map(HostScript::compile).transpose() {
+                Ok(script) => {
+                    let enabled = script.is_some();
+                    ra.script = script;
+                    GameResponse::ScriptUpdated { enabled }
+                }
+                Err(cause) => GameResponse::ServerError {
+                    cause: ServerError::Script {
+                        message: cause.to_string(),
+                    },
+                },
+            };
+            tracing::info!(?response, "Set script.");
+            ra.reply(user.sender.clone(), response);
+        }
+        GameRequest::Complete { kick_contestant } => {
+            let response = complete_game(server, ra, kick_contestant);
+            if let GameResponse::Completed { .. } = response {
+                ra.remember_terminal(user.id, request, response);
+            }
+            if kick_contestant {
+                ra.contestant = None;
+                ra.bot = None;
+            }
+        }
+        GameRequest::Ready { ready } => {
+            let response = room
+                .contestant_ready(ready)
+                .map(|_| GameResponse::Ready { ready })
+                .into();
+
+            tracing::info!(?ready, "Ready.");
+            ra.publish(response);
+        }
+        GameRequest::SetNonce { nonce } => {
+            let response = match (&mut ra.fairness, room.state()) {
+                (Some(fair), RoomState::Joined { .. }) if nonce.len() <= MAX_NONCE_LEN => {
+                    fair.nonce = Some(nonce.clone());
+                    GameResponse::NonceSet { nonce }
+                }
+                _ => GameResponse::GameError {
+                    cause: Error::InvalidOperation,
+                },
+            };
+            tracing::info!(?response, "Set nonce.");
+            ra.publish(response);
+        }
+        GameRequest::CommitSeed { seed } if seed.len() <= MAX_NONCE_LEN => {
+            let response = match room.apply(Command::CommitSeed { seed }) {
+                Ok(events) => match events.into_iter().next() {
+                    Some(RoomEvent::SeedCommitted { round, commitment }) => {
+                        GameResponse::SeedCommitted { round, commitment }
+                    }
+                    _ => GameResponse::GameError {
+                        cause: Error::Impossible,
+                    },
+                },
+                Err(cause) => GameResponse::GameError { cause },
+            };
+            tracing::info!(?response, "Commit seed.");
+            ra.publish(response);
+        }
+        GameRequest::SupplyNonce { nonce } if nonce.len() <= MAX_NONCE_LEN => {
+            let response = match room.apply(Command::SupplyNonce { nonce }) {
+                Ok(events) => match events.into_iter().next() {
+                    Some(RoomEvent::NonceSupplied { round, nonce }) => {
+                        GameResponse::NonceSupplied { round, nonce }
+                    }
+                    _ => GameResponse::GameError {
+                        cause: Error::Impossible,
+                    },
+                },
+                Err(cause) => GameResponse::GameError { cause },
+            };
+            tracing::info!(?response, "Supply nonce.");
+            ra.publish(response);
+        }
+        GameRequest::ShareGame { game } => {
+            let name = room.name().map(str::to_string);
+            let completed = match game {
+                Some(game) => ra.history.iter_mut().find(|completed| completed.id == game),
+                None => ra.history.back_mut(),
+            };
+            let response = match completed {
+                Some(completed) => {
+                    // 同一局游戏只生成一张卡片，卡片过期后重新生成
+                    let code = match &completed.share {
+                        Some(code) if server.shares.get(code).is_some() => code.clone(),
+                        _ => server.shares.share(name.as_deref(), &completed.result),
+                    };
+                    completed.share = Some(code.clone());
+                    GameResponse::GameShared {
+                        game: completed.id,
+                        code,
+                    }
+                }
+                None => GameResponse::ServerError {
+                    cause: ServerError::GameNotFound,
+                },
+            };
+            tracing::info!(?response, "Share game.");
+            ra.reply(user.sender.clone(), response);
+        }
+        GameRequest::Choose { chosen } => choose_door(ra, chosen),
+        GameRequest::Estimate { percent } => estimate(ra, percent),
+        GameRequest::OfferDeal { offer } => offer_deal(ra, offer),
+        GameRequest::Decide { decision } => {
+            let response = decide(server, ra, decision);
+            if let GameResponse::Decided { .. } = response {
+                ra.remember_terminal(user.id, request, response);
+            }
+        }
+        GameRequest::Chat { text } => {
+            if ra.muted.contains(&user.id) {
+                let response = GameResponse::ServerError {
+                    cause: ServerError::Muted,
+                };
+                ra.reply(user.sender.clone(), response);
+            } else {
+                let text = server.filter_text(user.id, TextKind::Chat, &text);
+                if !text.is_empty() {
+                    ra.publish(GameResponse::ChatMessage {
+                        user_id: user.id,
+                        text,
+                    });
+                }
+            }
+        }
+        GameRequest::Reaction { emoji } => {
+            if ra.muted.contains(&user.id) {
+                let response = GameResponse::ServerError {
+                    cause: ServerError::Muted,
+                };
+                ra.reply(user.sender.clone(), response);
+            } else if !Reactions::is_valid(&emoji) {
+                let response = GameResponse::ServerError {
+                    cause: ServerError::InvalidReaction,
+                };
+                ra.reply(user.sender.clone(), response);
+            } else if ra.reactions.add(user.id, emoji) {
+                let (server, room_id) = (server.clone(), *room.id());
+                tokio::spawn(async move {
+                    tokio::time::sleep(REACTION_WINDOW).await;
+                    if let Some(mut ra) = server.rooms.get_mut(&room_id) {
+                        ra.flush_reactions();
+                    }
+                });
+            }
+        }
+        request => {
+            let response = GameResponse::GameError {
+                cause: Error::InvalidOperation,
+            };
+            tracing::warn!(?request, ?user.role, "Invalid operation.");
+            ra.reply(user.sender.clone(), response);
+        }
+    }
+
+    Ok(remove)
+}
+
+/// 开始新的一轮，失败时返回需要发给操作者的消息。指定了 `others` 时 `prize` 和 `others` 就是所有奖品所在的门，
+/// 否则有多个奖品时由服务器随机放置其余奖品
+fn start_round(
+    server: &Server,
+    ra: &mut RoomAgent,
+    prize: Index,
+    others: Vec<u32>,
+    doors: Option<u32>,
+) -> Option<GameResponse> {
+    // 预留在开始失败提前返回时归还
+    let reservation = match server.quotas.reserve_round(&ra.tenant) {
+        Ok(reservation) => reservation,
+        Err(kind) => {
+            return Some(GameResponse::ServerError {
+                cause: ServerError::QuotaExceeded { kind },
+            })
+        }
+    };
+
+    // 公平模式房间的门数由设置决定，证明中记录的门数必须和设置一致
+    if doors.is_some() && ra.fairness.is_some() {
+        return Some(GameResponse::GameError {
+            cause: Error::InvalidOperation,
+        });
+    }
+    let proof = match server.fairness.place(ra, &prize, &others) {
+        Ok(proof) => proof,
+        Err(response) => return Some(*response),
+    };
+    server.random.start_round(ra, &server.audit);
+    let random = prize == Index::Random;
+    let command = match &proof {
+        Some(proof) => Command::Start {
+            prize: proof.prizes.first().copied(),
+            others: proof.prizes[1..].to_vec(),
+            doors: None,
+        },
+        None => Command::Start {
+            prize: prize.door(),
+            others,
+            doors,
+        },
+    };
+    let result = ra.room.apply(command).and_then(|_| match ra.room.state() {
+        RoomState::Started {
+            prize,
+            other_prizes,
+            doors,
+            ..
+        } => Ok((
+            GameResponse::Started {
+                prize: *prize,
+                others: other_prizes.clone(),
+                doors: *doors,
+                random,
+                announcement: None,
+            },
+            GameResponse::ContestantStarted {
+                doors: *doors,
+                random,
+                announcement: None,
+            },
+        )),
+        _ => Err(Error::InvalidOperation),
+    });
+
+    let (host_resp, contestant_resp) = match result {
+        Ok(responses) => responses,
+        Err(cause) => return Some(GameResponse::GameError { cause }),
+    };
+    tracing::info!(?host_resp, ?contestant_resp, "Start.");
+    if let (Some(fair), Some(proof)) = (&mut ra.fairness, proof) {
+        fair.record(proof);
+    }
+    reservation.commit();
+    if let RoomState::Started {
+        current_round: 0, ..
+    } = ra.room.state()
+    {
+        server.analytics.emit(AnalyticsEvent::GameStarted {
+            game: server.analytics.game_id(ra),
+            tenant: ra.tenant.clone(),
+            settings: ra.room.settings().clone(),
+        });
+    }
+    ra.publish_split(host_resp, contestant_resp);
+    None
+}
+
+/// 选择门，结果（包括错误）广播给房间成员
+fn choose_door(ra: &mut RoomAgent, chosen: Index) {
+    let random = chosen == Index::Random;
+    let response = ra
+        .room
+        .apply(Command::Choose {
+            chosen: chosen.door(),
+        })
+        .and_then(|events| match events.first() {
+            Some(RoomEvent::Chosen { chosen }) => Ok(*chosen),
+            _ => Err(Error::Impossible),
+        })
+        .map(|chosen| GameResponse::Chosen {
+            chosen,
+            random,
+            announcement: None,
+        })
+        .into();
+    tracing::info!(?response, "Choose.");
+    let chosen = matches!(response, GameResponse::Chosen { .. });
+    ra.publish(response);
+
+    // 2 个门或者留下所有门时没有揭示阶段，直接告诉所有人留下的门
+    let left = match ra.room.state() {
+        RoomState::Started {
+            stage: Stage::Decide { left, others, .. },
+            ..
+        } if chosen => Some((*left, others.clone())),
+        _ => None,
+    };
+    if let Some((left, others)) = left {
+        let response = GameResponse::Revealed {
+            left,
+            others,
+            random: false,
+            announcement: None,
+        };
+        tracing::info!(?response, "Reveal skipped.");
+        ra.publish(response);
+        request_estimate(ra);
+    }
+}
+
+/// 完成本局游戏，结果（包括错误）广播给房间成员
+fn complete_game(server: &Server, ra: &mut RoomAgent, kick_contestant: bool) -> GameResponse {
+    let response = match ra.room.complete(kick_contestant) {
+        Ok(results) => {
+            let result = server.rules.calculate(ra.room.settings(), &results);
+            server.analytics.emit(AnalyticsEvent::GameCompleted {
+                game: server.analytics.game_id(ra),
+                forced: false,
+                result: Box::new(result.clone()),
+            });
+            // 只收集真人挑战者完成的游戏
+            if ra.bot.is_none() && !ra.exhibition {
+                server.dataset.append(&results);
+            }
+            ra.record_game(result.clone(), results);
+            server.random.end_game(ra, &server.audit);
+            GameResponse::Completed {
+                result: Box::new(result),
+                proofs: ra
+                    .fairness
+                    .as_mut()
+                    .map(FairRoom::finish)
+                    .unwrap_or_default(),
+                announcement: None,
+            }
+        }
+        Err(cause) => GameResponse::GameError { cause },
+    };
+    tracing::info!(?response, %kick_contestant, "Complete.");
+    ra.publish(response.clone());
+    server.audit_rounds(ra);
+    if let GameResponse::Completed { result, .. } = &response {
+        if let Some(text) = ra.lesson.as_ref().and_then(|lesson| lesson.render(result)) {
+            ra.publish(GameResponse::LessonReport { text });
+        }
+    }
+    response
+}
+
+/// 提前结束本局游戏，只输出已经完成的轮次，结果和结束原因广播给房间成员
+fn force_complete_game(server: &Server, ra: &mut RoomAgent, reason: EarlyEnd) -> Result<()> {
+    let results = ra.room.complete_early(reason)?;
+    let result = server
+        .rules
+        .calculate(ra.room.settings(), &results)
+        .with_early_end(reason);
+    server.analytics.emit(AnalyticsEvent::GameCompleted {
+        game: server.analytics.game_id(ra),
+        forced: true,
+        result: Box::new(result.clone()),
+    });
+    ra.record_game(result.clone(), results);
+    server.random.end_game(ra, &server.audit);
+    let proofs = ra
+        .fairness
+        .as_mut()
+        .map(FairRoom::finish)
+        .unwrap_or_default();
+    ra.publish(GameResponse::Completed {
+        result: Box::new(result),
+        proofs,
+        announcement: None,
+    });
+    server.audit_rounds(ra);
+    Ok(())
+}
+
+/// 挑战者做出抉择，结果（包括错误）广播给房间成员，需要加赛时通知所有人
+fn decide(server: &Server, ra: &mut RoomAgent, decision: Decision) -> GameResponse {
+    let result = match ra.revealed_at {
+        Some(at) => ra.room.decide_with_latency(decision, at.elapsed()),
+        None => ra.room.decide(decision),
+    };
+    let response = decided(server, ra, result);
+    tracing::info!(?response, "Decide.");
+    publish_decided(ra, response.clone());
+    response
+}
+
+/// 邪恶的主持人不给改变选择的机会，本轮按挑战者的选择结束，结果和抉择一样广播
+fn decline_offer(server: &Server, ra: &mut RoomAgent) -> GameResponse {
+    let result = ra.room.decline();
+    let response = decided(server, ra, result);
+    tracing::info!(?response, "Decline.");
+    publish_decided(ra, response.clone());
+    response
+}
+
+/// 记录一轮结束的用时和统计事件
+fn decided(server: &Server, ra: &mut RoomAgent, result: Result<RoundResult>) -> GameResponse {
+    result
+        .map(|result| match ra.round_started_at.take() {
+            Some(at) => ra.room.time_round(at.elapsed()).unwrap_or(result),
+            None => result,
+        })
+        .map(|result| {
+            if let RoomState::Started { current_round, .. } = ra.room.state() {
+                server.analytics.emit(AnalyticsEvent::RoundDecided {
+                    game: server.analytics.game_id(ra),
+                    round: *current_round,
+                    result,
+                });
+            }
+            GameResponse::Decided {
+                result,
+                announcement: None,
+            }
+        })
+        .into()
+}
+
+/// 广播一轮的结果，打平时广播加赛
+fn publish_decided(ra: &mut RoomAgent, response: GameResponse) {
+    let decided = matches!(response, GameResponse::Decided { .. });
+    ra.publish(response);
+    if decided {
+        ra.revealed_at = None;
+        if let RoomState::Started { current_round, .. } = ra.room.state() {
+            if ra.room.needs_tiebreak() {
+                let round = *current_round + 1;
+                ra.publish(GameResponse::SuddenDeath {
+                    round,
+                    announcement: None,
+                });
+            }
+        }
+    }
+}
+
+/// 揭示门，结果（包括错误）广播给房间成员。指定了 `others` 时 `left` 和 `others` 就是所有留下的门，
+/// 否则需要留下多个门时由服务器随机补足。健忘的主持人打开了有奖的门时广播本轮作废
+fn reveal_door(ra: &mut RoomAgent, left: Index, others: Vec<u32>) {
+    let random = left == Index::Random;
+    let command = Command::Reveal {
+        left: left.door(),
+        others,
+    };
+    let response = ra
+        .room
+        .apply(command)
+        .and_then(|events| match (events.first(), ra.room.state()) {
+            (Some(RoomEvent::RoundVoided { result }), _) => Ok(GameResponse::RoundVoided {
+                result: *result,
+                announcement: None,
+            }),
+            (
+                _,
+                RoomState::Started {
+                    stage: Stage::Decide { left, others, .. },
+                    ..
+                },
+            ) => Ok(GameResponse::Revealed {
+                left: *left,
+                others: others.clone(),
+                random,
+                announcement: None,
+            }),
+            _ => Err(Error::InvalidOperation),
+        })
+        .into();
+
+    tracing::info!(?response, "Reveal.");
+    let revealed = matches!(response, GameResponse::Revealed { .. });
+    let voided = matches!(response, GameResponse::RoundVoided { .. });
+    ra.publish(response);
+    if revealed {
+        request_estimate(ra);
+    }
+    if voided {
+        ra.round_started_at = None;
+        if let RoomState::Started { current_round, .. } = ra.room.state() {
+            if ra.room.needs_tiebreak() {
+                let round = *current_round + 1;
+                ra.publish(GameResponse::SuddenDeath {
+                    round,
+                    announcement: None,
+                });
+            }
+        }
+    }
+}
+
+/// 测验模式下揭示之后请挑战者估计改变选择的中奖率
+fn request_estimate(ra: &mut RoomAgent) {
+    if !ra.room.settings().quiz {
+        return;
+    }
+    let question = match (&ra.lesson, ra.room.state()) {
+        (Some(lesson), RoomState::Started { current_round, .. }) => {
+            lesson.question(*current_round).map(str::to_string)
+        }
+        _ => None,
+    };
+    ra.publish(GameResponse::EstimateRequested { question });
+}
+
+/// 挑战者估计改变选择的中奖率，结果（包括错误）广播给房间成员
+fn estimate(ra: &mut RoomAgent, percent: u32) {
+    let response = ra
+        .room
+        .estimate(percent)
+        .map(|_| GameResponse::Estimated { percent })
+        .into();
+    tracing::info!(?response, "Estimate.");
+    ra.publish(response);
+}
+
+/// 主持人出价买断，结果（包括错误）广播给房间成员
+fn offer_deal(ra: &mut RoomAgent, offer: u32) {
+    let response = ra
+        .room
+        .offer_deal(offer)
+        .map(|_| GameResponse::DealOffered { offer })
+        .into();
+    tracing::info!(?response, "Offer deal.");
+    ra.publish(response);
+}
+
+/// 一个请求之后自动操作的最多轮数
+const MAX_AUTOMATED_STEPS: usize = 16;
+
+/// 房间的请求处理完之后替主持人脚本和机器人挑战者操作，它们可能互相触发，直到房间没有新的事件
+fn run_automation(server: &Server, ra: &mut RoomAgent) {
+    for _ in 0..MAX_AUTOMATED_STEPS {
+        let seq = ra.seq;
+        run_host_script(server, ra);
+        run_bot(server, ra);
+        if ra.seq == seq {
+            break;
+        }
+    }
+}
+
+/// 轮到机器人挑战者时替它准备、选择门或者做出抉择
+fn run_bot(server: &Server, ra: &mut RoomAgent) {
+    let bot = match &mut ra.bot {
+        Some(bot) => bot,
+        None => return,
+    };
+    if ra.room.proposed_settings().is_some() {
+        if let Ok(settings) = ra.room.answer_settings(true) {
+            server.rules.attach(&mut ra.room);
+            ra.publish(GameResponse::SettingsUpdated {
+                settings,
+                notify: true,
+            });
+        }
+        return;
+    }
+    if matches!(ra.room.state(), RoomState::Joined { ready: false, .. }) {
+        if ra.room.contestant_ready(true).is_ok() {
+            ra.publish(GameResponse::Ready { ready: true });
+        }
+        return;
+    }
+    let doors = ra.room.round_settings().doors;
+    match ra.room.state() {
+        RoomState::Started {
+            stage: Stage::Choose,
+            ..
+        } => {
+            let chosen = bot.strategy.choose(doors);
+            choose_door(ra, Index::Specified(chosen));
+        }
+        RoomState::Started {
+            stage: Stage::Decide { estimate: None, .. } | Stage::Deal { estimate: None, .. },
+            ..
+        } if ra.room.settings().quiz => {
+            let percent = bot.strategy.estimate(doors);
+            estimate(ra, percent);
+        }
+        // 机器人不接受买断，照常按策略抉择
+        RoomState::Started {
+            stage: Stage::Decide { chosen, left, .. } | Stage::Deal { chosen, left, .. },
+            ..
+        } => {
+            let decision = bot.strategy.decide(doors, *chosen, *left);
+            decide(server, ra, decision);
+        }
+        _ => {}
+    }
+}
+
+/// 运行房间的主持人脚本，脚本的操作和主持人自己的操作一样广播给房间成员，错误只发给主持人
+fn run_host_script(server: &Server, ra: &mut RoomAgent) {
+    let game = ra.game_number();
+    let result = match &mut ra.script {
+        Some(script) => script.run(game, &ra.room),
+        None => return,
+    };
+    let actions = match result {
+        Ok(actions) => actions,
+        Err(cause) => {
+            tracing::warn!(%cause, "Host script failed.");
+            let _ = ra.host.try_send(
+                GameResponse::ServerError {
+                    cause: ServerError::Script {
+                        message: cause.to_string(),
+                    },
+                }
+                .into(),
+            );
+            return;
+        }
+    };
+
+    let host = *ra.room.host();
+    for action in actions {
+        tracing::info!(?action, "Host script.");
+        match action {
+            ScriptAction::Start { prize } => {
+                if let Some(response) = start_round(server, ra, prize, vec![], None) {
+                    let _ = ra.host.try_send(response.into());
+                }
+            }
+            ScriptAction::Reveal { left } => reveal_door(ra, left, vec![]),
+            ScriptAction::Decline => {
+                decline_offer(server, ra);
+            }
+            ScriptAction::Chat { text } => {
+                let text = server.filter_text(host, TextKind::Chat, &text);
+                if !text.is_empty() {
+                    ra.publish(GameResponse::ChatMessage {
+                        user_id: host,
+                        text,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// 根据房间状态更新用户角色，被踢出房间时返回需要通知用户的消息
+fn refresh_role(server: &Server, user: &mut User) -> Option<GameResponse> {
+    let room_id = user.role.room_id()?;
+    let ra = server.rooms.get(&room_id)?;
+    match user.role {
+        Role::Contestant { .. } if ra.room.contestant() != Some(user.id) => {
+            tracing::error!(user = %user.id, room = %room_id, "User may be kicked out of room.");
+            user.role = Role::Guest;
+            Some(GameResponse::Exited { user_id: user.id })
+        }
+        Role::Spectator { .. } if ra.room.is_co_host(&user.id) => {
+            user.role = Role::CoHost { room_id };
+            None
+        }
+        Role::Spectator { .. } if !ra.room.is_spectator(&user.id) => {
+            tracing::error!(user = %user.id, room = %room_id, "Spectator may be kicked out of room.");
+            user.role = Role::Guest;
+            Some(GameResponse::Exited { user_id: user.id })
+        }
+        Role::CoHost { .. } if !ra.room.is_co_host(&user.id) => {
+            tracing::error!(user = %user.id, room = %room_id, "Co-host may be kicked out of room.");
+            user.role = Role::Guest;
+            Some(GameResponse::Exited { user_id: user.id })
+        }
+        _ => None,
+    }
+}
+
+async fn websocket_loop(
+    mut socket: WebSocket,
+    req_sender: Sender<ClientMessage>,
+    mut resp_receiver: Receiver<ServerMessage>,
+    mut recorder: Option<Recorder>,
+    compressor: Option<Compressor>,
+) -> anyhow::Result<()> {
+    // 监听 socket 以及 room 中其他成员广播的消息
+    loop {
+        tokio::select! {
+            option = socket.recv() => {
+                if let Some(result) = option {
+                    let message = result?;
+                    match message {
+                        Message::Text(request) => {
+                            if let Some(recorder) = &mut recorder {
+                                recorder.inbound(&request);
+                            }
+                            let message: ClientMessage = serde_json::from_str(&request)?;
+                            req_sender.send(message).await.map_err(send_error)?;
+                        }
+                        Message::Close(c) => match c {
+                            Some(c) => {
+                                tracing::info!(
+                                    "Connection closed: code = {}, reason = {}.",
+                                    c.code, c.reason
+                                );
+                                break;
+                            }
+                            None => {
+                                tracing::info!("Connection closed without close frame.",);
+                                break;
+                            }
+                        },
+                        _ => {}
+                    }
+                } else {
+                    tracing::error!("Connection closed.");
+                    break;
+                }
+            }
+            resp = resp_receiver.recv() => {
+                match resp {
+                    Some(response) => {
+                        let text = response.to_json()?.to_string();
+                        if let Some(recorder) = &mut recorder {
+                            recorder.outbound(&text);
+                        }
+                        let message = match &compressor {
+                            Some(compressor) => compressor.frame(text)?,
+                            None => Message::Text(text),
+                        };
+                        socket.send(message).await?;
+                    }
+                    None => {
+                        tracing::error!("Response channel closed.");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 收到 SIGHUP 时重新加载配置
+#[cfg(unix)]
+fn reload_on_hangup(server: Server) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(cause) => {
+            tracing::error!(%cause, "Failed to listen for SIGHUP.");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            if let Err(cause) = server.reload() {
+                tracing::error!(%cause, "Failed to reload config.");
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn reload_on_hangup(_server: Server) {}
+
+fn send_error<T>(_: T) -> anyhow::Error {
+    anyhow::anyhow!("Failed to send message: channel closed.")
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::footprint::RoomFootprint;
+
+/// 服务器运行指标
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// 请求处理 panic 后恢复的次数
+    pub supervised_restarts: AtomicU64,
+    /// 连接任务 panic 的次数
+    pub connection_panics: AtomicU64,
+    /// 被隔离的房间数
+    pub quarantined_rooms: AtomicU64,
+    /// 收到的废弃请求数
+    pub deprecated_requests: AtomicU64,
+    /// 重放核对的轮次数
+    pub round_audits: AtomicU64,
+    /// 重放结果与记录不一致的轮次数
+    pub round_audit_mismatches: AtomicU64,
+    /// 当前的 WebSocket 连接数
+    pub connections: AtomicU64,
+}
+
+impl Metrics {
+    /// Prometheus 文本格式，`rooms` 是每个租户的房间数，`footprints` 是每个房间占用的内存
+    pub fn render(&self, rooms: &BTreeMap<String, usize>, footprints: &[RoomFootprint]) -> String {
+        let mut text = String::new();
+        let counters = [
+            (
+                "ndoors_supervised_restarts_total",
+                &self.supervised_restarts,
+            ),
+            ("ndoors_connection_panics_total", &self.connection_panics),
+            ("ndoors_quarantined_rooms_total", &self.quarantined_rooms),
+            (
+                "ndoors_deprecated_requests_total",
+                &self.deprecated_requests,
+            ),
+            ("ndoors_round_audits_total", &self.round_audits),
+            (
+                "ndoors_round_audit_mismatches_total",
+                &self.round_audit_mismatches,
+            ),
+        ];
+        for (name, counter) in counters {
+            let _ = writeln!(text, "# TYPE {} counter", name);
+            let _ = writeln!(text, "{} {}", name, counter.load(Ordering::Relaxed));
+        }
+        let _ = writeln!(text, "# TYPE ndoors_rooms gauge");
+        for (tenant, count) in rooms {
+            let _ = writeln!(text, "ndoors_rooms{{tenant={:?}}} {}", tenant, count);
+        }
+        let _ = writeln!(text, "# TYPE ndoors_connections gauge");
+        let _ = writeln!(
+            text,
+            "ndoors_connections {}",
+            self.connections.load(Ordering::Relaxed)
+        );
+        let largest = footprints.iter().map(|footprint| footprint.bytes).max();
+        let _ = writeln!(text, "# TYPE ndoors_largest_room_bytes gauge");
+        let _ = writeln!(text, "ndoors_largest_room_bytes {}", largest.unwrap_or(0));
+        let oversized = footprints
+            .iter()
+            .filter(|footprint| !footprint.warnings.is_empty())
+            .count();
+        let _ = writeln!(text, "# TYPE ndoors_oversized_rooms gauge");
+        let _ = writeln!(text, "ndoors_oversized_rooms {}", oversized);
+        text
+    }
+}
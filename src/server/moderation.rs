@@ -0,0 +1,102 @@
+use ndoors::*;
+
+use crate::permission::Role;
+use crate::protocol::*;
+use crate::room::*;
+use crate::{force_complete_game, send_error, Server, User};
+
+/// 处理版主的管理操作，目标房间不要求是版主所在的房间
+pub async fn moderate(
+    server: &Server,
+    user: &mut User,
+    room_id: Uuid,
+    command: Moderation,
+) -> anyhow::Result<()> {
+    let mut ra = match server.rooms.get_mut(&room_id) {
+        Some(ra) => ra,
+        None => {
+            let response = GameResponse::ServerError {
+                cause: ServerError::RoomNotFound { id: room_id },
+            };
+            user.sender
+                .send(response.into())
+                .await
+                .map_err(send_error)?;
+            return Ok(());
+        }
+    };
+
+    let result = match &command {
+        Moderation::Watch => watch(user, &mut ra),
+        Moderation::Mute { user_id } => {
+            ra.muted.insert(*user_id);
+            ra.publish(GameResponse::Muted {
+                user_id: *user_id,
+                muted: true,
+            });
+            Ok(())
+        }
+        Moderation::Unmute { user_id } => {
+            ra.muted.remove(user_id);
+            ra.publish(GameResponse::Muted {
+                user_id: *user_id,
+                muted: false,
+            });
+            Ok(())
+        }
+        Moderation::Kick { user_id } => kick(&mut ra, *user_id),
+        Moderation::ForceComplete => force_complete_game(server, &mut ra, EarlyEnd::Forced),
+    };
+
+    let response = match result {
+        Ok(_) => match command {
+            Moderation::Watch => GameResponse::Watching {
+                info: RoomInfo::from(&*ra),
+            },
+            command => GameResponse::Moderated { room_id, command },
+        },
+        Err(cause) => GameResponse::GameError { cause },
+    };
+    tracing::info!(%room_id, ?response, "Moderate.");
+    user.sender
+        .send(response.into())
+        .await
+        .map_err(send_error)?;
+    Ok(())
+}
+
+/// 悄悄进入房间，只有不在任何房间中的版主才能旁观
+fn watch(user: &mut User, ra: &mut RoomAgent) -> Result<()> {
+    match user.role {
+        Role::Guest => {
+            ra.moderators.insert(user.id, user.sender.clone());
+            user.role = Role::Moderator {
+                room_id: *ra.room.id(),
+            };
+            Ok(())
+        }
+        _ => Err(Error::InvalidOperation),
+    }
+}
+
+/// 踢出挑战者、副主持人或观众，主持人不能被踢出
+fn kick(ra: &mut RoomAgent, user_id: Uuid) -> Result<()> {
+    let response = GameResponse::Exited { user_id };
+    if ra.room.contestant() == Some(user_id) {
+        ra.room.kick_contestant()?;
+        ra.publish(response);
+        ra.contestant = None;
+        ra.bot = None;
+    } else if ra.room.is_co_host(&user_id) {
+        ra.room.remove_co_host(&user_id)?;
+        ra.publish(response);
+        ra.co_hosts.remove(&user_id);
+    } else if ra.room.is_spectator(&user_id) {
+        ra.room.remove_spectator(&user_id)?;
+        ra.publish(response);
+        ra.spectators.remove(&user_id);
+    } else {
+        return Err(Error::InvalidOperation);
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use ndoors::{Decision, Room, RoomState, Stage};
+
+use crate::protocol::GameResponse;
+
+/// 门的编号从 1 开始，和界面上显示的一致
+fn door(index: u32) -> u32 {
+    index + 1
+}
+
+/// 把门编号连接成 `2, 4 and 5`
+fn doors(indexes: &[u32]) -> String {
+    let names: Vec<String> = indexes
+        .iter()
+        .map(|index| door(*index).to_string())
+        .collect();
+    match names.split_last() {
+        None => String::new(),
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+/// `door 3` 或者 `doors 1 and 3`
+fn doors_phrase(indexes: &[u32]) -> String {
+    match indexes {
+        [index] => format!("door {}", door(*index)),
+        indexes => format!("doors {}", doors(indexes)),
+    }
+}
+
+/// 当前轮次的描述，`Round 2 of 10`、`Sudden death round 11` 或者无尽模式的 `Round 12`
+fn round(room: &Room) -> String {
+    let rounds = room.settings().rounds;
+    match room.state() {
+        RoomState::Started { current_round, .. } if room.settings().endless => {
+            format!("Round {}", current_round + 1)
+        }
+        RoomState::Started { current_round, .. } if *current_round >= rounds => {
+            format!("Sudden death round {}", current_round + 1)
+        }
+        RoomState::Started { current_round, .. } => {
+            format!("Round {} of {}", current_round + 1, rounds)
+        }
+        _ => "Round".to_string(),
+    }
+}
+
+/// 广播前为响应加上旁白
+pub trait Narrate {
+    fn narrate(&mut self, room: &Room);
+}
+
+impl Narrate for GameResponse {
+    /// 根据广播时（状态已经改变之后）的房间状态生成供屏幕阅读器朗读的描述，
+    /// 客户端不需要自己实现游戏的旁白
+    fn narrate(&mut self, room: &Room) {
+        let doors_count = room.round_settings().doors;
+        let text = match self {
+            GameResponse::Started { prize, others, .. } if others.is_empty() => format!(
+                "{} started with {} doors. The prize is behind door {}.",
+                round(room),
+                doors_count,
+                door(*prize)
+            ),
+            GameResponse::Started { prize, others, .. } => {
+                let mut prizes = vec![*prize];
+                prizes.extend(others.iter());
+                format!(
+                    "{} started with {} doors. The prizes are behind {}.",
+                    round(room),
+                    doors_count,
+                    doors_phrase(&prizes)
+                )
+            }
+            GameResponse::ContestantStarted { .. } => format!(
+                "{} started with {} doors. Choose a door.",
+                round(room),
+                doors_count
+            ),
+            GameResponse::Chosen { chosen, .. } => {
+                format!("The contestant chose door {}.", door(*chosen))
+            }
+            GameResponse::Revealed { left, others, .. } => {
+                let chosen = match room.state() {
+                    RoomState::Started {
+                        stage: Stage::Decide { chosen, .. },
+                        ..
+                    } => *chosen,
+                    _ => return,
+                };
+                let mut remaining = vec![chosen, *left];
+                remaining.extend(others.iter());
+                remaining.sort_unstable();
+                let opened: Vec<u32> = (0..doors_count)
+                    .filter(|index| !remaining.contains(index))
+                    .collect();
+                let remain = format!("{} remain", doors_phrase(&remaining));
+                if opened.is_empty() {
+                    format!("{}.", capitalize(&remain))
+                } else {
+                    format!("The host opened {}; {}.", doors_phrase(&opened), remain)
+                }
+            }
+            GameResponse::Decided { result, .. } if !result.offered() => {
+                let outcome = if result.win() { "won" } else { "lost" };
+                format!(
+                    "The host offered no switch. The contestant {} with door {}. The prize was behind door {}.",
+                    outcome,
+                    door(result.chosen()),
+                    door(result.prize())
+                )
+            }
+            GameResponse::DealOffered { offer } => {
+                format!("The host offers {} to walk away from the doors.", offer)
+            }
+            GameResponse::Decided { result, .. } => {
+                let action = match result.decision() {
+                    Decision::TakeDeal(offer) => format!("took the deal of {}", offer),
+                    Decision::Stick => format!("stayed with door {}", door(result.chosen())),
+                    _ => format!("switched to door {}", door(result.left())),
+                };
+                let outcome = if result.win() { "won" } else { "lost" };
+                format!(
+                    "The contestant {} and {}. The prize was behind door {}.",
+                    action,
+                    outcome,
+                    door(result.prize())
+                )
+            }
+            GameResponse::RoundVoided { result, .. } => format!(
+                "The host opened door {} and revealed the prize. This round is void.",
+                door(result.prize())
+            ),
+            GameResponse::SuddenDeath { round, .. } => format!(
+                "The game is tied. Sudden death round {} decides it.",
+                *round + 1
+            ),
+            GameResponse::Completed { result, .. } => format!(
+                "Game over. The contestant won {} of {} rounds.",
+                result.win(),
+                result.stats().rounds()
+            ),
+            _ => return,
+        };
+        if let Some(announcement) = announcement_mut(self) {
+            *announcement = Some(text);
+        }
+    }
+}
+
+fn announcement_mut(response: &mut GameResponse) -> Option<&mut Option<String>> {
+    match response {
+        GameResponse::Started { announcement, .. }
+        | GameResponse::ContestantStarted { announcement, .. }
+        | GameResponse::Chosen { announcement, .. }
+        | GameResponse::Revealed { announcement, .. }
+        | GameResponse::Decided { announcement, .. }
+        | GameResponse::RoundVoided { announcement, .. }
+        | GameResponse::SuddenDeath { announcement, .. }
+        | GameResponse::Completed { announcement, .. } => Some(announcement),
+        _ => None,
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
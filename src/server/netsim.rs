@@ -0,0 +1,98 @@
+use ndoors::Uuid;
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::{BTreeMap, VecDeque};
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::time::{Duration, Instant};
+
+use crate::protocol::ServerMessage;
+
+/// 调试用的网络模拟，只作用于列出的测试用户发往客户端的消息
+#[derive(Debug, Deserialize, Clone)]
+pub struct NetworkSimulation {
+    /// 受影响的用户
+    pub users: Vec<Uuid>,
+    /// 每条消息的延迟在 `min_latency_ms` 到 `max_latency_ms` 之间随机
+    #[serde(default)]
+    pub min_latency_ms: u64,
+    #[serde(default)]
+    pub max_latency_ms: u64,
+    /// 一条消息最多被之后的多少条消息超过，0 表示保持顺序
+    #[serde(default)]
+    pub reorder_window: usize,
+    /// 每条消息发送前断开连接的概率
+    #[serde(default)]
+    pub disconnect_probability: f64,
+}
+
+impl NetworkSimulation {
+    pub fn applies_to(&self, user_id: &Uuid) -> bool {
+        self.users.contains(user_id)
+    }
+
+    fn latency(&self) -> Duration {
+        let max = self.max_latency_ms.max(self.min_latency_ms);
+        Duration::from_millis(rand::thread_rng().gen_range(self.min_latency_ms..=max))
+    }
+
+    fn disconnect(&self) -> bool {
+        self.disconnect_probability > 0.0
+            && rand::thread_rng().gen_bool(self.disconnect_probability.min(1.0))
+    }
+
+    /// 在响应通道和 WebSocket 之间插入模拟链路，返回的通道关闭时连接随之断开
+    pub fn link(&self, mut input: Receiver<ServerMessage>) -> Receiver<ServerMessage> {
+        let (output, receiver) = channel(16);
+        let simulation = self.clone();
+        tokio::spawn(async move {
+            // 按发送时间排序，序号保证同一时刻的消息保持原顺序
+            let mut pending = BTreeMap::new();
+            let mut recent = VecDeque::new();
+            let mut floor = Instant::now();
+            let mut seq = 0u64;
+            loop {
+                let next = pending
+                    .keys()
+                    .next()
+                    .map(|(at, _)| *at)
+                    .unwrap_or_else(|| Instant::now() + Duration::from_secs(3600));
+                tokio::select! {
+                    received = input.recv() => {
+                        match received {
+                            Some(response) => {
+                                if simulation.disconnect() {
+                                    tracing::warn!("Simulated disconnect.");
+                                    return;
+                                }
+                                // 不能早于已经移出重排窗口的消息
+                                while recent.len() > simulation.reorder_window {
+                                    floor = floor.max(recent.pop_front().unwrap_or(floor));
+                                }
+                                let at = (Instant::now() + simulation.latency()).max(floor);
+                                recent.push_back(at);
+                                pending.insert((at, seq), response);
+                                seq += 1;
+                            }
+                            None => break,
+                        }
+                    }
+                    _ = tokio::time::sleep_until(next) => {
+                        if let Some((_, response)) = pending.pop_first() {
+                            if output.send(response).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+
+            for ((at, _), response) in pending {
+                tokio::time::sleep_until(at).await;
+                if output.send(response).await.is_err() {
+                    return;
+                }
+            }
+        });
+        receiver
+    }
+}
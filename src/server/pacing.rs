@@ -0,0 +1,117 @@
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{unbounded_channel, Sender, UnboundedSender};
+use tokio::time::Instant;
+
+use crate::protocol::{GameResponse, ServerMessage};
+use crate::timestamp;
+
+pub use ndoors::protocol::Pacing;
+
+#[derive(Debug)]
+struct Delivery {
+    at: Instant,
+    recipients: Vec<Sender<ServerMessage>>,
+    response: GameResponse,
+    /// 本局游戏开始的时间，用来计算游戏时钟
+    game_started: Option<Instant>,
+}
+
+/// 房间的广播调度器
+///
+/// 所有广播按投递时间依次进入同一个队列，由单独的任务按顺序发送，
+/// 因此延迟发送的消息不会被后面的消息超过。房间内对单个成员的回复也经过这个队列，
+/// 每个成员看到的广播和回复的相对顺序与房间处理它们的顺序一致，
+/// 同时收到的成员看到的广播顺序也相同。
+///
+/// 发送不等待接收者：成员的发送缓冲已满（例如网络很慢的观众）时丢弃发给这个成员的消息，
+/// 不会让一个跟不上的成员拖住整个房间。丢失消息的客户端可以重新连接并恢复会话，按房间信息重新同步
+#[derive(Debug)]
+pub struct Outbox {
+    pacing: Pacing,
+    next: Instant,
+    queue: UnboundedSender<Delivery>,
+}
+
+impl Outbox {
+    pub fn new(pacing: Pacing) -> Self {
+        let (queue, mut receiver) = unbounded_channel::<Delivery>();
+        tokio::spawn(async move {
+            while let Some(delivery) = receiver.recv().await {
+                tokio::time::sleep_until(delivery.at).await;
+                let mut message = ServerMessage {
+                    response: delivery.response,
+                    server_time: timestamp(),
+                    game_clock: delivery.game_started.map(|started| {
+                        Instant::now()
+                            .saturating_duration_since(started)
+                            .as_millis() as u64
+                    }),
+                    encoded: None,
+                };
+                // 多个成员共享同一份序列化结果，观众很多的房间不用为每个成员重新序列化
+                if delivery.recipients.len() > 1 {
+                    match message.to_json() {
+                        Ok(encoded) => message.encoded = Some(encoded),
+                        Err(cause) => tracing::error!(%cause, "Failed to encode response."),
+                    }
+                }
+                for recipient in delivery.recipients {
+                    match recipient.try_send(message.clone()) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            tracing::warn!(response = ?message.response, "Recipient lagging, response dropped.")
+                        }
+                        Err(TrySendError::Closed(_)) => {
+                            tracing::warn!(response = ?message.response, "Recipient gone, response dropped.")
+                        }
+                    }
+                }
+            }
+        });
+
+        Self {
+            pacing,
+            next: Instant::now(),
+            queue,
+        }
+    }
+
+    pub fn pacing(&self) -> Pacing {
+        self.pacing
+    }
+
+    pub fn set_pacing(&mut self, pacing: Pacing) {
+        self.pacing = pacing;
+    }
+
+    /// 计算下一条消息的投递时间，阶段变化会把之后的阶段变化往后推
+    pub fn schedule(&mut self, transition: bool) -> Instant {
+        let at = self.next.max(Instant::now());
+        if transition {
+            self.next = at + self.pacing.delay();
+        }
+        at
+    }
+
+    /// 在指定时间把消息投递给所有接收者，投递时附上服务器时间和从 `game_started` 开始的游戏时钟
+    pub fn deliver(
+        &self,
+        at: Instant,
+        recipients: Vec<Sender<ServerMessage>>,
+        response: GameResponse,
+        game_started: Option<Instant>,
+    ) {
+        if self
+            .queue
+            .send(Delivery {
+                at,
+                recipients,
+                response,
+                game_started,
+            })
+            .is_err()
+        {
+            tracing::error!("Outbox closed.");
+        }
+    }
+}
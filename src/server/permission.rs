@@ -0,0 +1,116 @@
+use ndoors::Uuid;
+
+pub use ndoors::protocol::Action;
+
+/// 用户在服务器中的角色
+#[derive(Debug, Copy, Clone)]
+pub enum Role {
+    Guest,
+    Host {
+        room_id: Uuid,
+    },
+    CoHost {
+        room_id: Uuid,
+    },
+    Contestant {
+        room_id: Uuid,
+    },
+    Spectator {
+        room_id: Uuid,
+    },
+    /// 悄悄旁观房间的版主
+    Moderator {
+        room_id: Uuid,
+    },
+}
+
+impl Role {
+    /// 角色允许执行的操作
+    pub fn permissions(&self) -> &'static [Action] {
+        match self {
+            Role::Guest => &[
+                Action::ListRooms,
+                Action::CreateRoom,
+                Action::EnterRoom,
+                Action::Spectate,
+                Action::RegisterDevice,
+            ],
+            Role::Host { .. } => &[
+                Action::ListRooms,
+                Action::ExitRoom,
+                Action::UpdateSettings,
+                Action::UpdateDetails,
+                Action::UpdatePacing,
+                Action::Start,
+                Action::Reveal,
+                Action::Undo,
+                Action::AbortRound,
+                Action::Complete,
+                Action::Kick,
+                Action::PromoteCoHost,
+                Action::ManageTeams,
+                Action::Chat,
+                Action::SetScript,
+                Action::InviteBot,
+                Action::RegisterDevice,
+                Action::CommitSeed,
+                Action::ShareGame,
+            ],
+            Role::CoHost { .. } => &[
+                Action::ListRooms,
+                Action::ExitRoom,
+                Action::Start,
+                Action::Reveal,
+                Action::Undo,
+                Action::AbortRound,
+                Action::Chat,
+                Action::RegisterDevice,
+                Action::CommitSeed,
+                Action::ShareGame,
+            ],
+            Role::Contestant { .. } => &[
+                Action::ListRooms,
+                Action::ExitRoom,
+                Action::Ready,
+                Action::Choose,
+                Action::Decide,
+                Action::Estimate,
+                Action::AnswerSettings,
+                Action::JoinTeam,
+                Action::Chat,
+                Action::RegisterDevice,
+                Action::SetNonce,
+                Action::SupplyNonce,
+                Action::ShareGame,
+            ],
+            Role::Spectator { .. } => &[
+                Action::ListRooms,
+                Action::ExitRoom,
+                Action::JoinTeam,
+                Action::Chat,
+                Action::Reaction,
+                Action::RegisterDevice,
+                Action::ShareGame,
+            ],
+            Role::Moderator { .. } => {
+                &[Action::ListRooms, Action::ExitRoom, Action::RegisterDevice]
+            }
+        }
+    }
+
+    pub fn can(&self, action: Action) -> bool {
+        self.permissions().contains(&action)
+    }
+
+    /// 角色所在的房间
+    pub fn room_id(&self) -> Option<Uuid> {
+        match self {
+            Role::Guest => None,
+            Role::Host { room_id }
+            | Role::CoHost { room_id }
+            | Role::Contestant { room_id }
+            | Role::Spectator { room_id }
+            | Role::Moderator { room_id } => Some(*room_id),
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub use ndoors::protocol::{
+    ClientMessage, GameRequest, GameResponse, Index, Moderation, ServerError, ServerMessage,
+};
@@ -0,0 +1,312 @@
+use dashmap::DashMap;
+use ndoors::protocol::{Device, Platform};
+use ndoors::{RoomState, Uuid};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::sync::Arc;
+use tokio::time::{Duration, Instant};
+
+use crate::protocol::GameResponse;
+use crate::room::RoomAgent;
+
+/// 轮到用户的操作
+#[derive(Debug, Serialize, Copy, Clone, Eq, PartialEq)]
+pub enum Turn {
+    /// 挑战者已准备，主持人可以开始
+    Start,
+    /// 挑战者已选择，主持人需要揭示
+    Reveal,
+    /// 新的一轮开始，挑战者需要选择
+    Choose,
+    /// 测验模式下挑战者需要估计中奖率
+    Estimate,
+    /// 主持人已揭示，挑战者需要抉择
+    Decide,
+}
+
+/// 推送的通知
+#[derive(Debug, Serialize)]
+pub struct Notification {
+    pub room_id: Uuid,
+    pub turn: Turn,
+    pub title: String,
+    pub body: String,
+}
+
+impl Notification {
+    fn new(room_id: Uuid, turn: Turn) -> Self {
+        let body = match turn {
+            Turn::Start => "The contestant is ready. Start the round.",
+            Turn::Reveal => "The contestant has chosen. Open the other doors.",
+            Turn::Choose => "A new round has started. Choose a door.",
+            Turn::Estimate => "How likely is switching to win? Give your estimate.",
+            Turn::Decide => "The host has opened the doors. Switch or stick?",
+        };
+        Self {
+            room_id,
+            turn,
+            title: "Your turn".to_string(),
+            body: body.to_string(),
+        }
+    }
+}
+
+/// 推送通知的发送方式，部署时可以实现直接调用 APNs 或 FCM 的发送方式
+pub trait PushSender: Debug + Send + Sync {
+    fn send(&self, device: &Device, notification: &Notification);
+}
+
+/// 输出到 `push` target，用于调试
+#[derive(Debug)]
+pub struct LogSender;
+
+impl PushSender for LogSender {
+    fn send(&self, device: &Device, notification: &Notification) {
+        tracing::info!(target: "push", ?device, ?notification, "Push.");
+    }
+}
+
+/// 为每个通知运行一次外部程序，参数依次是平台（`Apns` 或 `Fcm`）、设备令牌和通知的 JSON
+#[derive(Debug)]
+pub struct CommandSender {
+    program: String,
+    args: Vec<String>,
+}
+
+impl PushSender for CommandSender {
+    fn send(&self, device: &Device, notification: &Notification) {
+        let json = match serde_json::to_string(notification) {
+            Ok(json) => json,
+            Err(cause) => {
+                tracing::error!(%cause, "Failed to serialize notification.");
+                return;
+            }
+        };
+        let mut command = tokio::process::Command::new(&self.program);
+        command
+            .args(&self.args)
+            .arg(format!("{:?}", device.platform))
+            .arg(&device.token)
+            .arg(json)
+            .kill_on_drop(true);
+        tokio::spawn(async move {
+            match command.status().await {
+                Ok(status) if status.success() => {}
+                Ok(status) => tracing::error!(%status, "Push command failed."),
+                Err(cause) => tracing::error!(%cause, "Failed to run push command."),
+            }
+        });
+    }
+}
+
+/// 发送到 [gorush](https://github.com/appleboy/gorush) 兼容的推送网关，
+/// 由网关持有 APNs 证书和 FCM 凭据
+#[derive(Debug)]
+pub struct GatewaySender {
+    client: hyper::Client<hyper::client::HttpConnector>,
+    uri: hyper::Uri,
+}
+
+impl GatewaySender {
+    async fn post(
+        client: hyper::Client<hyper::client::HttpConnector>,
+        uri: hyper::Uri,
+        json: String,
+    ) -> anyhow::Result<()> {
+        let request = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri(uri)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(hyper::Body::from(json))?;
+        let response = client.request(request).await?;
+        if !response.status().is_success() {
+            anyhow::bail!("Push gateway responded {}.", response.status());
+        }
+        Ok(())
+    }
+}
+
+impl PushSender for GatewaySender {
+    fn send(&self, device: &Device, notification: &Notification) {
+        // gorush 的平台编号：1 是 iOS，2 是 Android
+        let platform = match device.platform {
+            Platform::Apns => 1,
+            Platform::Fcm => 2,
+        };
+        let body = serde_json::json!({
+            "notifications": [{
+                "tokens": [device.token],
+                "platform": platform,
+                "title": notification.title,
+                "message": notification.body,
+                "data": {
+                    "room_id": notification.room_id,
+                    "turn": notification.turn,
+                },
+            }]
+        });
+        let json = body.to_string();
+        let (client, uri) = (self.client.clone(), self.uri.clone());
+        tokio::spawn(async move {
+            if let Err(cause) = GatewaySender::post(client, uri, json).await {
+                tracing::error!(%cause, "Failed to send push notification.");
+            }
+        });
+    }
+}
+
+/// 推送通知的发送方式配置
+#[derive(Debug, Deserialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PushSenderConfig {
+    /// 不推送（默认）
+    #[default]
+    Disabled,
+    Log,
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    Gateway {
+        url: String,
+    },
+}
+
+/// 推送通知配置
+#[derive(Debug, Deserialize)]
+pub struct PushConfig {
+    #[serde(default)]
+    pub sender: PushSenderConfig,
+    /// 已连接的用户超过这么多秒没有发送请求时也推送，默认 60 秒
+    #[serde(default = "default_idle_secs")]
+    pub idle_secs: u64,
+}
+
+fn default_idle_secs() -> u64 {
+    60
+}
+
+impl Default for PushConfig {
+    fn default() -> Self {
+        Self {
+            sender: PushSenderConfig::default(),
+            idle_secs: default_idle_secs(),
+        }
+    }
+}
+
+/// 轮到用户操作、而用户已经断开连接或者长时间没有操作时，向用户注册的设备推送通知，
+/// 这样一局游戏可以持续几个小时甚至几天
+#[derive(Debug)]
+pub struct Notifier {
+    sender: Option<Arc<dyn PushSender>>,
+    idle: Duration,
+    devices: DashMap<Uuid, Device>,
+    /// 已连接用户最近一次请求的时间，断开连接的用户不在其中
+    active: DashMap<Uuid, Instant>,
+}
+
+impl Notifier {
+    pub fn new(config: PushConfig) -> anyhow::Result<Self> {
+        let sender: Option<Arc<dyn PushSender>> = match config.sender {
+            PushSenderConfig::Disabled => None,
+            PushSenderConfig::Log => Some(Arc::new(LogSender)),
+            PushSenderConfig::Command { program, args } => {
+                Some(Arc::new(CommandSender { program, args }))
+            }
+            PushSenderConfig::Gateway { url } => Some(Arc::new(GatewaySender {
+                client: hyper::Client::new(),
+                uri: url.parse()?,
+            })),
+        };
+        Ok(Self {
+            sender,
+            idle: Duration::from_secs(config.idle_secs),
+            devices: DashMap::new(),
+            active: DashMap::new(),
+        })
+    }
+
+    /// 注册或者移除用户的推送设备，返回是否已注册，没有配置推送时不会注册
+    pub fn register(&self, user_id: Uuid, device: Option<Device>) -> bool {
+        match device {
+            Some(device) if self.sender.is_some() => {
+                self.devices.insert(user_id, device);
+                true
+            }
+            _ => {
+                self.devices.remove(&user_id);
+                false
+            }
+        }
+    }
+
+    /// 用户连接或者发送了请求
+    pub fn active(&self, user_id: Uuid) {
+        self.active.insert(user_id, Instant::now());
+    }
+
+    /// 用户断开了连接
+    pub fn disconnected(&self, user_id: Uuid) {
+        self.active.remove(&user_id);
+    }
+
+    /// 会话结束，不会再恢复
+    pub fn forget(&self, user_id: Uuid) {
+        self.active.remove(&user_id);
+        self.devices.remove(&user_id);
+    }
+
+    fn is_away(&self, user_id: &Uuid) -> bool {
+        match self.active.get(user_id) {
+            Some(at) => at.elapsed() >= self.idle,
+            None => true,
+        }
+    }
+
+    /// 房间广播之后检查是否轮到了某个真人成员
+    pub fn notify(&self, ra: &RoomAgent, response: &GameResponse) {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return,
+        };
+        if ra.exhibition {
+            return;
+        }
+        let (user_id, turn) = match turn_of(ra, response) {
+            Some(turn) => turn,
+            None => return,
+        };
+        if !self.is_away(&user_id) {
+            return;
+        }
+        if let Some(device) = self.devices.get(&user_id) {
+            tracing::info!(user = %user_id, room = %ra.room.id(), ?turn, "Turn notification.");
+            sender.send(&device, &Notification::new(*ra.room.id(), turn));
+        }
+    }
+}
+
+/// 广播的事件是否让轮到某个成员操作，脚本和机器人代替的成员不需要通知
+fn turn_of(ra: &RoomAgent, response: &GameResponse) -> Option<(Uuid, Turn)> {
+    let host = *ra.room.host();
+    let contestant = match ra.room.state() {
+        RoomState::Joined { contestant, .. } | RoomState::Started { contestant, .. } => {
+            Some(*contestant).filter(|_| ra.bot.is_none())
+        }
+        RoomState::Created => None,
+    };
+    let host = Some(host).filter(|_| ra.script.is_none());
+    match response {
+        GameResponse::Ready { ready: true } => host.map(|id| (id, Turn::Start)),
+        GameResponse::Chosen { .. } => host.map(|id| (id, Turn::Reveal)),
+        GameResponse::ContestantStarted { .. } => contestant.map(|id| (id, Turn::Choose)),
+        GameResponse::EstimateRequested { .. } => contestant.map(|id| (id, Turn::Estimate)),
+        // 测验模式下紧接着会请求估计
+        GameResponse::Revealed { .. } if !ra.room.settings().quiz => {
+            contestant.map(|id| (id, Turn::Decide))
+        }
+        _ => None,
+    }
+}
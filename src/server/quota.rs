@@ -0,0 +1,334 @@
+use ndoors::protocol::QuotaKind;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+use crate::timestamp;
+
+/// 一天的毫秒数，每日用量按 UTC 日期重置
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+/// 外部程序对同一个事件（租户、配额项、上限和事件类型都相同）最多每分钟运行一次
+const COMMAND_INTERVAL: u64 = 60 * 1000;
+
+/// 租户的配额，未设置的项不限制
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default, ToSchema)]
+pub struct Quota {
+    /// 同时存在的房间数上限
+    #[serde(default)]
+    pub max_rooms: Option<u32>,
+    /// 每天开始的轮数上限
+    #[serde(default)]
+    pub max_rounds_per_day: Option<u64>,
+}
+
+/// 配额事件
+#[derive(Debug, Serialize)]
+#[serde(tag = "event")]
+pub enum QuotaEvent {
+    /// 用量刚好达到上限，之后的请求会被拒绝
+    Reached {
+        tenant: String,
+        kind: QuotaKind,
+        limit: u64,
+    },
+    /// 请求因为超出配额被拒绝
+    Exceeded {
+        tenant: String,
+        kind: QuotaKind,
+        limit: u64,
+    },
+}
+
+/// 配额事件的处理方式，托管部署可以据此计费或通知租户
+pub trait QuotaHook: Debug + Send + Sync {
+    fn notify(&self, event: &QuotaEvent);
+}
+
+/// 输出到 `quota` target（默认）
+#[derive(Debug)]
+pub struct LogHook;
+
+impl QuotaHook for LogHook {
+    fn notify(&self, event: &QuotaEvent) {
+        tracing::warn!(target: "quota", ?event, "Quota.");
+    }
+}
+
+/// 为事件运行外部程序，事件的 JSON 作为最后一个参数。被拒绝的请求可能很多，
+/// 同一个事件在 [`COMMAND_INTERVAL`] 内只运行一次程序，所有事件都会记录日志
+#[derive(Debug)]
+pub struct CommandHook {
+    program: String,
+    args: Vec<String>,
+    /// 每个事件的 JSON 上次运行程序的时间
+    last_run: Mutex<HashMap<String, u64>>,
+}
+
+impl QuotaHook for CommandHook {
+    fn notify(&self, event: &QuotaEvent) {
+        LogHook.notify(event);
+        let json = match serde_json::to_string(event) {
+            Ok(json) => json,
+            Err(cause) => {
+                tracing::error!(%cause, "Failed to serialize quota event.");
+                return;
+            }
+        };
+        let now = timestamp();
+        {
+            let mut last_run = self.last_run.lock().unwrap();
+            if let Some(last) = last_run.get(&json) {
+                if now < last + COMMAND_INTERVAL {
+                    return;
+                }
+            }
+            last_run.insert(json.clone(), now);
+        }
+        let mut command = tokio::process::Command::new(&self.program);
+        command.args(&self.args).arg(json).kill_on_drop(true);
+        tokio::spawn(async move {
+            match command.status().await {
+                Ok(status) if status.success() => {}
+                Ok(status) => tracing::error!(%status, "Quota hook failed."),
+                Err(cause) => tracing::error!(%cause, "Failed to run quota hook."),
+            }
+        });
+    }
+}
+
+/// 配额事件处理配置
+#[derive(Debug, Deserialize, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuotaHookConfig {
+    #[default]
+    Log,
+    Command {
+        program: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+impl QuotaHookConfig {
+    pub fn build(self) -> Arc<dyn QuotaHook> {
+        match self {
+            QuotaHookConfig::Log => Arc::new(LogHook),
+            QuotaHookConfig::Command { program, args } => Arc::new(CommandHook {
+                program,
+                args,
+                last_run: Default::default(),
+            }),
+        }
+    }
+}
+
+/// 租户当天的用量
+#[derive(Debug, Default)]
+struct Usage {
+    day: u64,
+    rounds: u64,
+}
+
+/// 所有租户的配额和用量
+#[derive(Debug)]
+pub struct Quotas {
+    limits: HashMap<String, Quota>,
+    /// 每日用量，包括已经预留、还没有开始的轮次
+    usage: Mutex<HashMap<String, Usage>>,
+    /// 每个租户已经预留、还没有创建的房间数。统计已有房间和创建房间都在这个锁内进行
+    reserved_rooms: Mutex<HashMap<String, u32>>,
+    hook: Arc<dyn QuotaHook>,
+}
+
+impl Quotas {
+    pub fn new(limits: HashMap<String, Quota>, hook: Arc<dyn QuotaHook>) -> Self {
+        Self {
+            limits,
+            usage: Default::default(),
+            reserved_rooms: Default::default(),
+            hook,
+        }
+    }
+
+    /// 租户的配额，没有配置时不限制
+    pub fn quota(&self, tenant: &str) -> Quota {
+        self.limits.get(tenant).copied().unwrap_or_default()
+    }
+
+    /// 配置了配额的租户
+    pub fn tenants(&self) -> impl Iterator<Item = &String> {
+        self.limits.keys()
+    }
+
+    /// 租户当天已经开始的轮数
+    pub fn rounds_today(&self, tenant: &str) -> u64 {
+        let today = timestamp() / DAY_MILLIS;
+        match self.usage.lock().unwrap().get(tenant) {
+            Some(usage) if usage.day == today => usage.rounds,
+            _ => 0,
+        }
+    }
+
+    /// 为租户预留 `count` 个房间，超出上限时返回错误。有上限时在锁内调用 `rooms` 统计租户已有的房间数，
+    /// 加上其他请求预留的房间不能超过上限，所以同时创建房间的请求不会一起通过检查。
+    /// 房间用 [`RoomReservation::fulfil`] 创建，没有用完的名额在预留释放时归还
+    pub fn reserve_rooms(
+        self: &Arc<Self>,
+        tenant: &str,
+        count: u32,
+        rooms: impl FnOnce() -> u32,
+    ) -> Result<RoomReservation, QuotaKind> {
+        let limit = self.quota(tenant).max_rooms;
+        let mut reserved = self.reserved_rooms.lock().unwrap();
+        let used = match limit {
+            Some(_) => rooms() + reserved.get(tenant).copied().unwrap_or(0),
+            None => 0,
+        };
+        if let Some(limit) = limit {
+            if used + count > limit {
+                drop(reserved);
+                self.exceeded(tenant, QuotaKind::Rooms, limit as u64);
+                return Err(QuotaKind::Rooms);
+            }
+        }
+        *reserved.entry(tenant.to_string()).or_default() += count;
+        drop(reserved);
+        if limit == Some(used + count) {
+            self.hook.notify(&QuotaEvent::Reached {
+                tenant: tenant.to_string(),
+                kind: QuotaKind::Rooms,
+                limit: (used + count) as u64,
+            });
+        }
+        Ok(RoomReservation {
+            quotas: self.clone(),
+            tenant: tenant.to_string(),
+            remaining: count,
+        })
+    }
+
+    /// 为租户预留今天的一轮，达到每日上限时返回错误。预留立即计入用量，
+    /// 用 [`RoundReservation::commit`] 确认开始了这一轮，没有确认就释放时归还
+    pub fn reserve_round(self: &Arc<Self>, tenant: &str) -> Result<RoundReservation, QuotaKind> {
+        let today = timestamp() / DAY_MILLIS;
+        let limit = self.quota(tenant).max_rounds_per_day;
+        let rounds = {
+            let mut usage = self.usage.lock().unwrap();
+            let usage = usage.entry(tenant.to_string()).or_default();
+            if usage.day != today {
+                *usage = Usage {
+                    day: today,
+                    rounds: 0,
+                };
+            }
+            match limit {
+                Some(limit) if usage.rounds >= limit => Err(limit),
+                _ => {
+                    usage.rounds += 1;
+                    Ok(usage.rounds)
+                }
+            }
+        };
+        match rounds {
+            Ok(rounds) => Ok(RoundReservation {
+                quotas: self.clone(),
+                tenant: tenant.to_string(),
+                day: today,
+                rounds,
+                committed: false,
+            }),
+            Err(limit) => {
+                self.exceeded(tenant, QuotaKind::RoundsPerDay, limit);
+                Err(QuotaKind::RoundsPerDay)
+            }
+        }
+    }
+
+    fn exceeded(&self, tenant: &str, kind: QuotaKind, limit: u64) {
+        self.hook.notify(&QuotaEvent::Exceeded {
+            tenant: tenant.to_string(),
+            kind,
+            limit,
+        });
+    }
+}
+
+/// 预留的房间名额，释放时归还没有用完的名额
+pub struct RoomReservation {
+    quotas: Arc<Quotas>,
+    tenant: String,
+    remaining: u32,
+}
+
+impl RoomReservation {
+    /// 用一个名额创建房间。`insert` 在预留锁内把房间加入房间表，
+    /// 其他请求统计到的要么是这个名额，要么是创建好的房间，不会重复或者遗漏
+    pub fn fulfil<T>(&mut self, insert: impl FnOnce() -> T) -> T {
+        let mut reserved = self.quotas.reserved_rooms.lock().unwrap();
+        let result = insert();
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            release_rooms(&mut reserved, &self.tenant, 1);
+        }
+        result
+    }
+}
+
+impl Drop for RoomReservation {
+    fn drop(&mut self) {
+        if self.remaining > 0 {
+            let mut reserved = self.quotas.reserved_rooms.lock().unwrap();
+            release_rooms(&mut reserved, &self.tenant, self.remaining);
+        }
+    }
+}
+
+fn release_rooms(reserved: &mut HashMap<String, u32>, tenant: &str, count: u32) {
+    if let Some(pending) = reserved.get_mut(tenant) {
+        *pending = pending.saturating_sub(count);
+        if *pending == 0 {
+            reserved.remove(tenant);
+        }
+    }
+}
+
+/// 预留的一轮，没有确认就释放时归还
+pub struct RoundReservation {
+    quotas: Arc<Quotas>,
+    tenant: String,
+    day: u64,
+    /// 预留之后当天的轮数
+    rounds: u64,
+    committed: bool,
+}
+
+impl RoundReservation {
+    /// 确认开始了这一轮，刚好达到每日上限时通知
+    pub fn commit(mut self) {
+        self.committed = true;
+        if self.quotas.quota(&self.tenant).max_rounds_per_day == Some(self.rounds) {
+            self.quotas.hook.notify(&QuotaEvent::Reached {
+                tenant: self.tenant.clone(),
+                kind: QuotaKind::RoundsPerDay,
+                limit: self.rounds,
+            });
+        }
+    }
+}
+
+impl Drop for RoundReservation {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        let mut usage = self.quotas.usage.lock().unwrap();
+        if let Some(usage) = usage.get_mut(&self.tenant) {
+            // 跨天之后用量已经重置，不需要归还
+            if usage.day == self.day {
+                usage.rounds = usage.rounds.saturating_sub(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use drand_verify::{derive_randomness, G1Pubkey, G2PubkeyRfc, Pubkey};
+use ndoors::Uuid;
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use tokio::time::Duration;
+
+use crate::audit::{AuditEvent, AuditLog};
+use crate::http::{self, HttpClient};
+use crate::room::RoomAgent;
+use crate::timestamp;
+
+/// 刷新信标的间隔
+const BEACON_INTERVAL: Duration = Duration::from_secs(3);
+/// 保留的已经验证的信标值数量
+const MAX_VALUES: usize = 128;
+/// 每次刷新最多补取的错过的信标轮次
+const MAX_CATCH_UP: u64 = 16;
+
+/// 服务器的随机数来源
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RandomSource {
+    /// 操作系统的熵（默认）
+    #[default]
+    Os,
+    /// 每个房间都以 `seed` 为种子，相同的操作得到相同的游戏，用于可以重现的演示
+    Seeded { seed: u64 },
+    /// 每轮的种子由服务器事先承诺的秘密和随机信标（drand 的 HTTP 中继）指定的未来轮次共同决定，
+    /// 信标值按链的公钥 `public_key`（十六进制）验证签名。任何人都可以在一轮结束后根据审计日志验证奖品位置
+    Beacon { url: String, public_key: String },
+}
+
+/// 信标的一个值，字段和 drand 的 `/public/<轮次>` 相同
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BeaconValue {
+    pub round: u64,
+    /// 十六进制的随机数，即签名的 SHA-256
+    pub randomness: String,
+    /// 十六进制的 BLS 签名
+    pub signature: String,
+    /// 链式信标上一轮的签名，非链式信标为空
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub previous_signature: String,
+}
+
+/// 信标链的参数，字段和 drand 的 `/info` 相同
+#[derive(Debug, Deserialize, Clone)]
+struct ChainInfo {
+    public_key: String,
+    /// 每轮的间隔（秒）
+    period: u64,
+    /// 第一轮的 Unix 时间戳（秒）
+    genesis_time: u64,
+}
+
+impl ChainInfo {
+    /// 指定时间（Unix 时间戳，毫秒）应该已经发布的最新轮次
+    fn round_at(&self, now: u64) -> u64 {
+        (now / 1000).saturating_sub(self.genesis_time) / self.period.max(1) + 1
+    }
+}
+
+/// 审计日志中记录的随机数来源
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RandomRecord {
+    Os,
+    Seeded {
+        seed: u64,
+    },
+    /// 服务器承诺了下一轮的秘密（秘密的 SHA-256，十六进制），并指定了还没有发布的信标轮次
+    BeaconCommitted {
+        beacon_round: u64,
+        commitment: String,
+    },
+    /// 本轮使用的信标值，秘密在本轮结束后公布
+    Beacon {
+        #[serde(flatten)]
+        beacon: BeaconValue,
+    },
+    /// 公布已经结束的一轮（或者没有用到）的秘密
+    BeaconRevealed {
+        beacon_round: u64,
+        secret: String,
+    },
+    /// 没有事先承诺或者指定的信标值还没有取到，这一轮使用操作系统的熵
+    BeaconUnavailable,
+}
+
+/// 承诺的秘密和指定的信标轮次
+#[derive(Debug)]
+struct Draw {
+    secret: String,
+    beacon_round: u64,
+}
+
+/// 使用信标时房间的秘密：下一轮承诺的秘密和本轮正在使用、结束后公布的秘密
+#[derive(Debug, Default)]
+pub struct BeaconDraws {
+    pending: Option<Draw>,
+    active: Option<(u32, Draw)>,
+}
+
+/// 已经验证的信标值
+#[derive(Debug, Default)]
+struct Beacon {
+    chain: Option<ChainInfo>,
+    values: BTreeMap<u64, BeaconValue>,
+}
+
+/// 按配置的来源设置房间的随机数生成器
+#[derive(Debug)]
+pub struct Randomness {
+    source: RandomSource,
+    beacon: Arc<RwLock<Beacon>>,
+}
+
+impl Randomness {
+    /// 使用信标时在后台定期读取并验证最新值，公钥无效时返回错误
+    pub fn start(source: RandomSource) -> anyhow::Result<Self> {
+        let beacon = Arc::new(RwLock::new(Beacon::default()));
+        if let RandomSource::Beacon { url, public_key } = &source {
+            let key = ChainKey::parse(public_key)?;
+            let url = url.trim_end_matches('/').to_string();
+            poll(url, public_key.clone(), key, beacon.clone());
+        }
+        Ok(Self { source, beacon })
+    }
+
+    /// 新建房间时设置种子并记录到审计日志，使用信标时承诺第一轮的秘密
+    pub fn create_room(&self, ra: &mut RoomAgent, audit: &AuditLog) {
+        let source = match &self.source {
+            RandomSource::Os => RandomRecord::Os,
+            RandomSource::Seeded { seed } => {
+                ra.room.reseed(Some(*seed));
+                RandomRecord::Seeded { seed: *seed }
+            }
+            RandomSource::Beacon { .. } => return self.commit(ra, audit),
+        };
+        audit.record(AuditEvent::Randomness {
+            room_id: *ra.room.id(),
+            round: None,
+            source,
+        });
+    }
+
+    /// 使用信标时在每轮开始前公布上一轮的秘密，用承诺的秘密和指定的信标值设置本轮的种子，
+    /// 然后承诺下一轮的秘密。没有事先承诺（例如恢复的房间）或者还没有取到指定的信标值时本轮使用操作系统的熵
+    pub fn start_round(&self, ra: &mut RoomAgent, audit: &AuditLog) {
+        if !matches!(self.source, RandomSource::Beacon { .. }) {
+            return;
+        }
+        self.reveal(ra, audit);
+        let round = ra.room.next_round();
+        let draw = ra.beacon.pending.take();
+        let value = draw.as_ref().and_then(|draw| {
+            let beacon = self.beacon.read().unwrap();
+            beacon.values.get(&draw.beacon_round).cloned()
+        });
+        let source = match (draw, value) {
+            (Some(draw), Some(beacon)) => {
+                let seed = derive_seed(&draw.secret, &beacon.randomness, ra.room.id(), round);
+                ra.room.reseed_from_bytes(seed);
+                ra.beacon.active = Some((round, draw));
+                RandomRecord::Beacon { beacon }
+            }
+            (draw, _) => {
+                ra.room.reseed(None);
+                // 没有用到的秘密立即公布
+                if let Some(draw) = draw {
+                    audit.record(AuditEvent::Randomness {
+                        room_id: *ra.room.id(),
+                        round: None,
+                        source: RandomRecord::BeaconRevealed {
+                            beacon_round: draw.beacon_round,
+                            secret: draw.secret,
+                        },
+                    });
+                }
+                RandomRecord::BeaconUnavailable
+            }
+        };
+        audit.record(AuditEvent::Randomness {
+            room_id: *ra.room.id(),
+            round: Some(round),
+            source,
+        });
+        self.commit(ra, audit);
+    }
+
+    /// 一局结束后公布最后一轮的秘密
+    pub fn end_game(&self, ra: &mut RoomAgent, audit: &AuditLog) {
+        self.reveal(ra, audit);
+    }
+
+    /// 公布已经结束的一轮的秘密
+    fn reveal(&self, ra: &mut RoomAgent, audit: &AuditLog) {
+        if let Some((round, draw)) = ra.beacon.active.take() {
+            audit.record(AuditEvent::Randomness {
+                room_id: *ra.room.id(),
+                round: Some(round),
+                source: RandomRecord::BeaconRevealed {
+                    beacon_round: draw.beacon_round,
+                    secret: draw.secret,
+                },
+            });
+        }
+    }
+
+    /// 生成下一轮的秘密，指定下一个还没有发布的信标轮次，承诺记录到审计日志。还没有取到链的参数时不承诺
+    fn commit(&self, ra: &mut RoomAgent, audit: &AuditLog) {
+        let beacon_round = match &self.beacon.read().unwrap().chain {
+            Some(chain) => chain.round_at(timestamp()) + 1,
+            None => return,
+        };
+        let mut rng = rand::thread_rng();
+        let secret = format!("{:032x}{:032x}", rng.gen::<u128>(), rng.gen::<u128>());
+        audit.record(AuditEvent::Randomness {
+            room_id: *ra.room.id(),
+            round: None,
+            source: RandomRecord::BeaconCommitted {
+                beacon_round,
+                commitment: ndoors::fairness::commitment(&secret),
+            },
+        });
+        ra.beacon.pending = Some(Draw {
+            secret,
+            beacon_round,
+        });
+    }
+}
+
+/// 本轮的种子：SHA-256（秘密、信标的随机数、房间 ID 的 16 个字节、轮次（4 个字节，大端）），
+/// 秘密和随机数按十六进制字符串的字节计算
+fn derive_seed(secret: &str, randomness: &str, room_id: &Uuid, round: u32) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(secret.as_bytes());
+    hasher.update(randomness.as_bytes());
+    hasher.update(room_id.as_bytes());
+    hasher.update(round.to_be_bytes());
+    hasher.finalize().into()
+}
+
+/// 信标链的公钥：48 个字节的是 G1 上的公钥（drand 默认的链式网络），96 个字节的是 G2 上的公钥（quicknet）
+enum ChainKey {
+    G1(G1Pubkey),
+    G2(G2PubkeyRfc),
+}
+
+impl ChainKey {
+    fn parse(hex: &str) -> anyhow::Result<Self> {
+        let bytes = decode(hex).ok_or_else(|| anyhow::anyhow!("Invalid beacon public key."))?;
+        let key = match bytes.len() {
+            48 => G1Pubkey::from_variable(&bytes).map(ChainKey::G1).ok(),
+            96 => G2PubkeyRfc::from_variable(&bytes).map(ChainKey::G2).ok(),
+            _ => None,
+        };
+        key.ok_or_else(|| anyhow::anyhow!("Invalid beacon public key."))
+    }
+
+    /// 验证信标值的签名，以及随机数是签名的 SHA-256
+    fn verify(&self, value: &BeaconValue) -> anyhow::Result<()> {
+        let invalid = || anyhow::anyhow!("Invalid beacon round {}.", value.round);
+        let signature = decode(&value.signature).ok_or_else(invalid)?;
+        let previous = decode(&value.previous_signature).ok_or_else(invalid)?;
+        let valid = match self {
+            ChainKey::G1(key) => key.verify(value.round, &previous, &signature),
+            ChainKey::G2(key) => key.verify(value.round, &[], &signature),
+        };
+        if !valid.unwrap_or(false)
+            || decode(&value.randomness) != Some(derive_randomness(&signature).to_vec())
+        {
+            return Err(invalid());
+        }
+        Ok(())
+    }
+}
+
+fn poll(url: String, public_key: String, key: ChainKey, beacon: Arc<RwLock<Beacon>>) {
+    tokio::spawn(async move {
+        let client = http::client();
+        let mut interval = tokio::time::interval(BEACON_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(cause) = refresh(&client, &url, &public_key, &key, &beacon).await {
+                tracing::warn!(%cause, "Failed to fetch random beacon.");
+            }
+        }
+    });
+}
+
+/// 第一次刷新时读取链的参数并确认公钥一致，然后读取最新值和上次刷新之后错过的轮次，只保存签名有效的值
+async fn refresh(
+    client: &HttpClient,
+    url: &str,
+    public_key: &str,
+    key: &ChainKey,
+    beacon: &RwLock<Beacon>,
+) -> anyhow::Result<()> {
+    let chain = beacon.read().unwrap().chain.clone();
+    let chain = match chain {
+        Some(chain) => chain,
+        None => {
+            let chain: ChainInfo = fetch(client, &format!("{}/info", url)).await?;
+            if !chain.public_key.eq_ignore_ascii_case(public_key) {
+                anyhow::bail!("Random beacon serves a different chain.");
+            }
+            beacon.write().unwrap().chain = Some(chain.clone());
+            chain
+        }
+    };
+
+    let latest: BeaconValue = fetch(client, &format!("{}/public/latest", url)).await?;
+    key.verify(&latest)?;
+    // 链的参数没有签名，已经发布的轮次超过参数推算的轮次时说明参数有误，按参数指定的轮次可能已经发布
+    if latest.round > chain.round_at(timestamp()) {
+        anyhow::bail!("Random beacon chain info is inconsistent.");
+    }
+    let last = beacon.read().unwrap().values.keys().next_back().copied();
+    let from = match last {
+        Some(last) => (last + 1).max(latest.round.saturating_sub(MAX_CATCH_UP)),
+        None => latest.round,
+    };
+    let mut values = vec![latest.clone()];
+    for round in from..latest.round {
+        let value: BeaconValue = fetch(client, &format!("{}/public/{}", url, round)).await?;
+        if value.round != round {
+            anyhow::bail!(
+                "Random beacon returned round {} for {}.",
+                value.round,
+                round
+            );
+        }
+        key.verify(&value)?;
+        values.push(value);
+    }
+
+    let mut beacon = beacon.write().unwrap();
+    for value in values {
+        beacon.values.insert(value.round, value);
+    }
+    while beacon.values.len() > MAX_VALUES {
+        beacon.values.pop_first();
+    }
+    Ok(())
+}
+
+async fn fetch<T: DeserializeOwned>(client: &HttpClient, uri: &str) -> anyhow::Result<T> {
+    let request = hyper::Request::builder()
+        .method(hyper::Method::GET)
+        .uri(uri)
+        .body(hyper::Body::empty())?;
+    let response = client.request(request).await?;
+    if !response.status().is_success() {
+        anyhow::bail!("Random beacon responded with {}.", response.status());
+    }
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+fn decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() & 1 == 1 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,54 @@
+use ndoors::Uuid;
+use std::collections::{BTreeMap, HashMap};
+use tokio::time::Duration;
+
+/// 汇总观众反应的时间窗口，窗口内的反应合并为一次广播
+pub const REACTION_WINDOW: Duration = Duration::from_secs(1);
+
+/// 每个观众在一个窗口内计入的反应数，超出的反应直接丢弃
+const MAX_PER_USER: u32 = 3;
+
+/// 一个窗口内不同反应的种类数
+const MAX_KINDS: usize = 16;
+
+/// 一个反应最多的字符数，组合 emoji 由多个字符组成
+const MAX_CHARS: usize = 16;
+
+/// 房间内观众在当前窗口的反应
+#[derive(Debug, Default)]
+pub struct Reactions {
+    counts: BTreeMap<String, u32>,
+    per_user: HashMap<Uuid, u32>,
+}
+
+impl Reactions {
+    /// 反应只能是 emoji 之类的符号，不能夹带文字
+    pub fn is_valid(emoji: &str) -> bool {
+        !emoji.is_empty()
+            && emoji.chars().count() <= MAX_CHARS
+            && !emoji
+                .chars()
+                .any(|c| c.is_alphabetic() || c.is_whitespace() || c.is_control())
+    }
+
+    /// 计入一个反应，返回 `true` 表示这是窗口内的第一个反应，需要安排广播
+    pub fn add(&mut self, user_id: Uuid, emoji: String) -> bool {
+        let first = self.counts.is_empty();
+        let count = self.per_user.entry(user_id).or_default();
+        if *count >= MAX_PER_USER {
+            return false;
+        }
+        if !self.counts.contains_key(&emoji) && self.counts.len() >= MAX_KINDS {
+            return false;
+        }
+        *count += 1;
+        *self.counts.entry(emoji).or_default() += 1;
+        first
+    }
+
+    /// 结束当前窗口，返回窗口内每种反应的次数
+    pub fn take(&mut self) -> BTreeMap<String, u32> {
+        self.per_user.clear();
+        std::mem::take(&mut self.counts)
+    }
+}
@@ -0,0 +1,62 @@
+use ndoors::Uuid;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+
+use crate::timestamp;
+
+/// 会话记录文件中的一行，时间为 Unix 时间戳（毫秒）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Record {
+    /// 会话开始
+    Session { user_id: Uuid, started: u64 },
+    /// 客户端发来的帧
+    Inbound { at: u64, text: String },
+    /// 发往客户端的帧
+    Outbound { at: u64, text: String },
+}
+
+/// 将一个 WebSocket 会话的所有文本帧以 JSON Lines 格式写入文件
+#[derive(Debug)]
+pub struct Recorder {
+    writer: LineWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(dir: &Path, user_id: Uuid) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let started = timestamp();
+        let path = dir.join(format!("{}-{}.jsonl", user_id, started));
+        let mut recorder = Self {
+            writer: LineWriter::new(File::create(&path)?),
+        };
+        recorder.write(&Record::Session { user_id, started });
+        tracing::info!(path = %path.display(), "Recording session.");
+        Ok(recorder)
+    }
+
+    pub fn inbound(&mut self, text: &str) {
+        self.write(&Record::Inbound {
+            at: timestamp(),
+            text: text.to_string(),
+        });
+    }
+
+    pub fn outbound(&mut self, text: &str) {
+        self.write(&Record::Outbound {
+            at: timestamp(),
+            text: text.to_string(),
+        });
+    }
+
+    fn write(&mut self, record: &Record) {
+        let result = serde_json::to_string(record)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(self.writer, "{}", line)?));
+        if let Err(cause) = result {
+            tracing::error!(%cause, "Failed to write session record.");
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use ndoors::Uuid;
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::sync::mpsc::{channel, Receiver};
+use tokio::time::{timeout, Duration};
+
+use crate::config::Config;
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::record::Record;
+use crate::room::RoomDropper;
+use crate::{process_request, Server, User};
+
+/// 等待按房间节奏延迟发送的消息的最长时间
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 一个录制的会话
+struct Session {
+    path: String,
+    user: User,
+    room_dropper: RoomDropper,
+    receiver: Receiver<ServerMessage>,
+    expected: Vec<String>,
+    actual: Vec<String>,
+}
+
+impl Session {
+    fn drain(&mut self) -> anyhow::Result<()> {
+        while let Ok(response) = self.receiver.try_recv() {
+            self.actual.push(response.to_json()?.to_string());
+        }
+        Ok(())
+    }
+}
+
+/// 按时间顺序把所有会话的请求逐个交给请求处理逻辑，并与录制的响应比较
+///
+/// 录制时随机生成的房间 ID 会在回放中重新生成，比较时根据响应的对应关系自动替换。
+/// 随机选择的门号无法重现，这类请求的结果可能不一致。
+pub async fn run(paths: &[String]) -> anyhow::Result<()> {
+    if paths.is_empty() {
+        anyhow::bail!("Usage: ndoors-server replay <record>...");
+    }
+
+    let server = Server::new(Config::load()?)?;
+    let mut sessions = vec![];
+    let mut inputs = vec![];
+    for path in paths {
+        let content = std::fs::read_to_string(path)?;
+        let mut user_id = None;
+        let mut expected = vec![];
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str(line)? {
+                Record::Session { user_id: id, .. } => user_id = Some(id),
+                Record::Inbound { at, text } => inputs.push((at, sessions.len(), text)),
+                Record::Outbound { text, .. } => expected.push(text),
+            }
+        }
+        let user_id = user_id.ok_or_else(|| anyhow::anyhow!("{}: missing session record", path))?;
+        let (sender, receiver) = channel(4096);
+        sessions.push(Session {
+            path: path.clone(),
+            user: User::new(user_id, sender),
+            room_dropper: RoomDropper::new(server.rooms.clone()),
+            receiver,
+            expected,
+            actual: vec![],
+        });
+    }
+    // 排序是稳定的，同一时刻的请求保持录制时的顺序
+    inputs.sort_by_key(|(at, session, _)| (*at, *session));
+
+    let mut ids = HashMap::new();
+    for (_, index, text) in inputs {
+        let text = remap(&text, &ids);
+        let session = &mut sessions[index];
+        match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(message) => {
+                process_request(
+                    &server,
+                    &mut session.user,
+                    &mut session.room_dropper,
+                    message,
+                )
+                .await?
+            }
+            Err(cause) => tracing::warn!(%cause, %text, "Skip invalid request."),
+        }
+        for session in &mut sessions {
+            session.drain()?;
+            learn_ids(session, &mut ids);
+        }
+    }
+
+    for session in &mut sessions {
+        while let Ok(Some(response)) = timeout(SETTLE_TIMEOUT, session.receiver.recv()).await {
+            session.actual.push(serde_json::to_string(&response)?);
+            if session.actual.len() >= session.expected.len() {
+                break;
+            }
+        }
+        session.drain()?;
+        learn_ids(session, &mut ids);
+    }
+
+    let mut diverged = false;
+    for session in &sessions {
+        let matched = session
+            .expected
+            .iter()
+            .zip(&session.actual)
+            .take_while(|(expected, actual)| same(&remap(expected, &ids), actual))
+            .count();
+        let total = session.expected.len().max(session.actual.len());
+        println!("{}: {}/{} responses match", session.path, matched, total);
+        if matched < total {
+            diverged = true;
+            println!("  expected: {:?}", session.expected.get(matched));
+            println!("  actual:   {:?}", session.actual.get(matched));
+        }
+    }
+
+    if diverged {
+        anyhow::bail!("Replay diverged from the recorded sessions.");
+    }
+    Ok(())
+}
+
+/// 将录制的 ID 替换为回放中的 ID
+fn remap(text: &str, ids: &HashMap<String, String>) -> String {
+    ids.iter()
+        .fold(text.to_string(), |text, (recorded, replayed)| {
+            text.replace(recorded.as_str(), replayed)
+        })
+}
+
+/// 从位置相同的录制响应和回放响应中找出对应的 ID
+fn learn_ids(session: &Session, ids: &mut HashMap<String, String>) {
+    for (expected, actual) in session.expected.iter().zip(&session.actual) {
+        if let (Ok(expected), Ok(actual)) = (
+            serde_json::from_str::<Value>(expected),
+            serde_json::from_str::<Value>(actual),
+        ) {
+            collect_ids(&expected, &actual, ids);
+        }
+    }
+}
+
+fn collect_ids(expected: &Value, actual: &Value, ids: &mut HashMap<String, String>) {
+    match (expected, actual) {
+        (Value::String(recorded), Value::String(replayed))
+            if recorded != replayed
+                && Uuid::parse_str(recorded).is_ok()
+                && Uuid::parse_str(replayed).is_ok() =>
+        {
+            ids.entry(recorded.clone())
+                .or_insert_with(|| replayed.clone());
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for (expected, actual) in expected.iter().zip(actual) {
+                collect_ids(expected, actual, ids);
+            }
+        }
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                if let Some(actual) = actual.get(key) {
+                    collect_ids(expected, actual, ids);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 比较时忽略每条消息的服务器时间和游戏时钟
+fn same(expected: &str, actual: &str) -> bool {
+    match (
+        serde_json::from_str::<Value>(expected),
+        serde_json::from_str::<Value>(actual),
+    ) {
+        (Ok(expected), Ok(actual)) => without_clock(expected) == without_clock(actual),
+        _ => expected == actual,
+    }
+}
+
+fn without_clock(mut message: Value) -> Value {
+    if let Value::Object(fields) = &mut message {
+        fields.remove("server_time");
+        fields.remove("game_clock");
+    }
+    message
+}
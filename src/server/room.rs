@@ -0,0 +1,579 @@
+use dashmap::DashMap;
+use ndoors::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::Sender;
+use tokio::time::{Duration, Instant};
+
+use crate::bot::Bot;
+use crate::bridge::EventBridge;
+use crate::commentary::Templates;
+use crate::correspondence::{CorrespondenceState, RoomStore};
+use crate::fair::FairRoom;
+use crate::journal::{Entry, Journal, Traffic};
+use crate::lesson::LessonPack;
+use crate::lobby::Lobby;
+use crate::narration::Narrate;
+use crate::pacing::{Outbox, Pacing};
+use crate::permission::Role;
+use crate::protocol::{GameRequest, GameResponse, ServerMessage};
+use crate::push::Notifier;
+use crate::random::BeaconDraws;
+use crate::reaction::Reactions;
+use crate::sampling::RoundTape;
+use crate::script::HostScript;
+use crate::timestamp;
+
+pub use ndoors::protocol::{RoomInfo, RoomLifecycle, RoomPage, RoomStatus};
+
+#[derive(Debug)]
+pub struct RoomAgent {
+    pub room: Room,
+    /// 房间所属租户
+    pub tenant: String,
+    pub host: Sender<ServerMessage>,
+    pub co_hosts: HashMap<Uuid, Sender<ServerMessage>>,
+    pub contestant: Option<Sender<ServerMessage>>,
+    pub spectators: HashMap<Uuid, Sender<ServerMessage>>,
+    /// 悄悄旁观的版主，不计入观众人数
+    pub moderators: HashMap<Uuid, Sender<ServerMessage>>,
+    /// 被禁言的成员
+    pub muted: HashSet<Uuid>,
+    pub outbox: Outbox,
+    pub journal: Journal,
+    /// 处理请求时发生 panic，房间状态可能不一致
+    pub quarantined: bool,
+    /// 已经广播的事件数
+    pub seq: u64,
+    last_terminal: Option<Terminal>,
+    /// 揭示结果按节奏送达的时间，用来计算挑战者的抉择用时
+    pub revealed_at: Option<Instant>,
+    /// 本轮开始的消息按节奏送达的时间，用来计算每轮的用时
+    pub round_started_at: Option<Instant>,
+    /// 本局第一轮开始的消息按节奏送达的时间，用来计算每局的用时
+    game_started_at: Option<Instant>,
+    /// 最近完成的游戏
+    pub history: VecDeque<CompletedGame>,
+    next_game_id: u64,
+    /// 转发房间事件到外部消息系统
+    bridge: Arc<EventBridge>,
+    /// 轮到离开的成员操作时推送通知
+    notifier: Arc<Notifier>,
+    /// 主持人脚本
+    pub script: Option<HostScript>,
+    /// 机器人挑战者
+    pub bot: Option<Bot>,
+    /// 所属班级
+    pub class: Option<String>,
+    /// 使用的课程包
+    pub lesson: Option<Arc<LessonPack>>,
+    /// 发给观众的解说模板
+    pub commentary: Option<Arc<Templates>>,
+    /// 机器人对战房间，由服务器按节奏推进，成员的请求不会触发自动操作
+    pub exhibition: bool,
+    /// 通信对局房间的阶段时限和成员的恢复令牌
+    pub correspondence: Option<CorrespondenceState>,
+    /// 保存通信对局房间的快照
+    pub store: Option<Arc<RoomStore>>,
+    /// 公平模式房间的随机串和证明
+    pub fairness: Option<FairRoom>,
+    /// 使用随机信标时承诺的秘密
+    pub beacon: BeaconDraws,
+    /// 观众在当前窗口的反应
+    pub reactions: Reactions,
+    /// 房间外订阅事件的看板，收到的事件和观众相同
+    pub watchers: broadcast::Sender<GameResponse>,
+    lobby: Arc<Lobby>,
+    /// 大厅中列出的信息，被隔离时为空
+    listed: Option<RoomInfo>,
+    /// 最近一次广播的生命周期
+    lifecycle: RoomLifecycle,
+    /// 刚完成一局游戏，挑战者离开或者开始下一局后清除
+    completed: bool,
+    /// 房间已经关闭
+    closed: bool,
+    /// 本局每一轮的操作，本局结束后抽查
+    pub tape: RoundTape,
+}
+
+/// 每个房间为订阅事件的看板缓冲的事件数
+const WATCH_BUFFER: usize = 64;
+
+/// 每个房间保留的历史游戏数
+const MAX_HISTORY: usize = 20;
+
+/// 一局已经完成的游戏
+#[derive(Debug)]
+pub struct CompletedGame {
+    /// 房间内从 0 开始递增的编号
+    pub id: u64,
+    /// 完成时的 Unix 时间戳（毫秒）
+    pub completed_at: u64,
+    /// 从第一轮开始到完成的毫秒数，服务器重启前开始的游戏没有记录
+    pub duration_ms: Option<u64>,
+    pub result: GameResult,
+    pub rounds: Vec<RoundResult>,
+    /// 分享卡片的短代码，还没有分享时为空
+    pub share: Option<String>,
+}
+
+/// 重复提交的终结操作在这段时间内返回之前的结果
+const REPEAT_WINDOW: Duration = Duration::from_secs(3);
+
+/// 最近一次成功的终结操作（完成、抉择）
+#[derive(Debug)]
+struct Terminal {
+    user_id: Uuid,
+    request: GameRequest,
+    seq: u64,
+    at: Instant,
+    response: GameResponse,
+}
+
+impl From<&RoomAgent> for RoomInfo {
+    fn from(ra: &RoomAgent) -> Self {
+        Self {
+            id: *ra.room.id(),
+            name: ra.room.name().map(str::to_string),
+            description: ra.room.description().map(str::to_string),
+            settings: ra.room.settings().clone(),
+            pacing: ra.outbox.pacing(),
+            status: RoomStatus::from(ra.room.state()),
+            lifecycle: ra.lifecycle(),
+            spectators: ra.room.spectators().len() as u32,
+            seq: ra.seq,
+            deadline: ra
+                .correspondence
+                .as_ref()
+                .and_then(|correspondence| correspondence.deadline)
+                .or_else(|| ra.room.deadline()),
+            proposed_settings: ra.room.proposed_settings().cloned(),
+            fair: ra.fairness.is_some(),
+            teams: ra.room.teams().to_vec(),
+        }
+    }
+}
+
+/// 房间的完整调试信息，包括状态快照、事件记录和最近的请求
+#[derive(Debug, Serialize)]
+pub struct RoomDump<'a> {
+    info: RoomInfo,
+    room: &'a Room,
+    contestant_connected: bool,
+    quarantined: bool,
+    spectators: Vec<Uuid>,
+    moderators: Vec<Uuid>,
+    muted: Vec<Uuid>,
+    events: &'a VecDeque<Entry<GameResponse>>,
+    traffic: &'a VecDeque<Entry<Traffic>>,
+}
+
+impl<'a> From<&'a RoomAgent> for RoomDump<'a> {
+    fn from(ra: &'a RoomAgent) -> Self {
+        Self {
+            info: RoomInfo::from(ra),
+            room: &ra.room,
+            contestant_connected: ra.contestant.is_some(),
+            quarantined: ra.quarantined,
+            spectators: ra.room.spectators().to_vec(),
+            moderators: ra.moderators.keys().copied().collect(),
+            muted: ra.muted.iter().copied().collect(),
+            events: ra.journal.events(),
+            traffic: ra.journal.traffic(),
+        }
+    }
+}
+
+impl RoomAgent {
+    pub fn new(
+        room: Room,
+        tenant: String,
+        host: Sender<ServerMessage>,
+        pacing: Pacing,
+        bridge: Arc<EventBridge>,
+        notifier: Arc<Notifier>,
+        lobby: Arc<Lobby>,
+    ) -> Self {
+        let mut ra = Self {
+            room,
+            tenant,
+            host,
+            co_hosts: HashMap::new(),
+            contestant: None,
+            spectators: HashMap::new(),
+            moderators: HashMap::new(),
+            muted: HashSet::new(),
+            outbox: Outbox::new(pacing),
+            journal: Journal::default(),
+            quarantined: false,
+            seq: 0,
+            last_terminal: None,
+            revealed_at: None,
+            round_started_at: None,
+            game_started_at: None,
+            history: VecDeque::new(),
+            next_game_id: 0,
+            bridge,
+            notifier,
+            script: None,
+            bot: None,
+            class: None,
+            lesson: None,
+            commentary: None,
+            exhibition: false,
+            correspondence: None,
+            store: None,
+            fairness: None,
+            beacon: BeaconDraws::default(),
+            reactions: Reactions::default(),
+            watchers: broadcast::channel(WATCH_BUFFER).0,
+            lobby,
+            listed: None,
+            lifecycle: RoomLifecycle::Open,
+            completed: false,
+            closed: false,
+            tape: RoundTape::default(),
+        };
+        // 事件在每次广播时取出，见 `drain_events`
+        ra.room.record_events(true);
+        ra.lifecycle = ra.lifecycle();
+        ra.relist();
+        ra
+    }
+
+    /// 能看到主持人视角（包括奖品位置）的成员
+    pub fn hosts(&self) -> Vec<Sender<ServerMessage>> {
+        let mut hosts = vec![self.host.clone()];
+        hosts.extend(self.co_hosts.values().cloned());
+        hosts.extend(self.moderators.values().cloned());
+        hosts
+    }
+
+    /// 只能看到挑战者视角的成员
+    pub fn audience(&self) -> Vec<Sender<ServerMessage>> {
+        let mut audience: Vec<_> = self.contestant.iter().cloned().collect();
+        audience.extend(self.spectators.values().cloned());
+        audience
+    }
+
+    /// 房间内所有成员
+    pub fn members(&self) -> Vec<Sender<ServerMessage>> {
+        let mut members = self.hosts();
+        members.extend(self.audience());
+        members
+    }
+
+    /// 按房间节奏向所有成员广播
+    pub fn publish(&mut self, mut response: GameResponse) {
+        response.narrate(&self.room);
+        self.restart_clock(&response);
+        let follow_ups = self.drain_events();
+        self.seq += 1;
+        self.journal.event(&response);
+        self.bridge
+            .forward(&self.tenant, *self.room.id(), self.seq, &response);
+        self.notifier.notify(self, &response);
+        // 先保存再投递，成员看到的阶段都已经写入磁盘
+        self.persist();
+        let at = self.outbox.schedule(response.is_transition());
+        if let GameResponse::Revealed { .. } = response {
+            self.revealed_at = Some(at);
+        }
+        self.watch(&response);
+        self.comment(at, &response);
+        self.outbox
+            .deliver(at, self.members(), response, self.game_started_at);
+        self.relist();
+        for response in follow_ups {
+            self.publish(response);
+        }
+        self.publish_lifecycle();
+    }
+
+    /// 房间当前的生命周期
+    pub fn lifecycle(&self) -> RoomLifecycle {
+        if self.closed {
+            return RoomLifecycle::Closed;
+        }
+        if self.quarantined {
+            return RoomLifecycle::Archived;
+        }
+        match self.room.state() {
+            RoomState::Started { paused: true, .. } => RoomLifecycle::Paused,
+            RoomState::Started { .. } => RoomLifecycle::InGame,
+            RoomState::Joined { .. } if self.completed => RoomLifecycle::Completed,
+            _ => RoomLifecycle::Open,
+        }
+    }
+
+    /// 生命周期和最近一次广播的不同时广播新的生命周期
+    fn publish_lifecycle(&mut self) {
+        if !matches!(self.room.state(), RoomState::Joined { .. }) {
+            self.completed = false;
+        }
+        let lifecycle = self.lifecycle();
+        if lifecycle != self.lifecycle {
+            self.lifecycle = lifecycle;
+            self.publish(GameResponse::LifecycleChanged { lifecycle });
+        }
+    }
+
+    /// 关闭房间并通知所有成员，随后房间被删除
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.publish_lifecycle();
+    }
+
+    /// 经过房间的发件箱单独回复一个成员，排在已经安排的广播之后，不计入 `seq`
+    pub fn reply(&mut self, recipient: Sender<ServerMessage>, response: GameResponse) {
+        let at = self.outbox.schedule(false);
+        self.outbox
+            .deliver(at, vec![recipient], response, self.game_started_at);
+    }
+
+    /// 广播当前窗口内观众的反应。反应不是房间事件，不计入 `seq`，也不受节奏影响
+    pub fn flush_reactions(&mut self) {
+        let counts = self.reactions.take();
+        if !counts.is_empty() {
+            let at = self.outbox.schedule(false);
+            self.outbox.deliver(
+                at,
+                self.members(),
+                GameResponse::Reactions { counts },
+                self.game_started_at,
+            );
+        }
+    }
+
+    /// 同一时刻分别向主持人和其他成员广播不同的消息
+    pub fn publish_split(&mut self, mut host_resp: GameResponse, mut audience_resp: GameResponse) {
+        host_resp.narrate(&self.room);
+        audience_resp.narrate(&self.room);
+        self.restart_clock(&host_resp);
+        let follow_ups = self.drain_events();
+        self.seq += 1;
+        self.journal.event(&host_resp);
+        self.bridge
+            .forward(&self.tenant, *self.room.id(), self.seq, &host_resp);
+        self.notifier.notify(self, &host_resp);
+        self.persist();
+        let at = self.outbox.schedule(host_resp.is_transition());
+        if let GameResponse::ContestantStarted { .. } = audience_resp {
+            self.round_started_at = Some(at);
+            if let RoomState::Started {
+                current_round: 0, ..
+            } = self.room.state()
+            {
+                self.game_started_at = Some(at);
+            }
+        }
+        self.outbox
+            .deliver(at, self.hosts(), host_resp, self.game_started_at);
+        self.watch(&audience_resp);
+        self.comment(at, &audience_resp);
+        self.outbox
+            .deliver(at, self.audience(), audience_resp, self.game_started_at);
+        self.relist();
+        for response in follow_ups {
+            self.publish(response);
+        }
+        self.publish_lifecycle();
+    }
+
+    /// 按房间的解说模板为广播的事件生成解说，和事件同时只发给观众
+    fn comment(&self, at: Instant, response: &GameResponse) {
+        if self.spectators.is_empty() {
+            return;
+        }
+        let text = match &self.commentary {
+            Some(templates) => templates.comment(response, &self.room),
+            None => None,
+        };
+        if let Some(text) = text {
+            self.outbox.deliver(
+                at,
+                self.spectators.values().cloned().collect(),
+                GameResponse::Commentary { text },
+                self.game_started_at,
+            );
+        }
+    }
+
+    /// 把广播的事件发给订阅的看板，没有订阅时不复制事件
+    fn watch(&self, response: &GameResponse) {
+        if self.watchers.receiver_count() > 0 {
+            let _ = self.watchers.send(response.clone());
+        }
+    }
+
+    /// 每次广播时取出房间产生的事件写入日志，长期运行的房间（例如机器人对战）不会积累事件。
+    /// 返回操作附带产生、需要紧接着广播的事件（本轮结束时公布的种子）
+    fn drain_events(&mut self) -> Vec<GameResponse> {
+        let mut follow_ups = vec![];
+        for event in self.room.take_events() {
+            tracing::debug!(room = %self.room.id(), ?event, "Room event.");
+            self.tape.record(&event);
+            if let RoomEvent::SeedRevealed { round, seed, nonce } = event {
+                follow_ups.push(GameResponse::SeedRevealed { round, seed, nonce });
+            }
+        }
+        follow_ups
+    }
+
+    /// 通信对局房间在阶段变化后重新计时，并保存快照
+    fn restart_clock(&mut self, response: &GameResponse) {
+        if let Some(correspondence) = &mut self.correspondence {
+            if response.is_transition() || matches!(response, GameResponse::Ready { .. }) {
+                correspondence.restart_clock();
+            }
+        }
+    }
+
+    /// 大厅中列出的信息有变化时更新大厅，被隔离的房间不再列出。
+    /// 只有 `seq` 变化时不更新，大厅中的 `seq` 可能落后于房间
+    pub fn relist(&mut self) {
+        if self.quarantined {
+            if self.listed.take().is_some() {
+                self.lobby.unlist(&self.tenant, *self.room.id());
+            }
+            return;
+        }
+        let info = RoomInfo::from(&*self);
+        let changed = match &self.listed {
+            Some(listed) => {
+                RoomInfo {
+                    seq: listed.seq,
+                    ..info.clone()
+                } != *listed
+            }
+            None => true,
+        };
+        if changed {
+            self.lobby.list(&self.tenant, info.clone());
+            self.listed = Some(info);
+        }
+    }
+
+    /// 同步保存通信对局房间的快照
+    pub fn persist(&self) {
+        if let Some(store) = &self.store {
+            store.save(self);
+        }
+    }
+
+    /// 恢复会话后把发给成员的消息改为发到新的连接，断开期间被移出房间的成员不会恢复
+    pub fn rebind(&mut self, user_id: Uuid, role: &Role, sender: Sender<ServerMessage>) {
+        match role {
+            Role::Host { .. } => self.host = sender,
+            Role::CoHost { .. } if self.room.is_co_host(&user_id) => {
+                self.co_hosts.insert(user_id, sender);
+            }
+            Role::Contestant { .. } => match self.room.state() {
+                RoomState::Joined { contestant, .. } | RoomState::Started { contestant, .. }
+                    if *contestant == user_id =>
+                {
+                    self.contestant = Some(sender);
+                }
+                _ => {}
+            },
+            Role::Spectator { .. } if self.room.is_spectator(&user_id) => {
+                self.spectators.insert(user_id, sender);
+            }
+            Role::Moderator { .. } if self.moderators.contains_key(&user_id) => {
+                self.moderators.insert(user_id, sender);
+            }
+            _ => {}
+        }
+    }
+
+    /// 进行中（或者下一局）游戏的序号
+    pub fn game_number(&self) -> u64 {
+        self.next_game_id
+    }
+
+    /// 保存完成的游戏，超出上限后丢弃最早的一局
+    pub fn record_game(&mut self, result: GameResult, rounds: Vec<RoundResult>) {
+        self.completed = true;
+        if self.history.len() >= MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(CompletedGame {
+            id: self.next_game_id,
+            completed_at: timestamp(),
+            duration_ms: self
+                .game_started_at
+                .take()
+                .map(|at| at.elapsed().as_millis() as u64),
+            result,
+            rounds,
+            share: None,
+        });
+        self.next_game_id += 1;
+    }
+
+    /// 记录成功的终结操作，需要在广播结果之后调用
+    pub fn remember_terminal(
+        &mut self,
+        user_id: Uuid,
+        request: GameRequest,
+        response: GameResponse,
+    ) {
+        self.last_terminal = Some(Terminal {
+            user_id,
+            request,
+            seq: self.seq,
+            at: Instant::now(),
+            response,
+        });
+    }
+
+    /// 同一用户短时间内重复提交相同的终结操作，且房间没有新的事件时，返回之前的结果
+    pub fn repeated_terminal(&self, user_id: Uuid, request: &GameRequest) -> Option<GameResponse> {
+        let last = self.last_terminal.as_ref()?;
+        let repeated = last.user_id == user_id
+            && last.request == *request
+            && last.seq == self.seq
+            && last.at.elapsed() < REPEAT_WINDOW;
+        repeated.then(|| last.response.clone())
+    }
+}
+
+impl Drop for RoomAgent {
+    fn drop(&mut self) {
+        if self.listed.is_some() {
+            self.lobby.unlist(&self.tenant, *self.room.id());
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RoomDropper {
+    rooms: Arc<DashMap<Uuid, RoomAgent>>,
+    id: Option<Uuid>,
+}
+
+impl RoomDropper {
+    pub fn new(rooms: Arc<DashMap<Uuid, RoomAgent>>) -> Self {
+        Self { rooms, id: None }
+    }
+
+    pub fn set_room(&mut self, id: Uuid) {
+        if let Some(room_id) = self.id {
+            self.rooms.remove(&room_id);
+        }
+        self.id = Some(id);
+    }
+}
+
+impl Drop for RoomDropper {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            if let Some((_, mut ra)) = self.rooms.remove(&id) {
+                ra.close();
+            }
+            tracing::warn!(room = %id, "Room dropped.")
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use ndoors::{builtin_rules, GameResult, Room, RoundResult, Rules, Settings};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::protocol::ServerError;
+
+/// 内置的规则和从插件目录加载的规则，按名称排序
+#[derive(Default)]
+pub struct Rulebook {
+    rules: BTreeMap<String, Arc<dyn Rules + Send + Sync>>,
+}
+
+impl std::fmt::Debug for Rulebook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.rules.keys()).finish()
+    }
+}
+
+impl Rulebook {
+    /// 内置的规则加上插件目录中所有的 `<规则名称>.wasm` 插件，有误的插件和与已有规则重名的插件记录日志后跳过
+    pub fn load(dir: Option<&Path>) -> anyhow::Result<Self> {
+        let mut rulebook = Rulebook::default();
+        for rules in builtin_rules() {
+            rulebook
+                .rules
+                .insert(rules.name().to_string(), rules.into());
+        }
+        let dir = match dir {
+            Some(dir) => dir,
+            None => return Ok(rulebook),
+        };
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("wasm") {
+                continue;
+            }
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) if ndoors::valid_rules_name(name) => name.to_string(),
+                _ => {
+                    tracing::error!(path = %path.display(), "Invalid rules plugin name.");
+                    continue;
+                }
+            };
+            if rulebook.rules.contains_key(&name) {
+                tracing::error!(path = %path.display(), %name, "Rules already defined.");
+                continue;
+            }
+            match std::fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|wasm| plugin::load(name.clone(), &wasm))
+            {
+                Ok(rules) => {
+                    tracing::info!(path = %path.display(), %name, "Rules plugin loaded.");
+                    rulebook.rules.insert(name, rules);
+                }
+                Err(cause) => {
+                    tracing::error!(path = %path.display(), %cause, "Invalid rules plugin.")
+                }
+            }
+        }
+        Ok(rulebook)
+    }
+
+    /// 按设置中的规则名称调整设置，没有指定规则时原样返回
+    pub fn configure(&self, settings: Settings) -> Result<Settings, ServerError> {
+        let name = match &settings.rules {
+            Some(name) => name.clone(),
+            None => return Ok(settings),
+        };
+        let rules = self
+            .rules
+            .get(&name)
+            .ok_or_else(|| ServerError::RulesNotFound { name: name.clone() })?;
+        ndoors::apply_rules(rules.as_ref(), settings)
+            .map_err(|_| ServerError::RulesRejected { name })
+    }
+
+    /// 按房间设置中的规则名称设置房间的规则，没有指定规则或者找不到规则时按默认规则计分。
+    /// 创建、恢复房间和房间设置变化之后调用
+    pub fn attach(&self, room: &mut Room) {
+        match room
+            .settings()
+            .rules
+            .as_ref()
+            .and_then(|name| self.rules.get(name))
+        {
+            Some(rules) => room.set_rules(rules.clone()),
+            None => {
+                room.remove_rules();
+            }
+        }
+    }
+
+    /// 一局的结果，得分按设置中的规则计算
+    pub fn calculate(&self, settings: &Settings, results: &[RoundResult]) -> GameResult {
+        match settings
+            .rules
+            .as_ref()
+            .and_then(|name| self.rules.get(name))
+        {
+            Some(rules) => GameResult::calculate_with_rules(settings, results, rules.as_ref()),
+            None => GameResult::calculate(settings, results),
+        }
+    }
+}
+
+#[cfg(not(feature = "plugins"))]
+mod plugin {
+    use super::*;
+
+    pub fn load(_name: String, _wasm: &[u8]) -> anyhow::Result<Arc<dyn Rules + Send + Sync>> {
+        anyhow::bail!("Rules plugins require the `plugins` feature.")
+    }
+}
+
+#[cfg(feature = "plugins")]
+mod plugin {
+    use super::*;
+    use crate::sandbox::WasmModule;
+    use ndoors::Decision;
+
+    /// 模块的最大字节数
+    const MAX_MODULE_SIZE: usize = 1024 * 1024;
+
+    /// `options()` 返回值中的各个选项
+    const SUDDEN_DEATH: i32 = 0b1;
+    const QUIZ: i32 = 0b10;
+    const FORGETFUL: i32 = 0b100;
+    const EVIL: i32 = 0b1000;
+    const DEALS: i32 = 0b10000;
+
+    /// `score` 的参数：门数、是否赢了、是否改变了选择、奖品价值、接受的出价
+    type ScoreArgs = (i32, i32, i32, i32, i32);
+
+    /// 编译好的规则插件。
+    ///
+    /// 模块不能有导入，至少导出下面的一个函数：
+    /// `options() -> i32` 返回开启的选项（1 加赛，2 测验，4 健忘的主持人，8 邪恶的主持人，16 买断），
+    /// 没有开启的健忘的主持人、邪恶的主持人和买断会被关闭；
+    /// `score(doors: i32, win: i32, switched: i32, value: i32, deal: i32) -> i32` 返回一轮的得分，
+    /// 没有接受买断时 `deal` 为 -1。每次计分在新的实例中进行，一局的各轮共用一个实例，不同的计分之间不保留状态
+    pub struct WasmRules {
+        name: String,
+        module: WasmModule,
+    }
+
+    impl WasmRules {
+        fn new(name: String, wasm: &[u8]) -> anyhow::Result<Self> {
+            let module = WasmModule::compile(wasm, MAX_MODULE_SIZE)?;
+            if !module.exports("options") && !module.exports("score") {
+                anyhow::bail!("Rules plugins must export `options` or `score`.");
+            }
+            let rules = Self { name, module };
+            rules.options()?;
+            rules.call_score(&[(3, 1, 1, 1, -1)])?;
+            Ok(rules)
+        }
+
+        /// 开启的选项，没有导出 `options` 时为空
+        fn options(&self) -> anyhow::Result<Option<i32>> {
+            let mut instance = self.module.instantiate()?;
+            match instance.func::<(), i32>("options")? {
+                Some(options) => Ok(Some(instance.call(&options, ())?)),
+                None => Ok(None),
+            }
+        }
+
+        /// 按顺序计算每一轮的得分，所有调用在同一个实例中进行，没有导出 `score` 时为空
+        fn call_score(&self, args: &[ScoreArgs]) -> anyhow::Result<Option<Vec<u32>>> {
+            let mut instance = self.module.instantiate()?;
+            let score = match instance.func::<ScoreArgs, i32>("score")? {
+                Some(score) => score,
+                None => return Ok(None),
+            };
+            let mut scores = Vec::with_capacity(args.len());
+            for args in args {
+                match instance.call(&score, *args)? {
+                    score if score >= 0 => scores.push(score as u32),
+                    score => anyhow::bail!("Negative score {}.", score),
+                }
+            }
+            Ok(Some(scores))
+        }
+
+        /// 每一轮的得分，插件出错（包括燃料耗尽）时所有轮次按默认规则计分，并记录日志
+        fn scores(&self, results: &[RoundResult]) -> Vec<u32> {
+            let args: Vec<ScoreArgs> = results.iter().map(score_args).collect();
+            match self.call_score(&args) {
+                Ok(Some(scores)) => scores,
+                Ok(None) => results.iter().map(RoundResult::score).collect(),
+                Err(cause) => {
+                    tracing::warn!(rules = %self.name, %cause, "Rules plugin failed to score.");
+                    results.iter().map(RoundResult::score).collect()
+                }
+            }
+        }
+    }
+
+    fn score_args(result: &RoundResult) -> ScoreArgs {
+        let deal = match result.decision() {
+            Decision::TakeDeal(offer) => offer as i32,
+            _ => -1,
+        };
+        let switched = matches!(result.decision(), Decision::Switch | Decision::SwitchTo(_));
+        (
+            result.doors() as i32,
+            result.win() as i32,
+            switched as i32,
+            result.value() as i32,
+            deal,
+        )
+    }
+
+    impl Rules for WasmRules {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn configure(&self, settings: Settings) -> ndoors::Result<Settings> {
+            let options = match self.options() {
+                Ok(Some(options)) => options,
+                Ok(None) => return Ok(settings),
+                Err(cause) => {
+                    tracing::warn!(rules = %self.name, %cause, "Rules plugin failed to configure.");
+                    return Err(ndoors::Error::InvalidSettings);
+                }
+            };
+            let mut settings = settings
+                .with_forgetful(options & FORGETFUL != 0)
+                .with_evil(options & EVIL != 0)
+                .with_deals(options & DEALS != 0);
+            settings.sudden_death |= options & SUDDEN_DEATH != 0;
+            settings.quiz |= options & QUIZ != 0;
+            Ok(settings)
+        }
+
+        fn score(&self, result: &RoundResult) -> u32 {
+            self.scores(std::slice::from_ref(result))[0]
+        }
+
+        /// 所有轮次在同一个实例中计分
+        fn total(&self, results: &[RoundResult]) -> u64 {
+            self.scores(results).into_iter().map(u64::from).sum()
+        }
+    }
+
+    pub fn load(name: String, wasm: &[u8]) -> anyhow::Result<Arc<dyn Rules + Send + Sync>> {
+        Ok(Arc::new(WasmRules::new(name, wasm)?))
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::{anyhow, ensure};
+use ndoors::{Decision, Room, RoomEvent, RoomState, RoundResult, Settings, Stage, Uuid};
+
+/// 一轮游戏中决定结果的操作
+#[derive(Debug, Clone)]
+struct Actions {
+    /// 本局中从 0 开始的轮次
+    round: u32,
+    /// 所有奖品所在的门，从小到大排列
+    prizes: Vec<u32>,
+    chosen: Option<u32>,
+    /// 主持人揭示后留下的所有门，没有揭示（邪恶的主持人拒绝、健忘的主持人作废本轮）时为空
+    kept: Option<Vec<u32>>,
+}
+
+/// 从房间事件中记录的一轮游戏：操作和房间给出的结果
+#[derive(Debug, Clone)]
+pub struct TapedRound {
+    actions: Actions,
+    pub result: RoundResult,
+}
+
+impl TapedRound {
+    /// 本局中从 0 开始的轮次
+    pub fn round(&self) -> u32 {
+        self.actions.round
+    }
+}
+
+/// 按房间事件记录本局每一轮的操作，本局结束后交给 [`verify`] 抽查
+#[derive(Debug, Default)]
+pub struct RoundTape {
+    current: Option<Actions>,
+    rounds: Vec<TapedRound>,
+    completed: Vec<TapedRound>,
+}
+
+impl RoundTape {
+    pub fn record(&mut self, event: &RoomEvent) {
+        match event {
+            RoomEvent::RoundStarted { round, prizes } => {
+                self.current = Some(Actions {
+                    round: *round,
+                    prizes: prizes.clone(),
+                    chosen: None,
+                    kept: None,
+                })
+            }
+            RoomEvent::Chosen { chosen } => {
+                if let Some(current) = &mut self.current {
+                    current.chosen = Some(*chosen);
+                }
+            }
+            RoomEvent::Revealed { left, others } => {
+                if let Some(current) = &mut self.current {
+                    let mut kept = others.clone();
+                    kept.push(*left);
+                    current.kept = Some(kept);
+                }
+            }
+            RoomEvent::Undone { stage } => {
+                if let Some(current) = &mut self.current {
+                    match stage {
+                        Stage::Choose => {
+                            current.chosen = None;
+                            current.kept = None;
+                        }
+                        Stage::Reveal { .. } => current.kept = None,
+                        _ => {}
+                    }
+                }
+            }
+            RoomEvent::RoundRolledBack { .. } => {
+                if let Some(current) = &mut self.current {
+                    current.chosen = None;
+                    current.kept = None;
+                }
+            }
+            RoomEvent::RoundAborted { .. } => self.current = None,
+            RoomEvent::Decided { result } | RoomEvent::RoundVoided { result } => {
+                // 从快照恢复之前开始的一轮没有记录操作，无法重放
+                if let Some(actions) = self.current.take() {
+                    self.rounds.push(TapedRound {
+                        actions,
+                        result: *result,
+                    });
+                }
+            }
+            RoomEvent::Completed { .. } => {
+                self.current = None;
+                self.completed = std::mem::take(&mut self.rounds);
+            }
+            _ => {}
+        }
+    }
+
+    /// 取出最近完成的一局游戏记录的轮次
+    pub fn take_completed(&mut self) -> Vec<TapedRound> {
+        std::mem::take(&mut self.completed)
+    }
+}
+
+/// 在新的房间中按记录的操作重放一轮，检查房间给出的结果是否符合规则。
+/// 健忘的主持人作废了留下多个门的一轮时，打开的门没有记录，只检查结果本身是否自洽
+pub fn verify(settings: &Settings, taped: &TapedRound) -> anyhow::Result<()> {
+    let recorded = &taped.result;
+    let actions = &taped.actions;
+    if recorded.void() && actions.kept.is_none() && recorded.closed() > 1 {
+        ensure!(
+            actions.prizes.contains(&recorded.prize())
+                && recorded.prize() != recorded.chosen()
+                && recorded.left() != recorded.chosen()
+                && !recorded.win(),
+            "inconsistent void round {:?}",
+            recorded
+        );
+        return Ok(());
+    }
+
+    let replayed = replay(settings, taped)?;
+    let fields = |result: &RoundResult| {
+        (
+            result.doors(),
+            result.prize(),
+            result.prizes(),
+            result.chosen(),
+            result.left(),
+            result.closed(),
+            result.decision(),
+            result.win(),
+            result.value(),
+            result.void(),
+            result.offered(),
+            result.offer(),
+            result.estimate(),
+        )
+    };
+    ensure!(
+        fields(recorded) == fields(&replayed),
+        "recorded {:?}, replayed {:?}",
+        recorded,
+        replayed
+    );
+    Ok(())
+}
+
+/// 只进行这一轮的设置：门数和价值取自结果，关闭计时、无尽模式和双方共同决定奖品位置
+fn replay(settings: &Settings, taped: &TapedRound) -> anyhow::Result<RoundResult> {
+    let recorded = &taped.result;
+    let settings = Settings {
+        doors: recorded.doors(),
+        rounds: 1,
+        schedule: vec![],
+        sudden_death: false,
+        quiz: recorded.estimate().is_some(),
+        timeouts: None,
+        shared_entropy: false,
+        endless: false,
+        prize_value: Some(recorded.value()),
+        ..settings.clone()
+    };
+    let actions = &taped.actions;
+    let chosen = actions
+        .chosen
+        .ok_or_else(|| anyhow!("no choice recorded"))?;
+    let mut room = Room::create(Uuid::new_v4(), settings)?;
+    room.record_events(true);
+    room.accept_contestant(Uuid::new_v4())?;
+    room.contestant_ready(true)?;
+    room.start_with_prizes(&actions.prizes)?;
+    room.choose(chosen)?;
+    if matches!(
+        room.state(),
+        RoomState::Started {
+            stage: Stage::Reveal { .. },
+            ..
+        }
+    ) {
+        match &actions.kept {
+            Some(kept) => room.reveal_doors(kept)?,
+            None if recorded.void() => room.reveal_doors(&[recorded.left()])?,
+            None => {
+                room.decline()?;
+            }
+        }
+    }
+    if !recorded.void() && recorded.offered() {
+        if let Some(percent) = recorded.estimate() {
+            room.estimate(percent)?;
+        }
+        if let Some(offer) = recorded.offer() {
+            room.offer_deal(offer)?;
+        }
+        let decision = match recorded.decision() {
+            Decision::Switch => Decision::SwitchTo(recorded.left()),
+            decision => decision,
+        };
+        room.decide(decision)?;
+    }
+    room.take_events()
+        .into_iter()
+        .find_map(|event| match event {
+            RoomEvent::Decided { result } | RoomEvent::RoundVoided { result } => Some(result),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("replayed round did not end"))
+}
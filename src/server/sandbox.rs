@@ -0,0 +1,89 @@
+use std::sync::OnceLock;
+use wasmtime::{
+    Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+    WasmParams, WasmResults,
+};
+
+/// 每次调用可以消耗的燃料，大约是执行的指令数
+const FUEL: u64 = 1_000_000;
+/// 每个实例可以使用的内存
+const MAX_MEMORY: usize = 1024 * 1024;
+
+/// 所有上传的机器人和规则插件共用的引擎，开启燃料计量
+fn engine() -> anyhow::Result<Engine> {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    if let Some(engine) = ENGINE.get() {
+        return Ok(engine.clone());
+    }
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config)?;
+    Ok(ENGINE.get_or_init(|| engine).clone())
+}
+
+/// 编译好的不可信模块，模块不能有导入
+pub struct WasmModule {
+    module: Module,
+}
+
+impl WasmModule {
+    /// 编译模块，模块超过 `max_size` 字节或者有导入时返回错误。编译比较慢，异步上下文中在阻塞线程上调用
+    pub fn compile(wasm: &[u8], max_size: usize) -> anyhow::Result<Self> {
+        if wasm.len() > max_size {
+            anyhow::bail!("Module larger than {} bytes.", max_size);
+        }
+        let module = Module::new(&engine()?, wasm)?;
+        if module.imports().len() > 0 {
+            anyhow::bail!("Modules cannot have imports.");
+        }
+        Ok(Self { module })
+    }
+
+    /// 模块是否导出了指定名称的函数
+    pub fn exports(&self, name: &str) -> bool {
+        self.module.exports().any(|export| export.name() == name)
+    }
+
+    /// 新的实例，内存和实例数受限
+    pub fn instantiate(&self) -> anyhow::Result<WasmInstance> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(MAX_MEMORY)
+            .instances(1)
+            .build();
+        let mut store = Store::new(self.module.engine(), limits);
+        store.limiter(|limits| limits);
+        let instance = Instance::new(&mut store, &self.module, &[])?;
+        Ok(WasmInstance { store, instance })
+    }
+}
+
+/// 模块的一个实例，多次调用之间保留实例的状态
+pub struct WasmInstance {
+    store: Store<StoreLimits>,
+    instance: Instance,
+}
+
+impl WasmInstance {
+    /// 按名称和签名取出导出的函数，没有导出时为空
+    pub fn func<P, R>(&mut self, name: &str) -> anyhow::Result<Option<TypedFunc<P, R>>>
+    where
+        P: WasmParams,
+        R: WasmResults,
+    {
+        match self.instance.get_func(&mut self.store, name) {
+            Some(func) => Ok(Some(func.typed::<P, R, _>(&self.store)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// 调用导出的函数，每次调用之前补足燃料，燃料耗尽时返回错误
+    pub fn call<P, R>(&mut self, func: &TypedFunc<P, R>, params: P) -> anyhow::Result<R>
+    where
+        P: WasmParams,
+        R: WasmResults,
+    {
+        let remaining = self.store.consume_fuel(0)?;
+        self.store.add_fuel(FUEL - remaining)?;
+        Ok(func.call(&mut self.store, params)?)
+    }
+}
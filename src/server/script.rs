@@ -0,0 +1,204 @@
+use ndoors::Room;
+
+use crate::protocol::Index;
+
+/// 主持人脚本可以执行的操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptAction {
+    Start { prize: Index },
+    Reveal { left: Index },
+    Decline,
+    Chat { text: String },
+}
+
+/// 脚本编译或者运行失败（包括超出运行限制）
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ScriptError(String);
+
+/// 主持人脚本，由房间在每个请求处理完之后运行。
+///
+/// 脚本可以定义 `on_ready(state)`、`on_chosen(state)` 和 `on_decided(state)`，
+/// 分别在挑战者准备好、挑战者选择门之后和每轮结束之后调用，同一时机只调用一次。
+/// 脚本通过 `start(prize)`、`start_random()`、`reveal(left)`、`reveal_random()`、
+/// `decline()`（邪恶的主持人不给改变选择的机会）和 `chat(text)` 以主持人的身份操作。
+#[derive(Debug)]
+pub struct HostScript {
+    #[cfg(feature = "scripting")]
+    ast: rhai::AST,
+    /// 上一次调用的时机：对局序号、轮次和回调
+    #[cfg(feature = "scripting")]
+    last: Option<(u64, u32, &'static str)>,
+}
+
+#[cfg(not(feature = "scripting"))]
+impl HostScript {
+    pub fn compile(_source: &str) -> Result<Self, ScriptError> {
+        Err(ScriptError(
+            "Host scripts require the `scripting` feature.".to_string(),
+        ))
+    }
+
+    pub fn run(&mut self, _game: u64, _room: &Room) -> Result<Vec<ScriptAction>, ScriptError> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(feature = "scripting")]
+mod engine {
+    use super::*;
+    use ndoors::{GameResult, RoomState, Stage};
+    use rhai::{Array, Dynamic, Engine, Map, Scope};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// 每次回调最多执行的操作数
+    const MAX_OPERATIONS: u64 = 100_000;
+    /// 每次回调最长的运行时间
+    const TIME_LIMIT: Duration = Duration::from_millis(50);
+    /// 源代码的最大长度
+    const MAX_SOURCE_LEN: usize = 16 * 1024;
+
+    /// 沙盒引擎：没有文件和网络访问，限制操作数、运行时间、调用深度和数据大小
+    fn engine(actions: Arc<Mutex<Vec<ScriptAction>>>) -> Engine {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(16)
+            .set_max_expr_depths(32, 32)
+            .set_max_string_size(1024)
+            .set_max_array_size(1024)
+            .set_max_map_size(256)
+            .disable_symbol("eval");
+
+        let started = Instant::now();
+        engine.on_progress(move |_| (started.elapsed() > TIME_LIMIT).then_some(Dynamic::UNIT));
+        engine.on_print(|text| tracing::info!(target: "script", %text, "Print."));
+
+        let push = move |action| actions.lock().unwrap().push(action);
+        let start = push.clone();
+        engine.register_fn("start", move |prize: i64| {
+            start(ScriptAction::Start {
+                prize: Index::Specified(prize as u32),
+            })
+        });
+        let start_random = push.clone();
+        engine.register_fn("start_random", move || {
+            start_random(ScriptAction::Start {
+                prize: Index::Random,
+            })
+        });
+        let reveal = push.clone();
+        engine.register_fn("reveal", move |left: i64| {
+            reveal(ScriptAction::Reveal {
+                left: Index::Specified(left as u32),
+            })
+        });
+        let reveal_random = push.clone();
+        engine.register_fn("reveal_random", move || {
+            reveal_random(ScriptAction::Reveal {
+                left: Index::Random,
+            })
+        });
+        let decline = push.clone();
+        engine.register_fn("decline", move || decline(ScriptAction::Decline));
+        engine.register_fn("chat", move |text: &str| {
+            push(ScriptAction::Chat {
+                text: text.to_string(),
+            })
+        });
+        engine
+    }
+
+    /// 当前房间状态对应的回调和传给回调的状态
+    fn trigger(room: &Room) -> Option<(u32, &'static str, Map)> {
+        let settings = room.settings();
+        let mut state = Map::new();
+        state.insert("rounds".into(), (settings.rounds as i64).into());
+        let (round, hook) = match room.state() {
+            RoomState::Joined { ready: true, .. } => (0, "on_ready"),
+            RoomState::Started {
+                current_round,
+                prize,
+                results,
+                stage,
+                ..
+            } => {
+                let doors = settings.round(*current_round).doors;
+                let wins = GameResult::calculate(settings, results).win();
+                state.insert("round".into(), (*current_round as i64).into());
+                state.insert("doors".into(), (doors as i64).into());
+                state.insert("wins".into(), (wins as i64).into());
+                match stage {
+                    Stage::Reveal { chosen } => {
+                        // 挑战者没有选中奖品时只能留下奖品，否则可以留下除了已选之外的任意一个门。
+                        // 健忘的主持人可以留下任意一个门
+                        let legal: Array = (0..doors)
+                            .filter(|&door| {
+                                door != *chosen
+                                    && (settings.forgetful || *chosen == *prize || door == *prize)
+                            })
+                            .map(|door| (door as i64).into())
+                            .collect();
+                        state.insert("prize".into(), (*prize as i64).into());
+                        state.insert("chosen".into(), (*chosen as i64).into());
+                        state.insert("legal".into(), legal.into());
+                        (*current_round, "on_chosen")
+                    }
+                    Stage::End => {
+                        let result = results.last()?;
+                        let decision = format!("{:?}", result.decision());
+                        state.insert("win".into(), result.win().into());
+                        state.insert("void".into(), result.void().into());
+                        state.insert("offered".into(), result.offered().into());
+                        state.insert("decision".into(), decision.into());
+                        (*current_round, "on_decided")
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        Some((round, hook, state))
+    }
+
+    impl HostScript {
+        pub fn compile(source: &str) -> Result<Self, ScriptError> {
+            if source.len() > MAX_SOURCE_LEN {
+                return Err(ScriptError(format!(
+                    "Script longer than {} bytes.",
+                    MAX_SOURCE_LEN
+                )));
+            }
+            let ast = engine(Default::default())
+                .compile(source)
+                .map_err(|cause| ScriptError(cause.to_string()))?;
+            Ok(Self { ast, last: None })
+        }
+
+        /// 房间进入新的时机并且脚本定义了对应的回调时运行回调，返回脚本请求的操作
+        pub fn run(&mut self, game: u64, room: &Room) -> Result<Vec<ScriptAction>, ScriptError> {
+            let (round, hook, state) = match trigger(room) {
+                Some(trigger) => trigger,
+                None => {
+                    self.last = None;
+                    return Ok(Vec::new());
+                }
+            };
+            if self.last == Some((game, round, hook)) {
+                return Ok(Vec::new());
+            }
+            self.last = Some((game, round, hook));
+            if !self.ast.iter_functions().any(|f| f.name == hook) {
+                return Ok(Vec::new());
+            }
+
+            let actions = Arc::new(Mutex::new(Vec::new()));
+            engine(actions.clone())
+                .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, hook, (state,))
+                .map_err(|cause| ScriptError(format!("{}: {}", hook, cause)))?;
+            let actions = std::mem::take(&mut *actions.lock().unwrap());
+            Ok(actions)
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use dashmap::DashMap;
+use ndoors::Uuid;
+use tokio::time::{Duration, Instant};
+
+use crate::permission::Role;
+use crate::room::{RoomAgent, RoomDropper};
+use crate::User;
+
+/// 断开连接后保留的会话，包括用户在房间中的角色和创建的房间
+#[derive(Debug)]
+struct ParkedSession {
+    user: User,
+    room_dropper: RoomDropper,
+    parked_at: Instant,
+    /// 通信对局成员的会话不会过期，房间结束后才丢弃
+    expires: bool,
+}
+
+/// 断开连接的会话在 `window` 内可以用恢复令牌恢复，过期后和直接断开一样，用户创建的房间会被删除。
+/// 通信对局成员的会话一直保留到房间结束
+#[derive(Debug)]
+pub struct Sessions {
+    window: Option<Duration>,
+    parked: DashMap<Uuid, ParkedSession>,
+}
+
+impl Sessions {
+    pub fn new(window: Option<Duration>) -> Self {
+        Self {
+            window,
+            parked: DashMap::new(),
+        }
+    }
+
+    /// 会话保留的时间，`None` 表示不能恢复
+    pub fn window(&self) -> Option<Duration> {
+        self.window
+    }
+
+    /// 保留断开连接的会话
+    pub fn park(&self, user: User, room_dropper: RoomDropper, expires: bool) {
+        tracing::info!(user = %user.id, %expires, "Session parked.");
+        self.parked.insert(
+            user.resume_token,
+            ParkedSession {
+                user,
+                room_dropper,
+                parked_at: Instant::now(),
+                expires,
+            },
+        );
+    }
+
+    /// 取出保留的会话，恢复令牌就是凭据，只需要租户一致
+    pub fn resume(&self, token: Uuid, tenant: &str) -> Option<(User, RoomDropper)> {
+        let (_, session) = self
+            .parked
+            .remove_if(&token, |_, session| session.user.tenant == tenant)?;
+        Some((session.user, session.room_dropper))
+    }
+
+    /// 丢弃已经过期的会话，返回丢弃的会话中用户的角色
+    pub fn expire(&self, token: Uuid) -> Option<Role> {
+        let window = self.window?;
+        self.parked
+            .remove_if(&token, |_, session| {
+                session.expires && session.parked_at.elapsed() >= window
+            })
+            .map(|(_, session)| session.user.role)
+    }
+
+    /// 丢弃所在房间已经不存在的不过期会话
+    pub fn prune(&self, rooms: &DashMap<Uuid, RoomAgent>) {
+        self.parked.retain(|_, session| {
+            session.expires
+                || session
+                    .user
+                    .role
+                    .room_id()
+                    .is_some_and(|room_id| rooms.contains_key(&room_id))
+        });
+    }
+}
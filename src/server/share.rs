@@ -0,0 +1,184 @@
+use dashmap::DashMap;
+use ndoors::{EarlyEnd, GameResult, Settings};
+use rand::Rng;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+use crate::timestamp;
+
+/// 最多保留的分享卡片数，超出后丢弃最早的卡片
+const MAX_CARDS: usize = 10_000;
+/// 短代码的长度
+const CODE_LEN: usize = 8;
+/// 短代码使用的字符，去掉了容易混淆的 0、1、i、l 和 o
+const CODE_ALPHABET: &[u8] = b"23456789abcdefghjkmnpqrstuvwxyz";
+
+/// 一局游戏的分享卡片：设置、胜率和改变选择的情况，不包含用户和房间 ID
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ShareCard {
+    /// 短代码，卡片的地址是 `/s/{code}`
+    pub code: String,
+    /// 分享的时间（Unix 时间戳，毫秒）
+    pub created_at: u64,
+    /// 房间名称
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    /// 游戏设置，轮数为实际进行的轮数
+    pub settings: Settings,
+    pub rounds: u32,
+    pub win: u32,
+    pub win_rate: f64,
+    /// 改变选择的次数
+    pub switch: u32,
+    pub switch_rate: f64,
+    /// 改变选择后赢的次数
+    pub switch_win: u32,
+    /// 坚持选择的次数
+    pub stick: u32,
+    /// 坚持选择后赢的次数
+    pub stick_win: u32,
+    /// 设置了奖品价值或者允许买断时的得分
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<u64>,
+    /// 没有打完所有轮次就结束的原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ended_early: Option<EarlyEnd>,
+}
+
+impl ShareCard {
+    fn new(code: String, room: Option<&str>, result: &GameResult) -> Self {
+        let stats = result.stats();
+        Self {
+            code,
+            created_at: timestamp(),
+            room: room.map(str::to_string),
+            settings: result.settings().clone(),
+            rounds: stats.rounds(),
+            win: stats.win(),
+            win_rate: stats.win_rate(),
+            switch: stats.switch(),
+            switch_rate: stats.switch_rate(),
+            switch_win: stats.switch_win(),
+            stick: stats.stick(),
+            stick_win: stats.stick_win(),
+            score: result.score().map(|score| score.score()),
+            ended_early: result.ended_early(),
+        }
+    }
+
+    /// 1200×630 的 SVG 卡片，适合社交网站的预览图
+    pub fn svg(&self) -> String {
+        let title = match &self.room {
+            Some(room) => escape(room),
+            None => format!("{} doors", self.settings.doors),
+        };
+        let mut lines = vec![
+            format!(
+                "Won {} of {} rounds ({:.0}%)",
+                self.win,
+                self.rounds,
+                self.win_rate * 100.0
+            ),
+            format!(
+                "Switched {} times, won {} · stuck {} times, won {}",
+                self.switch, self.switch_win, self.stick, self.stick_win
+            ),
+        ];
+        if let Some(score) = self.score {
+            lines.push(format!("Score {}", score));
+        }
+
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="1200" height="630" viewBox="0 0 1200 630"><rect width="1200" height="630" fill="#1d2433"/><text x="80" y="130" font-family="sans-serif" font-size="64" font-weight="bold" fill="#ffffff">{}</text>"##,
+            title
+        );
+        for (i, line) in lines.iter().enumerate() {
+            let _ = write!(
+                svg,
+                r##"<text x="80" y="{}" font-family="sans-serif" font-size="40" fill="#d8dee9">{}</text>"##,
+                230 + i * 60,
+                line
+            );
+        }
+        for (i, (label, rate, color)) in [
+            ("win", self.win_rate, "#a3be8c"),
+            ("switch", self.switch_rate, "#ebcb8b"),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let y = 430 + i * 70;
+            let _ = write!(
+                svg,
+                r##"<text x="80" y="{}" font-family="sans-serif" font-size="32" fill="#d8dee9">{}</text><rect x="240" y="{}" width="880" height="36" rx="18" fill="#3b4252"/><rect x="240" y="{}" width="{:.0}" height="36" rx="18" fill="{}"/>"##,
+                y + 28,
+                label,
+                y,
+                y,
+                880.0 * rate.clamp(0.0, 1.0),
+                color
+            );
+        }
+        svg.push_str(r##"<text x="1120" y="600" text-anchor="end" font-family="sans-serif" font-size="28" fill="#81a1c1">ndoors</text></svg>"##);
+        svg
+    }
+}
+
+/// 转义 SVG 文本中的特殊字符
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 分享的卡片，只保存在内存中，服务器重启后失效
+#[derive(Debug, Default)]
+pub struct ShareCards {
+    cards: DashMap<String, Arc<ShareCard>>,
+    /// 按分享顺序排列的短代码
+    order: Mutex<VecDeque<String>>,
+}
+
+impl ShareCards {
+    /// 为一局游戏生成卡片，返回短代码
+    pub fn share(&self, room: Option<&str>, result: &GameResult) -> String {
+        let mut rng = rand::thread_rng();
+        let code = loop {
+            let code: String = (0..CODE_LEN)
+                .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+                .collect();
+            if !self.cards.contains_key(&code) {
+                break code;
+            }
+        };
+        self.cards.insert(
+            code.clone(),
+            Arc::new(ShareCard::new(code.clone(), room, result)),
+        );
+        let mut order = self.order.lock().unwrap();
+        order.push_back(code.clone());
+        while order.len() > MAX_CARDS {
+            if let Some(oldest) = order.pop_front() {
+                self.cards.remove(&oldest);
+            }
+        }
+        code
+    }
+
+    pub fn get(&self, code: &str) -> Option<Arc<ShareCard>> {
+        self.cards.get(code).map(|card| card.clone())
+    }
+}
@@ -0,0 +1,335 @@
+use ndoors::{Decision, Settings, Uuid};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::time::{timeout, Duration, Instant};
+
+use crate::config::Config;
+use crate::pacing::Pacing;
+use crate::protocol::*;
+use crate::room::RoomDropper;
+use crate::{request_handler, Server, User};
+
+/// 一个客户端等待一条响应的最长时间，超过时认为房间卡住了
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 所有客户端都没有进展的最长时间，超过时认为服务器死锁
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 所有房间关闭后等待断开的会话清理房间的时间
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 压力测试的结果
+#[derive(Debug)]
+pub struct Report {
+    pub games: u32,
+    pub rounds: u64,
+    pub requests: u64,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.elapsed.as_secs_f64();
+        writeln!(
+            f,
+            "{} games, {} rounds, {} requests in {:.2}s",
+            self.games, self.rounds, self.requests, secs
+        )?;
+        write!(
+            f,
+            "{:.1} games/s, {:.1} requests/s",
+            self.games as f64 / secs,
+            self.requests as f64 / secs
+        )
+    }
+}
+
+/// `ndoors-server stress [pairs] [rounds]`：在单独的线程和运行时中进行压力测试。
+/// 整个运行时都卡住（例如持有 `get_mut` 时删除同一个房间）时，调用线程上的看门狗在
+/// 所有客户端 [`STALL_TIMEOUT`] 没有进展后返回错误
+pub fn run(args: &[String]) -> anyhow::Result<Report> {
+    let mut args = args.iter().map(|arg| arg.parse::<u32>());
+    let pairs = args.next().transpose()?.unwrap_or(1000);
+    let rounds = args.next().transpose()?.unwrap_or(10);
+    if pairs == 0 || rounds == 0 {
+        anyhow::bail!("Usage: ndoors-server stress [pairs] [rounds]");
+    }
+
+    let progress = Arc::new(AtomicU64::new(0));
+    let (done, result) = mpsc::channel();
+    let counter = progress.clone();
+    std::thread::spawn(move || {
+        let report = tokio::runtime::Runtime::new()
+            .map_err(anyhow::Error::from)
+            .and_then(|runtime| runtime.block_on(stress(pairs, rounds, &counter)));
+        let _ = done.send(report);
+    });
+
+    let mut last = 0;
+    let mut since = std::time::Instant::now();
+    loop {
+        match result.recv_timeout(Duration::from_secs(1)) {
+            Ok(report) => return report,
+            Err(RecvTimeoutError::Disconnected) => anyhow::bail!("Stress test panicked."),
+            Err(RecvTimeoutError::Timeout) => {
+                let current = progress.load(Ordering::Relaxed);
+                if current != last {
+                    last = current;
+                    since = std::time::Instant::now();
+                } else if since.elapsed() > STALL_TIMEOUT {
+                    anyhow::bail!(
+                        "No progress for {:?} after {} requests, the server is deadlocked.",
+                        STALL_TIMEOUT,
+                        current
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// 在进程内启动服务器，用内存中的连接代替 WebSocket，让 `pairs` 对主持人和挑战者同时玩完
+/// `rounds` 轮游戏，`progress` 累计发出的请求数。
+///
+/// 请求经过和真实连接相同的 `request_handler`，覆盖 `rooms` 的 `get_mut`、删除和大厅更新交错的情况。
+/// 任何一步超时、出错或者结束后还有房间没有删除时失败
+async fn stress(pairs: u32, rounds: u32, progress: &Arc<AtomicU64>) -> anyhow::Result<Report> {
+    // 不读取配置文件，避免记录会话、收集数据集或者转发事件
+    let server = Server::new(Config::default())?;
+
+    let started = Instant::now();
+    let handles: Vec<_> = (0..pairs)
+        .map(|pair| {
+            let server = server.clone();
+            let progress = progress.clone();
+            tokio::spawn(async move { play(&server, &progress, pair, rounds).await })
+        })
+        .collect();
+    let mut failures = vec![];
+    for handle in handles {
+        if let Err(cause) = handle.await? {
+            failures.push(cause);
+        }
+    }
+    let elapsed = started.elapsed();
+
+    // 挑战者和一半的主持人断开连接后由会话清理房间
+    let settle = Instant::now();
+    while !server.rooms.is_empty() && settle.elapsed() < SETTLE_TIMEOUT {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    if let Some(cause) = failures.first() {
+        anyhow::bail!(
+            "{} of {} games failed, the first with: {}",
+            failures.len(),
+            pairs,
+            cause
+        );
+    }
+    if !server.rooms.is_empty() {
+        anyhow::bail!(
+            "{} rooms left after all sessions ended.",
+            server.rooms.len()
+        );
+    }
+    Ok(Report {
+        games: pairs,
+        rounds: pairs as u64 * rounds as u64,
+        requests: progress.load(Ordering::Relaxed),
+        elapsed,
+    })
+}
+
+/// 内存中的一个连接，请求由和 WebSocket 连接相同的请求处理任务处理
+struct Connection<'a> {
+    id: Uuid,
+    serial: Uuid,
+    server: &'a Server,
+    progress: &'a AtomicU64,
+    sender: Sender<ClientMessage>,
+    receiver: Receiver<ServerMessage>,
+}
+
+impl<'a> Connection<'a> {
+    fn open(server: &'a Server, progress: &'a AtomicU64) -> Self {
+        let (resp_sender, receiver) = channel(16);
+        let (sender, req_receiver) = channel(16);
+        let user = User::new(Uuid::new_v4(), resp_sender);
+        let id = user.id;
+        let (serial, takeover) = server.connections.register(id);
+        let room_dropper = RoomDropper::new(server.rooms.clone());
+        let s = server.clone();
+        tokio::spawn(async move {
+            if let Err(cause) = request_handler(user, room_dropper, s, req_receiver, takeover).await
+            {
+                tracing::error!(%cause, "Request handler error.");
+            }
+        });
+        Self {
+            id,
+            serial,
+            server,
+            progress,
+            sender,
+            receiver,
+        }
+    }
+
+    async fn send(&self, request: GameRequest) -> anyhow::Result<()> {
+        let message = ClientMessage {
+            request,
+            expected_seq: None,
+        };
+        self.sender
+            .send(message)
+            .await
+            .map_err(|_| anyhow::anyhow!("{}: request handler gone", self.id))?;
+        self.progress.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// 等待 `f` 接受的响应，跳过其他广播，收到错误时失败
+    async fn expect<T>(&mut self, f: impl Fn(&GameResponse) -> Option<T>) -> anyhow::Result<T> {
+        loop {
+            let message = match timeout(STEP_TIMEOUT, self.receiver.recv()).await {
+                Ok(Some(message)) => message,
+                Ok(None) => anyhow::bail!("{}: connection closed", self.id),
+                Err(_) => anyhow::bail!("{}: no response for {:?}", self.id, STEP_TIMEOUT),
+            };
+            match &message.response {
+                GameResponse::GameError { .. } | GameResponse::ServerError { .. } => {
+                    anyhow::bail!("{}: {:?}", self.id, message.response)
+                }
+                response => {
+                    if let Some(value) = f(response) {
+                        return Ok(value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 等待 `f` 接受的响应
+    async fn wait(&mut self, f: impl Fn(&GameResponse) -> bool) -> anyhow::Result<()> {
+        self.expect(|response| f(response).then_some(())).await
+    }
+
+    /// 关闭请求通道，请求处理任务随之结束会话
+    fn close(self) {
+        self.server.connections.unregister(&self.id, self.serial);
+    }
+}
+
+/// 一对主持人和挑战者玩完一局。单数的主持人直接断开连接，由会话清理房间
+async fn play(server: &Server, progress: &AtomicU64, pair: u32, rounds: u32) -> anyhow::Result<()> {
+    let mut host = Connection::open(server, progress);
+    let mut contestant = Connection::open(server, progress);
+
+    host.send(GameRequest::CreateRoom {
+        settings: Some(Settings::new(3, rounds)),
+        pacing: Some(Pacing::Instant),
+        class: None,
+        lesson: None,
+        correspondence: None,
+        fair: false,
+        commentary: None,
+        name: None,
+        description: None,
+    })
+    .await?;
+    let id = host
+        .expect(|response| match response {
+            GameResponse::RoomCreated { info } => Some(info.id),
+            _ => None,
+        })
+        .await?;
+
+    contestant.send(GameRequest::EnterRoom { id }).await?;
+    contestant
+        .wait(|response| matches!(response, GameResponse::ContestantRoomEntered { .. }))
+        .await?;
+    contestant.send(GameRequest::Ready { ready: true }).await?;
+    host.wait(|response| matches!(response, GameResponse::Ready { ready: true }))
+        .await?;
+
+    for _ in 0..rounds {
+        host.send(GameRequest::Start {
+            prize: Index::Random,
+            others: vec![],
+            doors: None,
+        })
+        .await?;
+        contestant
+            .wait(|response| matches!(response, GameResponse::ContestantStarted { .. }))
+            .await?;
+        // 游戏进行中读取大厅，和其他房间更新大厅交错
+        contestant
+            .send(GameRequest::ListRooms { page: 0, size: 10 })
+            .await?;
+        contestant
+            .wait(|response| matches!(response, GameResponse::RoomList { .. }))
+            .await?;
+        contestant
+            .send(GameRequest::Choose {
+                chosen: Index::Random,
+            })
+            .await?;
+        host.wait(|response| matches!(response, GameResponse::Chosen { .. }))
+            .await?;
+        host.send(GameRequest::Reveal {
+            left: Index::Random,
+            others: vec![],
+        })
+        .await?;
+        contestant
+            .wait(|response| matches!(response, GameResponse::Revealed { .. }))
+            .await?;
+        contestant
+            .send(GameRequest::Decide {
+                decision: Decision::Switch,
+            })
+            .await?;
+        host.wait(|response| matches!(response, GameResponse::Decided { .. }))
+            .await?;
+    }
+
+    host.send(GameRequest::Complete {
+        kick_contestant: false,
+    })
+    .await?;
+    contestant
+        .wait(|response| matches!(response, GameResponse::Completed { .. }))
+        .await?;
+
+    if pair & 1 == 0 {
+        host.send(GameRequest::ExitRoom { id }).await?;
+        contestant
+            .wait(|response| matches!(response, GameResponse::Exited { .. }))
+            .await?;
+    }
+    host.close();
+    contestant.close();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 并发的房间操作、大厅读取和房间删除不会死锁，所有房间最后都被删除
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn stress_() {
+        let progress = Arc::new(AtomicU64::new(0));
+        let report = timeout(Duration::from_secs(60), stress(16, 3, &progress))
+            .await
+            .expect("the server is deadlocked")
+            .unwrap();
+        assert_eq!(report.games, 16);
+        assert_eq!(report.rounds, 48);
+        assert!(report.requests > 0);
+    }
+}
@@ -0,0 +1,41 @@
+use ndoors::Settings;
+
+use crate::config::Config;
+use crate::filter::Filter;
+use crate::footprint::SizeLimits;
+
+/// 可以在运行时重新加载的配置，重新加载不影响已经建立的连接
+#[derive(Debug)]
+pub struct Tunables {
+    /// 创建房间时没有指定设置使用的默认设置
+    pub default_settings: Settings,
+    pub filter: Filter,
+    /// 新连接使用的压缩阈值
+    pub compression_threshold: usize,
+    /// 房间占用内存的告警阈值
+    pub room_size_limits: SizeLimits,
+    /// 每局游戏结束后重放核对的轮次比例
+    pub round_audit_rate: f64,
+}
+
+impl Tunables {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let default_settings = config
+            .default_settings
+            .clone()
+            .unwrap_or_else(|| Settings::new(3, 10));
+        default_settings.validate()?;
+        let round_audit_rate = config.round_audit_rate.unwrap_or(0.0);
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&round_audit_rate),
+            "round_audit_rate must be between 0 and 1"
+        );
+        Ok(Self {
+            default_settings,
+            filter: Filter::new(config.filter.clone()),
+            compression_threshold: config.compression_threshold.unwrap_or(16 * 1024),
+            room_size_limits: config.room_size_limits,
+            round_audit_rate,
+        })
+    }
+}
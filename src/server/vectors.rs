@@ -0,0 +1,33 @@
+use ndoors::{test_vectors, Direction, TestVector};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::protocol::{ClientMessage, GameResponse};
+
+/// 检查服务器对每条协议测试向量的解析和序列化结果与向量一致
+pub fn check() -> anyhow::Result<()> {
+    let vectors = test_vectors();
+    let mut failed = 0;
+    for vector in &vectors {
+        let result = match vector.direction {
+            Direction::Request => round_trip::<ClientMessage>(vector),
+            Direction::Response => round_trip::<GameResponse>(vector),
+        };
+        if let Err(cause) = result {
+            failed += 1;
+            println!("{}: {}", vector.name, cause);
+        }
+    }
+    println!("{}/{} vectors match", vectors.len() - failed, vectors.len());
+
+    if failed > 0 {
+        anyhow::bail!("The server does not conform to the protocol test vectors.");
+    }
+    Ok(())
+}
+
+fn round_trip<T: Serialize + DeserializeOwned>(vector: &TestVector) -> anyhow::Result<()> {
+    let message: T = serde_json::from_value(vector.message.clone())?;
+    vector.check(&serde_json::to_string(&message)?)?;
+    Ok(())
+}
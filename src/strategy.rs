@@ -0,0 +1,91 @@
+use rand::Rng;
+
+use crate::Decision;
+
+/// 挑战者策略，机器人挑战者用它选择门和做出抉择
+pub trait ContestantStrategy {
+    /// 在 `doors` 个门中选择一个，返回门序号
+    fn choose(&mut self, doors: u32) -> u32;
+
+    /// 主持人揭示后做出抉择，`chosen` 是已经选择的门，`left` 是主持人留下的门
+    fn decide(&mut self, doors: u32, chosen: u32, left: u32) -> Decision;
+
+    /// 测验模式下估计改变选择的中奖率（百分比），默认给出理论值
+    fn estimate(&mut self, doors: u32) -> u32 {
+        (doors - 1) * 100 / doors
+    }
+}
+
+/// 随机选择门，总是做出同样的抉择
+#[derive(Debug, Copy, Clone, Default)]
+pub struct FixedDecision(pub Decision);
+
+impl ContestantStrategy for FixedDecision {
+    fn choose(&mut self, doors: u32) -> u32 {
+        rand::thread_rng().gen_range(0..doors)
+    }
+
+    fn decide(&mut self, _doors: u32, _chosen: u32, _left: u32) -> Decision {
+        self.0
+    }
+}
+
+/// 随机选择门，随机做出抉择
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RandomDecision;
+
+impl ContestantStrategy for RandomDecision {
+    fn choose(&mut self, doors: u32) -> u32 {
+        rand::thread_rng().gen_range(0..doors)
+    }
+
+    fn decide(&mut self, _doors: u32, _chosen: u32, _left: u32) -> Decision {
+        rand::random()
+    }
+}
+
+/// 主持人策略，机器人对战房间中的主持人用它放置奖品和揭示门
+pub trait HostStrategy {
+    /// 新一轮开始时在 `doors` 个门中放置奖品，返回门序号
+    fn hide(&mut self, doors: u32) -> u32;
+
+    /// 挑战者选择 `chosen` 之后留下一个门，不能是 `chosen`，挑战者没有选中奖品时必须留下奖品
+    fn leave(&mut self, doors: u32, prize: u32, chosen: u32) -> u32;
+}
+
+/// 随机放置奖品，挑战者选中奖品时随机留下一个门
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RandomHost;
+
+impl HostStrategy for RandomHost {
+    fn hide(&mut self, doors: u32) -> u32 {
+        rand::thread_rng().gen_range(0..doors)
+    }
+
+    fn leave(&mut self, doors: u32, prize: u32, chosen: u32) -> u32 {
+        if chosen == prize {
+            crate::random_door(doors, chosen)
+        } else {
+            prize
+        }
+    }
+}
+
+/// 随机放置奖品，挑战者选中奖品时总是留下序号最小的门。
+/// 留下的门因此会泄露信息，用来演示主持人偏好对中奖率的影响
+#[derive(Debug, Copy, Clone, Default)]
+pub struct LazyHost;
+
+impl HostStrategy for LazyHost {
+    fn hide(&mut self, doors: u32) -> u32 {
+        rand::thread_rng().gen_range(0..doors)
+    }
+
+    fn leave(&mut self, _doors: u32, prize: u32, chosen: u32) -> u32 {
+        if chosen == prize {
+            u32::from(chosen == 0)
+        } else {
+            prize
+        }
+    }
+}
@@ -0,0 +1,453 @@
+//! 类型状态 API：在 [`crate::Room`] 之上用类型表示房间的状态，每个状态只提供合法的操作，
+//! 在错误的阶段调用操作无法通过编译，不会再得到 [`Error::InvalidOperation`]。
+//! 门序号、设置等只能在运行时检查的错误仍然会返回，失败时通过 [`Failed`] 交还原来状态的房间
+
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use crate::{Decision, Error, RoomObserver, RoomState, RoundResult, Settings, Stage, Uuid};
+
+/// 刚刚创建，等待挑战者加入
+#[derive(Debug)]
+pub struct Created;
+
+/// 挑战者已加入，尚未准备好
+#[derive(Debug)]
+pub struct Joined;
+
+/// 挑战者已准备好，等待主持人开始
+#[derive(Debug)]
+pub struct Ready;
+
+/// 等待挑战者选择
+#[derive(Debug)]
+pub struct Choosing;
+
+/// 等待主持人揭示
+#[derive(Debug)]
+pub struct Revealing;
+
+/// 等待挑战者抉择
+#[derive(Debug)]
+pub struct Deciding;
+
+/// 本轮结束，还有下一轮（包括加赛）
+#[derive(Debug)]
+pub struct RoundOver;
+
+/// 最后一轮结束，等待完成本局
+#[derive(Debug)]
+pub struct GameOver;
+
+/// 游戏暂停中，只能继续或者强制结束
+#[derive(Debug)]
+pub struct Paused;
+
+/// 游戏进行中的状态，可以在任意阶段强制结束
+pub trait Playing: private::Sealed {}
+
+impl Playing for Choosing {}
+impl Playing for Revealing {}
+impl Playing for Deciding {}
+impl Playing for RoundOver {}
+impl Playing for GameOver {}
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for super::Choosing {}
+    impl Sealed for super::Revealing {}
+    impl Sealed for super::Deciding {}
+    impl Sealed for super::RoundOver {}
+    impl Sealed for super::GameOver {}
+}
+
+/// 处于状态 `S` 的房间，可以通过 `Deref` 读取 [`crate::Room`] 的所有信息。
+/// 房间放在堆上，状态转换时只移动指针
+#[derive(Debug)]
+pub struct Room<S> {
+    room: Box<crate::Room>,
+    state: PhantomData<S>,
+}
+
+/// 操作失败，`room` 是保持原来状态的房间
+#[derive(Debug)]
+pub struct Failed<S> {
+    pub room: Room<S>,
+    pub error: Error,
+}
+
+impl<S> From<Failed<S>> for Error {
+    fn from(failed: Failed<S>) -> Self {
+        failed.error
+    }
+}
+
+/// 状态转换的结果，失败时交还原来状态的房间
+pub type Transition<T, S> = std::result::Result<T, Failed<S>>;
+
+/// 任意状态的房间，用来从 [`crate::Room`] 转换
+#[derive(Debug)]
+pub enum AnyRoom {
+    Created(Room<Created>),
+    Joined(Room<Joined>),
+    Ready(Room<Ready>),
+    Choosing(Room<Choosing>),
+    Revealing(Room<Revealing>),
+    Deciding(Room<Deciding>),
+    RoundOver(Room<RoundOver>),
+    GameOver(Room<GameOver>),
+    Paused(Room<Paused>),
+}
+
+impl From<crate::Room> for AnyRoom {
+    fn from(room: crate::Room) -> Self {
+        let room = Box::new(room);
+        match room.state() {
+            RoomState::Started { paused: true, .. } => AnyRoom::Paused(cast(room)),
+            RoomState::Created => AnyRoom::Created(cast(room)),
+            RoomState::Joined { ready: false, .. } => AnyRoom::Joined(cast(room)),
+            RoomState::Joined { ready: true, .. } => AnyRoom::Ready(cast(room)),
+            RoomState::Started { stage, .. } => match stage {
+                Stage::Choose => AnyRoom::Choosing(cast(room)),
+                Stage::Reveal { .. } => AnyRoom::Revealing(cast(room)),
+                Stage::Decide { .. } | Stage::Deal { .. } => AnyRoom::Deciding(cast(room)),
+                // 放弃的一轮和一轮结束一样等待主持人开始
+                Stage::End if room.has_next_round() => AnyRoom::RoundOver(cast(room)),
+                Stage::Aborted => AnyRoom::RoundOver(cast(room)),
+                Stage::End => AnyRoom::GameOver(cast(room)),
+            },
+        }
+    }
+}
+
+/// 挑战者选择之后，没有可以打开的门时直接进入抉择
+#[derive(Debug)]
+pub enum AfterChoose {
+    Revealing(Room<Revealing>),
+    Deciding(Room<Deciding>),
+}
+
+/// 主持人揭示之后，健忘的主持人打开了奖品所在的门时本轮作废，直接结束
+#[derive(Debug)]
+pub enum AfterReveal {
+    Deciding(Room<Deciding>),
+    Voided(AfterDecide),
+}
+
+/// 挑战者抉择之后，规定轮数打完且没有打平时进入 [`GameOver`]
+#[derive(Debug)]
+pub enum AfterDecide {
+    RoundOver(Room<RoundOver>),
+    GameOver(Room<GameOver>),
+}
+
+impl<S> Deref for Room<S> {
+    type Target = crate::Room;
+
+    fn deref(&self) -> &crate::Room {
+        &self.room
+    }
+}
+
+/// 转换为状态 `T`，调用者保证房间确实处于该状态
+fn cast<T>(room: Box<crate::Room>) -> Room<T> {
+    Room {
+        room,
+        state: PhantomData,
+    }
+}
+
+/// 挑战者选择之后的状态
+fn after_choose(room: Box<crate::Room>) -> AfterChoose {
+    match room.state() {
+        RoomState::Started {
+            stage: Stage::Reveal { .. },
+            ..
+        } => AfterChoose::Revealing(cast(room)),
+        _ => AfterChoose::Deciding(cast(room)),
+    }
+}
+
+/// 主持人揭示之后的状态
+fn after_reveal(room: Box<crate::Room>) -> AfterReveal {
+    match room.state() {
+        RoomState::Started {
+            stage: Stage::Decide { .. },
+            ..
+        } => AfterReveal::Deciding(cast(room)),
+        _ => AfterReveal::Voided(round_over(room)),
+    }
+}
+
+/// 一轮结束之后的状态
+fn round_over(room: Box<crate::Room>) -> AfterDecide {
+    if room.has_next_round() {
+        AfterDecide::RoundOver(cast(room))
+    } else {
+        AfterDecide::GameOver(cast(room))
+    }
+}
+
+impl<S> Room<S> {
+    /// 执行操作，失败时交还原来状态的房间
+    fn then<T>(
+        mut self,
+        f: impl FnOnce(&mut crate::Room) -> crate::Result<T>,
+    ) -> Transition<(Box<crate::Room>, T), S> {
+        match f(&mut self.room) {
+            Ok(value) => Ok((self.room, value)),
+            Err(error) => Err(Failed { room: self, error }),
+        }
+    }
+
+    /// 取出动态类型的房间
+    pub fn into_inner(self) -> crate::Room {
+        *self.room
+    }
+
+    /// 见 [`crate::Room::add_co_host`]
+    pub fn add_co_host(&mut self, id: Uuid) -> crate::Result<()> {
+        self.room.add_co_host(id)
+    }
+
+    /// 见 [`crate::Room::remove_co_host`]
+    pub fn remove_co_host(&mut self, id: &Uuid) -> crate::Result<()> {
+        self.room.remove_co_host(id)
+    }
+
+    /// 见 [`crate::Room::record_events`]
+    pub fn record_events(&mut self, recording: bool) {
+        self.room.record_events(recording)
+    }
+
+    /// 见 [`crate::Room::take_events`]
+    pub fn take_events(&mut self) -> Vec<crate::RoomEvent> {
+        self.room.take_events()
+    }
+
+    /// 见 [`crate::Room::set_observer`]
+    pub fn set_observer(&mut self, observer: impl RoomObserver + 'static) {
+        self.room.set_observer(observer)
+    }
+}
+
+impl Room<Created> {
+    /// 创建房间
+    pub fn create(host: Uuid, settings: Settings) -> crate::Result<Self> {
+        crate::Room::create(host, settings).map(|room| cast(Box::new(room)))
+    }
+
+    /// 使用指定的 ID 创建房间，见 [`crate::Room::create_with_id`]
+    pub fn create_with_id(id: Uuid, host: Uuid, settings: Settings) -> crate::Result<Self> {
+        crate::Room::create_with_id(id, host, settings).map(|room| cast(Box::new(room)))
+    }
+
+    /// 创建使用固定种子的房间，见 [`crate::Room::create_seeded`]
+    pub fn create_seeded(host: Uuid, settings: Settings, seed: u64) -> crate::Result<Self> {
+        crate::Room::create_seeded(host, settings, seed).map(|room| cast(Box::new(room)))
+    }
+
+    /// 挑战者进入前修改设置，立即生效
+    pub fn update_settings(&mut self, settings: Settings) -> crate::Result<()> {
+        self.room.update_settings(settings).map(|_| ())
+    }
+
+    /// 挑战者加入
+    pub fn accept_contestant(self, contestant: Uuid) -> Transition<Room<Joined>, Created> {
+        self.then(|room| room.accept_contestant(contestant))
+            .map(|(room, _)| cast(room))
+    }
+}
+
+impl Room<Joined> {
+    /// 提议设置，返回 `true` 表示需要挑战者确认，见 [`crate::Room::update_settings`]
+    pub fn update_settings(&mut self, settings: Settings) -> crate::Result<bool> {
+        self.room.update_settings(settings)
+    }
+
+    /// 挑战者确认或者拒绝提议的设置
+    pub fn answer_settings(&mut self, accept: bool) -> crate::Result<Settings> {
+        self.room.answer_settings(accept)
+    }
+
+    /// 挑战者准备好
+    pub fn ready(mut self) -> Room<Ready> {
+        self.room.contestant_ready(true).expect("joined room");
+        cast(self.room)
+    }
+
+    /// 请挑战者离开
+    pub fn kick_contestant(mut self) -> Room<Created> {
+        self.room.kick_contestant().expect("joined room");
+        cast(self.room)
+    }
+}
+
+impl Room<Ready> {
+    /// 挑战者取消准备
+    pub fn unready(mut self) -> Room<Joined> {
+        self.room.contestant_ready(false).expect("ready room");
+        cast(self.room)
+    }
+
+    /// 请挑战者离开
+    pub fn kick_contestant(mut self) -> Room<Created> {
+        self.room.kick_contestant().expect("ready room");
+        cast(self.room)
+    }
+
+    /// 开始游戏，见 [`crate::Room::start`]
+    pub fn start(self, prize: u32) -> Transition<Room<Choosing>, Ready> {
+        self.then(|room| room.start(prize))
+            .map(|(room, _)| cast(room))
+    }
+
+    /// 开始游戏，见 [`crate::Room::start_with_prizes`]
+    pub fn start_with_prizes(self, prizes: &[u32]) -> Transition<Room<Choosing>, Ready> {
+        self.then(|room| room.start_with_prizes(prizes))
+            .map(|(room, _)| cast(room))
+    }
+
+    /// 随机放置奖品并开始游戏
+    pub fn start_random(mut self) -> Room<Choosing> {
+        self.room.start_random().expect("ready room");
+        cast(self.room)
+    }
+}
+
+impl Room<Choosing> {
+    /// 挑战者选择
+    pub fn choose(self, chosen: u32) -> Transition<AfterChoose, Choosing> {
+        self.then(|room| room.choose(chosen))
+            .map(|(room, _)| after_choose(room))
+    }
+
+    /// 挑战者随机选择
+    pub fn choose_random(mut self) -> AfterChoose {
+        self.room.choose_random().expect("choosing room");
+        after_choose(self.room)
+    }
+}
+
+impl Room<Revealing> {
+    /// 主持人揭示，见 [`crate::Room::reveal`]
+    pub fn reveal(self, left: u32) -> Transition<AfterReveal, Revealing> {
+        self.then(|room| room.reveal(left))
+            .map(|(room, _)| after_reveal(room))
+    }
+
+    /// 主持人揭示并指定所有留下的门，见 [`crate::Room::reveal_doors`]
+    pub fn reveal_doors(self, left: &[u32]) -> Transition<AfterReveal, Revealing> {
+        self.then(|room| room.reveal_doors(left))
+            .map(|(room, _)| after_reveal(room))
+    }
+
+    /// 主持人随机揭示
+    pub fn reveal_random(mut self) -> AfterReveal {
+        self.room.reveal_random().expect("revealing room");
+        after_reveal(self.room)
+    }
+
+    /// 邪恶的主持人不给改变选择的机会，见 [`crate::Room::decline`]
+    pub fn decline(self) -> Transition<(AfterDecide, RoundResult), Revealing> {
+        self.then(|room| room.decline())
+            .map(|(room, result)| (round_over(room), result))
+    }
+}
+
+impl Room<Deciding> {
+    /// 测验模式下估计改变选择的中奖率
+    pub fn estimate(&mut self, percent: u32) -> crate::Result<()> {
+        self.room.estimate(percent)
+    }
+
+    /// 主持人出价买断，见 [`crate::Room::offer_deal`]
+    pub fn offer_deal(&mut self, offer: u32) -> crate::Result<()> {
+        self.room.offer_deal(offer)
+    }
+
+    /// 挑战者做出最终抉择，测验模式下需要先估计
+    pub fn decide(self, decision: Decision) -> Transition<(AfterDecide, RoundResult), Deciding> {
+        self.decide_with(|room| room.decide(decision))
+    }
+
+    /// 同 [`Room::decide`]，`latency` 是从看到揭示结果到做出抉择的时间
+    pub fn decide_with_latency(
+        self,
+        decision: Decision,
+        latency: std::time::Duration,
+    ) -> Transition<(AfterDecide, RoundResult), Deciding> {
+        self.decide_with(|room| room.decide_with_latency(decision, latency))
+    }
+
+    fn decide_with(
+        self,
+        f: impl FnOnce(&mut crate::Room) -> crate::Result<RoundResult>,
+    ) -> Transition<(AfterDecide, RoundResult), Deciding> {
+        self.then(f)
+            .map(|(room, result)| (round_over(room), result))
+    }
+}
+
+impl Room<RoundOver> {
+    /// 开始下一轮，见 [`crate::Room::start`]
+    pub fn start(self, prize: u32) -> Transition<Room<Choosing>, RoundOver> {
+        self.then(|room| room.start(prize))
+            .map(|(room, _)| cast(room))
+    }
+
+    /// 开始下一轮，见 [`crate::Room::start_with_prizes`]
+    pub fn start_with_prizes(self, prizes: &[u32]) -> Transition<Room<Choosing>, RoundOver> {
+        self.then(|room| room.start_with_prizes(prizes))
+            .map(|(room, _)| cast(room))
+    }
+
+    /// 随机放置奖品并开始下一轮
+    pub fn start_random(mut self) -> Room<Choosing> {
+        self.room.start_random().expect("round over");
+        cast(self.room)
+    }
+}
+
+impl Room<GameOver> {
+    /// 完成本局游戏，挑战者留在房间中
+    pub fn complete(mut self) -> (Room<Joined>, Vec<RoundResult>) {
+        let results = self.room.complete(false).expect("game over");
+        (cast(self.room), results)
+    }
+
+    /// 完成本局游戏并请挑战者离开
+    pub fn complete_and_kick(mut self) -> (Room<Created>, Vec<RoundResult>) {
+        let results = self.room.complete(true).expect("game over");
+        (cast(self.room), results)
+    }
+}
+
+impl<S: Playing> Room<S> {
+    /// 在任意阶段强制结束本局游戏，只输出已经完成的轮次
+    pub fn force_complete(mut self) -> (Room<Joined>, Vec<RoundResult>) {
+        let results = self.room.force_complete().expect("playing room");
+        (cast(self.room), results)
+    }
+
+    /// 暂停游戏，见 [`crate::Room::pause`]
+    pub fn pause(mut self) -> Room<Paused> {
+        self.room.pause().expect("playing room");
+        cast(self.room)
+    }
+}
+
+impl Room<Paused> {
+    /// 继续游戏，回到暂停前的状态
+    pub fn resume(mut self) -> AnyRoom {
+        self.room.resume().expect("paused room");
+        AnyRoom::from(*self.room)
+    }
+
+    /// 强制结束本局游戏，只输出已经完成的轮次
+    pub fn force_complete(mut self) -> (Room<Joined>, Vec<RoundResult>) {
+        let results = self.room.force_complete().expect("paused room");
+        (cast(self.room), results)
+    }
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 规范的协议消息，见 `vectors.json`
+const TEST_VECTORS: &str = include_str!("vectors.json");
+
+/// 消息方向
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// 客户端发给服务器
+    Request,
+    /// 服务器发给客户端
+    Response,
+}
+
+/// 一条协议测试向量，其他语言的客户端应当序列化出与 `message` 完全相同的 JSON
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TestVector {
+    /// 名称
+    pub name: String,
+    /// 消息方向
+    pub direction: Direction,
+    /// 规范的 JSON 消息
+    pub message: Value,
+}
+
+/// 与测试向量不一致的位置
+#[derive(Debug, thiserror::Error, Clone, Eq, PartialEq)]
+#[error("{path}: {reason}")]
+pub struct Mismatch {
+    /// JSON 路径，例如 `$.result.settings.doors`
+    pub path: String,
+    /// 不一致的原因
+    pub reason: String,
+}
+
+impl TestVector {
+    /// 检查客户端序列化出的 JSON 与测试向量是否一致，对象的字段顺序不影响结果
+    pub fn check(&self, json: &str) -> std::result::Result<(), Mismatch> {
+        let actual: Value = serde_json::from_str(json).map_err(|cause| Mismatch {
+            path: "$".to_string(),
+            reason: format!("invalid JSON: {}", cause),
+        })?;
+        compare("$", &self.message, &actual)
+    }
+}
+
+/// 所有协议测试向量
+pub fn test_vectors() -> Vec<TestVector> {
+    serde_json::from_str(TEST_VECTORS).expect("vectors.json is valid")
+}
+
+fn compare(path: &str, expected: &Value, actual: &Value) -> std::result::Result<(), Mismatch> {
+    let mismatch = |reason: String| Mismatch {
+        path: path.to_string(),
+        reason,
+    };
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                match actual.get(key) {
+                    Some(actual) => compare(&format!("{}.{}", path, key), expected, actual)?,
+                    None => return Err(mismatch(format!("missing field `{}`", key))),
+                }
+            }
+            match actual.keys().find(|key| !expected.contains_key(*key)) {
+                Some(key) => Err(mismatch(format!("unexpected field `{}`", key))),
+                None => Ok(()),
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            if expected.len() != actual.len() {
+                return Err(mismatch(format!(
+                    "expected {} items, found {}",
+                    expected.len(),
+                    actual.len()
+                )));
+            }
+            for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                compare(&format!("{}[{}]", path, index), expected, actual)?;
+            }
+            Ok(())
+        }
+        (expected, actual) if expected == actual => Ok(()),
+        (expected, actual) => Err(mismatch(format!("expected {}, found {}", expected, actual))),
+    }
+}